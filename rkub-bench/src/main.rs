@@ -0,0 +1,164 @@
+//! Puts a running server under load: many rooms of bots playing as fast as
+//! they're allowed to, then a report of how long the server took to answer
+//! and, given its pid, what it cost to run.
+
+mod player;
+mod stats;
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use tokio::sync::oneshot;
+use tokio::time::{interval, Instant};
+
+use rkub_common::WS_PATH;
+
+use player::{Report, Seat};
+use stats::Usage;
+
+const USAGE: &str = "usage: rkub-bench [--server URL] [--rooms N] [--players M] [--secs S]
+                  [--think MS] [--pid PID]
+
+Plays N rooms of M bots against the server for S seconds, with MS between
+each bot's moves. Given the server's PID, also reports its memory and CPU.";
+
+struct Args {
+    server: String,
+    rooms: usize,
+    players: usize,
+    duration: Duration,
+    think: Duration,
+    pid: Option<u32>,
+}
+
+impl Args {
+    fn parse() -> anyhow::Result<Self> {
+        let mut args = Args {
+            server: format!("ws://127.0.0.1:5555{}", WS_PATH),
+            rooms: 10,
+            players: 4,
+            duration: Duration::from_secs(30),
+            think: Duration::from_millis(50),
+            pid: None,
+        };
+
+        let mut argv = std::env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            let value = match arg.as_str() {
+                "-h" | "--help" => bail!(USAGE),
+                _ => argv.next().ok_or_else(|| anyhow!(USAGE))?,
+            };
+            let number = || value.parse::<u64>().map_err(|_| anyhow!(USAGE));
+
+            match arg.as_str() {
+                "--server" => args.server = value.clone(),
+                "--rooms" => args.rooms = number()? as usize,
+                "--players" => args.players = number()? as usize,
+                "--secs" => args.duration = Duration::from_secs(number()?),
+                "--think" => args.think = Duration::from_millis(number()?),
+                "--pid" => args.pid = Some(number()? as u32),
+                _ => bail!(USAGE),
+            }
+        }
+
+        if args.players < 2 || args.think == Duration::default() {
+            bail!("rooms need at least 2 players, and bots need time to think");
+        }
+
+        Ok(args)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse()?;
+    let deadline = Instant::now() + args.duration;
+    println!(
+        "{} rooms of {} players for {}s against {}",
+        args.rooms,
+        args.players,
+        args.duration.as_secs(),
+        args.server
+    );
+
+    let sampler = args.pid.map(|pid| tokio::spawn(watch_usage(pid, deadline)));
+
+    let mut players = Vec::new();
+    for room in 0..args.rooms {
+        let play = |name: String, seat| {
+            tokio::spawn(player::run(
+                args.server.clone(),
+                name,
+                seat,
+                args.players,
+                args.think,
+                deadline,
+            ))
+        };
+
+        let (code_tx, code) = oneshot::channel();
+        players.push(play(format!("bot{}-0", room), Seat::Create(code_tx)));
+        let code = code
+            .await
+            .map_err(|_| anyhow!("room {} was never created", room))?;
+
+        for i in 1..args.players {
+            players.push(play(format!("bot{}-{}", room, i), Seat::Join(code.clone())));
+        }
+    }
+
+    let mut report = Report::default();
+    let mut failed = 0;
+    for player in players {
+        match player.await? {
+            Ok(seen) => report.merge(seen),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "turns: {}, games won: {}, rejected: {}, players lost: {}",
+        report.turns, report.wins, report.rejected, failed
+    );
+    for (kind, latencies) in &report.latencies {
+        println!("{:<8}  {}", kind, latencies);
+    }
+
+    if let Some(sampler) = sampler {
+        match sampler.await? {
+            Some((start, end, peak_rss_kb)) => {
+                let cpu = end.cpu - start.cpu;
+                println!(
+                    "server: cpu {:.2}s ({:.0}% of a core), peak rss {:.1}MB",
+                    cpu.as_secs_f64(),
+                    cpu.as_secs_f64() / args.duration.as_secs_f64() * 100.0,
+                    peak_rss_kb as f64 / 1024.0
+                );
+            }
+            None => println!("server: couldn't read its usage from /proc"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Samples `pid` every second until `deadline`, returning the first and last
+/// samples and the most memory it used in between.
+async fn watch_usage(pid: u32, deadline: Instant) -> Option<(Usage, Usage, u64)> {
+    let start = Usage::sample(pid)?;
+    let mut last = start;
+    let mut peak_rss_kb = start.rss_kb;
+
+    let mut every_second = interval(Duration::from_secs(1));
+    while Instant::now() < deadline {
+        every_second.tick().await;
+
+        last = Usage::sample(pid)?;
+        peak_rss_kb = peak_rss_kb.max(last.rss_kb);
+    }
+
+    Some((start, last, peak_rss_kb))
+}
@@ -0,0 +1,335 @@
+//! One simulated player. It plays like a person would, only faster: it
+//! fiddles with a piece, plays a group if it has one and ends its turn,
+//! pinging the server all the while, and times every answer it gets.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use anyhow::bail;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::time::{interval, sleep_until, timeout, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use rkub_common::{
+    bot, ClientMessage, Coord, GameState, Move, MoveId, RoomOptions, Sequenced, ServerMessage,
+};
+
+use crate::stats::Latencies;
+
+/// How often idle players check in, like the browser client does.
+const PING_EVERY: Duration = Duration::from_millis(500);
+
+/// What one player saw, by kind of message for latencies.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub latencies: BTreeMap<&'static str, Latencies>,
+    pub turns: usize,
+    pub wins: usize,
+    /// Moves or boards the server turned down, which a correct bot shouldn't
+    /// see.
+    pub rejected: usize,
+}
+
+impl Report {
+    pub fn merge(&mut self, other: Report) {
+        for (kind, latencies) in other.latencies {
+            self.latencies.entry(kind).or_default().extend(latencies);
+        }
+        self.turns += other.turns;
+        self.wins += other.wins;
+        self.rejected += other.rejected;
+    }
+}
+
+/// Where a player goes once connected.
+pub enum Seat {
+    /// Create a room and send back its code.
+    Create(oneshot::Sender<String>),
+    Join(String),
+}
+
+struct Player {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The `seq` of the last message we sent.
+    sent: u64,
+    name: String,
+    players: Vec<String>,
+    state: GameState,
+    /// How many players the room waits for before the first turn.
+    room_size: usize,
+    code: Option<oneshot::Sender<String>>,
+    /// Moves waiting for the player to "think", sent one per tick.
+    queued: VecDeque<ClientMessage>,
+    /// When each message still waiting on an answer was sent. Each
+    /// connection's messages are answered in order.
+    pending: VecDeque<(&'static str, Instant)>,
+    report: Report,
+}
+
+/// Plays as `name` until `deadline`, with `think` between moves.
+pub async fn run(
+    server: String,
+    name: String,
+    seat: Seat,
+    room_size: usize,
+    think: Duration,
+    deadline: Instant,
+) -> anyhow::Result<Report> {
+    let (ws, _) = connect_async(server.as_str()).await?;
+
+    let (join, code) = match seat {
+        Seat::Create(code) => (
+            ClientMessage::CreateRoom(name.clone(), None, RoomOptions::default()),
+            Some(code),
+        ),
+        Seat::Join(room) => (ClientMessage::JoinRoom(name.clone(), room, None), None),
+    };
+
+    let mut player = Player {
+        ws,
+        sent: 0,
+        name,
+        players: Vec::new(),
+        state: GameState::default(),
+        room_size,
+        code,
+        queued: VecDeque::new(),
+        pending: VecDeque::new(),
+        report: Report::default(),
+    };
+    player.send(join).await?;
+
+    let mut think = interval(think);
+    let mut ping = interval(PING_EVERY);
+
+    loop {
+        tokio::select! {
+            _ = sleep_until(deadline) => break,
+            msg = player.ws.next() => match msg {
+                Some(Ok(Message::Text(json))) => {
+                    let msg: Sequenced<ServerMessage> = serde_json::from_str(&json)?;
+                    player.on_message(msg.msg)?
+                }
+                Some(Ok(Message::Close(_))) | None => bail!("{} was disconnected", player.name),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            _ = think.tick() => {
+                if let Some(msg) = player.queued.pop_front() {
+                    player.send_timed(msg).await?;
+                }
+            }
+            _ = ping.tick(), if player.me().is_some() => player.send_timed(ClientMessage::Ping).await?,
+        }
+    }
+
+    // Wait for the server to hang up, so it sees a clean close:
+    let _ = player.send(ClientMessage::Close).await;
+    let _ = player.ws.close(None).await;
+    while let Ok(Some(Ok(_))) = timeout(PING_EVERY, player.ws.next()).await {}
+
+    Ok(player.report)
+}
+
+impl Player {
+    fn me(&self) -> Option<usize> {
+        self.players.iter().position(|p| *p == self.name)
+    }
+
+    fn is_turn(&self) -> bool {
+        self.me() == Some(self.state.active_player)
+    }
+
+    /// Whether we can take the turn we've been given: everyone's in, and
+    /// we've been dealt. The first turn starts with the second player, so it
+    /// waits for the rest of a bigger room.
+    fn can_start(&self) -> bool {
+        self.is_turn()
+            && self.players.len() == self.room_size
+            && self.me().is_some_and(|me| !self.state.hands[me].is_empty())
+    }
+
+    async fn send(&mut self, msg: ClientMessage) -> anyhow::Result<()> {
+        self.sent += 1;
+        let json = serde_json::to_string(&Sequenced {
+            seq: self.sent,
+            msg,
+        })?;
+        self.ws.send(Message::text(json)).await?;
+
+        Ok(())
+    }
+
+    /// Sends `msg`, timing how long until it's answered if it will be.
+    async fn send_timed(&mut self, msg: ClientMessage) -> anyhow::Result<()> {
+        let kind = match msg {
+            ClientMessage::Ping => "ping",
+            ClientMessage::EndTurn => "end turn",
+            ClientMessage::Place(..) | ClientMessage::Pickup(..) | ClientMessage::Moves(_) => {
+                "move"
+            }
+            _ => return self.send(msg).await,
+        };
+
+        self.pending.push_back((kind, Instant::now()));
+        self.send(msg).await
+    }
+
+    /// Records the latency of the oldest message still waiting on an answer.
+    fn answered(&mut self) {
+        if let Some((kind, sent)) = self.pending.pop_front() {
+            self.report
+                .latencies
+                .entry(kind)
+                .or_default()
+                .record(sent.elapsed());
+        }
+    }
+
+    /// Queues up a whole turn: put a piece down and pick it back up, then play
+    /// whatever group the hand makes.
+    fn plan_turn(&mut self) {
+        let me = match self.me() {
+            Some(me) if self.players.len() >= self.room_size => me,
+            _ => return,
+        };
+
+        if let Some(&piece) = self.state.hands[me].first() {
+            let top = self.state.game.board().keys().map(|c| c.1).min();
+            let spare = Coord(0, top.unwrap_or(0) - 2);
+
+            self.queued.push_back(ClientMessage::Place(spare, piece));
+            self.queued.push_back(ClientMessage::Pickup(spare, piece));
+        }
+
+        let moves = bot::plan_turn(&self.state, me);
+        if !moves.is_empty() {
+            self.queued.push_back(ClientMessage::Moves(moves));
+        }
+
+        self.queued.push_back(ClientMessage::EndTurn);
+    }
+
+    /// Applies board edits the server accepted. Ours move pieces to and
+    /// from our hand too.
+    fn apply(&mut self, id: MoveId, moves: &[Move]) {
+        if let Some(me) = self.me().filter(|&me| me == id.seat) {
+            self.answered();
+            if self
+                .state
+                .game
+                .apply_moves(&mut self.state.hands[me], moves)
+                .is_err()
+            {
+                self.report.rejected += 1;
+            }
+            return;
+        }
+
+        let board = self.state.game.board_mut();
+        for m in moves {
+            match *m {
+                Move::Place(coord, piece) => {
+                    board.insert(coord, piece);
+                }
+                Move::Pickup(coord, _) => {
+                    board.remove(&coord);
+                }
+            }
+        }
+    }
+
+    fn on_message(&mut self, msg: ServerMessage) -> anyhow::Result<()> {
+        match msg {
+            ServerMessage::JoinedRoom {
+                room_name,
+                options,
+                snapshot,
+                ..
+            } => {
+                self.players = snapshot.players;
+                self.state.game.set_board(snapshot.board);
+                self.state.vertical_groups = options.vertical_groups;
+                self.state.hands = vec![Vec::new(); self.players.len()];
+                if let Some(me) = self.me() {
+                    self.state.hands[me] = snapshot.view.into_hand();
+                }
+                self.state.start_turn(snapshot.active_player);
+
+                if let Some(code) = self.code.take() {
+                    let _ = code.send(room_name);
+                }
+            }
+            ServerMessage::PlayerJoined(name) => {
+                self.players.push(name);
+                self.state.hands.push(Vec::new());
+
+                // Whoever drew the highest goes first, once everyone is in:
+                if self.can_start() {
+                    self.plan_turn();
+                }
+            }
+            ServerMessage::StartGame { order, .. } => self.state.start_turn(order[0]),
+            ServerMessage::StartTurn(_) => {
+                if let Some(me) = self.me() {
+                    self.state.start_turn(me);
+                }
+                if self.can_start() {
+                    self.plan_turn();
+                }
+            }
+            ServerMessage::TurnFinished {
+                next_player, board, ..
+            } => {
+                self.state.game.set_board(board);
+                self.state.start_turn(next_player);
+            }
+            ServerMessage::TurnSkipped(idx) if Some(idx) == self.me() => {
+                self.state.rollback_turn();
+            }
+            ServerMessage::Place(id, coord, piece) => self.apply(id, &[Move::Place(coord, piece)]),
+            ServerMessage::Pickup(id, coord, piece) => {
+                self.apply(id, &[Move::Pickup(coord, piece)])
+            }
+            ServerMessage::Moves(id, moves) => self.apply(id, &moves),
+            ServerMessage::DrawPiece(piece) => {
+                if let Some(me) = self.me() {
+                    self.state.hands[me].push(piece);
+                }
+            }
+            ServerMessage::HandSync(view) => {
+                if let Some(me) = self.me() {
+                    self.state.hands[me] = view.into_hand();
+                }
+            }
+            ServerMessage::EndTurnValid => {
+                self.answered();
+                self.report.turns += 1;
+            }
+            ServerMessage::MovesRejected(..)
+            | ServerMessage::InvalidBoardState
+            | ServerMessage::OnlyRearranged
+            | ServerMessage::KeptBoardPiece(_) => {
+                self.answered();
+                self.report.rejected += 1;
+            }
+            ServerMessage::PlayerWon { winner, .. } => {
+                if winner == self.name {
+                    self.answered();
+                    self.report.wins += 1;
+                }
+
+                self.queued.clear();
+                self.queued.push_back(ClientMessage::Rematch);
+            }
+            ServerMessage::Pong => self.answered(),
+            ServerMessage::Error(e) => bail!("{} got an error: {}", self.name, e),
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
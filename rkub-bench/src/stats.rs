@@ -0,0 +1,107 @@
+//! Latency percentiles, and what the server process costs while it's under
+//! load.
+
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+/// Round trip times for one kind of message.
+#[derive(Debug, Default, Clone)]
+pub struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.0.push(latency);
+    }
+
+    pub fn extend(&mut self, other: Latencies) {
+        self.0.extend(other.0);
+    }
+
+    /// The latency `p` percent of messages came back within.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let mut sorted = self.0.clone();
+        sorted.sort();
+
+        match sorted.len() {
+            0 => Duration::default(),
+            n => sorted[((n - 1) as f64 * p / 100.0).round() as usize],
+        }
+    }
+}
+
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |p| self.percentile(p).as_secs_f64() * 1000.0;
+
+        write!(
+            f,
+            "n={:<8} p50={:.2}ms p90={:.2}ms p99={:.2}ms max={:.2}ms",
+            self.0.len(),
+            ms(50.0),
+            ms(90.0),
+            ms(99.0),
+            ms(100.0)
+        )
+    }
+}
+
+/// A process's memory and CPU time at one moment, read from /proc, so only
+/// on Linux.
+#[derive(Debug, Copy, Clone)]
+pub struct Usage {
+    pub rss_kb: u64,
+    pub cpu: Duration,
+}
+
+/// Clock ticks per second in /proc/<pid>/stat. It's 100 on any Linux we'll
+/// run on.
+const TICKS_PER_SEC: u64 = 100;
+
+impl Usage {
+    pub fn sample(pid: u32) -> Option<Usage> {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let rss_kb = status
+            .lines()
+            .find(|line| line.starts_with("VmRSS:"))?
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()?;
+
+        // The name in brackets can hold spaces, so count fields from after
+        // it. utime and stime are the 14th and 15th:
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        let cpu = Duration::from_millis((utime + stime) * 1000 / TICKS_PER_SEC);
+
+        Some(Usage { rss_kb, cpu })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_pick_from_the_sorted_latencies() {
+        let mut latencies = Latencies::default();
+        for ms in (1..=100).rev() {
+            latencies.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(latencies.percentile(50.0), Duration::from_millis(51));
+        assert_eq!(latencies.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(latencies.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(Latencies::default().percentile(50.0), Duration::default());
+    }
+
+    #[test]
+    fn usage_of_this_process() {
+        let usage = Usage::sample(std::process::id()).unwrap();
+
+        assert!(usage.rss_kb > 0);
+    }
+}
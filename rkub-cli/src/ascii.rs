@@ -0,0 +1,107 @@
+//! Pieces and boards as text: `r5` is the red 5, `k13` the black 13 and `j`
+//! a joker.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use rkub_common::{Color, Coord, Piece};
+
+pub fn piece(piece: &Piece) -> String {
+    let color = match piece.color {
+        Color::Red => 'r',
+        Color::Blue => 'b',
+        Color::Yellow => 'y',
+        Color::Black => 'k',
+        Color::Joker => return "j".to_string(),
+    };
+
+    format!("{}{}", color, piece.num)
+}
+
+pub fn parse_piece(s: &str) -> Option<Piece> {
+    let s = s.trim().to_lowercase();
+    if s == "j" {
+        return Some(Piece::joker());
+    }
+
+    let mut chars = s.chars();
+    let color = match chars.next()? {
+        'r' => Color::Red,
+        'b' => Color::Blue,
+        'y' => Color::Yellow,
+        'k' => Color::Black,
+        _ => return None,
+    };
+
+    match chars.as_str().parse() {
+        Ok(num) if (1..=13).contains(&num) => Some(Piece::new(color, num)),
+        _ => None,
+    }
+}
+
+pub fn hand(hand: &[Piece]) -> String {
+    let mut hand = hand.to_vec();
+    hand.sort();
+
+    hand.iter().map(piece).collect::<Vec<_>>().join(" ")
+}
+
+/// The part of `board` with pieces on it, one cell per coord, with the
+/// coords along the top and left.
+pub fn board(board: &BTreeMap<Coord, Piece>) -> String {
+    if board.is_empty() {
+        return "(empty board)\n".to_string();
+    }
+
+    let min_x = board.keys().map(|c| c.0).min().unwrap();
+    let max_x = board.keys().map(|c| c.0).max().unwrap();
+    let min_y = board.keys().map(|c| c.1).min().unwrap();
+    let max_y = board.keys().map(|c| c.1).max().unwrap();
+
+    let mut out = String::from("    ");
+    for x in min_x..=max_x {
+        let _ = write!(out, "{:>4}", x);
+    }
+    out.push('\n');
+
+    for y in min_y..=max_y {
+        let _ = write!(out, "{:>4}", y);
+        for x in min_x..=max_x {
+            let cell = board.get(&Coord(x, y)).map(piece);
+            let _ = write!(out, "{:>4}", cell.as_deref().unwrap_or("."));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pieces_round_trip() {
+        for p in rkub_common::Game::create_pieces()
+            .into_iter()
+            .chain(Some(Piece::joker()))
+        {
+            assert_eq!(parse_piece(&piece(&p)), Some(p));
+        }
+
+        assert_eq!(parse_piece("K13"), Some(Piece::new(Color::Black, 13)));
+        for bad in ["", "r", "r0", "r14", "g5", "jj"].iter() {
+            assert_eq!(parse_piece(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn boards_are_drawn_in_rows() {
+        let mut grid = BTreeMap::new();
+        grid.insert(Coord(1, 0), Piece::new(Color::Red, 5));
+        grid.insert(Coord(2, 0), Piece::joker());
+        grid.insert(Coord(1, 1), Piece::new(Color::Black, 13));
+
+        assert_eq!(board(&grid), "       1   2\n   0  r5   j\n   1 k13   .\n");
+    }
+}
@@ -0,0 +1,680 @@
+//! A terminal client for rkub, to test and script the server without a
+//! browser. Commands are read a line at a time from stdin, so a whole game
+//! can be piped in.
+
+mod ascii;
+
+use anyhow::{anyhow, bail};
+
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use rkub_common::{
+    bot, AdminCommand, ClientMessage, Coord, GameEvent, GameRecord, GameState, Move, MoveId,
+    PastMessage, Phase, RoomOptions, RoomSnapshot, RoomSummary, Sequenced, ServerError,
+    ServerMessage, Severity, WS_PATH,
+};
+
+const USAGE: &str = "usage: rkub-cli [--server URL] [--password PASSWORD] [--bot]
+                [--passphrase PASSPHRASE [--register] [--ranked]] NAME [ROOM]
+
+Joins ROOM as NAME, or creates a new room without one. With --bot, the client
+plays its own turns and keeps going after stdin closes. With a passphrase, it
+logs in to the account NAME first, making it with --register. With --ranked,
+it waits to be matched into a ranked room instead.
+
+       rkub-cli [--server URL] --replay GAME
+
+Prints the saved game GAME turn by turn. GAME is either the id the server kept
+it under, or a file it was exported to.
+
+       rkub-cli [--server URL] --admin SECRET COMMAND
+
+Runs an operator's COMMAND on the server, which needs its admin secret:
+  rooms              list every room
+  inspect ROOM       show everything about ROOM, hands and all
+  end-turn ROOM      end the active player's turn in ROOM
+  close ROOM         stop ROOM, whoever's in it
+  open               open an empty room for players to join
+  announce LEVEL TEXT
+                     tell everyone in every room TEXT, where LEVEL is info,
+                     warning or critical";
+
+const HELP: &str = "commands:
+  board              show the board
+  hand               show your hand
+  place X,Y PIECE    put PIECE from your hand at X,Y, e.g. `place 0,0 r5`
+  pickup X,Y         take the piece at X,Y back into your hand
+  end                end your turn, drawing if you played nothing
+  skip               vote to skip the active player
+  nudge              hurry the active player along
+  away               have your turns skipped, drawing for you, until you're back
+  back               take your own turns again
+  rematch            ask to play again once the game is over
+  stats [NAME]       show a player's record on this server, yours by default
+  replay GAME        print a saved game turn by turn
+  sync               get the whole game from the server again
+  quit               leave
+
+pieces are a color and a number: r5, b13, y1, k7 (black), or j for a joker";
+
+struct Args {
+    server: String,
+    name: String,
+    room: Option<String>,
+    password: Option<String>,
+    bot: bool,
+    passphrase: Option<String>,
+    register: bool,
+    ranked: bool,
+    replay: Option<String>,
+    /// The admin secret, and the command to run with it.
+    admin: Option<(String, AdminCommand)>,
+}
+
+impl Args {
+    fn parse() -> anyhow::Result<Self> {
+        let mut server = format!("ws://127.0.0.1:5555{}", WS_PATH);
+        let mut password = None;
+        let mut bot = false;
+        let mut passphrase = None;
+        let mut register = false;
+        let mut ranked = false;
+        let mut replay = None;
+        let mut secret = None;
+        let mut positional = Vec::new();
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server" => server = args.next().ok_or_else(|| anyhow!(USAGE))?,
+                "--password" => password = Some(args.next().ok_or_else(|| anyhow!(USAGE))?),
+                "--bot" => bot = true,
+                "--passphrase" => passphrase = Some(args.next().ok_or_else(|| anyhow!(USAGE))?),
+                "--register" => register = true,
+                "--ranked" => ranked = true,
+                "--replay" => replay = Some(args.next().ok_or_else(|| anyhow!(USAGE))?),
+                "--admin" => secret = Some(args.next().ok_or_else(|| anyhow!(USAGE))?),
+                "-h" | "--help" => bail!(USAGE),
+                _ => positional.push(arg),
+            }
+        }
+
+        // Admin commands take the rest of the line instead of a name and room:
+        let admin = match secret {
+            Some(secret) => Some((
+                secret,
+                parse_admin(&positional).ok_or_else(|| anyhow!(USAGE))?,
+            )),
+            None => None,
+        };
+        if admin.is_some() {
+            positional.clear();
+        }
+
+        let mut positional = positional.into_iter();
+        // Replays are watched, and admin commands run, without playing as
+        // anyone:
+        let name = match positional.next() {
+            Some(name) => name,
+            None if replay.is_some() || admin.is_some() => String::new(),
+            None => bail!(USAGE),
+        };
+        let room = positional.next();
+        let needs_account = register || ranked;
+        if positional.next().is_some()
+            || (needs_account && passphrase.is_none())
+            || (ranked && room.is_some())
+        {
+            bail!(USAGE);
+        }
+
+        Ok(Args {
+            server,
+            name,
+            room,
+            password,
+            bot,
+            passphrase,
+            register,
+            ranked,
+            replay,
+            admin,
+        })
+    }
+}
+
+/// The admin command `words` ask for, as typed after `--admin SECRET`.
+fn parse_admin(words: &[String]) -> Option<AdminCommand> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let command = match words.as_slice() {
+        ["rooms"] => AdminCommand::ListRooms,
+        ["inspect", room] => AdminCommand::InspectRoom(room.to_string()),
+        ["end-turn", room] => AdminCommand::ForceEndTurn(room.to_string()),
+        ["close", room] => AdminCommand::CloseRoom(room.to_string()),
+        ["open"] => AdminCommand::CreateRoom(RoomOptions::default()),
+        ["announce", level, text @ ..] if !text.is_empty() => {
+            let severity = match *level {
+                "info" => Severity::Info,
+                "warning" => Severity::Warning,
+                "critical" => Severity::Critical,
+                _ => return None,
+            };
+            AdminCommand::Broadcast(text.join(" "), severity)
+        }
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+/// The game as far as this player can see it, kept up to date from what the
+/// server sends. Only our own hand is known.
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The `seq` of the last message we sent.
+    sent: u64,
+    name: String,
+    players: Vec<String>,
+    state: GameState,
+    bot: bool,
+    /// What we sent to get into a room, to send again wherever we're
+    /// redirected.
+    joining: Vec<ClientMessage>,
+}
+
+impl Client {
+    /// Our index, once we've joined.
+    fn me(&self) -> Option<usize> {
+        self.players.iter().position(|p| *p == self.name)
+    }
+
+    async fn send(&mut self, msg: ClientMessage) -> anyhow::Result<()> {
+        self.sent += 1;
+        let json = serde_json::to_string(&Sequenced {
+            seq: self.sent,
+            msg,
+        })?;
+        self.ws.send(Message::text(json)).await?;
+
+        Ok(())
+    }
+
+    /// Sends what gets us into the room we're after.
+    async fn join(&mut self) -> anyhow::Result<()> {
+        for msg in self.joining.clone() {
+            self.send(msg).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies board edits the server accepted. Ours move pieces to and
+    /// from our hand too.
+    fn apply(&mut self, id: MoveId, moves: &[Move]) {
+        if let Some(me) = self.me().filter(|&me| me == id.seat) {
+            if let Err(bad) = self
+                .state
+                .game
+                .apply_moves(&mut self.state.hands[me], moves)
+            {
+                println!("out of sync with the server at {:?}", bad);
+            }
+            return;
+        }
+
+        let board = self.state.game.board_mut();
+        for m in moves {
+            match *m {
+                Move::Place(coord, piece) => {
+                    board.insert(coord, piece);
+                }
+                Move::Pickup(coord, _) => {
+                    board.remove(&coord);
+                }
+            }
+        }
+    }
+
+    /// Takes the server's word for the whole game, and shows it.
+    fn catch_up(&mut self, snapshot: RoomSnapshot) {
+        self.players = snapshot.players;
+        self.state.game.set_board(snapshot.board);
+        self.state.hands = vec![Vec::new(); self.players.len()];
+        if let Some(me) = self.me() {
+            self.state.hands[me] = snapshot.view.into_hand();
+        }
+        if snapshot.dealt {
+            self.state.start_turn(snapshot.active_player);
+        }
+
+        print!("{}", ascii::board(self.state.game.board()));
+        println!(
+            "hand: {}",
+            ascii::hand(&self.state.hands[self.me().unwrap_or(0)])
+        );
+    }
+
+    /// Handles one message from the server, returning whether to keep going.
+    async fn on_message(&mut self, msg: ServerMessage) -> anyhow::Result<bool> {
+        match msg {
+            ServerMessage::JoinedRoom {
+                room_name,
+                options,
+                snapshot,
+                ..
+            } => {
+                println!(
+                    "joined room {} with {}",
+                    room_name,
+                    snapshot.players.join(", ")
+                );
+                self.state.vertical_groups = options.vertical_groups;
+                self.catch_up(*snapshot);
+            }
+            ServerMessage::FullSync(snapshot) => {
+                println!("caught up with the server");
+                self.catch_up(*snapshot);
+            }
+            ServerMessage::PlayerJoined(name) => {
+                println!("{} joined", name);
+                self.players.push(name);
+                self.state.hands.push(Vec::new());
+            }
+            ServerMessage::StartGame { order, .. } => {
+                let first = order[0];
+                self.state.start_turn(first);
+                println!(
+                    "{} drew the highest piece and goes first",
+                    self.players[first]
+                );
+            }
+            ServerMessage::StartTurn(turn) => {
+                if let Some(me) = self.me() {
+                    self.state.start_turn(me);
+                }
+                println!("turn {}, your turn", turn);
+
+                if self.bot {
+                    self.play_bot_turn().await?;
+                }
+            }
+            ServerMessage::TurnFinished {
+                ending_player,
+                ending_drew,
+                next_player,
+                board,
+                turn,
+                ..
+            } => {
+                self.state.game.set_board(board);
+                self.state.start_turn(next_player);
+
+                let drew = if ending_drew { ", and drew" } else { "" };
+                println!("{} ended their turn{}", ending_player, drew);
+                print!("{}", ascii::board(self.state.game.board()));
+                println!("turn {}, it's {}'s turn", turn, self.players[next_player]);
+            }
+            ServerMessage::TurnSkipped(idx) => {
+                if Some(idx) == self.me() {
+                    self.state.rollback_turn();
+                }
+                println!("{} was skipped", self.players[idx]);
+            }
+            ServerMessage::PlayerAway(idx) => {
+                println!("{} is away, their turns are skipped", self.players[idx])
+            }
+            ServerMessage::PlayerBack(idx) => println!("{} is back", self.players[idx]),
+            ServerMessage::Nudged(idx) => println!("{} says hurry up", self.players[idx]),
+            ServerMessage::WaitingForReconnect { player, wait_secs } => println!(
+                "waiting {}s for {} to reconnect",
+                wait_secs, self.players[player]
+            ),
+            ServerMessage::Place(id, coord, piece) => self.apply(id, &[Move::Place(coord, piece)]),
+            ServerMessage::Pickup(id, coord, piece) => {
+                self.apply(id, &[Move::Pickup(coord, piece)])
+            }
+            ServerMessage::Moves(id, moves) => self.apply(id, &moves),
+            ServerMessage::DrawPiece(piece) => {
+                if let Some(me) = self.me() {
+                    self.state.hands[me].push(piece);
+                }
+                println!("drew {}", ascii::piece(&piece));
+            }
+            ServerMessage::HandSync(view) => {
+                if let Some(me) = self.me() {
+                    self.state.hands[me] = view.into_hand();
+                }
+            }
+            ServerMessage::EndTurnValid => println!("turn over"),
+            ServerMessage::MovesRejected(_, moves) => println!("the server refused {:?}", moves),
+            ServerMessage::InvalidBoardState => println!("the board isn't valid yet"),
+            ServerMessage::OnlyRearranged => {
+                println!("play something from your hand, or put the board back to draw")
+            }
+            ServerMessage::KeptBoardPiece(piece) => {
+                println!("{} was on the board, put it back", ascii::piece(&piece))
+            }
+            ServerMessage::PlayerWon { winner, hands, .. } => {
+                println!("{} won!", winner);
+                for (name, hand) in self.players.iter().zip(&hands) {
+                    println!("  {}: {}", name, ascii::hand(hand));
+                }
+
+                if self.bot {
+                    self.send(ClientMessage::Rematch).await?;
+                }
+            }
+            ServerMessage::Redirect { ws_url } => {
+                println!("the room is on {}, connecting there", ws_url);
+                let _ = self.ws.close(None).await;
+                self.ws = connect_async(ws_url.as_str()).await?.0;
+                self.sent = 0;
+                self.join().await?;
+            }
+            ServerMessage::LoggedIn { name, .. } => {
+                println!("logged in as {}", name);
+                self.name = name;
+            }
+            ServerMessage::GameSaved(id) => println!("saved the game, `replay {}` to watch it", id),
+            ServerMessage::Announcement { text, severity } => {
+                println!("from the server ({:?}): {}", severity, text)
+            }
+            ServerMessage::History(past) => {
+                for PastMessage { msg, .. } in past {
+                    println!("earlier: {:?}", msg);
+                }
+            }
+            ServerMessage::Replay { id, record } => match record {
+                Some(record) => print_replay(&record),
+                None => println!("there's no game {}", id),
+            },
+            ServerMessage::InRankedQueue { rating } => {
+                println!("waiting for players rated near {}", rating)
+            }
+            ServerMessage::PlayerStats { name, stats } => println!(
+                "{}: rated {}, {} games, {} wins, {:.1}s and {:.1} tiles a turn",
+                name,
+                stats.rating,
+                stats.games_played,
+                stats.wins,
+                stats.avg_turn_secs(),
+                stats.avg_tiles_per_turn()
+            ),
+            // Not the end of the connection, unlike the others:
+            ServerMessage::Error(e @ ServerError::OffBoard(_)) => println!("{}", e),
+            ServerMessage::Error(e) => {
+                println!("error: {}", e);
+                return Ok(false);
+            }
+            msg => println!("{:?}", msg),
+        }
+
+        Ok(true)
+    }
+
+    async fn play_bot_turn(&mut self) -> anyhow::Result<()> {
+        let me = match self.me() {
+            Some(me) if self.players.len() > 1 => me,
+            _ => return Ok(()),
+        };
+
+        let moves = bot::plan_turn(&self.state, me);
+        if !moves.is_empty() {
+            self.send(ClientMessage::Moves(moves)).await?;
+        }
+
+        self.send(ClientMessage::EndTurn).await
+    }
+
+    /// Handles one line typed at the terminal, returning whether to keep
+    /// going.
+    async fn on_command(&mut self, line: &str) -> anyhow::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            [] => {}
+            ["board"] => print!("{}", ascii::board(self.state.game.board())),
+            ["hand"] => {
+                let hand = self.me().map(|me| self.state.hands[me].as_slice());
+                println!("{}", ascii::hand(hand.unwrap_or_default()));
+            }
+            ["place", coord, piece] => match (parse_coord(coord), ascii::parse_piece(piece)) {
+                (Some(coord), Some(piece)) => self.send(ClientMessage::Place(coord, piece)).await?,
+                _ => println!("usage: place X,Y PIECE"),
+            },
+            ["pickup", coord] => {
+                let piece = parse_coord(coord)
+                    .and_then(|coord| Some((coord, *self.state.game.board().get(&coord)?)));
+                match piece {
+                    Some((coord, piece)) => self.send(ClientMessage::Pickup(coord, piece)).await?,
+                    None => println!("there's nothing at {}", coord),
+                }
+            }
+            ["end"] => self.send(ClientMessage::EndTurn).await?,
+            ["skip"] => self.send(ClientMessage::VoteSkip).await?,
+            ["nudge"] => self.send(ClientMessage::Nudge).await?,
+            ["away"] => self.send(ClientMessage::SetAway(true)).await?,
+            ["back"] => self.send(ClientMessage::SetAway(false)).await?,
+            ["rematch"] => self.send(ClientMessage::Rematch).await?,
+            ["stats"] => self.send(ClientMessage::Stats(self.name.clone())).await?,
+            ["stats", name] => self.send(ClientMessage::Stats(name.to_string())).await?,
+            ["sync"] => self.send(ClientMessage::RequestSync).await?,
+            ["replay", id] => {
+                self.send(ClientMessage::FetchReplay(id.to_string()))
+                    .await?
+            }
+            ["quit"] => return Ok(false),
+            _ => println!("{}", HELP),
+        }
+
+        Ok(true)
+    }
+}
+
+/// Prints the server's answer to an admin command.
+fn print_admin(answer: ServerMessage) -> anyhow::Result<()> {
+    match answer {
+        ServerMessage::AdminRooms(rooms) if rooms.is_empty() => println!("no rooms"),
+        ServerMessage::AdminRooms(rooms) => {
+            for room in &rooms {
+                println!("{}", summary(room));
+            }
+        }
+        ServerMessage::AdminRoom {
+            summary: room,
+            snapshot,
+            hands,
+        } => {
+            println!("{}", summary(&room));
+            if let Some(snapshot) = snapshot {
+                print!("{}", ascii::board(&snapshot.board));
+                println!("{} pieces left to draw", snapshot.pieces_remaining);
+            }
+            for (name, hand) in room.players.iter().zip(&hands) {
+                println!("  {}: {}", name, ascii::hand(hand));
+            }
+        }
+        ServerMessage::AdminRoomCreated(id) => println!("opened {}", id),
+        ServerMessage::AdminDone => println!("done"),
+        ServerMessage::Error(e) => bail!("{}", e),
+        msg => println!("{:?}", msg),
+    }
+
+    Ok(())
+}
+
+/// One line about `room`, for operators.
+fn summary(room: &RoomSummary) -> String {
+    let playing = match room.phase {
+        Phase::Over => "game over".to_string(),
+        Phase::Waiting => "waiting for players".to_string(),
+        Phase::Playing => format!(
+            "turn {}, {} to play, idle {}s",
+            room.turn,
+            room.players
+                .get(room.active_player)
+                .map_or("nobody", String::as_str),
+            room.idle_secs
+        ),
+    };
+
+    format!("{}: {} ({})", room.id, room.players.join(", "), playing)
+}
+
+/// Prints `record`'s board as each turn starts, and how the game ended.
+fn print_replay(record: &GameRecord) {
+    let players = &record.players;
+    let mut turns = 0;
+    let mut last = GameState::default();
+
+    for (event, state) in record.replay() {
+        match event {
+            GameEvent::TurnStarted(player) => {
+                turns += 1;
+                println!("turn {}, {} to play:", turns, players[*player]);
+                print!("{}", ascii::board(state.game.board()));
+            }
+            GameEvent::Kicked(player) => println!("{} was kicked", players[*player]),
+            _ => {}
+        }
+        last = state;
+    }
+
+    match last.winner {
+        Some(winner) => println!("{} won after {} turns:", players[winner], turns),
+        None => println!("unfinished after {} turns:", turns),
+    }
+    print!("{}", ascii::board(last.game.board()));
+
+    if !record.scores.is_empty() {
+        let scores: Vec<String> = players
+            .iter()
+            .zip(&record.scores)
+            .map(|(name, score)| format!("{} {}", name, score))
+            .collect();
+        println!("scores: {}", scores.join(", "));
+    }
+}
+
+fn parse_coord(s: &str) -> Option<Coord> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).ok()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse()?;
+
+    // Exported games don't need a server to watch:
+    if let Some(path) = args.replay.as_deref() {
+        if std::path::Path::new(path).is_file() {
+            let json = std::fs::read_to_string(path)?;
+            let record = GameRecord::import(&json).map_err(|e| anyhow!("{}", e))?;
+            print_replay(&record);
+
+            return Ok(());
+        }
+    }
+
+    let (ws, _) = connect_async(args.server.as_str()).await?;
+    let mut client = Client {
+        ws,
+        sent: 0,
+        name: args.name.clone(),
+        players: Vec::new(),
+        state: GameState::default(),
+        bot: args.bot,
+        joining: Vec::new(),
+    };
+
+    if let Some((secret, command)) = args.admin {
+        client.send(ClientMessage::Admin(secret, command)).await?;
+        while let Some(msg) = client.ws.next().await {
+            if let Message::Text(json) = msg? {
+                let msg = serde_json::from_str::<Sequenced<ServerMessage>>(&json)?.msg;
+                if msg != ServerMessage::Pong {
+                    print_admin(msg)?;
+                    break;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    if let Some(id) = args.replay {
+        client.send(ClientMessage::FetchReplay(id)).await?;
+        while let Some(msg) = client.ws.next().await {
+            if let Message::Text(json) = msg? {
+                let msg = serde_json::from_str::<Sequenced<ServerMessage>>(&json)?.msg;
+                if let ServerMessage::Replay { .. } = msg {
+                    client.on_message(msg).await?;
+                    break;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    if let Some(passphrase) = args.passphrase {
+        let log_in = if args.register {
+            ClientMessage::Register(args.name.clone(), passphrase)
+        } else {
+            ClientMessage::LogIn(args.name.clone(), passphrase)
+        };
+        client.joining.push(log_in);
+    }
+
+    let join = match args.room {
+        _ if args.ranked => ClientMessage::QueueRanked,
+        Some(room) => ClientMessage::JoinRoom(args.name, room, args.password),
+        None => ClientMessage::CreateRoom(args.name, args.password, RoomOptions::default()),
+    };
+    client.joining.push(join);
+    client.join().await?;
+
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdin_open = true;
+
+    loop {
+        // Commands wait until we're in the room, so piped ones aren't lost:
+        let joined = client.me().is_some();
+
+        tokio::select! {
+            msg = client.ws.next() => match msg {
+                Some(Ok(Message::Text(json))) => {
+                    let msg: Sequenced<ServerMessage> = serde_json::from_str(&json)?;
+                    if !client.on_message(msg.msg).await? {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            line = stdin.next_line(), if stdin_open && joined => match line? {
+                Some(line) => {
+                    if !client.on_command(&line).await? {
+                        break;
+                    }
+                }
+                None => {
+                    stdin_open = false;
+                    if !client.bot {
+                        break;
+                    }
+                }
+            },
+        }
+    }
+
+    let _ = client.send(ClientMessage::Close).await;
+    let _ = client.ws.close(None).await;
+
+    Ok(())
+}
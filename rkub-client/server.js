@@ -1,7 +1,28 @@
 var express = require('express');
+var net = require('net');
 var app = express();
 
 app.use(express.static('deploy')); 
 app.use(express.static('pkg')); 
 
-var server = app.listen(8080);
\ No newline at end of file
+var server = app.listen(8080);
+
+// The client connects back to wherever it was loaded from, so pass its
+// websocket through to the game server:
+server.on('upgrade', function (req, socket, head) {
+    if (req.url !== '/ws') {
+        return socket.destroy();
+    }
+
+    var game = net.connect(5555, '127.0.0.1', function () {
+        var lines = [req.method + ' ' + req.url + ' HTTP/' + req.httpVersion];
+        for (var i = 0; i < req.rawHeaders.length; i += 2) {
+            lines.push(req.rawHeaders[i] + ': ' + req.rawHeaders[i + 1]);
+        }
+        game.write(lines.join('\r\n') + '\r\n\r\n');
+        game.write(head);
+        socket.pipe(game).pipe(socket);
+    });
+    game.on('error', function () { socket.destroy(); });
+    socket.on('error', function () { game.destroy(); });
+});
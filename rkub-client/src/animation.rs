@@ -0,0 +1,47 @@
+/// Slides a rendered element from one spot to another over time. Driven by
+/// `requestAnimationFrame` timestamps, in milliseconds.
+#[derive(Debug)]
+pub struct Slide {
+    pub name: String,
+    from: (f32, f32),
+    to: (f32, f32),
+    duration: f64,
+    /// Set on the first frame so a slide doesn't skip ahead while waiting
+    /// for it.
+    start: Option<f64>,
+}
+
+impl Slide {
+    pub fn new(name: String, from: (f32, f32), to: (f32, f32), duration: f64) -> Self {
+        Self {
+            name,
+            from,
+            to,
+            duration,
+            start: None,
+        }
+    }
+
+    /// Where the element should be drawn at `now`.
+    pub fn position(&mut self, now: f64) -> (f32, f32) {
+        let start = *self.start.get_or_insert(now);
+        let t = ease_out(((now - start) / self.duration).min(1.0) as f32);
+
+        (
+            self.from.0 + (self.to.0 - self.from.0) * t,
+            self.from.1 + (self.to.1 - self.from.1) * t,
+        )
+    }
+
+    pub fn finished(&self, now: f64) -> bool {
+        match self.start {
+            Some(start) => now - start >= self.duration,
+            None => false,
+        }
+    }
+}
+
+/// Starts fast and settles into place.
+fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t) * (1.0 - t)
+}
@@ -0,0 +1,34 @@
+//! The banner across the top of the page for what the server's operators
+//! announce, like a restart coming up. Unlike a toast it stays up until it's
+//! dismissed, or the next announcement takes its place.
+
+use rkub_common::Severity;
+use web_sys::{Element, MouseEvent};
+
+use crate::ui;
+use crate::{set_event_cb, JsResult};
+
+/// Wires up the banner's dismiss button.
+pub fn init(root: &Element) -> JsResult<()> {
+    let dismiss = ui::find(root, "announcement_dismiss")?;
+    set_event_cb(&dismiss, "click", move |_e: MouseEvent| {
+        ui::element("announcement")?.set_attribute("hidden", "")
+    })
+    .forget();
+
+    Ok(())
+}
+
+/// Puts `text` up on the banner, in place of whatever was there.
+pub fn show(text: &str, severity: Severity) -> JsResult<()> {
+    let banner = ui::element("announcement")?;
+
+    let class = match severity {
+        Severity::Info => "",
+        Severity::Warning => "announcement_warning",
+        Severity::Critical => "announcement_critical",
+    };
+    banner.set_class_name(class);
+    ui::element("announcement_text")?.set_text_content(Some(text));
+    banner.remove_attribute("hidden")
+}
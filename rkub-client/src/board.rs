@@ -1,10 +1,15 @@
 use std::collections::BTreeMap;
-use wasm_bindgen::JsCast;
 use wasm_svg_graphics::prelude::*;
 
+use crate::animation::Slide;
+use crate::settings;
 use crate::svg::AsSVG;
 use rkub_common::{Color, Coord, Piece};
 
+/// How many colors players are told apart by, see `.player_0` and on in the
+/// stylesheet. Any more players share them.
+pub const PLAYER_COLORS: usize = 6;
+
 // const CELL_WIDTH: usize = 40;
 // const CELL_HEIGHT: usize = 50;
 
@@ -15,13 +20,49 @@ pub struct Board {
     grid: BTreeMap<Coord, Piece>,
     // played_pieces: Vec<LocatedPiece>,
     // hand_pieces: Vec<LocatedPiece>,
+    /// The id of the rendered element for every piece in `grid`. Pieces are
+    /// drawn once and then moved or deleted individually by name.
+    tiles: BTreeMap<Coord, u32>,
+    next_tile: u32,
     renderer: SVGRenderer,
+    /// The element it's drawn in, and its id.
+    root: web_sys::Element,
     root_name: &'static str,
     rows: i32,
     cols: i32,
     cell_width: i32,
     cell_height: i32,
+    /// When set, resizing keeps cells this wide and changes `cols` instead.
+    fit_cell_width: Option<i32>,
+    width: i32,
+    height: i32,
+    /// The camera: the board position shown at the top left of the svg and
+    /// how far it's zoomed in.
+    view_x: f32,
+    view_y: f32,
+    zoom: f32,
     last_highlight: Option<Coord>,
+    /// The pieces drawn in the highlight layer, which is kept around and
+    /// moved with the cursor rather than redrawn.
+    highlight: Option<Vec<(i32, Piece)>>,
+    highlight_visible: bool,
+    /// The cell selected with the keyboard, drawn as an outline.
+    cursor: Option<Coord>,
+    /// Tiles picked up by another player, hidden but kept around so they
+    /// can slide from where they were if they're placed again.
+    lifted: Vec<(Piece, u32, Coord)>,
+    slides: Vec<Slide>,
+    /// How many last move outlines are drawn.
+    marks: usize,
+    /// Tiles outlined in the color of whoever put them down, and by whom.
+    credits: Vec<(Coord, usize)>,
+    credits_drawn: usize,
+    /// Whether to draw grid lines, and guides to where held pieces can go.
+    guides: bool,
+    guides_drawn: bool,
+    /// Whether groups can go down a column too, so the guides show that.
+    vertical_groups: bool,
+    column_drawn: bool,
 }
 
 impl Board {
@@ -38,72 +79,613 @@ impl Board {
 
         Self {
             grid: BTreeMap::new(),
+            tiles: BTreeMap::new(),
+            next_tile: 0,
             renderer,
+            root: root_element.clone(),
             root_name,
             rows,
             cols,
             cell_width: width / cols,
             cell_height: height / rows,
+            fit_cell_width: None,
+            width,
+            height,
+            view_x: 0.0,
+            view_y: 0.0,
+            zoom: 1.0,
             last_highlight: None,
+            highlight: None,
+            highlight_visible: false,
+            cursor: None,
+            lifted: Vec::new(),
+            slides: Vec::new(),
+            marks: 0,
+            credits: Vec::new(),
+            credits_drawn: 0,
+            guides: false,
+            guides_drawn: false,
+            vertical_groups: false,
+            column_drawn: false,
+        }
+    }
+
+    /// Draw faint grid lines under the pieces, and while something's held,
+    /// the row it would go in and the spots next to what's already down.
+    pub fn with_guides(mut self) -> Self {
+        self.guides = true;
+        self
+    }
+
+    /// Lays the board out `rows` by `cols` cells, for rooms whose board isn't
+    /// the size it was made with.
+    pub fn set_size(&mut self, rows: i32, cols: i32) {
+        if (rows, cols) == (self.rows, self.cols) || rows < 1 || cols < 1 {
+            return;
         }
+
+        self.rows = rows;
+        self.cols = cols;
+        self.cell_width = self.width / cols;
+        self.cell_height = self.height / rows;
+        self.rerender();
+    }
+
+    /// Show where pieces can go down a column as well as across a row, for
+    /// rooms that allow groups that way.
+    pub fn set_vertical_groups(&mut self, vertical_groups: bool) {
+        self.remove_guides();
+        self.vertical_groups = vertical_groups;
+    }
+
+    /// Keep cells at their current width when resized, changing the number of
+    /// columns instead. Used for the hand so pieces stay a readable size.
+    pub fn with_fit_cols(mut self) -> Self {
+        self.fit_cell_width = Some(self.cell_width.max(1));
+        self
     }
 
     pub fn resize(&mut self) {
-        let document = web_sys::window().unwrap().document().unwrap();
-        let root: web_sys::HtmlElement = document
-            .get_element_by_id(self.root_name)
-            .unwrap()
-            .dyn_into()
-            .unwrap();
+        let width = self.root.client_width();
+        let height = self.root.client_height();
 
-        let width = root.client_width() as i32;
-        let height = root.client_height() as i32;
+        // Hidden or not laid out yet, keep the old sizes:
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        if let Some(cell_width) = self.fit_cell_width {
+            self.cols = (width / cell_width).max(2);
+        }
 
         self.cell_width = width / self.cols;
         self.cell_height = height / self.rows;
+        self.width = width;
+        self.height = height;
 
         crate::console_log!("new viewbox: ({}, {})", width, height);
 
-        self.renderer.adjust_viewbox(0, 0, width, height);
+        self.update_viewbox();
+
+        if self.fit_cell_width.is_some() {
+            self.relayout_overflow();
+        }
+
         self.rerender();
     }
 
+    fn update_viewbox(&self) {
+        self.renderer.adjust_viewbox(
+            self.view_x.round() as i32,
+            self.view_y.round() as i32,
+            (self.width as f32 / self.zoom).round() as i32,
+            (self.height as f32 / self.zoom).round() as i32,
+        );
+    }
+
+    /// Moves the camera so the board follows a drag of `(dx, dy)` pixels.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.view_x -= dx as f32 / self.zoom;
+        self.view_y -= dy as f32 / self.zoom;
+        self.update_viewbox();
+    }
+
+    /// Zooms by `factor`, keeping the board point under `(x, y)` in place.
+    pub fn zoom_at(&mut self, x: i32, y: i32, factor: f32) {
+        let zoom = (self.zoom * factor).clamp(0.25, 4.0);
+
+        let (world_x, world_y) = self.screen_to_world(x, y);
+        self.view_x = world_x - x as f32 / zoom;
+        self.view_y = world_y - y as f32 / zoom;
+        self.zoom = zoom;
+
+        self.update_viewbox();
+    }
+
+    /// Maps a position relative to the svg element onto the board through
+    /// the camera.
+    fn screen_to_world(&self, x: i32, y: i32) -> (f32, f32) {
+        (
+            self.view_x + x as f32 / self.zoom,
+            self.view_y + y as f32 / self.zoom,
+        )
+    }
+
+    /// Moves pieces that no longer fit in the columns back into their rows.
+    fn relayout_overflow(&mut self) {
+        let overflow: Vec<Coord> = self
+            .grid
+            .keys()
+            .filter(|Coord(x, _)| *x >= self.cols - 1)
+            .copied()
+            .collect();
+
+        for coord in overflow {
+            if let Some(piece) = self.grid.remove(&coord) {
+                self.insert_into_hand(piece);
+            }
+        }
+    }
+
     pub fn grid(&self) -> &BTreeMap<Coord, Piece> {
         &self.grid
     }
 
-    pub fn grid_mut(&mut self) -> &mut BTreeMap<Coord, Piece> {
-        &mut self.grid
+    pub fn cols(&self) -> i32 {
+        self.cols
+    }
+
+    pub fn rows(&self) -> i32 {
+        self.rows
+    }
+
+    pub fn cursor(&self) -> Option<Coord> {
+        self.cursor
+    }
+
+    /// Moves the keyboard cursor to `coord`, clamped to the grid, and returns
+    /// where it ended up.
+    pub fn set_cursor(&mut self, coord: Coord) -> Coord {
+        let coord = Coord(
+            coord.0.max(0).min(self.cols - 1),
+            coord.1.max(0).min(self.rows - 1),
+        );
+        let name = self.cursor_name();
+
+        // Redraw rather than move so the outline stays above newer tiles:
+        if self.cursor.is_some() {
+            self.renderer.delete_named(&name);
+        }
+
+        let outline = SVGElem::new(Tag::Rect)
+            .set(Attr::Class, "cursor")
+            .set(Attr::Width, self.cell_width)
+            .set(Attr::Height, self.cell_height)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0);
+
+        self.renderer
+            .render_named(&name, outline, self.grid_to_world(coord));
+        self.cursor = Some(coord);
+
+        coord
+    }
+
+    pub fn hide_cursor(&mut self) {
+        if self.cursor.take().is_some() {
+            self.renderer.delete_named(&self.cursor_name());
+        }
+    }
+
+    /// Outlines `coords` to show what just changed. The outlines fade out
+    /// on their own and are replaced by the next call.
+    pub fn mark_cells(&mut self, coords: &[Coord]) {
+        for i in 0..self.marks {
+            self.renderer.delete_named(&self.mark_name(i));
+        }
+
+        for (i, coord) in coords.iter().enumerate() {
+            let outline = SVGElem::new(Tag::Rect)
+                .set(Attr::Class, "last_move")
+                .set(Attr::Width, self.cell_width)
+                .set(Attr::Height, self.cell_height)
+                .set(Attr::X, 0)
+                .set(Attr::Y, 0);
+
+            self.renderer
+                .render_named(&self.mark_name(i), outline, self.grid_to_world(*coord));
+        }
+
+        self.marks = coords.len();
+    }
+
+    fn mark_name(&self, i: usize) -> String {
+        format!("{}_mark_{}", self.root_name, i)
+    }
+
+    /// Outlines each of `cells` in its player's color, replacing the last
+    /// ones. They stay until the next call, redrawn with the board.
+    pub fn outline_credits(&mut self, cells: Vec<(Coord, usize)>) {
+        for i in 0..self.credits_drawn {
+            self.renderer.delete_named(&self.credit_name(i));
+        }
+
+        for (i, (coord, player)) in cells.iter().enumerate() {
+            let outline = SVGElem::new(Tag::Rect)
+                .set(
+                    Attr::Class,
+                    format!("credit player_{}", player % PLAYER_COLORS),
+                )
+                .set(Attr::Width, self.cell_width)
+                .set(Attr::Height, self.cell_height)
+                .set(Attr::X, 0)
+                .set(Attr::Y, 0);
+
+            self.renderer
+                .render_named(&self.credit_name(i), outline, self.grid_to_world(*coord));
+        }
+
+        self.credits_drawn = cells.len();
+        self.credits = cells;
+    }
+
+    fn credit_name(&self, i: usize) -> String {
+        format!("{}_credit_{}", self.root_name, i)
+    }
+
+    fn cursor_name(&self) -> String {
+        format!("{}_cursor", self.root_name)
+    }
+
+    /// Replaces every piece on the board, redrawing from scratch.
+    pub fn set_grid(&mut self, grid: BTreeMap<Coord, Piece>) {
+        self.grid = grid;
+        self.rerender();
     }
 
     pub fn remove_highlight(&mut self) {
         self.last_highlight = None;
-        self.rerender();
+        self.remove_guides();
+
+        if self.highlight_visible {
+            self.renderer.hide_named(&self.highlight_name());
+            self.highlight_visible = false;
+        }
     }
 
-    pub fn played_grid(&self) -> BTreeMap<Coord, Piece> {
-        self.grid
+    fn highlight_name(&self) -> String {
+        format!("{}_highlight", self.root_name)
+    }
+
+    fn grid_to_world(&self, coord: Coord) -> (f32, f32) {
+        (
+            (coord.0 * self.cell_width) as f32,
+            (coord.1 * self.cell_height) as f32,
+        )
+    }
+
+    fn tile_name(&self, id: u32) -> String {
+        format!("{}_tile_{}", self.root_name, id)
+    }
+
+    fn render_tile(&mut self, coord: Coord, piece: Piece) {
+        let id = self.next_tile;
+        self.next_tile += 1;
+
+        if let Some(old) = self.tiles.insert(coord, id) {
+            self.delete_tile(old);
+        }
+
+        self.renderer.render_named(
+            &self.tile_name(id),
+            piece.as_svg(self.cell_width, self.cell_height),
+            self.grid_to_world(coord),
+        );
+        self.reveal(coord);
+    }
+
+    /// Moves the camera back far enough to show a tile at `coord` that's
+    /// left of or above the grid, which a board from an older build can
+    /// have. Tiles on the grid are left to the player to pan to.
+    fn reveal(&mut self, coord: Coord) {
+        if coord.0 >= 0 && coord.1 >= 0 {
+            return;
+        }
+
+        let (x, y) = self.grid_to_world(coord);
+        if x < self.view_x || y < self.view_y {
+            self.view_x = self.view_x.min(x);
+            self.view_y = self.view_y.min(y);
+            self.update_viewbox();
+        }
+    }
+
+    fn unrender_tile(&mut self, coord: Coord) {
+        if let Some(id) = self.tiles.remove(&coord) {
+            self.delete_tile(id);
+        }
+    }
+
+    fn delete_tile(&mut self, id: u32) {
+        let name = self.tile_name(id);
+        self.slides.retain(|slide| slide.name != name);
+        self.renderer.delete_named(&name);
+    }
+
+    /// Moves the element `id` from cell `from` to `to`, sliding it there if
+    /// animations are on.
+    fn slide_tile(&mut self, id: u32, from: Coord, to: Coord) {
+        let name = self.tile_name(id);
+        let duration = settings::get().animation_speed.millis();
+
+        self.slides.retain(|slide| slide.name != name);
+
+        self.reveal(to);
+
+        if duration > 0.0 && from != to {
+            self.slides.push(Slide::new(
+                name,
+                self.grid_to_world(from),
+                self.grid_to_world(to),
+                duration,
+            ));
+        } else {
+            self.renderer.move_named(&name, self.grid_to_world(to));
+        }
+    }
+
+    pub fn is_animating(&self) -> bool {
+        !self.slides.is_empty()
+    }
+
+    /// Moves every sliding tile to where it should be at `now`.
+    pub fn step_animations(&mut self, now: f64) {
+        for slide in &mut self.slides {
+            let position = slide.position(now);
+            self.renderer.move_named(&slide.name, position);
+        }
+
+        self.slides.retain(|slide| !slide.finished(now));
+    }
+
+    /// Takes the piece at `coord` off the board for someone else's pickup.
+    pub fn lift(&mut self, coord: Coord) -> Option<Piece> {
+        let piece = self.grid.remove(&coord)?;
+
+        if let Some(id) = self.tiles.remove(&coord) {
+            let name = self.tile_name(id);
+            self.slides.retain(|slide| slide.name != name);
+            self.renderer.hide_named(&name);
+            self.lifted.push((piece, id, coord));
+        }
+
+        Some(piece)
+    }
+
+    /// Places a piece for someone else, sliding it over from where it was
+    /// lifted if we saw it picked up.
+    pub fn drop_lifted(&mut self, coord: Coord, piece: Piece) {
+        match self.lifted.iter().position(|(p, _, _)| *p == piece) {
+            Some(i) => {
+                let (_, id, from) = self.lifted.remove(i);
+
+                self.unrender_tile(coord);
+                self.grid.insert(coord, piece);
+                self.tiles.insert(coord, id);
+                self.renderer.show_named(&self.tile_name(id));
+                self.slide_tile(id, from, coord);
+            }
+            None => {
+                self.grid_insert(coord, piece);
+            }
+        }
+    }
+
+    /// Changes the board to `grid`, sliding pieces that moved instead of
+    /// redrawing them. Lifted tiles that don't come back are deleted.
+    pub fn transition_to(&mut self, grid: BTreeMap<Coord, Piece>) {
+        let mut leaving = std::mem::take(&mut self.lifted);
+
+        let stale: Vec<Coord> = self
+            .grid
             .iter()
-            .filter(|(Coord(_x, y), _)| *y < self.rows - 5)
-            .map(|(Coord(x, y), p)| (Coord(*x, *y), *p))
-            .collect()
+            .filter(|(coord, piece)| grid.get(coord) != Some(piece))
+            .map(|(coord, _)| *coord)
+            .collect();
+
+        for coord in stale {
+            let piece = self.grid.remove(&coord).unwrap();
+
+            if let Some(id) = self.tiles.remove(&coord) {
+                leaving.push((piece, id, coord));
+            }
+        }
+
+        for (coord, piece) in grid {
+            if self.grid.contains_key(&coord) {
+                continue;
+            }
+
+            match leaving.iter().position(|(p, _, _)| *p == piece) {
+                Some(i) => {
+                    let (_, id, from) = leaving.swap_remove(i);
+
+                    self.grid.insert(coord, piece);
+                    self.tiles.insert(coord, id);
+                    self.renderer.show_named(&self.tile_name(id));
+                    self.slide_tile(id, from, coord);
+                }
+                None => {
+                    self.grid_insert(coord, piece);
+                }
+            }
+        }
+
+        for (_, id, _) in leaving {
+            self.delete_tile(id);
+        }
     }
 
     pub fn render(&mut self) {
-        for (Coord(grid_x, grid_y), piece) in self.grid.iter() {
-            self.renderer.render(
-                piece.as_svg(self.cell_width, self.cell_height),
-                (
-                    (grid_x * self.cell_width) as f32,
-                    (grid_y * self.cell_height) as f32,
-                ),
-            );
+        let pieces: Vec<(Coord, Piece)> = self.grid.iter().map(|(c, p)| (*c, *p)).collect();
+
+        for (coord, piece) in pieces {
+            self.render_tile(coord, piece);
         }
     }
 
+    /// Clears the SVG and draws every piece again. Only needed when the cell
+    /// size changes or the whole grid is replaced.
     pub fn rerender(&mut self) {
         self.renderer.clear();
+        self.tiles.clear();
+        self.lifted.clear();
+        self.slides.clear();
+        self.marks = 0;
+        self.highlight = None;
+        self.highlight_visible = false;
+        self.last_highlight = None;
+        self.guides_drawn = false;
+        self.column_drawn = false;
+
+        // Drawn first so every piece goes on top:
+        if self.guides {
+            self.render_grid_lines();
+        }
+
         self.render();
+
+        self.credits_drawn = 0;
+        let credits = std::mem::take(&mut self.credits);
+        self.outline_credits(credits);
+
+        if let Some(cursor) = self.cursor.take() {
+            self.set_cursor(cursor);
+        }
+    }
+
+    fn render_grid_lines(&self) {
+        let width = self.cols * self.cell_width;
+        let height = self.rows * self.cell_height;
+        let line = |x1: i32, y1: i32, x2: i32, y2: i32| {
+            SVGElem::new(Tag::Line)
+                .set(Attr::X1, x1)
+                .set(Attr::Y1, y1)
+                .set(Attr::X2, x2)
+                .set(Attr::Y2, y2)
+        };
+
+        let mut lines = SVGElem::new(Tag::G).set(Attr::Class, "grid_lines");
+        for x in 0..=self.cols {
+            lines = lines.append(line(x * self.cell_width, 0, x * self.cell_width, height));
+        }
+        for y in 0..=self.rows {
+            lines = lines.append(line(0, y * self.cell_height, width, y * self.cell_height));
+        }
+
+        self.renderer
+            .render_named(&format!("{}_grid_lines", self.root_name), lines, (0.0, 0.0));
+    }
+
+    /// Outlines the row a group held at `coord` would go in, red if
+    /// something's in the way, and lights up the empty cells next to pieces
+    /// already down, where it could join them. Where groups can go down a
+    /// column, a single piece gets its column outlined too, and the cells
+    /// above and below pieces light up.
+    fn render_guides(&mut self, coord: Coord, group: &[(i32, Piece)]) {
+        self.remove_guides();
+
+        let Coord(x, y) = coord;
+        let covered: Vec<Coord> = group.iter().map(|(dx, _)| Coord(x + dx, y)).collect();
+        let fits = covered.iter().all(|&Coord(x, y)| {
+            x >= 0 && x < self.cols && y >= 0 && y < self.rows && !self.contains(Coord(x, y))
+        });
+
+        let row = SVGElem::new(Tag::Rect)
+            .set(
+                Attr::Class,
+                if fits {
+                    "drop_row"
+                } else {
+                    "drop_row drop_blocked"
+                },
+            )
+            .set(Attr::Width, self.cols * self.cell_width)
+            .set(Attr::Height, self.cell_height)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0);
+
+        let mut hints = SVGElem::new(Tag::G);
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let cell = Coord(x, y);
+                let next_to_group = self.contains(Coord(x - 1, y))
+                    || self.contains(Coord(x + 1, y))
+                    || (self.vertical_groups
+                        && (self.contains(Coord(x, y - 1)) || self.contains(Coord(x, y + 1))));
+
+                if next_to_group && !self.contains(cell) && !covered.contains(&cell) {
+                    let (world_x, world_y) = self.grid_to_world(cell);
+                    hints = hints.append(
+                        SVGElem::new(Tag::Rect)
+                            .set(Attr::Class, "drop_hint")
+                            .set(Attr::Width, self.cell_width)
+                            .set(Attr::Height, self.cell_height)
+                            .set(Attr::X, world_x)
+                            .set(Attr::Y, world_y),
+                    );
+                }
+            }
+        }
+
+        self.renderer.render_named(
+            &self.guide_name("row"),
+            row,
+            self.grid_to_world(Coord(0, y)),
+        );
+        self.renderer
+            .render_named(&self.guide_name("hints"), hints, (0.0, 0.0));
+        self.guides_drawn = true;
+
+        if self.vertical_groups && group.len() == 1 {
+            let column = SVGElem::new(Tag::Rect)
+                .set(
+                    Attr::Class,
+                    if fits {
+                        "drop_row drop_column"
+                    } else {
+                        "drop_row drop_column drop_blocked"
+                    },
+                )
+                .set(Attr::Width, self.cell_width)
+                .set(Attr::Height, self.rows * self.cell_height)
+                .set(Attr::X, 0)
+                .set(Attr::Y, 0);
+
+            self.renderer.render_named(
+                &self.guide_name("column"),
+                column,
+                self.grid_to_world(Coord(x, 0)),
+            );
+            self.column_drawn = true;
+        }
+    }
+
+    fn remove_guides(&mut self) {
+        if self.guides_drawn {
+            self.renderer.delete_named(&self.guide_name("row"));
+            self.renderer.delete_named(&self.guide_name("hints"));
+            self.guides_drawn = false;
+        }
+
+        if self.column_drawn {
+            self.renderer.delete_named(&self.guide_name("column"));
+            self.column_drawn = false;
+        }
+    }
+
+    fn guide_name(&self, guide: &str) -> String {
+        format!("{}_guide_{}", self.root_name, guide)
     }
 
     pub fn render_pieces(&mut self, pieces: &[Piece]) {
@@ -134,118 +716,261 @@ impl Board {
         self.grid.contains_key(&coord)
     }
 
-    pub fn world_contains(&self, world_x: i32, world_y: i32) -> bool {
-        let grid_x = world_x / self.cell_width;
-        let grid_y = world_y / self.cell_height;
+    /// Returns the coords of the contiguous row of pieces containing
+    /// `coord`, left to right. Empty if there is no piece at `coord`.
+    pub fn group_at(&self, coord: Coord) -> Vec<Coord> {
+        if !self.contains(coord) {
+            return Vec::new();
+        }
+
+        let Coord(x, y) = coord;
+
+        let mut start = x;
+        while self.contains(Coord(start - 1, y)) {
+            start -= 1;
+        }
 
-        self.grid.contains_key(&Coord(grid_x, grid_y))
+        let mut end = x;
+        while self.contains(Coord(end + 1, y)) {
+            end += 1;
+        }
+
+        (start..=end).map(|x| Coord(x, y)).collect()
+    }
+
+    pub fn world_contains(&self, world_x: i32, world_y: i32) -> bool {
+        self.grid
+            .contains_key(&self.world_to_grid(world_x, world_y))
     }
 
     pub fn world_to_grid(&self, world_x: i32, world_y: i32) -> Coord {
-        Coord(world_x / self.cell_width, world_y / self.cell_height)
+        let (x, y) = self.screen_to_world(world_x, world_y);
+        cell_at(x, y, self.cell_width, self.cell_height)
+    }
+
+    /// The middle of the cell at `coord` relative to the svg element, where
+    /// [`Board::world_to_grid`] finds `coord` again.
+    pub fn grid_to_screen(&self, coord: Coord) -> (i32, i32) {
+        let (x, y) = self.grid_to_world(coord);
+        let x = x + self.cell_width as f32 / 2.0;
+        let y = y + self.cell_height as f32 / 2.0;
+        (
+            ((x - self.view_x) * self.zoom).round() as i32,
+            ((y - self.view_y) * self.zoom).round() as i32,
+        )
     }
 
     pub fn world_render_highlight(&mut self, world_x: i32, world_y: i32, piece: &Piece) {
+        self.world_render_group_highlight(world_x, world_y, &[(0, *piece)]);
+    }
+
+    pub fn world_render_group_highlight(
+        &mut self,
+        world_x: i32,
+        world_y: i32,
+        group: &[(i32, Piece)],
+    ) {
         let coord = self.world_to_grid(world_x, world_y);
+        self.grid_render_group_highlight(coord, group);
+    }
 
-        if self.last_highlight != Some(coord) {
-            let background = SVGElem::new(Tag::Rect)
-                .set(Attr::Fill, "lightgrey")
-                .set(Attr::Width, self.cell_width)
-                .set(Attr::Height, self.cell_height)
-                .set(Attr::X, 0)
-                .set(Attr::Y, 0);
+    pub fn grid_render_highlight(&mut self, coord: Coord, piece: &Piece) {
+        self.grid_render_group_highlight(coord, &[(0, *piece)]);
+    }
 
-            let num = SVGElem::new(Tag::Text)
-                .set(Attr::Fill, piece.color)
-                .set(Attr::Transform, "scale(1, 2)")
-                .set(Attr::X, self.cell_width / 2)
-                .set(Attr::Y, self.cell_height / 4)
-                .set(Attr::DominantBaseline, "central")
-                .set(Attr::TextAnchor, "middle")
-                .set(Attr::Class, "piece_text")
-                .set(Attr::TextLength, self.cell_width - 5)
-                .set(Attr::LengthAdjust, "spacingAndGlyphs")
-                .set_inner(&piece.num.to_string());
-
-            let piece = SVGElem::new(Tag::G).append(background).append(num);
-
-            self.rerender();
-            self.renderer.render(
-                piece,
-                (
-                    (coord.0 * self.cell_width) as f32,
-                    (coord.1 * self.cell_height) as f32,
-                ),
-            );
+    /// Highlights a held group, each piece drawn at its x offset from
+    /// `coord`.
+    pub fn grid_render_group_highlight(&mut self, coord: Coord, group: &[(i32, Piece)]) {
+        let name = self.highlight_name();
+
+        if self.highlight.as_deref() != Some(group) {
+            // What we're holding changed, so rebuild the highlight layer:
+            if self.highlight.is_some() {
+                self.renderer.delete_named(&name);
+            }
+
+            let mut layer = SVGElem::new(Tag::G);
+            for (offset, piece) in group {
+                let svg = self.highlight_svg(piece).set(
+                    Attr::Transform,
+                    format!("translate({}, 0)", offset * self.cell_width),
+                );
 
+                layer = layer.append(svg);
+            }
+
+            self.renderer
+                .render_named(&name, layer, self.grid_to_world(coord));
+            self.highlight = Some(group.to_vec());
+            self.highlight_visible = true;
             self.last_highlight = Some(coord);
+        } else if self.last_highlight != Some(coord) {
+            self.renderer.move_named(&name, self.grid_to_world(coord));
+
+            if !self.highlight_visible {
+                self.renderer.show_named(&name);
+                self.highlight_visible = true;
+            }
+
+            self.last_highlight = Some(coord);
+        } else {
+            return;
+        }
+
+        if self.guides {
+            self.render_guides(coord, group);
         }
     }
 
+    fn highlight_svg(&self, piece: &Piece) -> SVGElem {
+        let background = SVGElem::new(Tag::Rect)
+            .set(Attr::Fill, "lightgrey")
+            .set(Attr::Width, self.cell_width)
+            .set(Attr::Height, self.cell_height)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0);
+
+        let face = settings::get()
+            .theme
+            .tiles()
+            .face(*piece, self.cell_width, self.cell_height);
+
+        SVGElem::new(Tag::G).append(background).append(face)
+    }
+
     pub fn remove_piece_at(&mut self, world_x: i32, world_y: i32) -> Option<Piece> {
         let coord = self.world_to_grid(world_x, world_y);
-        self.grid.remove(&coord)
+        self.grid_remove(coord)
     }
 
     pub fn grid_remove(&mut self, coord: Coord) -> Option<Piece> {
         crate::console_log!("grid_remove: {:?}, {:?}", coord, self.grid.get(&coord));
+        self.unrender_tile(coord);
         self.grid.remove(&coord)
     }
 
     pub fn world_insert(&mut self, world_x: i32, world_y: i32, piece: Piece) -> Option<Piece> {
         let coord = self.world_to_grid(world_x, world_y);
-        self.grid.insert(coord, piece)
+        self.grid_insert(coord, piece)
     }
 
     pub fn grid_insert(&mut self, coord: Coord, piece: Piece) -> Option<Piece> {
+        self.render_tile(coord, piece);
         self.grid.insert(coord, piece)
     }
 
-    pub fn insert_as_hand(&mut self, pieces: &[Piece]) {
-        let mut red = pieces.iter().filter(|p| p.color == Color::Red);
-        let mut blue = pieces.iter().filter(|p| p.color == Color::Blue);
-        let mut yellow = pieces.iter().filter(|p| p.color == Color::Yellow);
-        let mut black = pieces
-            .iter()
-            .filter(|p| p.color == Color::Black || p.color == Color::Joker);
-
-        for x in 0..self.cols - 1 {
-            if let Some(&p) = red.next() {
-                self.grid.insert(Coord(x, 0), p);
-            }
+    /// Moves the piece at `from` to `to`, reusing its rendered element.
+    pub fn grid_move(&mut self, from: Coord, to: Coord) -> bool {
+        if self.grid.contains_key(&to) {
+            return false;
+        }
 
-            if let Some(&p) = blue.next() {
-                self.grid.insert(Coord(x, 1), p);
+        match (self.grid.remove(&from), self.tiles.remove(&from)) {
+            (Some(piece), Some(id)) => {
+                self.renderer
+                    .move_named(&self.tile_name(id), self.grid_to_world(to));
+                self.grid.insert(to, piece);
+                self.tiles.insert(to, id);
+                true
             }
-
-            if let Some(&p) = yellow.next() {
-                self.grid.insert(Coord(x, 2), p);
+            (Some(piece), None) => {
+                self.grid_insert(to, piece);
+                true
             }
+            _ => false,
+        }
+    }
 
-            if let Some(&p) = black.next() {
-                self.grid.insert(Coord(x, 3), p);
-            }
+    pub fn insert_as_hand(&mut self, pieces: &[Piece]) {
+        for &piece in pieces {
+            self.insert_into_hand(piece);
         }
     }
 
+    /// Lays `hand` out where `saved` had its pieces, as far as they still
+    /// fit, placing whatever `saved` doesn't account for as if just drawn.
+    pub fn arrange_hand(&mut self, saved: &BTreeMap<Coord, Piece>, hand: &[Piece]) {
+        let (kept, new) = reconcile_hand(saved, hand, self.cols, self.rows);
+
+        self.set_grid(kept);
+        self.insert_as_hand(&new);
+    }
+
+    /// Puts `piece` in its color's row of the hand, spilling into the other
+    /// rows if that one's full, and into a new row if they all are.
     pub fn insert_into_hand(&mut self, piece: Piece) {
-        let y = match piece.color {
-            Color::Red => 0,
-            Color::Blue => 1,
-            Color::Yellow => 2,
-            Color::Black | Color::Joker => 3,
-        };
+        let spot = hand_spot(piece.color, self.cols, self.rows, |coord| {
+            self.grid.contains_key(&coord)
+        });
 
-        for x in 0..self.cols - 1 {
-            if !self.grid.contains_key(&Coord(x, y)) {
-                self.grid.insert(Coord(x, y), piece);
-                break;
+        let coord = match spot {
+            Some(coord) => coord,
+            None => {
+                self.rows += 1;
+                self.resize();
+                Coord(0, self.rows - 1)
             }
-        }
+        };
+
+        self.grid_insert(coord, piece);
+    }
+
+    /// Lays the hand out again in order, one row per color.
+    pub fn sort_hand(&mut self) {
+        let mut pieces: Vec<Piece> = self.grid.values().copied().collect();
+        pieces.sort();
+
+        self.set_grid(BTreeMap::new());
+        self.insert_as_hand(&pieces);
+    }
+
+    /// Removes one copy of `piece` from wherever it sits in the hand.
+    pub fn remove_from_hand(&mut self, piece: Piece) -> Option<Coord> {
+        let coord = self
+            .grid
+            .iter()
+            .find(|(_, p)| **p == piece)
+            .map(|(c, _)| *c)?;
+
+        self.grid_remove(coord);
+        Some(coord)
+    }
+}
+
+/// The cell the board point `(x, y)` is in. Rounds down, so points left of
+/// or above the grid land in negative cells rather than the first ones.
+fn cell_at(x: f32, y: f32, cell_width: i32, cell_height: i32) -> Coord {
+    Coord(
+        (x / cell_width as f32).floor() as i32,
+        (y / cell_height as f32).floor() as i32,
+    )
+}
+
+/// The row of the hand a color goes in. Jokers get a row of their own under
+/// the colors, since they can go with any of them.
+fn hand_row(color: Color) -> i32 {
+    match color {
+        Color::Red => 0,
+        Color::Blue => 1,
+        Color::Yellow => 2,
+        Color::Black => 3,
+        Color::Joker => 4,
     }
 }
 
+/// Where a piece of `color` goes in a hand `cols` by `rows`: the first cell
+/// that isn't `taken` in its own row, or else in the rows after it, wrapping
+/// back round to the top. The last column is left free to drop pieces in.
+/// `None` if the hand is full.
+fn hand_spot(color: Color, cols: i32, rows: i32, taken: impl Fn(Coord) -> bool) -> Option<Coord> {
+    let home = hand_row(color);
+
+    (0..rows)
+        .map(|i| (home + i) % rows)
+        .flat_map(|y| (0..cols - 1).map(move |x| Coord(x, y)))
+        .find(|&coord| !taken(coord))
+}
+
 #[derive(Debug)]
 pub struct LocatedPiece {
     pub x: f32,
@@ -255,30 +980,101 @@ pub struct LocatedPiece {
 
 impl AsSVG for Piece {
     fn as_svg(&self, width: i32, height: i32) -> SVGElem {
-        let color = self.color.to_string();
-        let number = self.num.to_string();
+        settings::get().theme.tiles().draw(*self, width, height)
+    }
+}
 
-        let background = SVGElem::new(Tag::Rect)
-            .set(Attr::Class, "piece_tile")
-            .set(Attr::Width, width)
-            .set(Attr::Height, height)
-            .set(Attr::X, 0)
-            .set(Attr::Y, 0);
+/// Splits `hand` into the pieces that can go back where `saved` had them in
+/// a hand `cols` by `rows`, and the rest. Each saved spot takes at most one
+/// piece, so duplicates are only kept as often as they're still there.
+fn reconcile_hand(
+    saved: &BTreeMap<Coord, Piece>,
+    hand: &[Piece],
+    cols: i32,
+    rows: i32,
+) -> (BTreeMap<Coord, Piece>, Vec<Piece>) {
+    let mut rest = hand.to_vec();
+    let mut kept = BTreeMap::new();
+
+    for (&coord, piece) in saved {
+        let Coord(x, y) = coord;
+        if x < 0 || x >= cols - 1 || y < 0 || y >= rows {
+            continue;
+        }
+
+        if let Some(i) = rest.iter().position(|p| p == piece) {
+            kept.insert(coord, rest.remove(i));
+        }
+    }
+
+    (kept, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn cells_round_down_on_both_sides_of_the_origin() {
+        assert_eq!(cell_at(0.0, 0.0, 40, 50), Coord(0, 0));
+        assert_eq!(cell_at(39.9, 99.0, 40, 50), Coord(0, 1));
+        assert_eq!(cell_at(-0.5, -0.5, 40, 50), Coord(-1, -1));
+        assert_eq!(cell_at(-40.0, -51.0, 40, 50), Coord(-1, -2));
+    }
+
+    #[test]
+    fn full_rows_spill_over_instead_of_dropping_pieces() {
+        let mut taken = BTreeSet::new();
+        let mut place = |color| {
+            let spot = hand_spot(color, 4, 5, |coord| taken.contains(&coord))?;
+            taken.insert(spot);
+            Some(spot)
+        };
+
+        for x in 0..3 {
+            assert_eq!(place(Color::Red), Some(Coord(x, 0)));
+        }
+        assert_eq!(place(Color::Red), Some(Coord(0, 1)));
+        assert_eq!(place(Color::Blue), Some(Coord(1, 1)));
+
+        // The last row wraps round to the first free cell from the top:
+        for x in 0..3 {
+            assert_eq!(place(Color::Joker), Some(Coord(x, 4)));
+        }
+        assert_eq!(place(Color::Joker), Some(Coord(2, 1)));
+
+        for _ in 0..6 {
+            assert!(place(Color::Yellow).is_some());
+        }
+        assert_eq!(place(Color::Black), None);
+    }
+
+    #[test]
+    fn saved_hands_keep_their_layout() {
+        let red = Piece::new(Color::Red, 7);
+        let blue = Piece::new(Color::Blue, 3);
+        let joker = Piece::joker();
+
+        let saved: BTreeMap<Coord, Piece> = vec![
+            (Coord(2, 3), red),
+            (Coord(0, 0), red),
+            (Coord(1, 1), blue),
+            (Coord(9, 0), joker),
+        ]
+        .into_iter()
+        .collect();
+
+        // One red was played and a joker was drawn since, and the hand got
+        // narrower:
+        let hand = vec![blue, joker, red, Piece::new(Color::Black, 1)];
+        let (kept, new) = reconcile_hand(&saved, &hand, 5, 5);
 
-        let num = SVGElem::new(Tag::Text)
-            .set(Attr::Fill, color)
-            .set(Attr::Transform, "scale(1, 1.5)")
-            .set(Attr::X, width / 2)
-            .set(Attr::Y, height / 3)
-            .set(Attr::DominantBaseline, "central")
-            .set(Attr::TextAnchor, "middle")
-            .set(Attr::Class, "piece_text")
-            .set(Attr::TextLength, width - (width / 5))
-            .set(Attr::LengthAdjust, "spacingAndGlyphs")
-            .set_inner(&number);
-
-        let piece = SVGElem::new(Tag::G).append(background).append(num);
-
-        piece
+        let expected: BTreeMap<Coord, Piece> = vec![(Coord(0, 0), red), (Coord(1, 1), blue)]
+            .into_iter()
+            .collect();
+        assert_eq!(kept, expected);
+        assert_eq!(new, vec![joker, Piece::new(Color::Black, 1)]);
     }
 }
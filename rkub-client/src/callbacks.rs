@@ -0,0 +1,127 @@
+//! Callbacks handed to the browser, kept alive for as long as whatever they're
+//! for and no longer. Each state keeps its own [`CallbackRegistry`], so
+//! moving on to the next one takes the last one's listeners off the page and
+//! stops its timers, rather than leaving them to call into closures that are
+//! gone.
+
+use std::any::Any;
+use std::fmt;
+
+use js_sys::Function;
+use wasm_bindgen::convert::FromWasmAbi;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::EventTarget;
+
+use crate::{build_cb, JsError, JsResult};
+
+struct Listener {
+    target: EventTarget,
+    name: &'static str,
+    function: Function,
+}
+
+enum Timer {
+    Interval(i32),
+    Timeout(i32),
+}
+
+#[derive(Default)]
+pub struct CallbackRegistry {
+    listeners: Vec<Listener>,
+    timers: Vec<Timer>,
+    /// The closures themselves, whatever they take.
+    closures: Vec<Box<dyn Any>>,
+}
+
+impl CallbackRegistry {
+    /// Calls `f` with every `name` event on `obj`.
+    pub fn listen<E, F, T>(&mut self, obj: &E, name: &'static str, f: F)
+    where
+        E: JsCast,
+        F: FnMut(T) -> JsError + 'static,
+        T: FromWasmAbi + 'static,
+    {
+        let target: EventTarget = obj
+            .dyn_ref::<EventTarget>()
+            .expect("Could not convert into `EventTarget`")
+            .clone();
+        let function = self.keep(build_cb(f));
+
+        target
+            .add_event_listener_with_callback(name, &function)
+            .expect("Could not add event listener");
+
+        self.listeners.push(Listener {
+            target,
+            name,
+            function,
+        });
+    }
+
+    /// Calls `f` every `ms` milliseconds.
+    pub fn every<F>(&mut self, ms: i32, f: F) -> JsResult<()>
+    where
+        F: FnMut() + 'static,
+    {
+        let function = self.keep(Closure::wrap(Box::new(f) as Box<dyn FnMut()>));
+        let interval = web_sys::window()
+            .unwrap()
+            .set_interval_with_callback_and_timeout_and_arguments_0(&function, ms)?;
+
+        self.timers.push(Timer::Interval(interval));
+        Ok(())
+    }
+
+    /// Calls `f` once, in `ms` milliseconds.
+    pub fn after<F>(&mut self, ms: i32, f: F) -> JsResult<()>
+    where
+        F: FnMut() + 'static,
+    {
+        let function = self.keep(Closure::wrap(Box::new(f) as Box<dyn FnMut()>));
+        let timeout = web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&function, ms)?;
+
+        self.timers.push(Timer::Timeout(timeout));
+        Ok(())
+    }
+
+    /// Keeps `closure` alive for callbacks handed over some other way, like
+    /// animation frames and observers, returning the function to hand over.
+    pub fn keep<T>(&mut self, closure: Closure<T>) -> Function
+    where
+        T: ?Sized + 'static,
+    {
+        let function = closure.as_ref().unchecked_ref::<Function>().clone();
+        self.closures.push(Box::new(closure));
+        function
+    }
+}
+
+impl Drop for CallbackRegistry {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            let _ = listener
+                .target
+                .remove_event_listener_with_callback(listener.name, &listener.function);
+        }
+
+        let window = web_sys::window().unwrap();
+        for timer in &self.timers {
+            match *timer {
+                Timer::Interval(interval) => window.clear_interval_with_handle(interval),
+                Timer::Timeout(timeout) => window.clear_timeout_with_handle(timeout),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CallbackRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallbackRegistry")
+            .field("listeners", &self.listeners.len())
+            .field("timers", &self.timers.len())
+            .finish()
+    }
+}
@@ -0,0 +1,240 @@
+//! The connection to the server: numbering what goes over it both ways, and
+//! getting back into the room when it drops. The states only send
+//! `ClientMessage`s and take [`ConnectionEvent`]s, and never see the
+//! [`Transport`] underneath, so a practice room in the page can stand in for
+//! the server.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rkub_common::{ClientMessage, Sequenced, ServerMessage};
+
+use crate::callbacks::CallbackRegistry;
+use crate::debug;
+use crate::outbox::{self, Outbox};
+use crate::practice::PracticeRoom;
+use crate::transport::{MemoryTransport, SocketTransport, Transport};
+use crate::ui;
+use crate::{console_log, JsResult};
+
+/// How often to ping the server, so a dead connection's noticed.
+const HEARTBEAT_MS: i32 = 3_000;
+
+/// What a connection has to tell the game.
+#[derive(Debug)]
+pub enum ConnectionEvent {
+    /// The socket's open, the first time or again after a drop.
+    Opened,
+    Message(ServerMessage),
+    /// This many messages from the server never arrived.
+    Missed(u64),
+    /// The socket closed, whether or not we closed it.
+    Closed,
+    /// Time for another try at getting back in, see
+    /// [`Connection::retry_later`].
+    Retry,
+    /// Time to ping the server.
+    Heartbeat,
+}
+
+/// Where a connection's events go.
+pub type OnEvent = fn(ConnectionEvent) -> JsResult<()>;
+
+#[derive(Debug)]
+pub struct Connection {
+    transport: Box<dyn Transport>,
+    on_event: OnEvent,
+    /// The `seq` of the last message we sent, numbered on from whatever the
+    /// room last took from us when we join it.
+    sent_seq: u64,
+    /// While we're getting back into the room, what we've sent meanwhile.
+    outbox: Option<Outbox>,
+    /// The next try at getting back in, waiting to run.
+    retry: CallbackRegistry,
+    heartbeat: CallbackRegistry,
+    /// Whether we're done with the connection, so it closing isn't a drop.
+    hung_up: bool,
+}
+
+impl Connection {
+    /// Connects to the server that served the page. Everything that happens
+    /// to the connection from then on goes to `on_event`.
+    pub fn open(on_event: OnEvent) -> JsResult<Self> {
+        let socket = SocketTransport::open(on_event)?;
+        Ok(Connection::over(Box::new(socket), on_event))
+    }
+
+    /// Plays against `bots` bots right here instead, with no server at all.
+    /// Everything that happens goes to `on_event` just the same.
+    pub fn practice(bots: usize, on_event: OnEvent) -> JsResult<Self> {
+        let seed = (js_sys::Math::random() * u64::MAX as f64) as u64;
+        let room = Rc::new(RefCell::new(PracticeRoom::new(bots, seed)));
+        let memory = MemoryTransport::new(room, on_event)?;
+        Ok(Connection::over(Box::new(memory), on_event))
+    }
+
+    /// Sends and takes messages over `transport`, whatever it is.
+    pub fn over(transport: Box<dyn Transport>, on_event: OnEvent) -> Self {
+        Connection {
+            transport,
+            on_event,
+            sent_seq: 0,
+            outbox: None,
+            retry: CallbackRegistry::default(),
+            heartbeat: CallbackRegistry::default(),
+            hung_up: false,
+        }
+    }
+
+    /// Pings the server every so often from now on.
+    pub fn start_heartbeat(&mut self) -> JsResult<()> {
+        let on_event = self.on_event;
+        self.heartbeat.every(HEARTBEAT_MS, move || {
+            let _ = on_event(ConnectionEvent::Heartbeat);
+        })
+    }
+
+    pub fn is_hung_up(&self) -> bool {
+        self.hung_up
+    }
+
+    /// Whether this is a practice room's, with no server behind it.
+    pub fn is_practice(&self) -> bool {
+        self.transport.is_local()
+    }
+
+    /// Sends `msg`, or keeps it until we're back in the room if the
+    /// connection's down. Returns the `seq` it went as, if it went now.
+    pub fn send(&mut self, msg: ClientMessage) -> JsResult<Option<u64>> {
+        if self.hung_up {
+            return Ok(None);
+        }
+
+        if self.outbox.is_none() && !self.transport.is_open() {
+            self.outbox = Some(Outbox::default());
+        }
+        if let Some(outbox) = &mut self.outbox {
+            outbox.push(msg);
+            self.show_status()?;
+            return Ok(None);
+        }
+
+        self.send_now(msg)?;
+        Ok(Some(self.sent_seq))
+    }
+
+    /// Sends `msg` as the next message, whether or not we're in the room.
+    pub fn send_now(&mut self, msg: ClientMessage) -> JsResult<()> {
+        self.sent_seq += 1;
+        let msg = Sequenced {
+            seq: self.sent_seq,
+            msg,
+        };
+        // Heartbeats would crowd out everything worth seeing:
+        if !matches!(msg.msg, ClientMessage::Ping) {
+            debug::sent(&msg);
+        }
+        self.transport.send(msg)
+    }
+
+    /// We're in the room, which last took message `last_seq` from us. After
+    /// a drop, sends on what waited, then asks for the whole game, since the
+    /// room only told us how it was before.
+    pub fn joined(&mut self, last_seq: u64) -> JsResult<()> {
+        // Anything the room's already taken from us would be turned down:
+        self.sent_seq = self.sent_seq.max(last_seq);
+
+        let mut outbox = match self.outbox.take() {
+            Some(outbox) => outbox,
+            None => return Ok(()),
+        };
+        self.show_status()?;
+
+        let queued = outbox.take();
+        if queued.is_empty() {
+            return Ok(());
+        }
+
+        console_log!("sending {} messages that waited", queued.len());
+        for msg in queued {
+            self.send_now(msg)?;
+        }
+        self.send_now(ClientMessage::RequestSync)
+    }
+
+    /// Tries to connect again in a while, waiting longer each time, or
+    /// returns false once it's tried enough.
+    pub fn retry_later(&mut self) -> JsResult<bool> {
+        let outbox = self.outbox.get_or_insert_with(Outbox::default);
+        if outbox.attempts >= outbox::MAX_ATTEMPTS {
+            self.hung_up = true;
+            return Ok(false);
+        }
+
+        let delay = outbox.delay_ms();
+        outbox.attempts += 1;
+        console_log!("connection dropped, trying again in {}ms", delay);
+        self.show_status()?;
+
+        let on_event = self.on_event;
+        self.retry = CallbackRegistry::default();
+        self.retry.after(delay, move || {
+            let _ = on_event(ConnectionEvent::Retry);
+        })?;
+
+        Ok(true)
+    }
+
+    /// Opens a new socket in place of the one that dropped.
+    pub fn reconnect(&mut self) -> JsResult<()> {
+        self.transport = Box::new(SocketTransport::open(self.on_event)?);
+        Ok(())
+    }
+
+    /// Moves over to the server at `ws_url`, which runs the room we asked
+    /// for. The new socket opens like any other, and the old one's left
+    /// without anything listening, so it closing isn't a drop.
+    pub fn redirect(&mut self, ws_url: &str) -> JsResult<()> {
+        let socket = SocketTransport::open_url(ws_url, self.on_event)?;
+        let mut old = std::mem::replace(&mut self.transport, Box::new(socket));
+        old.close()
+    }
+
+    /// Closes the connection for good, and stops pinging or trying to get
+    /// back in on it.
+    pub fn hang_up(&mut self) -> JsResult<()> {
+        self.hung_up = true;
+        self.heartbeat = CallbackRegistry::default();
+        self.retry = CallbackRegistry::default();
+        self.transport.close()
+    }
+
+    /// How the connection's doing, for the debug panel.
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "{}, last sent #{}",
+            self.transport.describe(),
+            self.sent_seq
+        );
+        if let Some(outbox) = &self.outbox {
+            description.push_str(&format!(", {}", outbox.status()));
+        }
+        if self.hung_up {
+            description.push_str(", hung up");
+        }
+        description
+    }
+
+    /// Shows how getting back into the room is going, if we are.
+    fn show_status(&self) -> JsResult<()> {
+        let status = ui::element("reconnecting")?;
+
+        match &self.outbox {
+            Some(outbox) => {
+                status.set_text_content(Some(&outbox.status()));
+                status.remove_attribute("hidden")
+            }
+            None => status.set_attribute("hidden", ""),
+        }
+    }
+}
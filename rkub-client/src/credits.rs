@@ -0,0 +1,109 @@
+//! Who put down each tile on the board, for the teaching mode that outlines
+//! every tile in its player's color. Tiles keep whoever first put them down
+//! however they're moved around, so new players can see how the board came
+//! to be.
+
+use std::collections::BTreeMap;
+
+use rkub_common::{Coord, Piece};
+
+#[derive(Debug, Default)]
+pub struct Credits {
+    /// Who put down the tile in each cell, where we know.
+    by: BTreeMap<Coord, usize>,
+    /// The board as of the last update.
+    board: BTreeMap<Coord, Piece>,
+    /// Tiles taken off the board this turn, and who put them down, for when
+    /// they go back on.
+    lifted: Vec<(Piece, Option<usize>)>,
+}
+
+impl Credits {
+    /// Catches up with `board`, after `mover` changed it. Tiles that were
+    /// taken off this turn keep whoever first put them down, and anything
+    /// else new is the mover's. Who made a change isn't always known, like
+    /// catching up with the server, and then new tiles go to nobody.
+    pub fn update(&mut self, board: &BTreeMap<Coord, Piece>, mover: Option<usize>) {
+        for (coord, piece) in &self.board {
+            if board.get(coord) != Some(piece) {
+                self.lifted.push((*piece, self.by.remove(coord)));
+            }
+        }
+
+        for (coord, piece) in board {
+            if self.board.get(coord) == Some(piece) {
+                continue;
+            }
+
+            let by = match self.lifted.iter().position(|(p, _)| p == piece) {
+                Some(i) => self.lifted.remove(i).1,
+                None => mover,
+            };
+            match by {
+                Some(by) => self.by.insert(*coord, by),
+                None => self.by.remove(coord),
+            };
+        }
+
+        self.board = board.clone();
+    }
+
+    /// Whatever was taken off the board and not put back went into a hand.
+    pub fn end_turn(&mut self) {
+        self.lifted.clear();
+    }
+
+    /// Each tile we know the player of, and who that was.
+    pub fn cells(&self) -> Vec<(Coord, usize)> {
+        self.by.iter().map(|(coord, by)| (*coord, *by)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::Color;
+
+    fn board(tiles: &[(i32, u8)]) -> BTreeMap<Coord, Piece> {
+        tiles
+            .iter()
+            .map(|&(x, num)| (Coord(x, 0), Piece::new(Color::Red, num)))
+            .collect()
+    }
+
+    #[test]
+    fn moved_tiles_keep_whoever_put_them_down() {
+        let mut credits = Credits::default();
+        credits.update(&board(&[(0, 1), (1, 2), (2, 3)]), Some(0));
+        credits.end_turn();
+
+        // Bob picks up the 3, adds a 4, and later puts the 3 back elsewhere:
+        credits.update(&board(&[(0, 1), (1, 2), (5, 4)]), Some(1));
+        credits.update(&board(&[(0, 1), (1, 2), (5, 4), (6, 3)]), Some(1));
+        assert_eq!(
+            credits.cells(),
+            vec![
+                (Coord(0, 0), 0),
+                (Coord(1, 0), 0),
+                (Coord(5, 0), 1),
+                (Coord(6, 0), 0)
+            ]
+        );
+
+        // Once his turn is over, anything he kept is his to put down again:
+        credits.update(&board(&[(0, 1), (5, 4), (6, 3)]), Some(1));
+        credits.end_turn();
+        credits.update(&board(&[(0, 1), (1, 2), (5, 4), (6, 3)]), Some(1));
+        assert_eq!(credits.cells()[1], (Coord(1, 0), 1));
+    }
+
+    #[test]
+    fn tiles_from_nobody_in_particular_are_left_uncredited() {
+        let mut credits = Credits::default();
+        credits.update(&board(&[(0, 1)]), Some(0));
+        credits.update(&board(&[(0, 1), (1, 2)]), None);
+
+        assert_eq!(credits.cells(), vec![(Coord(0, 0), 0)]);
+    }
+}
@@ -0,0 +1,161 @@
+//! A panel for bug reports, hidden until the player presses the backquote
+//! key. It shows where the game thinks it is and the last messages that
+//! went back and forth with the server, and copies it all in one go as a
+//! [`BugReport`], so there's no digging through the browser's console.
+//! `rkub-server verify` reads the copy back to find where we fell out of
+//! step with the server.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{TimeZone, Utc};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Document, Element, HtmlInputElement, KeyboardEvent, MouseEvent};
+
+use rkub_common::{
+    BugReport, ClientMessage, Sequenced, ServerMessage, TimedMessage, Traced, REPORT_FORMAT,
+};
+
+use crate::callbacks::CallbackRegistry;
+use crate::ui;
+use crate::{set_event_cb, toasts, JsResult, STATE};
+
+/// How many messages are kept, oldest dropped first.
+const MAX_TRACED: usize = 50;
+
+/// How often the open panel catches up with the game.
+const REFRESH_MS: i32 = 1_000;
+
+const HOTKEY: &str = "`";
+
+#[derive(Default)]
+struct Tracer {
+    traced: VecDeque<TimedMessage>,
+    /// Keeps the panel up to date while it's open.
+    refresh: Option<CallbackRegistry>,
+}
+
+// Like `State`, only ever touched from the page's one thread:
+unsafe impl Send for Tracer {}
+
+lazy_static::lazy_static! {
+    static ref TRACER: Mutex<Tracer> = Mutex::new(Tracer::default());
+}
+
+/// Notes a message we sent, as it went over the socket.
+pub fn sent(msg: &Sequenced<ClientMessage>) {
+    trace(Traced::Sent(msg.clone()));
+}
+
+/// Notes a message from the server, as it came over the socket.
+pub fn received(msg: &Sequenced<ServerMessage>) {
+    trace(Traced::Received(msg.clone()));
+}
+
+fn trace(message: Traced) {
+    let mut tracer = TRACER.lock().unwrap();
+    if tracer.traced.len() == MAX_TRACED {
+        tracer.traced.pop_front();
+    }
+    tracer.traced.push_back(TimedMessage {
+        at_ms: now_ms(),
+        message,
+    });
+}
+
+fn now_ms() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+fn report() -> BugReport {
+    let info = STATE.lock().unwrap().debug_info();
+    let trace = TRACER.lock().unwrap().traced.iter().cloned().collect();
+
+    BugReport {
+        format: REPORT_FORMAT,
+        taken_ms: now_ms(),
+        info,
+        trace,
+    }
+}
+
+/// The report as the panel shows it, a message a line.
+fn text(report: &BugReport) -> String {
+    let mut lines = report.info.clone();
+    lines.push(String::new());
+
+    for TimedMessage { at_ms, message } in &report.trace {
+        let at = Utc.timestamp_millis(*at_ms as i64).format("%H:%M:%S%.3f");
+        let line = match message {
+            Traced::Sent(msg) => format!("{} → {}", at, serde_json::to_string(msg).unwrap()),
+            Traced::Received(msg) => format!("{} ← {}", at, serde_json::to_string(msg).unwrap()),
+        };
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+fn render() -> JsResult<()> {
+    ui::element("debug_report")?.set_text_content(Some(&text(&report())));
+    Ok(())
+}
+
+/// Shows the panel if it's hidden and hides it if it's shown.
+fn toggle() -> JsResult<()> {
+    let panel = ui::element("debug_panel")?;
+    let open = !panel.toggle_attribute("hidden")?;
+    if !open {
+        TRACER.lock().unwrap().refresh = None;
+        return Ok(());
+    }
+
+    render()?;
+    let mut refresh = CallbackRegistry::default();
+    refresh.every(REFRESH_MS, || {
+        let _ = render();
+    })?;
+    TRACER.lock().unwrap().refresh = Some(refresh);
+
+    Ok(())
+}
+
+/// Hooks up the hotkey and the copy button. The callbacks live for the
+/// whole session.
+pub fn init(doc: &Document, root: &Element) -> JsResult<()> {
+    set_event_cb(doc, "keydown", move |e: KeyboardEvent| {
+        // The key can still be typed into the lobby's fields:
+        let typing = e
+            .target()
+            .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+            .is_some();
+        if e.key() != HOTKEY || typing {
+            return Ok(());
+        }
+
+        e.prevent_default();
+        toggle()
+    })
+    .forget();
+
+    let copy = ui::find(root, "debug_copy")?;
+    set_event_cb(&copy, "click", move |e: MouseEvent| {
+        e.prevent_default();
+
+        let window = web_sys::window().unwrap();
+        let json = serde_json::to_string_pretty(&report()).unwrap();
+        let copying = window.navigator().clipboard().write_text(&json);
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = match JsFuture::from(copying).await {
+                Ok(_) => toasts::success("Copied the debug report"),
+                Err(_) => toasts::error("Couldn't copy the debug report"),
+            };
+        });
+
+        Ok(())
+    })
+    .forget();
+
+    Ok(())
+}
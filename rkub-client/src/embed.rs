@@ -0,0 +1,151 @@
+//! What the module offers pages that embed the game, so they can drive it
+//! from their own controls rather than only through the lobby's:
+//!
+//! ```js
+//! import init, { createRoom, onStateChange } from "./rkub_client.js";
+//!
+//! await init();
+//! onStateChange(state => console.log("the game is", state));
+//! createRoom("Alice");
+//! ```
+//!
+//! The game puts itself in the element with the id `rkub`, if the page has
+//! one. Otherwise it waits to be told where, with [`mount`].
+
+use std::sync::{Mutex, MutexGuard};
+
+use rkub_common::RoomOptions;
+use wasm_bindgen::prelude::*;
+
+use crate::settings;
+use crate::states::{State, StateError};
+use crate::ui::UiError;
+use crate::{JsResult, STATE};
+
+/// The page's callback for [`on_state_change`].
+struct Listener(js_sys::Function);
+
+// There's only the one thread:
+unsafe impl Send for Listener {}
+
+lazy_static::lazy_static! {
+    static ref LISTENER: Mutex<Option<Listener>> = Mutex::new(None);
+}
+
+/// Puts the game in the element with the id `root_id` and starts it, for
+/// pages that didn't have somewhere for it when the module loaded.
+#[wasm_bindgen]
+pub fn mount(root_id: &str) -> JsResult<()> {
+    if !matches!(*STATE.lock().unwrap(), State::Empty) {
+        return Err(UiError::AlreadyMounted.into());
+    }
+
+    crate::start(root_id)
+}
+
+/// Creates a room with the usual options and joins it as `name`, from the
+/// lobby.
+#[wasm_bindgen(js_name = createRoom)]
+pub fn create_room(name: String) -> JsResult<()> {
+    let mut state = in_lobby("createRoom")?;
+    check_name(&name)?;
+
+    settings::update(|s| s.name = name.clone())?;
+    state.on_create_start(name, None, RoomOptions::default())
+}
+
+/// Joins the room with the code `room` as `name`, from the lobby.
+#[wasm_bindgen(js_name = joinRoom)]
+pub fn join_room(name: String, room: String) -> JsResult<()> {
+    let mut state = in_lobby("joinRoom")?;
+    check_name(&name)?;
+    if room.is_empty() {
+        return Err(JsValue::from_str("Please enter a valid room ID"));
+    }
+
+    settings::update(|s| {
+        s.name = name.clone();
+        s.last_room = room.clone();
+    })?;
+    state.on_join_start(name, room, None)
+}
+
+/// Leaves the room for the lobby, whether the game's over or not.
+#[wasm_bindgen(js_name = leaveRoom)]
+pub fn leave_room() -> JsResult<()> {
+    let mut state = STATE.lock().unwrap();
+    match &*state {
+        State::Playing(_) => state.on_leave_game(),
+        State::GameOver(_) => state.on_leave_room(),
+        other => Err(not_now("leaveRoom", other)),
+    }
+}
+
+/// Calls `callback` with what the game's doing whenever that changes, and
+/// right away with what it's doing now. That's one of "lobby",
+/// "connecting", "playing" or "game_over". Takes the place of any callback
+/// from before.
+#[wasm_bindgen(js_name = onStateChange)]
+pub fn on_state_change(callback: js_sys::Function) -> JsResult<()> {
+    *LISTENER.lock().unwrap() = Some(Listener(callback));
+
+    let state = STATE.lock().unwrap();
+    if matches!(*state, State::Empty) {
+        return Ok(());
+    }
+    state_changed(&state)
+}
+
+/// Tells the page the game's now in `state`, if it asked to know.
+pub(crate) fn state_changed(state: &State) -> JsResult<()> {
+    let listener = LISTENER.lock().unwrap();
+    let callback = match &*listener {
+        Some(Listener(callback)) => callback,
+        None => return Ok(()),
+    };
+
+    let name = match state {
+        State::Empty => return Ok(()),
+        State::CreateOrJoin(_) => "lobby",
+        State::Connecting(_) => "connecting",
+        State::Playing(_) => "playing",
+        State::GameOver(_) => "game_over",
+    };
+
+    // Once we're done here, so the callback can call back in without
+    // finding the state locked:
+    web_sys::window()
+        .unwrap()
+        .set_timeout_with_callback_and_timeout_and_arguments_1(
+            callback,
+            0,
+            &JsValue::from_str(name),
+        )?;
+
+    Ok(())
+}
+
+/// The state, if it's the lobby that `event` starts from.
+fn in_lobby(event: &'static str) -> JsResult<MutexGuard<'static, State>> {
+    let state = STATE.lock().unwrap();
+    match &*state {
+        State::CreateOrJoin(_) => Ok(state),
+        other => Err(not_now(event, other)),
+    }
+}
+
+fn check_name(name: &str) -> JsResult<()> {
+    if name.is_empty() {
+        return Err(JsValue::from_str("Please enter a name"));
+    }
+
+    Ok(())
+}
+
+fn not_now(event: &'static str, state: &State) -> JsValue {
+    let e = StateError {
+        event,
+        state: state.name(),
+    };
+    JsValue::from_str(&e.to_string())
+}
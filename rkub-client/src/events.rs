@@ -0,0 +1,141 @@
+use chrono::{Local, TimeZone};
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element};
+
+use crate::ui::{self, UiError};
+use crate::JsResult;
+
+/// Older entries are dropped past this many.
+const MAX_EVENTS: u32 = 200;
+
+/// The game log in the sidebar, so players who looked away can catch up.
+/// Entries say how long ago they happened, by the server's clock where it
+/// said, with the time itself on hover.
+pub struct EventLog {
+    doc: Document,
+    list: Element,
+    /// How far the server's clock is ahead of ours, in milliseconds, as of
+    /// the last entry it timed.
+    offset_ms: i64,
+}
+
+impl EventLog {
+    pub fn new(doc: &Document, root: &Element) -> Result<Self, UiError> {
+        let list = ui::find(root, "event_log")?;
+
+        Ok(Self {
+            doc: doc.clone(),
+            list,
+            offset_ms: 0,
+        })
+    }
+
+    /// Adds a line to the bottom of the log for something that just
+    /// happened. `text` may contain player names so it's set as text, never
+    /// html.
+    pub fn push(&mut self, text: &str) -> JsResult<()> {
+        self.add(text, self.now_ms()).map(drop)
+    }
+
+    /// Like [`EventLog::push`], for something the server says happened at
+    /// `at_ms`, in milliseconds since the Unix epoch by its clock.
+    pub fn push_at(&mut self, text: &str, at_ms: u64) -> JsResult<()> {
+        self.offset_ms = at_ms as i64 - js_sys::Date::now() as i64;
+        self.add(text, at_ms).map(drop)
+    }
+
+    /// Like [`EventLog::push_at`], for something from before we were here,
+    /// set apart so it doesn't read as happening now.
+    pub fn push_past(&mut self, text: &str, at_ms: u64) -> JsResult<()> {
+        self.add(text, at_ms)?.set_class_name("past");
+        Ok(())
+    }
+
+    /// Brings every entry's "how long ago" up to date.
+    pub fn refresh(&self) -> JsResult<()> {
+        let now_ms = self.now_ms();
+        let times = self.list.query_selector_all("time[data-at]")?;
+
+        for i in 0..times.length() {
+            let time: Element = match times.item(i) {
+                Some(node) => node.unchecked_into(),
+                None => continue,
+            };
+            let at_ms = time
+                .get_attribute("data-at")
+                .and_then(|at| at.parse().ok())
+                .unwrap_or(now_ms);
+
+            let text = ago(now_ms.saturating_sub(at_ms));
+            if time.text_content().as_deref() != Some(&text) {
+                time.set_text_content(Some(&text));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add(&mut self, text: &str, at_ms: u64) -> JsResult<Element> {
+        let time = self.doc.create_element("time")?;
+        time.set_attribute("data-at", &at_ms.to_string())?;
+        if let Some(at) = Local.timestamp_millis_opt(at_ms as i64).single() {
+            time.set_attribute("datetime", &at.to_rfc3339())?;
+            time.set_attribute("title", &at.format("%H:%M:%S").to_string())?;
+        }
+        time.set_text_content(Some(&ago(self.now_ms().saturating_sub(at_ms))));
+
+        let message = self.doc.create_element("span")?;
+        message.set_text_content(Some(text));
+
+        let item = self.doc.create_element("li")?;
+        item.append_child(&time)?;
+        item.append_child(&message)?;
+        self.list.append_child(&item)?;
+
+        while self.list.child_element_count() > MAX_EVENTS {
+            if let Some(first) = self.list.first_element_child() {
+                first.remove();
+            }
+        }
+
+        // Keep the newest entry in view:
+        self.list.set_scroll_top(self.list.scroll_height());
+
+        Ok(item)
+    }
+
+    /// Now by the server's clock, as far as we know it.
+    fn now_ms(&self) -> u64 {
+        (js_sys::Date::now() as i64 + self.offset_ms).max(0) as u64
+    }
+}
+
+/// How long `ms` milliseconds is, said the way the log says it, e.g.
+/// "3 minutes ago".
+fn ago(ms: u64) -> String {
+    let mins = ms / 60_000;
+    let hours = mins / 60;
+
+    match (hours, mins) {
+        (0, 0) => "just now".to_string(),
+        (0, 1) => "a minute ago".to_string(),
+        (0, mins) => format!("{} minutes ago", mins),
+        (1, _) => "an hour ago".to_string(),
+        (hours, _) => format!("{} hours ago", hours),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_are_rounded_down_to_the_minute_or_hour() {
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(59_999), "just now");
+        assert_eq!(ago(60_000), "a minute ago");
+        assert_eq!(ago(2 * 60_000 + 30_000), "2 minutes ago");
+        assert_eq!(ago(90 * 60_000), "an hour ago");
+        assert_eq!(ago(5 * 60 * 60_000), "5 hours ago");
+    }
+}
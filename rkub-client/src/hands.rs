@@ -0,0 +1,52 @@
+//! The hand as we last arranged it, kept in localStorage so coming back to
+//! the room after a refresh or a dropped connection doesn't sort it back
+//! into color rows. Only the latest room's hand is kept.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::console_log;
+use rkub_common::{Coord, Piece};
+
+const STORAGE_KEY: &str = "rkub_hand";
+
+#[derive(Serialize, Deserialize)]
+struct SavedHand {
+    room: String,
+    /// Who we were in the room, which is what the server knows us by when we
+    /// come back.
+    name: String,
+    grid: BTreeMap<Coord, Piece>,
+}
+
+/// The hand we had in `room` as `name`, or nothing if we haven't been there.
+pub fn load(room: &str, name: &str) -> BTreeMap<Coord, Piece> {
+    let saved = web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten());
+
+    match saved.map(|json| serde_json::from_str::<SavedHand>(&json)) {
+        Some(Ok(hand)) if hand.room == room && hand.name == name => hand.grid,
+        Some(Err(e)) => {
+            console_log!("ignoring saved hand: {}", e);
+            BTreeMap::new()
+        }
+        _ => BTreeMap::new(),
+    }
+}
+
+/// Remembers `grid` as our hand in `room`. Failing to is only logged, since
+/// the hand can always be laid out again.
+pub fn save(room: &str, name: &str, grid: &BTreeMap<Coord, Piece>) {
+    let hand = SavedHand {
+        room: room.to_string(),
+        name: name.to_string(),
+        grid: grid.clone(),
+    };
+    let json = serde_json::to_string(&hand).unwrap();
+
+    let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+    if let Some(Err(e)) = storage.map(|storage| storage.set_item(STORAGE_KEY, &json)) {
+        console_log!("couldn't save the hand: {:?}", e);
+    }
+}
@@ -1,20 +1,39 @@
 #![allow(unused_unsafe)]
 #![allow(deprecated)]
+mod animation;
+mod announcement;
 mod board;
-mod states;
+mod callbacks;
+pub mod connection;
+mod credits;
+mod debug;
+pub mod embed;
+mod events;
+mod hands;
+mod outbox;
+pub mod practice;
+mod replay;
+mod settings;
+pub mod states;
 mod svg;
+mod theme;
+mod toasts;
+pub mod transport;
+pub mod ui;
 
 use chrono::Utc;
 
 use std::sync::Mutex;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{convert::FromWasmAbi, JsCast};
-use web_sys::EventTarget;
+use web_sys::{AudioContext, Document, Element, EventTarget, HtmlElement, Window};
 
 use crate::states::*;
 
 use rkub_common::ServerMessage;
 
+use crate::connection::ConnectionEvent;
+
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
@@ -72,7 +91,39 @@ fn timestamp() -> String {
     Utc::now().format("%T%.3f").to_string()
 }
 
+pub fn on_connection_event(event: ConnectionEvent) -> JsResult<()> {
+    match event {
+        ConnectionEvent::Opened => {
+            STATE.lock().unwrap().on_opened()?;
+            on_playing()
+        }
+        ConnectionEvent::Message(msg) => on_message(msg),
+        ConnectionEvent::Missed(count) => STATE.lock().unwrap().on_missed_messages(count),
+        ConnectionEvent::Closed => STATE.lock().unwrap().on_socket_closed(),
+        ConnectionEvent::Retry => STATE.lock().unwrap().reconnect(),
+        ConnectionEvent::Heartbeat => {
+            console_log!("Client: Ping");
+            STATE.lock().unwrap().send_ping()
+        }
+    }
+}
+
+/// Goes through the messages that came before we were playing, in order,
+/// now that we might be.
+fn on_playing() -> JsResult<()> {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    pending.into_iter().try_for_each(on_message)
+}
+
 fn on_message(msg: ServerMessage) -> JsResult<()> {
+    // The socket opening and the first messages on it can race, so those
+    // wait until there's a game for them:
+    if !STATE.lock().unwrap().is_in_game() {
+        console_log!("holding {:?} until we're playing", msg);
+        PENDING.lock().unwrap().push(msg);
+        return Ok(());
+    }
+
     match msg {
         ServerMessage::Pong => {
             console_log!("Server: Pong");
@@ -80,40 +131,82 @@ fn on_message(msg: ServerMessage) -> JsResult<()> {
         }
         ServerMessage::JoinedRoom {
             room_name,
-            players,
-            hand,
-            pieces_remaining,
-            board,
-        } => crate::STATE.lock().unwrap().on_joined_room(
-            room_name,
-            players,
-            hand,
-            pieces_remaining,
-            board,
-        ),
+            options,
+            last_seq,
+            snapshot,
+        } => {
+            let mut state = crate::STATE.lock().unwrap();
+
+            // Everyone agreed to a rematch and the server dealt again:
+            if state.is_game_over() {
+                state.on_rematch_started()?;
+            }
+
+            state.on_joined_room(room_name, options, last_seq, *snapshot)
+        }
         ServerMessage::TurnFinished {
             ending_player,
             ending_drew,
             next_player,
+            hand_sizes,
             pieces_remaining,
             board,
-        } => crate::STATE.lock().unwrap().on_turn_finished(
-            ending_player,
-            ending_drew,
-            next_player,
-            pieces_remaining,
-            board,
-        ),
-        ServerMessage::PlayerWon(name) => crate::STATE.lock().unwrap().on_player_won(name),
-        ServerMessage::CurrentPlayer(idx) => crate::STATE.lock().unwrap().on_current_player(idx),
+            state_hash,
+            thinking_ms,
+            pool,
+            turn,
+            at_ms,
+        } => {
+            let mut state = crate::STATE.lock().unwrap();
+            state.on_turn_finished(
+                ending_player,
+                ending_drew,
+                next_player,
+                hand_sizes,
+                pieces_remaining,
+                board,
+                thinking_ms,
+                pool,
+                turn,
+                at_ms,
+            )?;
+            state.on_turn_hash(state_hash)
+        }
+        ServerMessage::StateCheck(hash) => crate::STATE.lock().unwrap().on_state_check(hash),
+        ServerMessage::FullSync(snapshot) => crate::STATE.lock().unwrap().on_full_sync(*snapshot),
+        ServerMessage::PlayerWon {
+            winner,
+            hands,
+            at_ms,
+        } => crate::STATE
+            .lock()
+            .unwrap()
+            .on_player_won(winner, hands, at_ms),
+        ServerMessage::StartGame { order, at_ms } => {
+            crate::STATE.lock().unwrap().on_game_start(order, at_ms)
+        }
         ServerMessage::PlayerJoined(name) => crate::STATE.lock().unwrap().on_player_joined(name),
         ServerMessage::DrawPiece(piece) => crate::STATE.lock().unwrap().on_draw_piece(piece),
-        ServerMessage::Place(coord, piece) => {
-            crate::STATE.lock().unwrap().on_piece_place(coord, piece)
+        ServerMessage::HandSync(view) => {
+            crate::STATE.lock().unwrap().on_hand_sync(view.into_hand())
+        }
+        ServerMessage::Place(id, coord, piece) => crate::STATE
+            .lock()
+            .unwrap()
+            .on_piece_place(id, coord, piece),
+        ServerMessage::Pickup(id, coord, piece) => {
+            crate::STATE.lock().unwrap().on_pickup(id, coord, piece)
+        }
+        ServerMessage::Moves(id, moves) => crate::STATE.lock().unwrap().on_moves(id, moves),
+        ServerMessage::MovesRejected(seq, moves) => {
+            crate::STATE.lock().unwrap().on_moves_rejected(seq, moves)
         }
-        ServerMessage::Pickup(coord, piece) => crate::STATE.lock().unwrap().on_pickup(coord, piece),
         ServerMessage::InvalidBoardState => crate::STATE.lock().unwrap().on_invalid_board(),
-        ServerMessage::StartTurn => crate::STATE.lock().unwrap().on_turn_start(),
+        ServerMessage::OnlyRearranged => crate::STATE.lock().unwrap().on_only_rearranged(),
+        ServerMessage::KeptBoardPiece(piece) => {
+            crate::STATE.lock().unwrap().on_kept_board_piece(piece)
+        }
+        ServerMessage::StartTurn(turn) => crate::STATE.lock().unwrap().on_turn_start(turn),
         ServerMessage::EndTurnValid => crate::STATE.lock().unwrap().on_end_turn_valid(),
         ServerMessage::PlayerDisconnected(idx) => {
             crate::STATE.lock().unwrap().on_player_disconnected(idx)
@@ -121,6 +214,50 @@ fn on_message(msg: ServerMessage) -> JsResult<()> {
         ServerMessage::PlayerReconnected(idx) => {
             crate::STATE.lock().unwrap().on_player_reconnected(idx)
         }
+        ServerMessage::PlayerKicked(idx) => crate::STATE.lock().unwrap().on_player_kicked(idx),
+        ServerMessage::PlayerBecameBot(idx) => {
+            crate::STATE.lock().unwrap().on_player_became_bot(idx)
+        }
+        ServerMessage::HostChanged(idx) => crate::STATE.lock().unwrap().on_host_changed(idx),
+        ServerMessage::SkipVotes { votes, needed } => {
+            crate::STATE.lock().unwrap().on_skip_votes(votes, needed)
+        }
+        ServerMessage::SkipVoteTooEarly { wait_secs } => crate::STATE
+            .lock()
+            .unwrap()
+            .on_skip_vote_too_early(wait_secs),
+        ServerMessage::TurnSkipped(idx) => crate::STATE.lock().unwrap().on_turn_skipped(idx),
+        ServerMessage::Nudged(from) => crate::STATE.lock().unwrap().on_nudged(from),
+        ServerMessage::PlayerAway(idx) => crate::STATE.lock().unwrap().on_player_away(idx),
+        ServerMessage::PlayerBack(idx) => crate::STATE.lock().unwrap().on_player_back(idx),
+        ServerMessage::WaitingForReconnect { player, wait_secs } => crate::STATE
+            .lock()
+            .unwrap()
+            .on_waiting_for_reconnect(player, wait_secs),
+        ServerMessage::LoggedIn { name, token } => {
+            crate::STATE.lock().unwrap().on_logged_in(name, token)
+        }
+        ServerMessage::InRankedQueue { rating } => {
+            crate::STATE.lock().unwrap().on_in_ranked_queue(rating)
+        }
+        ServerMessage::PlayerStats { name, stats } => {
+            crate::STATE.lock().unwrap().on_player_stats(name, stats)
+        }
+        ServerMessage::GameSaved(id) => match crate::STATE.lock().unwrap().game_over() {
+            Some(game_over) => game_over.on_game_saved(id),
+            None => Ok(()),
+        },
+        ServerMessage::Replay { id, record } => match crate::STATE.lock().unwrap().game_over() {
+            Some(game_over) => game_over.on_replay(id, record),
+            None => Ok(()),
+        },
+        ServerMessage::Redirect { ws_url } => crate::STATE.lock().unwrap().on_redirect(ws_url),
+        ServerMessage::Announcement { text, severity } => {
+            announcement::show(&text, severity)?;
+            crate::STATE.lock().unwrap().on_announcement(text)
+        }
+        ServerMessage::History(past) => crate::STATE.lock().unwrap().on_history(past),
+        ServerMessage::Error(error) => crate::STATE.lock().unwrap().on_server_error(error),
         _ => {
             console_log!("unhandled message: {:?}", msg);
             Ok(())
@@ -130,6 +267,8 @@ fn on_message(msg: ServerMessage) -> JsResult<()> {
 
 lazy_static::lazy_static! {
     pub static ref STATE: Mutex<State> = Mutex::new(State::Empty);
+    /// Server messages that came before we were playing, oldest first.
+    static ref PENDING: Mutex<Vec<ServerMessage>> = Mutex::new(Vec::new());
 }
 
 #[wasm_bindgen(start)]
@@ -139,34 +278,65 @@ pub fn main() -> JsResult<()> {
 
     console_log!("Starting Application");
 
+    // Pages that put the game somewhere else start it when they're ready,
+    // see `embed::mount`:
+    let doc = web_sys::window().unwrap().document().unwrap();
+    if doc.get_element_by_id(ui::DEFAULT_ROOT).is_none() {
+        console_log!(
+            "no #{} on the page, waiting to be mounted",
+            ui::DEFAULT_ROOT
+        );
+        return Ok(());
+    }
+
+    start(ui::DEFAULT_ROOT)
+}
+
+/// Puts the game in the element `root_id` and shows the lobby, joining
+/// straight away if we came from a link to a room.
+fn start(root_id: &str) -> JsResult<()> {
     let window = web_sys::window().unwrap();
     let doc = window.document().unwrap();
 
-    let global = Global { window, doc };
-    let create_or_join = CreateOrJoin::new(global).unwrap();
-    *STATE.lock().unwrap() = State::CreateOrJoin(create_or_join);
+    let root = ui::mount(&doc, root_id)?;
+    set_up_page(&window, &doc, &root)?;
+
+    let global = Global { window, doc, root };
+    let create_or_join = CreateOrJoin::new(global)?;
+    let auto_join = create_or_join.auto_join();
+    {
+        let mut state = STATE.lock().unwrap();
+        *state = State::CreateOrJoin(create_or_join);
+        embed::state_changed(&state)?;
+    }
+
+    if auto_join {
+        // Go through the button so the inputs are checked as usual:
+        let join_button: HtmlElement = ui::element("join_room")?.dyn_into()?;
+        join_button.click();
+    }
 
     Ok(())
 }
 
-pub fn create_heartbeat() -> JsResult<()> {
-    console_log!("Creating Heartbeat");
-    let heartbeat = Closure::wrap(Box::new(|| {
-        console_log!("Client: Ping");
-        {
-            let mut lock = STATE.lock().unwrap();
-            lock.send_ping().unwrap();
-        }
-    }) as Box<dyn FnMut()>);
+/// Sets up what's on the page whatever state it's in: settings, toasts and
+/// the debug panel.
+pub fn set_up_page(window: &Window, doc: &Document, root: &Element) -> JsResult<()> {
+    settings::init(window, doc, root)?;
+    toasts::init()?;
+    announcement::init(root)?;
+    debug::init(doc, root)
+}
 
-    let window = web_sys::window().unwrap();
-    let _id = window.set_interval_with_callback_and_timeout_and_arguments_0(
-        heartbeat.as_ref().unchecked_ref(),
-        3_000,
-    )?;
+/// A short beep, used to tell the player it's their turn.
+pub fn play_chime() -> JsResult<()> {
+    let ctx = AudioContext::new()?;
+    let oscillator = ctx.create_oscillator()?;
 
-    console_log!("Forgetting Heartbeat");
-    heartbeat.forget();
+    oscillator.frequency().set_value(660.0);
+    oscillator.connect_with_audio_node(&ctx.destination())?;
+    oscillator.start()?;
+    oscillator.stop_with_when(ctx.current_time() + 0.15)?;
 
     Ok(())
 }
@@ -0,0 +1,88 @@
+//! What we send while the connection's down. It's kept in order until we're
+//! back in the room, then sent on as if nothing happened. Messages are only
+//! numbered as they go out, after the room's said what it last took from us,
+//! so none of them is turned down as already taken.
+
+use std::collections::VecDeque;
+
+use rkub_common::ClientMessage;
+
+/// How many times to try getting back in before giving up on the room.
+pub const MAX_ATTEMPTS: u32 = 6;
+
+/// The longest to wait between tries.
+const MAX_DELAY_MS: i32 = 30_000;
+
+/// Getting back into the room after the connection dropped.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    /// Tries so far, each waiting longer than the last.
+    pub attempts: u32,
+    queued: VecDeque<ClientMessage>,
+}
+
+impl Outbox {
+    /// Keeps `msg` to send once we're back. Pings only keep the connection
+    /// open, so there's no sending them late.
+    pub fn push(&mut self, msg: ClientMessage) {
+        if !matches!(msg, ClientMessage::Ping) {
+            self.queued.push_back(msg);
+        }
+    }
+
+    /// Everything kept, oldest first.
+    pub fn take(&mut self) -> VecDeque<ClientMessage> {
+        std::mem::take(&mut self.queued)
+    }
+
+    /// How long to wait before the next try: a second, doubling each time.
+    pub fn delay_ms(&self) -> i32 {
+        (1_000 << self.attempts.min(5)).min(MAX_DELAY_MS)
+    }
+
+    /// What to tell the player while we're at it.
+    pub fn status(&self) -> String {
+        match self.queued.len() {
+            0 => "Reconnecting…".to_string(),
+            1 => "Reconnecting — 1 move pending".to_string(),
+            n => format!("Reconnecting — {} moves pending", n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::{Color, Coord, Piece};
+
+    #[test]
+    fn moves_wait_in_order_and_pings_dont() {
+        let mut outbox = Outbox::default();
+        assert_eq!(outbox.status(), "Reconnecting…");
+
+        let piece = Piece::new(Color::Red, 3);
+        outbox.push(ClientMessage::Place(Coord(0, 0), piece));
+        outbox.push(ClientMessage::Ping);
+        outbox.push(ClientMessage::EndTurn);
+        assert_eq!(outbox.status(), "Reconnecting — 2 moves pending");
+
+        let queued = outbox.take();
+        assert!(matches!(queued[0], ClientMessage::Place(Coord(0, 0), p) if p == piece));
+        assert!(matches!(queued[1], ClientMessage::EndTurn));
+        assert_eq!(outbox.take().len(), 0);
+    }
+
+    #[test]
+    fn tries_back_off() {
+        let mut outbox = Outbox::default();
+        let delays: Vec<i32> = (0..MAX_ATTEMPTS)
+            .map(|attempt| {
+                outbox.attempts = attempt;
+                outbox.delay_ms()
+            })
+            .collect();
+
+        assert_eq!(delays, vec![1_000, 2_000, 4_000, 8_000, 16_000, 30_000]);
+    }
+}
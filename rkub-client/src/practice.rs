@@ -0,0 +1,410 @@
+//! A room of our own against bots, played without a server. It takes the
+//! `ClientMessage`s the game would send over the websocket and answers with
+//! the `ServerMessage`s a server's room would, playing by the same rules and
+//! with the same bot as the command line client, so the rest of the game
+//! can't tell the difference. It's the [`Peer`] on the other end of a
+//! [`MemoryTransport`](crate::transport::MemoryTransport).
+
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use rkub_common::{
+    bot, pool_counts, reduce, scores, state_hash, Action, ClientMessage, Event, GameState, MoveId,
+    Phase, Piece, PlayerStats, Rejection, RoomOptions, RoomSnapshot, Sequenced, ServerError,
+    ServerMessage,
+};
+
+use crate::transport::Peer;
+
+/// The most bots a practice room seats.
+pub const MAX_BOTS: usize = 3;
+/// How long bots take over their turns, so there's time to see them.
+const BOT_TURN_MS: u64 = 1_200;
+/// Our seat. Bots sit after us.
+const ME: usize = 0;
+
+/// What the room's called on screen. It's never shared, so it needn't be
+/// unique.
+pub const ROOM_NAME: &str = "Practice";
+
+#[derive(Debug)]
+pub struct PracticeRoom {
+    state: GameState,
+    players: Vec<String>,
+    options: RoomOptions,
+    /// Where each game's pool is shuffled from.
+    rng: StdRng,
+    dealt: bool,
+    /// The number of the turn being played, counted like a server's rooms.
+    turn: u64,
+    /// The `seq` of the last message each seat played, ours from what we
+    /// sent and the bots' counted here.
+    last_seq: Vec<u64>,
+    /// How long each seat has spent on their turns this game, and when the
+    /// current turn started, in milliseconds.
+    thinking_ms: Vec<u64>,
+    turn_started_ms: u64,
+    /// When the bot whose turn it is plays it.
+    bot_turn_at: Option<u64>,
+    /// The time as of the last message or tick, in milliseconds.
+    now_ms: u64,
+    /// What's waiting to go to us, oldest first.
+    outgoing: VecDeque<ServerMessage>,
+}
+
+impl PracticeRoom {
+    /// A room for `bots` bots and whoever creates it. The first game's pool
+    /// is shuffled from `seed`, and every game after from the one before.
+    pub fn new(bots: usize, seed: u64) -> Self {
+        let bots = bots.clamp(1, MAX_BOTS);
+        PracticeRoom {
+            state: GameState::default(),
+            players: (1..=bots).map(|i| format!("Bot {}", i)).collect(),
+            options: RoomOptions::default(),
+            rng: StdRng::seed_from_u64(seed),
+            dealt: false,
+            turn: 0,
+            last_seq: vec![0; bots + 1],
+            thinking_ms: vec![0; bots + 1],
+            turn_started_ms: 0,
+            bot_turn_at: None,
+            now_ms: 0,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    fn send(&mut self, msg: ServerMessage) {
+        self.outgoing.push_back(msg);
+    }
+
+    /// Deals everyone a fresh hand, and starts the turn of whoever draws the
+    /// highest piece.
+    fn start_game(&mut self) {
+        self.dealt = true;
+        self.turn = 1;
+        self.thinking_ms = vec![0; self.players.len()];
+        self.turn_started_ms = self.now_ms;
+
+        let seed = self.rng.gen();
+        self.state
+            .reset_for_new_round(seed, &vec![true; self.players.len()]);
+        self.state.vertical_groups = self.options.vertical_groups;
+
+        let seats: Vec<usize> = (0..self.players.len()).collect();
+        let first = self.state.highest_draw(&seats).unwrap_or(ME);
+        self.state.start_turn(first);
+
+        self.send(self.joined_room());
+        let order = (0..seats.len())
+            .map(|i| (first + i) % seats.len())
+            .collect();
+        self.send(ServerMessage::StartGame {
+            order,
+            at_ms: self.now_ms,
+        });
+        self.start_turn(first);
+    }
+
+    /// Tells us it's our turn, or has the bot whose turn it is play it in a
+    /// while.
+    fn start_turn(&mut self, idx: usize) {
+        if idx == ME {
+            self.send(ServerMessage::StartTurn(self.turn));
+        } else {
+            self.bot_turn_at = Some(self.now_ms + BOT_TURN_MS);
+        }
+    }
+
+    /// Plays the active bot's turn: the first group it can lay down, if
+    /// there is one, and otherwise a draw.
+    fn play_bot(&mut self) {
+        let idx = self.state.active_player;
+        if idx == ME || self.state.winner.is_some() {
+            return;
+        }
+
+        let moves = bot::plan_turn(&self.state, idx);
+        if !moves.is_empty() {
+            self.play(idx, Action::Moves(moves));
+        }
+        self.play(idx, Action::EndTurn);
+    }
+
+    /// Runs `idx`'s `action` through the rules, and tells us what came of
+    /// it, the way a server's room does.
+    fn play(&mut self, idx: usize, action: Action) {
+        let (state, events) = match reduce(&self.state, idx, &action) {
+            Ok(played) => played,
+            Err(rejection) => {
+                if idx == ME {
+                    self.reject(rejection, action);
+                }
+                return;
+            }
+        };
+        self.state = state;
+
+        if idx != ME {
+            self.last_seq[idx] += 1;
+        }
+        let id = MoveId {
+            seat: idx,
+            seq: self.last_seq[idx],
+        };
+        for event in events {
+            match event {
+                Event::Place(coord, piece) => self.send(ServerMessage::Place(id, coord, piece)),
+                Event::Pickup(coord, piece) => self.send(ServerMessage::Pickup(id, coord, piece)),
+                Event::Moves(moves) => self.send(ServerMessage::Moves(id, moves)),
+                Event::Drew(piece) if idx == ME => self.send(ServerMessage::DrawPiece(piece)),
+                Event::Drew(_) => {}
+                Event::Won(winner) => {
+                    self.bot_turn_at = None;
+                    self.send(ServerMessage::PlayerWon {
+                        winner: self.players[winner].clone(),
+                        hands: self.state.hands.clone(),
+                        at_ms: self.now_ms,
+                    });
+                }
+                Event::TurnEnded { drew } => {
+                    if idx == ME {
+                        self.send(ServerMessage::EndTurnValid);
+                    }
+                    self.pass_turn(idx, drew);
+                }
+            }
+        }
+    }
+
+    /// Tells us why `action` was turned down.
+    fn reject(&mut self, rejection: Rejection, action: Action) {
+        match rejection {
+            Rejection::InvalidBoard => self.send(ServerMessage::InvalidBoardState),
+            Rejection::OnlyRearranged => self.send(ServerMessage::OnlyRearranged),
+            Rejection::KeptBoardPiece(piece) => self.send(ServerMessage::KeptBoardPiece(piece)),
+            Rejection::InvalidMove(bad) | Rejection::OffBoard(bad) => {
+                let moves = match action {
+                    Action::Moves(moves) => moves,
+                    _ => vec![bad],
+                };
+                self.send(ServerMessage::MovesRejected(self.last_seq[ME], moves));
+
+                if let Rejection::OffBoard(bad) = rejection {
+                    self.send(ServerMessage::Error(ServerError::OffBoard(bad)));
+                }
+            }
+            Rejection::NotYourTurn | Rejection::GameOver => {}
+        }
+    }
+
+    /// Hands the turn on from `ending`, who drew if `drew`, to the next seat.
+    fn pass_turn(&mut self, ending: usize, drew: bool) {
+        self.thinking_ms[ending] += self.now_ms.saturating_sub(self.turn_started_ms);
+        self.turn_started_ms = self.now_ms;
+
+        let next = (ending + 1) % self.players.len();
+        self.turn += 1;
+        self.state.start_turn(next);
+        self.start_turn(next);
+
+        if ending == ME {
+            self.send(ServerMessage::HandSync(self.state.private_view(ME)));
+        }
+        self.send(ServerMessage::TurnFinished {
+            ending_player: self.players[ending].clone(),
+            ending_drew: drew,
+            next_player: next,
+            hand_sizes: self.hand_sizes(),
+            pieces_remaining: self.state.game.remaining_pieces().len(),
+            board: self.state.game.board().clone(),
+            state_hash: state_hash(self.state.game.board(), &self.hand_sizes()),
+            thinking_ms: self.thinking_ms.clone(),
+            pool: self.pool(),
+            turn: self.turn,
+            at_ms: self.now_ms,
+        });
+    }
+
+    fn joined_room(&self) -> ServerMessage {
+        ServerMessage::JoinedRoom {
+            room_name: ROOM_NAME.to_string(),
+            options: self.options.clone(),
+            last_seq: self.last_seq[ME],
+            snapshot: Box::new(self.snapshot()),
+        }
+    }
+
+    fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            players: self.players.clone(),
+            hand_sizes: self.hand_sizes(),
+            host: ME,
+            view: self.state.private_view(ME),
+            board: self.state.game.board().clone(),
+            board_size: self.state.board_size,
+            pieces_remaining: self.state.game.remaining_pieces().len(),
+            dealt: self.dealt,
+            active_player: self.state.active_player,
+            phase: if self.state.winner.is_some() {
+                Phase::Over
+            } else if !self.dealt {
+                Phase::Waiting
+            } else {
+                Phase::Playing
+            },
+            scores: self
+                .state
+                .winner
+                .map(|winner| scores(&self.state.hands, winner)),
+            // The bots are who we came to play, not stand-ins for anyone:
+            disconnected: Vec::new(),
+            bots: Vec::new(),
+            away: Vec::new(),
+            kicked: Vec::new(),
+            thinking_ms: self.thinking_ms.clone(),
+            turn_ms: self.now_ms.saturating_sub(self.turn_started_ms),
+            pool: self.pool(),
+            turn: self.turn,
+        }
+    }
+
+    fn hand_sizes(&self) -> Vec<usize> {
+        self.state.hands.iter().map(Vec::len).collect()
+    }
+
+    /// What's left to draw, if we asked to see it.
+    fn pool(&self) -> Option<Vec<(Piece, usize)>> {
+        if !self.options.show_pool {
+            return None;
+        }
+
+        Some(pool_counts(self.state.game.remaining_pieces()))
+    }
+}
+
+impl Peer for PracticeRoom {
+    /// Takes a message from us at `now_ms`. Whatever the room says back
+    /// waits for the next tick.
+    fn receive(&mut self, msg: Sequenced<ClientMessage>, now_ms: u64) {
+        self.now_ms = now_ms;
+        if msg.seq <= self.last_seq[ME] {
+            return;
+        }
+        self.last_seq[ME] = msg.seq;
+
+        match msg.msg {
+            ClientMessage::CreateRoom(name, _, options) if !self.dealt => {
+                self.players.insert(ME, name);
+                self.options = options;
+                self.start_game();
+            }
+            ClientMessage::Place(coord, piece) => self.play(ME, Action::Place(coord, piece)),
+            ClientMessage::Pickup(coord, piece) => self.play(ME, Action::Pickup(coord, piece)),
+            ClientMessage::Moves(moves) => self.play(ME, Action::Moves(moves)),
+            ClientMessage::EndTurn => self.play(ME, Action::EndTurn),
+            ClientMessage::Rematch if self.state.winner.is_some() => self.start_game(),
+            ClientMessage::RequestSync if self.dealt => {
+                self.send(ServerMessage::FullSync(Box::new(self.snapshot())))
+            }
+            ClientMessage::Stats(name) => self.send(ServerMessage::PlayerStats {
+                name,
+                stats: PlayerStats::default(),
+            }),
+            ClientMessage::Ping => self.send(ServerMessage::Pong),
+            // There's nobody to kick, vote out, hurry along or step away
+            // from, and nothing kept to fetch or log in to:
+            _ => {}
+        }
+    }
+
+    /// Plays the bot whose turn it is, once it's had long enough, and
+    /// returns everything waiting to go to us as of `now_ms`.
+    fn tick(&mut self, now_ms: u64) -> Vec<ServerMessage> {
+        self.now_ms = now_ms;
+        if let Some(at) = self.bot_turn_at {
+            if now_ms >= at {
+                self.bot_turn_at = None;
+                self.play_bot();
+            }
+        }
+
+        self.outgoing.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(room: &mut PracticeRoom) {
+        let msg = ClientMessage::CreateRoom("Alice".into(), None, RoomOptions::default());
+        room.receive(Sequenced { seq: 1, msg }, 0);
+    }
+
+    /// Plays on until it's our turn, returning everything sent meanwhile.
+    fn until_our_turn(room: &mut PracticeRoom) -> Vec<ServerMessage> {
+        let mut sent = Vec::new();
+        for now in (0..).step_by(BOT_TURN_MS as usize).take(10) {
+            sent.extend(room.tick(now));
+            if sent
+                .iter()
+                .any(|m| matches!(m, ServerMessage::StartTurn(_)))
+            {
+                return sent;
+            }
+        }
+        panic!("it never came back to us: {:?}", sent);
+    }
+
+    #[test]
+    fn games_start_straight_away_and_come_round_to_us() {
+        let mut room = PracticeRoom::new(2, 7);
+        create(&mut room);
+
+        let sent = until_our_turn(&mut room);
+        match &sent[0] {
+            ServerMessage::JoinedRoom { snapshot, .. } => {
+                assert_eq!(snapshot.players, &["Alice", "Bot 1", "Bot 2"]);
+                assert_eq!(snapshot.view.hand().len(), rkub_common::HAND_SIZE);
+            }
+            msg => panic!("expected JoinedRoom, got {:?}", msg),
+        }
+        assert!(matches!(sent[1], ServerMessage::StartGame { .. }));
+
+        // Each bot's turn is told like anyone else's, and ours is numbered
+        // on from theirs:
+        let finished = sent
+            .iter()
+            .filter(|m| matches!(m, ServerMessage::TurnFinished { .. }))
+            .count() as u64;
+        assert!(sent.contains(&ServerMessage::StartTurn(1 + finished)));
+    }
+
+    #[test]
+    fn our_turns_are_played_by_the_rules() {
+        let mut room = PracticeRoom::new(1, 7);
+        create(&mut room);
+        until_our_turn(&mut room);
+
+        let end = ClientMessage::EndTurn;
+        room.receive(Sequenced { seq: 2, msg: end }, 0);
+        let sent = room.tick(0);
+        assert!(matches!(sent[0], ServerMessage::DrawPiece(_)));
+        assert_eq!(sent[1], ServerMessage::EndTurnValid);
+        assert!(matches!(
+            sent.last(),
+            Some(ServerMessage::TurnFinished {
+                ending_drew: true,
+                next_player: 1,
+                ..
+            })
+        ));
+
+        // Out of turn, it's turned down quietly like a server would:
+        let end = ClientMessage::EndTurn;
+        room.receive(Sequenced { seq: 3, msg: end }, 0);
+        assert!(room.tick(0).is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Stepping through a finished game, kept by the server or opened from a
+//! file, an event or a turn at a time.
+
+use rkub_common::{GameEvent, GameRecord, GameState};
+
+pub struct Replayer {
+    players: Vec<String>,
+    /// The game after every event, from before the first.
+    frames: Vec<GameState>,
+    /// The frames each turn starts at, in order.
+    turn_starts: Vec<usize>,
+    /// When each frame came about, in milliseconds since the game started.
+    times: Vec<u64>,
+    at: usize,
+}
+
+impl Replayer {
+    pub fn new(record: &GameRecord) -> Self {
+        let mut frames = vec![GameState::new_with_seed(record.seed)];
+        let mut turn_starts = Vec::new();
+        let mut times = vec![0];
+
+        for ((event, state), timed) in record.replay().zip(&record.events) {
+            frames.push(state);
+            times.push(timed.at_ms);
+
+            // The first game's first turn starts once everyone's sat down,
+            // without an event of its own:
+            let first_turn = turn_starts.is_empty()
+                && !matches!(event, GameEvent::Seated { .. } | GameEvent::TurnStarted(_));
+            if first_turn {
+                turn_starts.push(frames.len() - 2);
+            }
+            if let GameEvent::TurnStarted(_) = event {
+                turn_starts.push(frames.len() - 1);
+            }
+        }
+
+        let at = turn_starts.first().copied().unwrap_or(0);
+        Replayer {
+            players: record.players.clone(),
+            frames,
+            turn_starts,
+            times,
+            at,
+        }
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    pub fn frame(&self) -> &GameState {
+        &self.frames[self.at]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.at + 1 >= self.frames.len()
+    }
+
+    /// Moves on one event. Returns false if there are none left.
+    pub fn step(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        self.at += 1;
+        true
+    }
+
+    /// How long the game went before the next event, in milliseconds, or
+    /// None if there are none left.
+    pub fn pause_ms(&self) -> Option<u64> {
+        let next = self.times.get(self.at + 1)?;
+        Some(next.saturating_sub(self.times[self.at]))
+    }
+
+    /// Moves on to the start of the next turn, or the end of the game.
+    pub fn next_turn(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        self.at = self
+            .turn_starts
+            .iter()
+            .copied()
+            .find(|&start| start > self.at)
+            .unwrap_or(self.frames.len() - 1);
+        true
+    }
+
+    /// Goes to the last event, as the game finished.
+    pub fn finish(&mut self) {
+        self.at = self.frames.len() - 1;
+    }
+
+    /// Where the replay is up to, e.g. "Turn 3 of 20: alice to play".
+    pub fn describe(&self) -> String {
+        let frame = self.frame();
+        if let Some(winner) = frame.winner {
+            return format!("{} won", self.players[winner]);
+        }
+
+        let turn = self.turn_starts.iter().filter(|&&s| s <= self.at).count();
+        format!(
+            "Turn {} of {}: {} to play",
+            turn.max(1),
+            self.turn_starts.len(),
+            self.players
+                .get(frame.active_player)
+                .map_or("nobody", String::as_str)
+        )
+    }
+}
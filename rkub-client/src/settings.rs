@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, Event, HtmlInputElement, HtmlSelectElement, MouseEvent, Window};
+
+use crate::theme::{self, TileTheme};
+use crate::ui;
+use crate::{console_log, set_event_cb, JsResult, STATE};
+
+const STORAGE_KEY: &str = "rkub_settings";
+
+/// Player preferences, kept in localStorage between visits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Filled into the name box when creating or joining a room.
+    pub name: String,
+    /// Play a chime when it becomes our turn.
+    pub sound: bool,
+    pub theme: Theme,
+    /// Only read, from before there were themes, to keep high contrast for
+    /// whoever had it on.
+    #[serde(skip_serializing)]
+    high_contrast: bool,
+    /// Draw a shape for each color on the tiles.
+    pub glyphs: bool,
+    pub animation_speed: AnimationSpeed,
+    /// Keep the hand sorted as pieces are drawn.
+    pub auto_sort: bool,
+    /// Outline each tile on the board in the color of whoever put it down,
+    /// for learning how the board came together.
+    pub who_played: bool,
+    /// The room we were last in, offered again on the join form.
+    pub last_room: String,
+    /// The account we last logged in to, and the token that logs back in to
+    /// it without the passphrase.
+    pub account: String,
+    pub token: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            sound: true,
+            theme: Theme::Classic,
+            high_contrast: false,
+            glyphs: false,
+            animation_speed: AnimationSpeed::Normal,
+            auto_sort: false,
+            who_played: false,
+            last_room: String::new(),
+            account: String::new(),
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationSpeed {
+    Off,
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl AnimationSpeed {
+    const ALL: [AnimationSpeed; 4] = [
+        AnimationSpeed::Off,
+        AnimationSpeed::Slow,
+        AnimationSpeed::Normal,
+        AnimationSpeed::Fast,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            AnimationSpeed::Off => "off",
+            AnimationSpeed::Slow => "slow",
+            AnimationSpeed::Normal => "normal",
+            AnimationSpeed::Fast => "fast",
+        }
+    }
+
+    /// How long a tile takes to slide to its new spot.
+    pub fn millis(self) -> f64 {
+        match self {
+            AnimationSpeed::Off => 0.0,
+            AnimationSpeed::Slow => 600.0,
+            AnimationSpeed::Normal => 300.0,
+            AnimationSpeed::Fast => 150.0,
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|speed| speed.as_str() == s)
+            .unwrap_or(AnimationSpeed::Normal)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Classic,
+    Flat,
+    HighContrast,
+}
+
+impl Theme {
+    const ALL: [Theme; 3] = [Theme::Classic, Theme::Flat, Theme::HighContrast];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Flat => "flat",
+            Theme::HighContrast => "high_contrast",
+        }
+    }
+
+    /// What draws the tiles.
+    pub fn tiles(self) -> &'static dyn TileTheme {
+        match self {
+            Theme::Classic => &theme::Classic,
+            Theme::Flat => &theme::Flat,
+            Theme::HighContrast => &theme::HighContrast,
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.as_str() == s)
+            .unwrap_or(Theme::Classic)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: Mutex<Settings> = Mutex::new(Settings::default());
+}
+
+/// The current settings.
+pub fn get() -> Settings {
+    SETTINGS.lock().unwrap().clone()
+}
+
+/// Changes the settings outside of the panel, saving them and keeping the
+/// panel in sync.
+pub fn update<F: FnOnce(&mut Settings)>(f: F) -> JsResult<()> {
+    let window = web_sys::window().unwrap();
+
+    let mut settings = SETTINGS.lock().unwrap();
+    f(&mut settings);
+    settings.fill_panel(&ui::root()?)?;
+    settings.save(&window)
+}
+
+impl Settings {
+    pub fn load(window: &Window) -> Settings {
+        let saved = window
+            .local_storage()
+            .ok()
+            .flatten()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten());
+
+        match saved.map(|json| serde_json::from_str::<Settings>(&json)) {
+            Some(Ok(mut settings)) => {
+                if std::mem::take(&mut settings.high_contrast) {
+                    settings.theme = Theme::HighContrast;
+                }
+                settings
+            }
+            Some(Err(e)) => {
+                console_log!("ignoring saved settings: {}", e);
+                Settings::default()
+            }
+            None => Settings::default(),
+        }
+    }
+
+    pub fn save(&self, window: &Window) -> JsResult<()> {
+        let json = serde_json::to_string(self).unwrap();
+
+        match window.local_storage()? {
+            Some(storage) => storage.set_item(STORAGE_KEY, &json),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies the display settings as classes on the body for the
+    /// stylesheet to pick up.
+    fn apply(&self, doc: &Document) -> JsResult<()> {
+        let classes = doc.body().unwrap().class_list();
+
+        classes.toggle_with_force("glyphs", self.glyphs)?;
+
+        // The tiles draw themselves, this is for the rest of the page:
+        for theme in Theme::ALL.iter() {
+            classes.toggle_with_force(theme.as_str(), *theme == self.theme)?;
+        }
+
+        for speed in AnimationSpeed::ALL.iter() {
+            let class = format!("animation_{}", speed.as_str());
+            classes.toggle_with_force(&class, *speed == self.animation_speed)?;
+        }
+
+        Ok(())
+    }
+
+    fn fill_panel(&self, root: &Element) -> JsResult<()> {
+        input(root, "settings_name")?.set_value(&self.name);
+        input(root, "settings_sound")?.set_checked(self.sound);
+        select(root, "settings_theme")?.set_value(self.theme.as_str());
+        input(root, "settings_glyphs")?.set_checked(self.glyphs);
+        input(root, "settings_auto_sort")?.set_checked(self.auto_sort);
+        input(root, "settings_who_played")?.set_checked(self.who_played);
+        select(root, "settings_animation")?.set_value(self.animation_speed.as_str());
+
+        Ok(())
+    }
+
+    /// The settings chosen in the panel, keeping whatever the panel doesn't
+    /// show from `self`.
+    fn read_panel(&self, root: &Element) -> JsResult<Settings> {
+        Ok(Settings {
+            name: input(root, "settings_name")?.value(),
+            sound: input(root, "settings_sound")?.checked(),
+            theme: Theme::from_str(&select(root, "settings_theme")?.value()),
+            high_contrast: false,
+            glyphs: input(root, "settings_glyphs")?.checked(),
+            animation_speed: AnimationSpeed::from_str(&select(root, "settings_animation")?.value()),
+            auto_sort: input(root, "settings_auto_sort")?.checked(),
+            who_played: input(root, "settings_who_played")?.checked(),
+            last_room: self.last_room.clone(),
+            account: self.account.clone(),
+            token: self.token.clone(),
+        })
+    }
+}
+
+fn input(root: &Element, id: &str) -> JsResult<HtmlInputElement> {
+    Ok(ui::find(root, id)?.dyn_into()?)
+}
+
+fn select(root: &Element, id: &str) -> JsResult<HtmlSelectElement> {
+    Ok(ui::find(root, id)?.dyn_into()?)
+}
+
+/// Loads the saved settings, applies them to the page and hooks up the
+/// settings panel. The callbacks live for the whole session.
+pub fn init(window: &Window, doc: &Document, root: &Element) -> JsResult<()> {
+    let settings = Settings::load(window);
+    settings.fill_panel(root)?;
+    settings.apply(doc)?;
+
+    *SETTINGS.lock().unwrap() = settings;
+
+    let toggle = ui::find(root, "settings_toggle")?;
+    set_event_cb(&toggle, "click", move |e: MouseEvent| {
+        e.prevent_default();
+
+        let panel = ui::element("settings_panel")?;
+        let open = !panel.toggle_attribute("hidden")?;
+
+        ui::element("settings_toggle")?.set_attribute("aria-expanded", &open.to_string())
+    })
+    .forget();
+
+    // Every input in the panel bubbles its changes up to here:
+    let panel = ui::find(root, "settings_panel")?;
+    set_event_cb(&panel, "change", move |_e: Event| {
+        let window = web_sys::window().unwrap();
+        let doc = window.document().unwrap();
+
+        let mut current = SETTINGS.lock().unwrap();
+        let settings = current.read_panel(&ui::root()?)?;
+        settings.apply(&doc)?;
+        settings.save(&window)?;
+
+        let retheme = settings.theme != current.theme;
+        let recredit = settings.who_played != current.who_played;
+        *current = settings;
+        drop(current);
+
+        // Tiles already drawn keep the old look until they're drawn again:
+        if retheme {
+            STATE.lock().unwrap().rerender()?;
+        }
+        if recredit {
+            STATE.lock().unwrap().update_credits()?;
+        }
+
+        Ok(())
+    })
+    .forget();
+
+    Ok(())
+}
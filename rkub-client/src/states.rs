@@ -1,15 +1,30 @@
-use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, VecDeque};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{
-    Document, Element, Event, HtmlInputElement, MessageEvent, MouseEvent, PointerEvent, WebSocket,
-    Window,
+    Blob, BlobPropertyBag, Document, Element, Event, FileReader, HtmlAnchorElement,
+    HtmlButtonElement, HtmlElement, HtmlInputElement, HtmlSelectElement, KeyboardEvent, MouseEvent,
+    PointerEvent, ProgressEvent, ResizeObserver, Url, UrlSearchParams, WheelEvent, Window,
 };
 
-use crate::board::Board;
+use crate::board::{Board, PLAYER_COLORS};
+use crate::callbacks::CallbackRegistry;
+use crate::connection::Connection;
+use crate::credits::Credits;
+use crate::events::EventLog;
+use crate::hands;
+use crate::replay::Replayer;
+use crate::settings;
+use crate::toasts;
+use crate::ui::{self, UiError};
 use crate::STATE;
-use crate::{console_log, set_event_cb};
-use rkub_common::{ClientMessage, Coord, Game, Piece, ServerMessage};
+use crate::{build_cb, console_log, set_event_cb};
+use rkub_common::{
+    board_diff, points, reduce, scores, state_hash, tidy_board, Action, BoardSize, ClientMessage,
+    Color, Coord, Game, GameRecord, GameState, Move, MoveId, PastMessage, Phase, Piece,
+    PlayerStats, Rejection, RoomOptions, RoomSnapshot, ServerError, ServerMessage, OPENING_POINTS,
+};
 
 type JsResult<T> = Result<T, JsValue>;
 type JsError = Result<(), JsValue>;
@@ -20,10 +35,15 @@ macro_rules! methods {
        $(,)?) =>
     {
         $($(
+        // Takes whatever the method it forwards to does:
+        #[allow(clippy::too_many_arguments)]
         pub fn $name(&mut self, $($var: $type),* ) -> JsError {
             match self {
                 State::$sub(s) => s.$name($($var),*),
-                _ => panic!("Invalid state transition"),
+                // The finished game stays on screen behind the standings, so
+                // it keeps taking updates:
+                State::GameOver(s) => s.playing.$name($($var),*),
+                _ => self.ignore(stringify!($name)),
             }
         }
         )+)+
@@ -40,90 +60,175 @@ macro_rules! transitions {
             let s = std::mem::replace(self, State::Empty);
             match s {
                 State::$sub(s) => *self = State::$into(s.$name($($var),*)?),
-                _ => panic!("Invalid state"),
+                other => {
+                    *self = other;
+                    return self.ignore(stringify!($name));
+                }
             }
-            Ok(())
+            crate::embed::state_changed(self)
         }
         )+)+
     }
 }
 
+/// Something that only makes sense in another state, like a game message
+/// before we're playing or a click on a board that isn't up yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateError {
+    pub event: &'static str,
+    pub state: &'static str,
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} doesn't apply while {}", self.event, self.state)
+    }
+}
+
 #[derive(Debug)]
 pub struct Global {
     pub doc: Document,
     pub window: Window,
+    /// What the game was mounted in. Everything it shows is under here.
+    pub root: Element,
+}
+
+impl Global {
+    /// The game's element with the id `id`.
+    pub fn element(&self, id: &str) -> Result<Element, UiError> {
+        ui::find(&self.root, id)
+    }
 }
 
 #[derive(Debug)]
 pub struct CreateOrJoin {
     global: Global,
-    join_cb: JsClosure<MouseEvent>,
-    create_cb: JsClosure<MouseEvent>,
+    _callbacks: CallbackRegistry,
+    auto_join: bool,
 }
 
 impl CreateOrJoin {
     pub fn new(global: Global) -> JsResult<CreateOrJoin> {
-        let doc: &Document = &global.doc;
-
-        let html = doc.get_element_by_id("create_or_join").unwrap();
+        let html = global.element("create_or_join")?;
         html.toggle_attribute("hidden")?;
 
-        let join_button = doc.get_element_by_id("join_room").unwrap();
-        let join_cb = set_event_cb(&join_button, "click", |_e: MouseEvent| {
+        // Offer the name and room from last time, or the room from a shared
+        // link:
+        let settings = settings::get();
+        let linked_room = linked_room(&global.window);
+
+        let name_input: HtmlInputElement = global.element("input_name")?.dyn_into()?;
+        name_input.set_value(&settings.name);
+
+        let room_input: HtmlInputElement = global.element("input_room")?.dyn_into()?;
+        room_input.set_value(linked_room.as_deref().unwrap_or(&settings.last_room));
+
+        let auto_join = linked_room.is_some() && !settings.name.is_empty();
+
+        // The lobby's buttons stay on the page for the next time it's shown,
+        // and our callbacks come off them with us:
+        let mut callbacks = CallbackRegistry::default();
+
+        let join_button = global.element("join_room")?;
+        callbacks.listen(&join_button, "click", |_e: MouseEvent| {
             console_log!("join_button clicked");
 
-            let window = web_sys::window().unwrap();
-            let room_input: HtmlInputElement = window
-                .document()
-                .unwrap()
-                .get_element_by_id("input_room")
-                .unwrap()
-                .dyn_into()?;
+            let room_input: HtmlInputElement = ui::element("input_room")?.dyn_into()?;
 
-            let name_input: HtmlInputElement = window
-                .document()
-                .unwrap()
-                .get_element_by_id("input_name")
-                .unwrap()
-                .dyn_into()?;
+            let name_input: HtmlInputElement = ui::element("input_name")?.dyn_into()?;
 
             let room_name = room_input.value();
             let player_name = name_input.value();
+            let password = entered_password()?;
 
             if room_name.is_empty() {
-                window.alert_with_message("Please enter a valid room ID")?;
+                toasts::warning("Please enter a valid room ID")?;
             } else {
                 if player_name.is_empty() {
-                    window.alert_with_message("Please enter name")?;
+                    toasts::warning("Please enter name")?;
                 } else {
+                    settings::update(|s| {
+                        s.name = player_name.clone();
+                        s.last_room = room_name.clone();
+                    })?;
+
                     STATE
                         .lock()
                         .unwrap()
-                        .on_join_start(player_name, room_name)?;
+                        .on_join_start(player_name, room_name, password)?;
                 }
             }
 
             Ok(())
         });
 
-        let create_button = doc.get_element_by_id("create_room").unwrap();
-        let create_cb = set_event_cb(&create_button, "click", |_e: MouseEvent| {
+        let create_button = global.element("create_room")?;
+        callbacks.listen(&create_button, "click", |_e: MouseEvent| {
             console_log!("create_button clicked");
 
-            let window = web_sys::window().unwrap();
+            let name_input: HtmlInputElement = ui::element("input_name")?.dyn_into()?;
 
-            let name_input: HtmlInputElement = window
-                .document()
-                .unwrap()
-                .get_element_by_id("input_name")
-                .unwrap()
-                .dyn_into()?;
+            let player_name = name_input.value();
+            if player_name.is_empty() {
+                toasts::warning("please enter a name")?;
+            } else {
+                let password = entered_password()?;
+                let options = entered_options()?;
+
+                settings::update(|s| s.name = player_name.clone())?;
+                STATE
+                    .lock()
+                    .unwrap()
+                    .on_create_start(player_name, password, options)?;
+            }
+
+            Ok(())
+        });
+
+        let practice_button = global.element("practice_room")?;
+        callbacks.listen(&practice_button, "click", |_e: MouseEvent| {
+            console_log!("practice_button clicked");
 
+            let name_input: HtmlInputElement = ui::element("input_name")?.dyn_into()?;
+            let bots_select: HtmlSelectElement = ui::element("input_bots")?.dyn_into()?;
+
+            let player_name = name_input.value();
+            if player_name.is_empty() {
+                toasts::warning("please enter a name")?;
+            } else {
+                let bots = bots_select.value().parse().unwrap_or(1);
+                let options = entered_options()?;
+
+                settings::update(|s| s.name = player_name.clone())?;
+                STATE
+                    .lock()
+                    .unwrap()
+                    .on_practice_start(player_name, bots, options)?;
+            }
+
+            Ok(())
+        });
+
+        let ranked_button = global.element("ranked_room")?;
+        callbacks.listen(&ranked_button, "click", |_e: MouseEvent| {
+            console_log!("ranked_button clicked");
+
+            let name_input: HtmlInputElement = ui::element("input_name")?.dyn_into()?;
+            let passphrase_input: HtmlInputElement = ui::element("input_passphrase")?.dyn_into()?;
+
+            // Ranked games are for accounts, so we need some way to log in:
             let player_name = name_input.value();
+            let settings = settings::get();
+            let can_log_in = !passphrase_input.value().is_empty()
+                || (settings.account == player_name && !settings.token.is_empty());
+
             if player_name.is_empty() {
-                window.alert_with_message("please enter a name")?;
+                toasts::warning("please enter a name")?;
+            } else if !can_log_in {
+                toasts::warning(&ServerError::LogInFirst.to_string())?;
             } else {
-                STATE.lock().unwrap().on_create_start(player_name)?;
+                settings::update(|s| s.name = player_name.clone())?;
+                STATE.lock().unwrap().on_ranked_start(player_name)?;
             }
 
             Ok(())
@@ -131,282 +236,593 @@ impl CreateOrJoin {
 
         Ok(CreateOrJoin {
             global,
-            join_cb,
-            create_cb,
+            _callbacks: callbacks,
+            auto_join,
         })
     }
 
-    pub fn on_join_start(self, player_name: String, room_name: String) -> JsResult<Connecting> {
-        let html = self.global.doc.get_element_by_id("create_or_join").unwrap();
+    /// Whether we came from a shared link and already know the player's name,
+    /// so there's nothing left to fill in.
+    pub fn auto_join(&self) -> bool {
+        self.auto_join
+    }
+
+    pub fn on_join_start(
+        self,
+        player_name: String,
+        room_name: String,
+        password: Option<String>,
+    ) -> JsResult<Connecting> {
+        let html = self.global.element("create_or_join")?;
+        html.set_attribute("style", "display:none")?;
+
+        Connecting::new(
+            self.global,
+            player_name,
+            RoomChoice::Join(room_name),
+            password,
+        )
+    }
+
+    pub fn on_create_start(
+        self,
+        player_name: String,
+        password: Option<String>,
+        options: RoomOptions,
+    ) -> JsResult<Connecting> {
+        let html = self.global.element("create_or_join")?;
+        html.set_attribute("style", "display:none")?;
+
+        Connecting::new(
+            self.global,
+            player_name,
+            RoomChoice::Create(options),
+            password,
+        )
+    }
+
+    pub fn on_ranked_start(self, player_name: String) -> JsResult<Connecting> {
+        let html = self.global.element("create_or_join")?;
         html.set_attribute("style", "display:none")?;
-        // html.
 
-        Connecting::new(self.global, player_name, Some(room_name))
+        Connecting::new(self.global, player_name, RoomChoice::Ranked, None)
     }
 
-    pub fn on_create_start(self, player_name: String) -> JsResult<Connecting> {
-        let html = self.global.doc.get_element_by_id("create_or_join").unwrap();
+    pub fn on_practice_start(
+        self,
+        player_name: String,
+        bots: usize,
+        options: RoomOptions,
+    ) -> JsResult<Connecting> {
+        let html = self.global.element("create_or_join")?;
         html.set_attribute("style", "display:none")?;
 
-        Connecting::new(self.global, player_name, None)
+        Connecting::new(
+            self.global,
+            player_name,
+            RoomChoice::Practice(bots, options),
+            None,
+        )
+    }
+}
+
+/// Which room the lobby sends us to once we're connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomChoice {
+    Create(RoomOptions),
+    Join(String),
+    /// Wait for the server to match us with players of about our rating.
+    Ranked,
+    /// Play this many bots in the page, without the server.
+    Practice(usize, RoomOptions),
+}
+
+/// How to log in as `player_name`: with the passphrase from the form if one
+/// was typed in, otherwise with the token from last time if it's theirs.
+/// Guests don't log in at all.
+fn log_in_message(global: &Global, player_name: &str) -> JsResult<Option<ClientMessage>> {
+    let passphrase_input: HtmlInputElement = global.element("input_passphrase")?.dyn_into()?;
+    let register_input: HtmlInputElement = global.element("input_register")?.dyn_into()?;
+
+    let passphrase = passphrase_input.value();
+    // It's not needed again once it's been traded for a token:
+    passphrase_input.set_value("");
+
+    let settings = settings::get();
+    Ok(if !passphrase.is_empty() {
+        let name = player_name.to_string();
+        Some(if register_input.checked() {
+            ClientMessage::Register(name, passphrase)
+        } else {
+            ClientMessage::LogIn(name, passphrase)
+        })
+    } else if settings.account == player_name && !settings.token.is_empty() {
+        Some(ClientMessage::Resume(settings.token))
+    } else {
+        None
+    })
+}
+
+/// What to ask for to get into `room` as `player_name`.
+fn join_message(player_name: &str, password: &Option<String>, room: &RoomChoice) -> ClientMessage {
+    let player_name = player_name.to_string();
+
+    match room {
+        RoomChoice::Join(room_name) => {
+            ClientMessage::JoinRoom(player_name, room_name.clone(), password.clone())
+        }
+        RoomChoice::Create(options) => {
+            ClientMessage::CreateRoom(player_name, password.clone(), options.clone())
+        }
+        RoomChoice::Ranked => ClientMessage::QueueRanked,
+        RoomChoice::Practice(_, options) => {
+            ClientMessage::CreateRoom(player_name, None, options.clone())
+        }
     }
 }
 
+/// How the form says to set up a new room.
+fn entered_options() -> JsResult<RoomOptions> {
+    let checked = |id: &str| -> JsResult<bool> {
+        let input: HtmlInputElement = ui::element(id)?.dyn_into()?;
+        Ok(input.checked())
+    };
+
+    Ok(RoomOptions {
+        show_pool: checked("input_show_pool")?,
+        vertical_groups: checked("input_vertical_groups")?,
+    })
+}
+
+/// The room password from the form, if one was typed in.
+fn entered_password() -> JsResult<Option<String>> {
+    let password_input: HtmlInputElement = ui::element("input_password")?.dyn_into()?;
+
+    let password = password_input.value();
+    Ok(if password.is_empty() {
+        None
+    } else {
+        Some(password)
+    })
+}
+
 #[derive(Debug)]
 pub struct Connecting {
     pub global: Global,
-    pub ws: WebSocket,
+    pub conn: Connection,
     pub player_name: String,
-    pub room_name: Option<String>,
+    pub room: RoomChoice,
+    pub password: Option<String>,
 }
 
 impl Connecting {
-    pub fn new(global: Global, player_name: String, room_name: Option<String>) -> JsResult<Self> {
-        let html = global.doc.get_element_by_id("connecting").unwrap();
+    pub fn new(
+        global: Global,
+        player_name: String,
+        room: RoomChoice,
+        password: Option<String>,
+    ) -> JsResult<Self> {
+        let html = global.element("connecting")?;
         html.toggle_attribute("hidden")?;
 
-        // Thanks mkeeter for the following hostname code:
-        let location = global.doc.location().expect("Could not get doc location");
-        let hostname = location.hostname()?;
-
-        // Pick the port based on the connection type
-        let (ws_protocol, ws_port) = if location.protocol()? == "https:" {
-            ("wss", 5556)
-        } else {
-            ("ws", 5555)
-        };
-        let hostname = format!("{}://{}:{}", ws_protocol, hostname, ws_port);
-        console_log!("Host: {}", hostname);
-
-        // Set up the websocket
-        let ws = WebSocket::new(&hostname)?;
-        set_event_cb(&ws, "open", move |_: JsValue| {
-            console_log!("WS Connected");
-
-            {
-                STATE.lock().unwrap().on_connected().unwrap();
+        let conn = match &room {
+            RoomChoice::Practice(bots, _) => {
+                Connection::practice(*bots, crate::on_connection_event)?
             }
-
-            Ok(())
-        })
-        .forget();
+            _ => Connection::open(crate::on_connection_event)?,
+        };
 
         Ok(Connecting {
             global,
-            ws,
+            conn,
             player_name,
-            room_name,
+            room,
+            password,
         })
     }
 
     pub fn on_connected(self) -> JsResult<Playing> {
-        let html = self.global.doc.get_element_by_id("connecting").unwrap();
+        let html = self.global.element("connecting")?;
         html.toggle_attribute("hidden")?;
 
-        Playing::new(self.global, self.ws, self.player_name, self.room_name)
+        Playing::new(
+            self.global,
+            self.conn,
+            self.player_name,
+            self.room,
+            self.password,
+        )
     }
 }
 
 // #[derive(Debug)]
 pub struct Playing {
-    pub ws: WebSocket,
+    pub conn: Connection,
     pub global: Global,
     pub board: Board,
     pub hand: Board,
+    pub player_name: String,
     pub room_name: String,
+    /// Which room we asked for, until we're in it, to ask again wherever
+    /// we're sent.
+    pub joining: Option<RoomChoice>,
     pub is_turn: bool,
     pub active_player: usize,
+    /// The number of the turn being played, counted from 1 each game the
+    /// way the server counts them.
+    pub turn: u64,
     pub players: Vec<String>,
     pub disconnected: Vec<usize>,
+    /// Disconnected players whose turns a bot is taking.
+    pub bots: Vec<usize>,
+    /// Players the host removed. They keep their index.
+    pub kicked: Vec<usize>,
+    /// Players whose turns are skipped for running out of time, until they
+    /// do something.
+    pub away: Vec<usize>,
+    /// How long the active player's turn still waits for them to reconnect.
+    pub countdown: Option<Countdown>,
+    /// How long each player spent on their turns before this one, in
+    /// milliseconds, as of the last update.
+    pub thinking_ms: Vec<u64>,
+    /// When the current turn started, to run its player's clock from.
+    pub turn_started: DateTime<Utc>,
+    /// Ticks the active player's clock along.
+    pub clock: Option<Clock>,
+    /// The player who can kick others and hand turns to bots.
+    pub host: usize,
+    /// Whether we voted to skip the active player this turn.
+    pub skip_voted: bool,
+    /// Whether we've nudged the active player this turn.
+    pub nudged: bool,
+    /// How many pieces each player holds, as of the last update.
+    pub hand_sizes: Vec<usize>,
+    /// Whether we've played from our hand yet this game. Until then, the
+    /// hand value counts towards `OPENING_POINTS`.
+    pub opened: bool,
+    /// The board as the current turn started, to see what that turn changed.
+    pub turn_start_board: BTreeMap<Coord, Piece>,
+    /// Whether the room lets groups be laid down a column.
+    pub vertical_groups: bool,
+    /// How big the room's board is.
+    pub board_size: BoardSize,
+    /// Whether the last `StateCheck` didn't match our copy of the game. One
+    /// can just be moves still on their way, two in a row and we ask for the
+    /// whole game again.
+    pub out_of_step: bool,
+    /// Kept to get back into the room with if the connection drops.
+    pub password: Option<String>,
+    pub events: EventLog,
     // pub hand: Vec<Piece>,
     pub selected_piece: Option<Piece>,
+    /// A whole group picked up from the board, stored as (x offset from the
+    /// grabbed piece, piece).
+    pub selected_group: Vec<(i32, Piece)>,
+    /// The last pointer position while dragging the board camera around.
+    pub panning: Option<(i32, i32)>,
+    /// This turn's board edits, newest last, so they can be undone.
+    pub history: Vec<Vec<Move>>,
+    /// Board edits we've made here and sent, by the `seq` they went as,
+    /// that the server hasn't had its say on yet. Oldest first.
+    pub pending: VecDeque<(u64, Vec<Move>)>,
+    /// Who put down each tile on the board, for the teaching outlines.
+    pub credits: Credits,
+    /// Whether the keyboard cursor is in the hand rather than on the board.
+    pub cursor_in_hand: bool,
     pub players_div: Element,
     pub board_div: Element,
     pub board_svg: Element,
     pub hand_div: Element,
     pub hand_svg: Element,
-    pub on_board_click: JsClosure<PointerEvent>,
-    pub on_board_move: JsClosure<PointerEvent>,
-    pub on_board_leave: JsClosure<Event>,
-    pub on_hand_click: JsClosure<PointerEvent>,
-    pub on_hand_move: JsClosure<PointerEvent>,
-    pub on_hand_leave: JsClosure<Event>,
-    pub on_end_turn: JsClosure<PointerEvent>,
-    pub on_window_resize: JsClosure<Event>,
+    /// Everything on the page calling back into the game, taken off it
+    /// when we're done with the room.
+    pub callbacks: CallbackRegistry,
+    pub resize_observer: ResizeObserver,
+    pub on_animation_frame: js_sys::Function,
+    /// Whether an animation frame has been requested and not run yet.
+    pub frame: Option<i32>,
 }
 
 impl Playing {
     pub fn new(
         global: Global,
-        ws: WebSocket,
+        mut conn: Connection,
         player_name: String,
-        room_name: Option<String>,
+        room: RoomChoice,
+        password: Option<String>,
     ) -> JsResult<Self> {
         // Display the game board:
-        let html = global.doc.get_element_by_id("playing").unwrap();
+        let html = global.element("playing")?;
         html.toggle_attribute("hidden")?;
 
         // We have connected so setup the websocket heartbeat:
-        // crate::create_heartbeat()?;
-
-        // Handle websocket message:
-        set_event_cb(&ws, "message", move |e: MessageEvent| {
-            let msg: ServerMessage = serde_json::from_str(&e.data().as_string().unwrap())
-                .map_err(|e| JsValue::from_str(&e.to_string()))?;
-            crate::on_message(msg)
-        })
-        .forget();
-
-        // Handle websocket error:
-        set_event_cb(&ws, "error", move |e: Event| {
-            console_log!("WS Error: {:?}", e);
-            Ok(())
-        })
-        .forget();
-
-        // Handle websocket close:
-        set_event_cb(&ws, "close", move |e: Event| {
-            console_log!("WS Closed: {:?}", e);
-            Ok(())
-        })
-        .forget();
+        conn.start_heartbeat()?;
 
-        let board_div = global.doc.get_element_by_id("board").unwrap();
-        // let board_svg = global.doc.get_element_by_id("board_svg").unwrap();
+        let board_div = global.element("board")?;
+        // let board_svg = global.element("board_svg")?;
 
-        let hand_div = global.doc.get_element_by_id("hand").unwrap();
-        // let hand_svg = global.doc.get_element_by_id("hand_svg").unwrap();
+        let hand_div = global.element("hand")?;
+        // let hand_svg = global.element("hand_svg")?;
 
-        let players_div = global.doc.get_element_by_id("players").unwrap();
+        let players_div = global.element("players")?;
+        let events = EventLog::new(&global.doc, &global.root)?;
 
-        let board = Board::new(15, 25, &board_div, "board");
+        // Until we're in a room and know how big its board is:
+        let size = BoardSize::default();
+        let board = Board::new(size.rows, size.cols, &board_div, "board").with_guides();
         let board_svg = board_div.get_elements_by_tag_name("svg").item(0).unwrap();
 
-        let hand = Board::new(5, 25, &hand_div, "hand");
+        let hand = Board::new(5, 25, &hand_div, "hand").with_fit_cols();
         let hand_svg = hand_div.get_elements_by_tag_name("svg").item(0).unwrap();
 
-        let on_board_click = set_event_cb(&board_svg, "click", move |e: PointerEvent| {
+        let mut callbacks = CallbackRegistry::default();
+
+        callbacks.listen(&board_svg, "click", move |e: PointerEvent| {
             e.prevent_default();
-            STATE.lock().unwrap().on_board_click(e.x(), e.y())
+            STATE
+                .lock()
+                .unwrap()
+                .on_board_click(e.x(), e.y(), e.shift_key())
         });
 
-        let on_board_move = set_event_cb(&board_svg, "mousemove", move |e: PointerEvent| {
+        callbacks.listen(&board_svg, "mousemove", move |e: PointerEvent| {
             e.prevent_default();
             STATE.lock().unwrap().on_board_move(e.x(), e.y())
         });
 
-        let on_board_leave = set_event_cb(&board_svg, "mouseleave", move |e: Event| {
+        callbacks.listen(&board_svg, "mouseleave", move |e: Event| {
             e.prevent_default();
             STATE.lock().unwrap().on_board_leave()
         });
 
-        let on_hand_click = set_event_cb(&hand_svg, "click", move |e: PointerEvent| {
+        // Middle mouse drags pan the board:
+        callbacks.listen(&board_svg, "pointerdown", move |e: PointerEvent| {
+            if e.button() == 1 {
+                e.prevent_default();
+            }
+            STATE
+                .lock()
+                .unwrap()
+                .on_board_down(e.x(), e.y(), e.button())
+        });
+
+        callbacks.listen(&board_svg, "pointerup", move |e: PointerEvent| {
+            STATE.lock().unwrap().on_board_up(e.button())
+        });
+
+        // Two finger scrolls pan, pinches (sent as ctrl + wheel) zoom:
+        callbacks.listen(&board_svg, "wheel", move |e: WheelEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_board_wheel(
+                e.x(),
+                e.y(),
+                e.delta_x() as i32,
+                e.delta_y() as i32,
+                e.ctrl_key(),
+            )
+        });
+
+        callbacks.listen(&hand_svg, "click", move |e: PointerEvent| {
             e.prevent_default();
             STATE.lock().unwrap().on_hand_click(e.x(), e.y())
         });
 
-        let on_hand_move = set_event_cb(&hand_svg, "mousemove", move |e: PointerEvent| {
+        callbacks.listen(&hand_svg, "mousemove", move |e: PointerEvent| {
             e.prevent_default();
             STATE.lock().unwrap().on_hand_move(e.x(), e.y())
         });
 
-        let on_hand_leave = set_event_cb(&hand_svg, "mouseleave", move |e: Event| {
+        callbacks.listen(&hand_svg, "mouseleave", move |e: Event| {
             e.prevent_default();
             STATE.lock().unwrap().on_hand_leave()
         });
 
-        let end_turn = global.doc.get_element_by_id("end_turn").unwrap();
-        let on_end_turn = set_event_cb(&end_turn, "click", move |e: PointerEvent| {
+        let end_turn = global.element("end_turn")?;
+        callbacks.listen(&end_turn, "click", move |e: PointerEvent| {
             e.prevent_default();
             STATE.lock().unwrap().on_end_turn()
         });
 
+        let tidy = global.element("tidy_board")?;
+        callbacks.listen(&tidy, "click", move |e: PointerEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_tidy()
+        });
+
+        // The host's kick and bot buttons are redrawn with the player list,
+        // so listen on the list itself:
+        callbacks.listen(&players_div, "click", move |e: MouseEvent| {
+            let target = match e.target().and_then(|t| t.dyn_into::<Element>().ok()) {
+                Some(target) => target,
+                None => return Ok(()),
+            };
+
+            let idx = |attr: &str| target.get_attribute(attr).and_then(|i| i.parse().ok());
+            if let Some(idx) = idx("data-kick") {
+                STATE.lock().unwrap().on_kick(idx)
+            } else if let Some(idx) = idx("data-bot") {
+                STATE.lock().unwrap().on_make_bot(idx)
+            } else {
+                Ok(())
+            }
+        });
+
+        let vote_skip = global.element("vote_skip")?;
+        callbacks.listen(&vote_skip, "click", move |e: MouseEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_vote_skip()
+        });
+
+        let nudge = global.element("nudge")?;
+        callbacks.listen(&nudge, "click", move |e: MouseEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_nudge()
+        });
+
+        let away = global.element("away")?;
+        callbacks.listen(&away, "click", move |e: MouseEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_toggle_away()
+        });
+
+        callbacks.listen(&global.doc, "keydown", move |e: KeyboardEvent| {
+            // Leave typing in the settings panel alone:
+            let tag = e
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .map(|element| element.tag_name());
+            if let Some("INPUT") | Some("SELECT") = tag.as_deref() {
+                return Ok(());
+            }
+
+            // Keep the arrows and space from scrolling the page:
+            match e.key().as_str() {
+                "ArrowUp" | "ArrowDown" | "ArrowLeft" | "ArrowRight" | " " => e.prevent_default(),
+                _ => (),
+            }
+            STATE.lock().unwrap().on_key(e.key(), e.shift_key())
+        });
+
         let window = &global.window;
-        let on_window_resize = set_event_cb(window, "resize", move |e: Event| {
+        callbacks.listen(window, "resize", move |e: Event| {
             e.prevent_default();
             STATE.lock().unwrap().on_window_resize()
         });
 
-        console_log!("sending join message");
-
-        let mut is_turn = false;
-        if let Some(room_name) = room_name {
-            let join_message =
-                serde_json::to_string(&ClientMessage::JoinRoom(player_name, room_name)).unwrap();
-            ws.send_with_str(&join_message)?;
-        } else {
-            let join_message =
-                serde_json::to_string(&ClientMessage::CreateRoom(player_name)).unwrap();
-            ws.send_with_str(&join_message)?;
-            console_log!("created room");
+        // Phones put pages in the background to sleep, and they miss whatever
+        // was sent meanwhile:
+        callbacks.listen(&global.doc, "visibilitychange", move |_e: Event| {
+            let doc = web_sys::window().unwrap().document().unwrap();
+            if doc.hidden() {
+                return Ok(());
+            }
+            STATE.lock().unwrap().on_page_shown()
+        });
 
-            is_turn = true;
+        // The grid can change size without the window resizing, so watch the
+        // containers themselves too:
+        let on_container_resize = callbacks.keep(build_cb(move |_entries: JsValue| {
+            STATE.lock().unwrap().on_window_resize()
+        }));
+        let resize_observer = ResizeObserver::new(&on_container_resize)?;
+        resize_observer.observe(&board_div);
+        resize_observer.observe(&hand_div);
+
+        let on_animation_frame = callbacks.keep(build_cb(move |now: f64| {
+            STATE.lock().unwrap().on_animation_frame(now)
+        }));
+
+        // Rooms only know us by our account if we log in first. Practice
+        // rooms don't keep anything to log in for:
+        if !conn.is_practice() {
+            if let Some(log_in) = log_in_message(&global, &player_name)? {
+                conn.send_now(log_in)?;
+            }
         }
 
-        console_log!("is turn: {}", is_turn);
+        console_log!("sending join message");
+        conn.send_now(join_message(&player_name, &password, &room))?;
 
         let mut this = Self {
-            ws,
+            conn,
             global,
             board,
             hand,
+            player_name,
             room_name: String::new(),
-            is_turn,
+            joining: Some(room),
+            // The server says whose turn it is once the game's dealt:
+            is_turn: false,
             active_player: 0,
+            turn: 0,
             players: Vec::new(),
             disconnected: Vec::new(),
+            bots: Vec::new(),
+            kicked: Vec::new(),
+            away: Vec::new(),
+            countdown: None,
+            thinking_ms: Vec::new(),
+            turn_started: Utc::now(),
+            clock: None,
+            host: 0,
+            skip_voted: false,
+            nudged: false,
+            hand_sizes: Vec::new(),
+            opened: false,
+            turn_start_board: BTreeMap::new(),
+            vertical_groups: false,
+            board_size: BoardSize::default(),
+            out_of_step: false,
+            password,
+            events,
             selected_piece: None,
+            selected_group: Vec::new(),
+            panning: None,
+            history: Vec::new(),
+            pending: VecDeque::new(),
+            credits: Credits::default(),
+            cursor_in_hand: false,
             board_div,
             board_svg,
             hand_div,
             hand_svg,
             players_div,
-            on_board_click,
-            on_board_move,
-            on_board_leave,
-            on_hand_click,
-            on_hand_move,
-            on_hand_leave,
-            on_end_turn,
-            on_window_resize,
+            callbacks,
+            resize_observer,
+            on_animation_frame,
+            frame: None,
         };
 
-        this.update_players();
+        this.update_players()?;
 
         Ok(this)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn on_joined_room(
         &mut self,
         room_name: String,
-        players: Vec<String>,
-        mut hand: Vec<Piece>,
-        pieces_remaining: usize,
-        board: BTreeMap<Coord, Piece>,
+        options: RoomOptions,
+        last_seq: u64,
+        snapshot: RoomSnapshot,
     ) -> JsResult<()> {
-        hand.sort();
-
-        self.global
-            .doc
-            .get_element_by_id("room")
-            .unwrap()
-            .set_inner_html(&room_name);
-
-        self.global
-            .doc
-            .get_element_by_id("pieces_remaining")
-            .unwrap()
-            .set_inner_html(&format!("{}", pieces_remaining));
+        self.conn.joined(last_seq)?;
+        self.joining = None;
+
+        self.global.element("room")?.set_inner_html(&room_name);
+
+        // Make refreshing or sharing the page come back to this room. A
+        // practice room is gone once the page is:
+        if !self.conn.is_practice() {
+            let url = format!("?room={}", room_name);
+            self.global
+                .window
+                .history()?
+                .replace_state_with_url(&JsValue::NULL, "", Some(&url))?;
+            settings::update(|s| s.last_room = room_name.clone())?;
+        }
 
-        *self.board.grid_mut() = board;
+        self.vertical_groups = options.vertical_groups;
+        self.board.set_vertical_groups(options.vertical_groups);
         self.room_name = room_name;
-        self.players = players;
 
-        self.hand.insert_as_hand(&hand);
+        // Joining shows the board as it is, rather than moving pieces into
+        // place, and the turn starts from it:
+        self.board.set_grid(snapshot.board.clone());
+        self.turn_start_board = snapshot.board.clone();
+        self.credit(None);
+        self.credits.end_turn();
+        self.history.clear();
 
-        self.board.rerender();
-        self.hand.rerender();
-        self.update_players();
+        let saved = hands::load(&self.room_name, &self.player_name);
+        // Only a `StartTurn` announces our turn, but we can play it either
+        // way:
+        self.is_turn = self.catch_up(snapshot, &saved)?;
+        self.update_turn_banner()?;
+
+        self.events
+            .push(&format!("Joined room {}", self.room_name))?;
+        self.send_message(ClientMessage::Stats(self.player_name.clone()))?;
 
         console_log!(
             "[{}] {:?} pieces, {:?}",
@@ -418,365 +834,2572 @@ impl Playing {
         Ok(())
     }
 
-    fn update_players(&mut self) {
+    fn update_players(&mut self) -> JsResult<()> {
+        let is_host = self.is_host();
         let mut inner_html = String::new();
 
         for (i, player) in self.players.iter().enumerate() {
-            if i == self.active_player {
+            // Players who joined since the last update don't have a count yet:
+            let count = match self.hand_sizes.get(i) {
+                Some(size) => format!("<td class=\"hand_size\">{}</td>", size),
+                None => "<td></td>".to_string(),
+            };
+
+            let clock = format!(
+                "<td class=\"clock\" data-clock=\"{}\">{}</td>",
+                i,
+                format_clock(self.thinking_ms(i))
+            );
+
+            let mut name = player.clone();
+            if settings::get().who_played {
+                name = format!(
+                    "<span class=\"swatch player_{}\"></span>{}",
+                    i % PLAYER_COLORS,
+                    name
+                );
+            }
+            if i == self.host {
+                name.push_str(" <span class=\"host\" title=\"Host\">★</span>");
+            }
+            if self.bots.contains(&i) {
+                name.push_str(" <span class=\"bot\">(bot)</span>");
+            }
+            if self.away.contains(&i) {
+                name.push_str(" <span class=\"away\">(away)</span>");
+            }
+
+            let mut actions = String::new();
+            if is_host && i != self.host && !self.kicked.contains(&i) {
+                if self.disconnected.contains(&i) && !self.bots.contains(&i) {
+                    actions.push_str(&format!("<button data-bot=\"{}\">Bot</button>", i));
+                }
+                actions.push_str(&format!("<button data-kick=\"{}\">Kick</button>", i));
+            }
+            let actions = format!("<td class=\"player_actions\">{}</td>", actions);
+            // Away players are greyed out, whatever else they are:
+            let row = if self.away.contains(&i) {
+                "<tr class=\"away_player\">"
+            } else {
+                "<tr>"
+            };
+
+            if self.kicked.contains(&i) {
                 inner_html.push_str(&format!(
-                    "<tr><td class=\"active_player\">{}</td></tr>",
-                    player
+                    "<tr><td class=\"kicked\">{}</td><td></td><td></td>{}</tr>",
+                    name, actions
+                ));
+            } else if i == self.active_player {
+                inner_html.push_str(&format!(
+                    "{}<td class=\"active_player\">{}</td>{}{}{}</tr>",
+                    row, name, count, clock, actions
                 ));
             } else if self.disconnected.contains(&i) {
                 inner_html.push_str(&format!(
-                    "<tr><td class=\"disconnected\">{}</td></tr>",
-                    player
+                    "{}<td class=\"disconnected\">{}</td>{}{}{}</tr>",
+                    row, name, count, clock, actions
                 ));
             } else {
-                inner_html.push_str(&format!("<tr><td>{}</td></tr>", player));
+                inner_html.push_str(&format!(
+                    "{}<td>{}</td>{}{}{}</tr>",
+                    row, name, count, clock, actions
+                ));
             }
         }
 
         inner_html = format!("<table>{}</table>", inner_html);
         self.players_div.set_inner_html(&inner_html);
-    }
-
-    fn on_board_click(&mut self, x: i32, y: i32) -> JsResult<()> {
-        let rect = self.board_svg.get_bounding_client_rect();
-        let x = x - rect.x() as i32;
-        let y = y - rect.y() as i32;
 
-        let coord = self.board.world_to_grid(x, y);
-        console_log!("Board Click: ({}, {})", coord.0, coord.1);
+        self.update_vote_skip()?;
+        self.update_away()?;
+        self.update_turn_banner()?;
 
-        // The player has clicked and wants to place a piece:
-        if let Some(piece) = self.selected_piece {
-            console_log!("placing piece: {:?}", piece);
+        Ok(())
+    }
 
-            if self.board.contains(coord) {
-                // user is trying to place on another tile, don't let them
-                console_log!("piece already there");
-            } else if !self.is_turn {
-                self.global.window.alert_with_message(
-                    "You cannot place on the board when it is not your turn.",
-                )?;
-            } else {
-                // Player is placing on board and it's their turn, place
-                // the piece and send the message.
-                let _ = self.board.world_insert(x, y, piece);
-                self.send_message(ClientMessage::Place(coord, piece))?;
-                self.selected_piece = None;
-            }
+    /// Says we're waiting whenever the server hasn't started our turn, and
+    /// on whom.
+    fn update_turn_banner(&self) -> JsResult<()> {
+        let banner: HtmlElement = self.global.element("turn_banner")?.unchecked_into();
+        let text = if self.players.len() < 2 {
+            "Waiting for someone to join".to_string()
+        } else if self.my_index() == Some(self.active_player) {
+            "Waiting for your turn".to_string()
         } else {
-            // Player wants to pickup a piece
-            if self.is_turn {
-                if let Some(piece) = self.board.grid_remove(coord) {
-                    // Tell the server we picked up the piece.
-                    self.send_message(ClientMessage::Pickup(coord, piece))?;
-                    self.selected_piece = Some(piece);
-                } else {
-                    console_log!("no piece there");
-                }
-            }
-        }
+            format!(
+                "Waiting for your turn, it's {}'s",
+                self.players[self.active_player]
+            )
+        };
 
-        self.board.rerender();
+        banner.set_text_content(Some(&text));
+        banner.set_hidden(self.is_turn);
 
         Ok(())
     }
 
-    fn on_board_move(&mut self, x: i32, y: i32) -> JsResult<()> {
-        let rect = self.board_svg.get_bounding_client_rect();
-        let x = x - rect.x() as i32;
-        let y = y - rect.y() as i32;
+    /// Offers to vote out or nudge whoever is playing, unless that's us.
+    fn update_vote_skip(&self) -> JsResult<()> {
+        let vote_skip: HtmlButtonElement = self.global.element("vote_skip")?.unchecked_into();
+        let nudge: HtmlButtonElement = self.global.element("nudge")?.unchecked_into();
 
-        if let Some(piece) = self.selected_piece {
-            if !self.board.world_contains(x, y) {
-                self.board.world_render_highlight(x, y, &piece);
-            }
-        }
+        let hidden = self.is_turn || self.players.len() < 2;
+        vote_skip.set_hidden(hidden);
+        vote_skip.set_disabled(self.skip_voted);
+        nudge.set_hidden(hidden);
+        nudge.set_disabled(self.nudged);
 
         Ok(())
     }
 
-    fn on_board_leave(&mut self) -> JsResult<()> {
-        self.board.remove_highlight();
+    /// Offers to step away, or come back, once there's a game to step away
+    /// from. Practice bots wait however long we take anyway.
+    fn update_away(&self) -> JsResult<()> {
+        let away: HtmlButtonElement = self.global.element("away")?.unchecked_into();
+
+        away.set_hidden(self.players.len() < 2 || self.conn.is_practice());
+        away.set_text_content(Some(if self.is_away() {
+            "I'm Back"
+        } else {
+            "Step Away"
+        }));
+
         Ok(())
     }
 
-    fn on_hand_click(&mut self, x: i32, y: i32) -> JsResult<()> {
-        let rect = self.hand_svg.get_bounding_client_rect();
-        let x = x - rect.x() as i32;
-        let y = y - rect.y() as i32;
+    fn is_away(&self) -> bool {
+        matches!(self.my_index(), Some(me) if self.away.contains(&me))
+    }
 
-        let coord = self.board.world_to_grid(x, y);
-        console_log!("Hand Click: ({}, {})", coord.0, coord.1);
+    /// Asks to have our turns skipped, or taken by us again. The server says
+    /// when it's done, see [`Playing::on_player_away`].
+    fn on_toggle_away(&mut self) -> JsResult<()> {
+        self.send_message(ClientMessage::SetAway(!self.is_away()))
+    }
 
-        // The player has clicked and wants to place a piece in their hand:
-        if let Some(piece) = self.selected_piece {
-            console_log!("placing piece: {:?}", piece);
-            if self.board.contains(coord) {
-                // user is trying to place on another tile, don't let them
-                console_log!("piece already there");
-            } else {
-                // Player is placing on board and it's in their hand, always succeed
-                let _ = self.hand.world_insert(x, y, piece);
-                self.selected_piece = None;
-            }
-        } else if let Some(piece) = self.hand.grid_remove(coord) {
-            // Player wants to pickup a piece in their hand
-            self.selected_piece = Some(piece);
-        } else {
-            console_log!("no piece there");
+    fn on_nudge(&mut self) -> JsResult<()> {
+        if self.is_turn || self.nudged {
+            return Ok(());
         }
 
-        console_log!("Hand: {:?}", self.hand.grid());
-
-        self.hand.rerender();
-
-        Ok(())
+        self.nudged = true;
+        self.update_vote_skip()?;
+        self.send_message(ClientMessage::Nudge)
     }
 
-    fn on_hand_move(&mut self, x: i32, y: i32) -> JsResult<()> {
-        let rect = self.hand_svg.get_bounding_client_rect();
-        let x = x - rect.x() as i32;
-        let y = y - rect.y() as i32;
+    /// Someone's hurrying the active player along. If that's us, it's hard
+    /// to miss.
+    pub fn on_nudged(&mut self, from: usize) -> JsResult<()> {
+        let from = &self.players[from];
+        if !self.is_turn {
+            return self.events.push(&format!(
+                "{} nudged {}",
+                from, self.players[self.active_player]
+            ));
+        }
 
-        if let Some(piece) = self.selected_piece {
-            if !self.hand.world_contains(x, y) {
-                self.hand.world_render_highlight(x, y, &piece);
+        self.events.push(&format!("{} nudged you", from))?;
+        toasts::warning(&format!("{} is waiting on you, it's your turn!", from))?;
+
+        if settings::get().sound {
+            if let Err(e) = crate::play_chime() {
+                console_log!("couldn't play chime: {:?}", e);
             }
         }
 
         Ok(())
     }
 
-    fn on_hand_leave(&mut self) -> JsResult<()> {
-        self.hand.remove_highlight();
-        Ok(())
+    fn on_vote_skip(&mut self) -> JsResult<()> {
+        if self.is_turn || self.skip_voted {
+            return Ok(());
+        }
+
+        self.skip_voted = true;
+        self.update_vote_skip()?;
+        self.send_message(ClientMessage::VoteSkip)
     }
 
-    fn on_draw_piece(&mut self, piece: Piece) -> JsResult<()> {
-        self.hand.insert_into_hand(piece);
-        self.hand.rerender();
+    pub fn on_skip_votes(&mut self, votes: usize, needed: usize) -> JsResult<()> {
+        self.events.push(&format!(
+            "{} of {} votes to skip {}",
+            votes, needed, self.players[self.active_player]
+        ))
+    }
+
+    pub fn on_skip_vote_too_early(&mut self, wait_secs: u64) -> JsResult<()> {
+        // Let us try again later:
+        self.skip_voted = false;
+        self.update_vote_skip()?;
+
+        self.events.push(&format!(
+            "{} can be skipped in {} seconds",
+            self.players[self.active_player], wait_secs
+        ))
+    }
+
+    /// The others voted the active player out. If that's us, the server
+    /// undid our moves, so do the same here.
+    pub fn on_turn_skipped(&mut self, idx: usize) -> JsResult<()> {
+        if self.players[idx] == self.player_name {
+            while let Some(moves) = self.history.pop() {
+                self.revert_moves(&moves)?;
+            }
+            self.credit(Some(idx));
+            self.pending.clear();
+            self.update_cursor_highlight();
+            self.is_turn = false;
+            self.update_turn_banner()?;
+
+            self.events.push("Your turn was skipped")?;
+        } else {
+            self.events
+                .push(&format!("{}'s turn was skipped", self.players[idx]))?;
+        }
+
+        Ok(())
+    }
+
+    /// Follows the active player's hand size as they play, until the next
+    /// `TurnFinished` has the real counts.
+    fn adjust_active_hand_size(&mut self, delta: isize) -> JsResult<()> {
+        if let Some(size) = self.hand_sizes.get_mut(self.active_player) {
+            *size = (*size as isize + delta).max(0) as usize;
+            self.update_players()?;
+        }
+
+        Ok(())
+    }
+
+    fn on_board_click(&mut self, x: i32, y: i32, shift: bool) -> JsResult<()> {
+        let rect = self.board_svg.get_bounding_client_rect();
+        let x = x - rect.x() as i32;
+        let y = y - rect.y() as i32;
+
+        let coord = self.board.world_to_grid(x, y);
+        console_log!("Board Click: ({}, {})", coord.0, coord.1);
+
+        self.board_action(coord, shift)?;
+        self.board.remove_highlight();
+
+        Ok(())
+    }
+
+    /// Picks up or places at `coord` on the board, for both clicks and the
+    /// keyboard cursor.
+    fn board_action(&mut self, coord: Coord, shift: bool) -> JsResult<()> {
+        if !self.selected_group.is_empty() {
+            // The player is holding a group and wants to drop it:
+            self.place_group(coord)?;
+        } else if let Some(piece) = self.selected_piece {
+            // The player has clicked and wants to place a piece:
+            console_log!("placing piece: {:?}", piece);
+
+            match self.predict(Action::Place(coord, piece)) {
+                Ok(()) => {
+                    // Player is placing on board and it's their turn, place
+                    // the piece and send the message.
+                    let _ = self.board.grid_insert(coord, piece);
+                    let placed = vec![Move::Place(coord, piece)];
+                    self.send_edits(ClientMessage::Place(coord, piece), placed.clone())?;
+                    self.history.push(placed);
+                    self.selected_piece = None;
+                }
+                Err(Rejection::NotYourTurn) => {
+                    toasts::warning("You cannot place on the board when it is not your turn.")?;
+                }
+                Err(_) => {
+                    // Another tile is there, or it's off the board:
+                    console_log!("can't place there");
+                }
+            }
+        } else if shift {
+            // Player wants to pickup the whole group under the cursor
+            if self.is_turn {
+                self.pickup_group(coord)?;
+            }
+        } else {
+            // Player wants to pickup a piece
+            if self.is_turn {
+                if let Some(piece) = self.board.grid_remove(coord) {
+                    // Tell the server we picked up the piece.
+                    let picked = vec![Move::Pickup(coord, piece)];
+                    self.send_edits(ClientMessage::Pickup(coord, piece), picked.clone())?;
+                    self.history.push(picked);
+                    self.selected_piece = Some(piece);
+                } else {
+                    console_log!("no piece there");
+                }
+            }
+        }
+
+        self.update_hand_value()?;
+        Ok(())
+    }
+
+    fn pickup_group(&mut self, coord: Coord) -> JsResult<()> {
+        let mut moves = Vec::new();
+
+        for grid_coord in self.board.group_at(coord) {
+            if let Some(piece) = self.board.grid_remove(grid_coord) {
+                moves.push(Move::Pickup(grid_coord, piece));
+                self.selected_group.push((grid_coord.0 - coord.0, piece));
+            }
+        }
+
+        console_log!("picked up group: {:?}", self.selected_group);
+
+        self.send_moves(moves)
+    }
+
+    fn place_group(&mut self, coord: Coord) -> JsResult<()> {
+        let targets: Vec<Coord> = self
+            .selected_group
+            .iter()
+            .map(|(offset, _)| Coord(coord.0 + offset, coord.1))
+            .collect();
+
+        let blocked =
+            |target: &Coord| !self.board_size.contains(*target) || self.board.contains(*target);
+        if targets.iter().any(blocked) {
+            // Some of the group would land on other tiles or off the board:
+            console_log!("group doesn't fit there");
+            return Ok(());
+        }
+
+        let group = std::mem::take(&mut self.selected_group);
+        let mut moves = Vec::new();
+
+        for ((_, piece), target) in group.into_iter().zip(targets) {
+            let _ = self.board.grid_insert(target, piece);
+            moves.push(Move::Place(target, piece));
+        }
+
+        self.send_moves(moves)
+    }
+
+    /// Sends a batch of board edits, remembering it for undo.
+    fn send_moves(&mut self, moves: Vec<Move>) -> JsResult<()> {
+        self.history.push(moves.clone());
+        self.send_edits(ClientMessage::Moves(moves.clone()), moves)
+    }
+
+    /// Sends `msg`, with the board edits `moves` we've already made here,
+    /// and keeps them until the server says how they went.
+    fn send_edits(&mut self, msg: ClientMessage, moves: Vec<Move>) -> JsResult<()> {
+        self.credit(self.my_index());
+        // Anything that waits for a reconnect is caught up on by joining:
+        if let Some(seq) = self.conn.send(msg)? {
+            self.pending.push_back((seq, moves));
+        }
+        Ok(())
+    }
+
+    fn on_board_down(&mut self, x: i32, y: i32, button: i16) -> JsResult<()> {
+        if button == 1 {
+            self.panning = Some((x, y));
+        }
+
+        Ok(())
+    }
+
+    fn on_board_up(&mut self, button: i16) -> JsResult<()> {
+        if button == 1 {
+            self.panning = None;
+        }
+
+        Ok(())
+    }
+
+    fn on_board_wheel(&mut self, x: i32, y: i32, dx: i32, dy: i32, zoom: bool) -> JsResult<()> {
+        if zoom {
+            let rect = self.board_svg.get_bounding_client_rect();
+            let x = x - rect.x() as i32;
+            let y = y - rect.y() as i32;
+
+            self.board.zoom_at(x, y, (-dy as f32 * 0.01).exp());
+        } else {
+            self.board.pan(-dx, -dy);
+        }
+
+        Ok(())
+    }
+
+    fn on_board_move(&mut self, x: i32, y: i32) -> JsResult<()> {
+        if let Some((last_x, last_y)) = self.panning {
+            self.board.pan(x - last_x, y - last_y);
+            self.panning = Some((x, y));
+            return Ok(());
+        }
+
+        let rect = self.board_svg.get_bounding_client_rect();
+        let x = x - rect.x() as i32;
+        let y = y - rect.y() as i32;
+
+        if !self.selected_group.is_empty() {
+            self.board
+                .world_render_group_highlight(x, y, &self.selected_group);
+        } else if let Some(piece) = self.selected_piece {
+            if !self.board.world_contains(x, y) {
+                self.board.world_render_highlight(x, y, &piece);
+            } else {
+                self.board.remove_highlight();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_board_leave(&mut self) -> JsResult<()> {
+        self.panning = None;
+        self.board.remove_highlight();
+        Ok(())
+    }
+
+    fn on_hand_click(&mut self, x: i32, y: i32) -> JsResult<()> {
+        let rect = self.hand_svg.get_bounding_client_rect();
+        let x = x - rect.x() as i32;
+        let y = y - rect.y() as i32;
+
+        let coord = self.hand.world_to_grid(x, y);
+        console_log!("Hand Click: ({}, {})", coord.0, coord.1);
+
+        self.hand_action(coord);
+        self.hand.remove_highlight();
+
+        Ok(())
+    }
+
+    fn hand_action(&mut self, coord: Coord) {
+        if !self.selected_group.is_empty() {
+            // Groups only move around the board
+            console_log!("groups can only be placed on the board");
+        } else if let Some(piece) = self.selected_piece {
+            // The player has clicked and wants to place a piece in their hand:
+            console_log!("placing piece: {:?}", piece);
+            if self.hand.contains(coord) {
+                // user is trying to place on another tile, don't let them
+                console_log!("piece already there");
+            } else {
+                // Player is placing on board and it's in their hand, always succeed
+                let _ = self.hand.grid_insert(coord, piece);
+                self.selected_piece = None;
+            }
+        } else if let Some(piece) = self.hand.grid_remove(coord) {
+            // Player wants to pickup a piece in their hand
+            self.selected_piece = Some(piece);
+        } else {
+            console_log!("no piece there");
+        }
+
+        console_log!("Hand: {:?}", self.hand.grid());
+        self.save_hand();
+    }
+
+    fn on_key(&mut self, key: String, shift: bool) -> JsResult<()> {
+        match key.as_str() {
+            "ArrowUp" => self.move_cursor(0, -1),
+            "ArrowDown" => self.move_cursor(0, 1),
+            "ArrowLeft" => self.move_cursor(-1, 0),
+            "ArrowRight" => self.move_cursor(1, 0),
+            "Enter" | " " => self.on_cursor_select(shift),
+            "e" | "E" => self.on_end_turn(),
+            "u" | "U" => self.on_undo(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the keyboard cursor, stepping between the bottom of the board
+    /// and the top of the hand.
+    fn move_cursor(&mut self, dx: i32, dy: i32) -> JsResult<()> {
+        let current = if self.cursor_in_hand {
+            self.hand.cursor()
+        } else {
+            self.board.cursor()
+        };
+
+        let Coord(x, y) = match current {
+            Some(Coord(x, y)) => Coord(x + dx, y + dy),
+            // The first key press only shows the cursor:
+            None => Coord(0, 0),
+        };
+
+        if !self.cursor_in_hand && y >= self.board.rows() {
+            self.board.hide_cursor();
+            self.board.remove_highlight();
+            self.cursor_in_hand = true;
+            self.hand.set_cursor(Coord(x, 0));
+        } else if self.cursor_in_hand && y < 0 {
+            self.hand.hide_cursor();
+            self.hand.remove_highlight();
+            self.cursor_in_hand = false;
+            self.board.set_cursor(Coord(x, self.board.rows() - 1));
+        } else if self.cursor_in_hand {
+            self.hand.set_cursor(Coord(x, y));
+        } else {
+            self.board.set_cursor(Coord(x, y));
+        }
+
+        self.update_cursor_highlight();
+        self.announce_cursor()
+    }
+
+    fn on_cursor_select(&mut self, shift: bool) -> JsResult<()> {
+        if self.cursor_in_hand {
+            if let Some(coord) = self.hand.cursor() {
+                self.hand_action(coord);
+            }
+        } else if let Some(coord) = self.board.cursor() {
+            self.board_action(coord, shift)?;
+        }
+
+        self.update_cursor_highlight();
+        self.announce_cursor()
+    }
+
+    /// Shows whatever is held at the keyboard cursor, like hovering does.
+    fn update_cursor_highlight(&mut self) {
+        let target = if self.cursor_in_hand {
+            &mut self.hand
+        } else {
+            &mut self.board
+        };
+
+        let coord = match target.cursor() {
+            Some(coord) => coord,
+            None => return,
+        };
+
+        if !self.selected_group.is_empty() && !self.cursor_in_hand {
+            target.grid_render_group_highlight(coord, &self.selected_group);
+        } else {
+            match self.selected_piece {
+                Some(piece) if !target.contains(coord) => {
+                    target.grid_render_highlight(coord, &piece)
+                }
+                _ => target.remove_highlight(),
+            }
+        }
+    }
+
+    /// Reads out the cell under the keyboard cursor for screen readers.
+    fn announce_cursor(&self) -> JsResult<()> {
+        let (name, target) = if self.cursor_in_hand {
+            ("Hand", &self.hand)
+        } else {
+            ("Board", &self.board)
+        };
+
+        let coord = match target.cursor() {
+            Some(coord) => coord,
+            None => return Ok(()),
+        };
+
+        let cell = match target.grid().get(&coord) {
+            Some(piece) => describe_piece(piece),
+            None => "empty".to_string(),
+        };
+
+        let mut text = format!(
+            "{} column {}, row {}: {}",
+            name,
+            coord.0 + 1,
+            coord.1 + 1,
+            cell
+        );
+
+        if let Some(piece) = self.selected_piece {
+            text.push_str(&format!(", holding {}", describe_piece(&piece)));
+        } else if !self.selected_group.is_empty() {
+            text.push_str(&format!(", holding {} pieces", self.selected_group.len()));
+        }
+
+        self.global
+            .element("announcer")?
+            .set_text_content(Some(&text));
+
+        Ok(())
+    }
+
+    fn on_hand_move(&mut self, x: i32, y: i32) -> JsResult<()> {
+        let rect = self.hand_svg.get_bounding_client_rect();
+        let x = x - rect.x() as i32;
+        let y = y - rect.y() as i32;
+
+        if let Some(piece) = self.selected_piece {
+            if !self.hand.world_contains(x, y) {
+                self.hand.world_render_highlight(x, y, &piece);
+            } else {
+                self.hand.remove_highlight();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_hand_leave(&mut self) -> JsResult<()> {
+        self.hand.remove_highlight();
+        Ok(())
+    }
+
+    fn on_draw_piece(&mut self, piece: Piece) -> JsResult<()> {
+        self.hand.insert_into_hand(piece);
+
+        if settings::get().auto_sort {
+            self.hand.sort_hand();
+            self.save_hand();
+        }
+        self.update_hand_value()?;
+
+        Ok(())
+    }
+
+    /// The server's copy of our hand, as one of our turns ends. If ours got
+    /// out of step it's put right, keeping pieces where the player laid
+    /// them out.
+    fn on_hand_sync(&mut self, hand: Vec<Piece>) -> JsResult<()> {
+        let mut ours: Vec<Piece> = self.hand.grid().values().copied().collect();
+        ours.extend(self.selected_piece);
+        ours.extend(self.selected_group.iter().map(|(_, piece)| *piece));
+        ours.sort();
+        let mut theirs = hand.clone();
+        theirs.sort();
+        if ours == theirs {
+            return Ok(());
+        }
+
+        console_log!(
+            "hand out of step, had {:?}, the server has {:?}",
+            ours,
+            theirs
+        );
+        self.selected_piece = None;
+        self.selected_group.clear();
+        let current = self.hand.grid().clone();
+        self.hand.arrange_hand(&current, &hand);
+        self.save_hand();
+        self.update_hand_value()?;
+
+        self.events
+            .push("Your hand was out of step with the server, and has been put right")
+    }
+
+    fn on_invalid_board(&mut self) -> JsResult<()> {
+        toasts::warning("The board is in an invalid state")
+    }
+
+    fn on_only_rearranged(&mut self) -> JsResult<()> {
+        toasts::warning(
+            "You have to play a tile from your hand after rearranging the board. \
+             Put the board back to draw instead.",
+        )
+    }
+
+    fn on_kept_board_piece(&mut self, piece: Piece) -> JsResult<()> {
+        toasts::warning(&format!(
+            "The {} was on the board when your turn started, so it has to go back on it.",
+            describe_piece(&piece)
+        ))
+    }
+
+    fn on_piece_place(&mut self, id: MoveId, coord: Coord, piece: Piece) -> JsResult<()> {
+        if self.my_index() == Some(id.seat) {
+            return self.confirm_moves(id.seq, vec![Move::Place(coord, piece)]);
+        }
+
+        console_log!("place: {:?} {:?}", coord, piece);
+        self.events.push(&format!(
+            "{} placed {}",
+            self.players[id.seat],
+            describe_piece(&piece)
+        ))?;
+        self.adjust_active_hand_size(-1)?;
+
+        if self.board.contains(coord) {
+            console_log!("[ERROR] overwriting piece at {:?}", coord);
+        }
+
+        self.board.drop_lifted(coord, piece);
+        self.credit(Some(id.seat));
+        self.request_frame()?;
+
+        Ok(())
+    }
+
+    fn on_pickup(&mut self, id: MoveId, coord: Coord, piece: Piece) -> JsResult<()> {
+        if self.my_index() == Some(id.seat) {
+            return self.confirm_moves(id.seq, vec![Move::Pickup(coord, piece)]);
+        }
+
+        console_log!("pickup: {:?} {:?}", coord, piece);
+        self.events.push(&format!(
+            "{} picked up {}",
+            self.players[id.seat],
+            describe_piece(&piece)
+        ))?;
+        self.adjust_active_hand_size(1)?;
+
+        if let Some(removed) = self.board.lift(coord) {
+            console_log!("{:?}: removed {:?}, expected {:?}", coord, removed, piece);
+        }
+        self.credit(Some(id.seat));
+
+        Ok(())
+    }
+
+    fn on_moves(&mut self, id: MoveId, moves: Vec<Move>) -> JsResult<()> {
+        if self.my_index() == Some(id.seat) {
+            return self.confirm_moves(id.seq, moves);
+        }
+
+        console_log!("moves: {:?}", moves);
+        self.events.push(&format!(
+            "{} moved {} pieces",
+            self.players[id.seat],
+            moves.len()
+        ))?;
+
+        let delta = moves
+            .iter()
+            .map(|m| match m {
+                Move::Pickup(..) => 1,
+                Move::Place(..) => -1,
+            })
+            .sum();
+        self.adjust_active_hand_size(delta)?;
+
+        for m in moves {
+            match m {
+                Move::Pickup(coord, _) => {
+                    let _ = self.board.lift(coord);
+                }
+                Move::Place(coord, piece) => self.board.drop_lifted(coord, piece),
+            }
+        }
+        self.credit(Some(id.seat));
+
+        self.request_frame()?;
+
+        Ok(())
+    }
+
+    fn on_moves_rejected(&mut self, seq: u64, moves: Vec<Move>) -> JsResult<()> {
+        console_log!("moves rejected: {:?}", moves);
+
+        self.pending.retain(|(sent, _)| *sent != seq);
+        if let Some(i) = self.history.iter().rposition(|batch| *batch == moves) {
+            self.history.remove(i);
+        }
+
+        self.revert_moves(&moves)?;
+        self.credit(self.my_index());
+
+        Ok(())
+    }
+
+    /// The server took our edits up to message `seq`, as `moves`. They're
+    /// almost always what we already made here, but if not, ours are undone
+    /// and the server's made instead.
+    fn confirm_moves(&mut self, seq: u64, moves: Vec<Move>) -> JsResult<()> {
+        let mut guessed = Vec::new();
+        while self.pending.front().is_some_and(|(sent, _)| *sent <= seq) {
+            guessed.extend(self.pending.pop_front().map(|(_, batch)| batch));
+        }
+        if guessed.concat() == moves {
+            return Ok(());
+        }
+
+        console_log!("the server took {:?}, we guessed {:?}", moves, guessed);
+        for batch in guessed.iter().rev() {
+            if let Some(i) = self.history.iter().rposition(|b| b == batch) {
+                self.history.remove(i);
+            }
+            self.revert_moves(batch)?;
+        }
+
+        for m in &moves {
+            match *m {
+                Move::Place(coord, piece) => {
+                    let _ = self.hand.remove_from_hand(piece);
+                    let _ = self.board.grid_insert(coord, piece);
+                }
+                Move::Pickup(coord, piece) => {
+                    let _ = self.board.grid_remove(coord);
+                    self.hand.insert_into_hand(piece);
+                }
+            }
+        }
+        self.history.push(moves);
+        self.credit(self.my_index());
+
+        self.update_cursor_highlight();
+        self.update_hand_value()?;
+        self.request_frame()
+    }
+
+    /// Undoes our optimistic moves locally, newest first. Pieces picked up
+    /// go back to the board and placed pieces go back to the hand.
+    fn revert_moves(&mut self, moves: &[Move]) -> JsResult<()> {
+        for m in moves.iter().rev() {
+            match *m {
+                Move::Pickup(coord, piece) => {
+                    let held = self.selected_group.iter().position(|(_, p)| *p == piece);
+
+                    if self.selected_piece == Some(piece) {
+                        self.selected_piece = None;
+                    } else if let Some(i) = held {
+                        self.selected_group.remove(i);
+                    } else {
+                        let _ = self.hand.remove_from_hand(piece);
+                    }
+
+                    let _ = self.board.grid_insert(coord, piece);
+                }
+                Move::Place(coord, piece) => {
+                    let _ = self.board.grid_remove(coord);
+                    self.hand.insert_into_hand(piece);
+                }
+            }
+        }
+
+        self.board.remove_highlight();
+        self.hand.remove_highlight();
+        self.update_hand_value()?;
+
+        Ok(())
+    }
+
+    /// Takes back our last board edit this turn.
+    fn on_undo(&mut self) -> JsResult<()> {
+        if !self.is_turn {
+            return Ok(());
+        }
+
+        let moves = match self.history.pop() {
+            Some(moves) => moves,
+            None => {
+                console_log!("nothing to undo");
+                return Ok(());
+            }
+        };
+
+        console_log!("undo: {:?}", moves);
+
+        self.revert_moves(&moves)?;
+        self.update_cursor_highlight();
+
+        let undo: Vec<Move> = moves.iter().rev().map(|m| m.inverse()).collect();
+        self.send_edits(ClientMessage::Moves(undo.clone()), undo)
+    }
+
+    fn on_end_turn(&mut self) -> JsResult<()> {
+        console_log!("on_end_turn");
+
+        // No need to ask the server about a board we can already see is wrong:
+        match self.predict(Action::EndTurn) {
+            Err(Rejection::InvalidBoard) => return self.on_invalid_board(),
+            Err(Rejection::OnlyRearranged) => return self.on_only_rearranged(),
+            Err(Rejection::KeptBoardPiece(piece)) => return self.on_kept_board_piece(piece),
+            _ => {}
+        }
+
+        self.send_message(ClientMessage::EndTurn)
+    }
+
+    fn on_tidy(&mut self) -> JsResult<()> {
+        if !self.is_turn {
+            return toasts::warning("You can only tidy the board on your turn.");
+        }
+
+        if self.selected_piece.is_some() || !self.selected_group.is_empty() {
+            console_log!("can't tidy while holding pieces");
+            return Ok(());
+        }
+
+        let tidy = tidy_board(self.board.grid(), self.board.cols(), self.vertical_groups);
+        let moves = board_diff(self.board.grid(), &tidy);
+
+        if moves.is_empty() {
+            return Ok(());
+        }
+
+        self.board.transition_to(tidy);
+        self.request_frame()?;
+        self.send_moves(moves)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn on_turn_finished(
+        &mut self,
+        ending_player: String,
+        ending_drew: bool,
+        next_player: usize,
+        hand_sizes: Vec<usize>,
+        pieces_remaining: usize,
+        board: BTreeMap<Coord, Piece>,
+        thinking_ms: Vec<u64>,
+        pool: Option<Vec<(Piece, usize)>>,
+        turn: u64,
+        at_ms: u64,
+    ) -> JsResult<()> {
+        console_log!("Turn Finished for {}", ending_player);
+        console_log!("{} drew? {}", ending_player, ending_drew);
+        console_log!("{} is the next player", self.players[next_player]);
+        console_log!("There are {} pieces remaining", pieces_remaining);
+        console_log!("board: {:?}", board);
+
+        self.stop_countdown()?;
+        self.active_player = next_player;
+        self.set_turn(turn)?;
+        // Only `StartTurn` starts ours, so anyone else's means it's over:
+        if self.my_index() != Some(next_player) {
+            self.is_turn = false;
+        }
+        self.hand_sizes = hand_sizes;
+        self.thinking_ms = thinking_ms;
+        self.turn_started = Utc::now();
+        self.skip_voted = false;
+        self.nudged = false;
+        self.update_pool(pool.as_deref())?;
+
+        let text = if ending_drew {
+            format!("{} drew a piece and ended their turn", ending_player)
+        } else {
+            format!("{} ended their turn", ending_player)
+        };
+        self.events.push_at(&text, at_ms)?;
+
+        self.global
+            .element("current_player")?
+            .set_inner_html(&format!("{}", self.players[next_player]));
+
+        self.global
+            .element("last_player")?
+            .set_inner_html(&format!("{}", ending_player));
+
+        self.global
+            .element("pieces_remaining")?
+            .set_inner_html(&format!("{}", pieces_remaining));
+
+        if self.board.grid() != &board {
+            console_log!("board out of sync, replacing");
+        }
+
+        // Point out what an opponent changed, we know what we did:
+        let changed: Vec<Coord> = if ending_player != self.player_name {
+            board_diff(&self.turn_start_board, &board)
+                .into_iter()
+                .filter_map(|m| match m {
+                    Move::Place(coord, _) => Some(coord),
+                    Move::Pickup(..) => None,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        self.turn_start_board = board.clone();
+
+        // Slide anything that moved and drop tiles that went into a hand:
+        self.board.transition_to(board);
+        self.board.mark_cells(&changed);
+        let ending = self.players.iter().position(|p| *p == ending_player);
+        self.credit(ending);
+        self.credits.end_turn();
+        self.request_frame()?;
+
+        self.update_players()?;
+        self.update_hand_value()?;
+
+        Ok(())
+    }
+
+    /// Checks the hash that came with `TurnFinished`. Everything else was
+    /// just sent with it, so only our hand can be wrong, and nothing of ours
+    /// is still on its way.
+    pub fn on_turn_hash(&mut self, state_hash: u64) -> JsResult<()> {
+        if state_hash != self.state_hash() {
+            self.request_sync()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn on_state_check(&mut self, state_hash: u64) -> JsResult<()> {
+        if state_hash == self.state_hash() {
+            self.out_of_step = false;
+        } else if self.out_of_step {
+            self.request_sync()?;
+        } else {
+            self.out_of_step = true;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces our copy of the game with the server's, after we fell out of
+    /// step or the page was asleep.
+    pub fn on_full_sync(&mut self, snapshot: RoomSnapshot) -> JsResult<()> {
+        let current = self.hand.grid().clone();
+        let our_turn = self.catch_up(snapshot, &current)?;
+        if our_turn && !self.is_turn {
+            self.on_turn_start(self.turn)?;
+        }
+        self.is_turn = our_turn;
+        self.update_turn_banner()?;
+
+        self.events.push("Caught up with the server")
+    }
+
+    /// Takes the server's word for everything in `snapshot`, keeping our
+    /// hand arranged like `arranged` where it can. Returns whether it's our
+    /// turn, for the caller to start or not.
+    fn catch_up(
+        &mut self,
+        snapshot: RoomSnapshot,
+        arranged: &BTreeMap<Coord, Piece>,
+    ) -> JsResult<bool> {
+        let RoomSnapshot {
+            players,
+            hand_sizes,
+            host,
+            view,
+            board,
+            board_size,
+            pieces_remaining,
+            dealt,
+            active_player,
+            phase,
+            scores,
+            disconnected,
+            bots,
+            away,
+            kicked,
+            thinking_ms,
+            turn_ms,
+            pool,
+            turn,
+        } = snapshot;
+        let mut hand = view.into_hand();
+        hand.sort();
+
+        self.global
+            .element("pieces_remaining")?
+            .set_inner_html(&format!("{}", pieces_remaining));
+
+        self.players = players;
+        self.hand_sizes = hand_sizes;
+        self.host = host;
+        self.disconnected = disconnected;
+        self.bots = bots;
+        self.away = away;
+        self.kicked = kicked;
+        self.thinking_ms = thinking_ms;
+        self.turn_started = Utc::now() - chrono::Duration::milliseconds(turn_ms as i64);
+        self.out_of_step = false;
+        self.update_pool(pool.as_deref())?;
+
+        // Anything we were holding is back in the hand we were sent:
+        self.selected_piece = None;
+        self.selected_group.clear();
+        // The server's say on anything we sent before is already in it:
+        self.pending.clear();
+        self.hand.arrange_hand(arranged, &hand);
+
+        // Our own turn keeps what it started from, so undo still works:
+        let our_turn = dealt && phase == Phase::Playing && self.my_index() == Some(active_player);
+        if !(our_turn && self.is_turn) {
+            self.turn_start_board = board.clone();
+        }
+        self.board_size = board_size;
+        self.board.set_size(board_size.rows, board_size.cols);
+        self.board.transition_to(board);
+        self.credit(None);
+        self.request_frame()?;
+
+        self.set_turn(turn)?;
+        if dealt {
+            self.on_current_player(active_player)?;
+        }
+        self.update_players()?;
+        self.update_hand_value()?;
+
+        if let Some(scores) = scores {
+            let standings: Vec<String> = self
+                .players
+                .iter()
+                .zip(&scores)
+                .map(|(player, score)| format!("{} {:+}", player, score))
+                .collect();
+            self.events
+                .push(&format!("The game is over: {}", standings.join(", ")))?;
+        }
+
+        Ok(our_turn)
+    }
+
+    /// Our copy of the game's [`state_hash`], counting anything we're holding
+    /// as still in our hand, like the server does.
+    fn state_hash(&self) -> u64 {
+        let mut hand_sizes = self.hand_sizes.clone();
+        let held = self.selected_group.len() + self.selected_piece.iter().count();
+        if let Some(size) = self.my_index().and_then(|me| hand_sizes.get_mut(me)) {
+            *size = self.hand.grid().len() + held;
+        }
+
+        state_hash(self.board.grid(), &hand_sizes)
+    }
+
+    pub fn on_page_shown(&mut self) -> JsResult<()> {
+        self.send_message(ClientMessage::RequestSync)
+    }
+
+    /// `count` messages from the server never arrived, so whatever they
+    /// said is missing from our copy of the game.
+    pub fn on_missed_messages(&mut self, count: u64) -> JsResult<()> {
+        console_log!("missed {} messages from the server", count);
+        self.request_sync()
+    }
+
+    /// The connection dropped. Unless we meant it to, we try to get back
+    /// into the room a few times before giving up on it.
+    pub fn on_socket_closed(&mut self) -> JsResult<()> {
+        if self.conn.is_hung_up() {
+            return Ok(());
+        }
+
+        // Without a room yet there's nothing to get back into:
+        if self.room_name.is_empty() {
+            toasts::after_reload("Lost the connection to the server")?;
+            return self.global.window.location().set_search("");
+        }
+
+        if !self.conn.retry_later()? {
+            toasts::after_reload("Couldn't get back into the room")?;
+            return self.global.window.location().set_search("");
+        }
+
+        Ok(())
+    }
+
+    pub fn reconnect(&mut self) -> JsResult<()> {
+        console_log!("reconnecting to {}", self.room_name);
+        self.conn.reconnect()
+    }
+
+    /// Asks for our seat back. Until the room gives it to us, everything
+    /// else waits.
+    pub fn on_reconnected(&mut self) -> JsResult<()> {
+        if let Some(log_in) = log_in_message(&self.global, &self.player_name)? {
+            self.conn.send_now(log_in)?;
+        }
+
+        let join = match &self.joining {
+            // We were sent here before we were ever in, so ask again:
+            Some(room) => join_message(&self.player_name, &self.password, room),
+            None => ClientMessage::JoinRoom(
+                self.player_name.clone(),
+                self.room_name.clone(),
+                self.password.clone(),
+            ),
+        };
+        self.conn.send_now(join)
+    }
+
+    /// The room we asked for runs on another server, so we go there.
+    pub fn on_redirect(&mut self, ws_url: String) -> JsResult<()> {
+        console_log!("the room is at {}", ws_url);
+        self.conn.redirect(&ws_url)
+    }
+
+    fn request_sync(&mut self) -> JsResult<()> {
+        console_log!("out of step with the server, asking for the whole game");
+        self.out_of_step = false;
+        self.send_message(ClientMessage::RequestSync)
+    }
+
+    pub fn on_turn_start(&mut self, turn: u64) -> JsResult<()> {
+        self.is_turn = true;
+        self.set_turn(turn)?;
+        self.history.clear();
+        self.update_vote_skip()?;
+        self.update_turn_banner()?;
+
+        if settings::get().sound {
+            // Browsers refuse to play sound before the page is interacted with:
+            if let Err(e) = crate::play_chime() {
+                console_log!("couldn't play chime: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn on_end_turn_valid(&mut self) -> JsResult<()> {
+        self.opened |= self.points_played() > 0;
+        self.is_turn = false;
+        self.history.clear();
+        self.update_vote_skip()?;
+        self.update_turn_banner()?;
+        Ok(())
+    }
+
+    /// The game's been dealt, with everyone's seat in the order they take
+    /// turns. Whoever's first is told it's their turn on its own.
+    pub fn on_game_start(&mut self, order: Vec<usize>, at_ms: u64) -> JsResult<()> {
+        let first = match order.first() {
+            Some(&first) => first,
+            None => return Ok(()),
+        };
+
+        self.events.push_at(
+            &format!(
+                "{} drew the highest piece and goes first",
+                self.players[first]
+            ),
+            at_ms,
+        )?;
+        self.set_turn(1)?;
+        self.on_current_player(first)
+    }
+
+    /// Shows which turn of the game it is, for anyone reporting a bug to
+    /// point at.
+    fn set_turn(&mut self, turn: u64) -> JsResult<()> {
+        self.turn = turn;
+        let text = if turn == 0 {
+            "Not started".to_string()
+        } else {
+            turn.to_string()
+        };
+        self.global
+            .element("turn_number")?
+            .set_text_content(Some(&text));
+
+        Ok(())
+    }
+
+    pub fn on_player_joined(&mut self, name: String) -> JsResult<()> {
+        console_log!("{} joined", name);
+
+        self.events.push(&format!("{} joined", name))?;
+        self.players.push(name);
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    pub fn on_player_disconnected(&mut self, idx: usize) -> JsResult<()> {
+        console_log!("on_player_disconnected");
+        self.disconnected.push(idx);
+        self.events
+            .push(&format!("{} disconnected", self.players[idx]))?;
+
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    /// We're logged in to the account `name`, and play under it.
+    pub fn on_logged_in(&mut self, name: String, token: String) -> JsResult<()> {
+        settings::update(|s| {
+            s.name = name.clone();
+            s.account = name.clone();
+            s.token = token;
+        })?;
+
+        self.events.push(&format!("Logged in as {}", name))?;
+        self.player_name = name;
+
+        Ok(())
+    }
+
+    /// The server wouldn't let us in, so say why and go back to the lobby.
+    pub fn on_server_error(&mut self, error: ServerError) -> JsResult<()> {
+        console_log!("server error: {:?}", error);
+
+        // Only says why moves were rejected, and those are rolled back
+        // already:
+        if let ServerError::OffBoard(_) = error {
+            return toasts::warning(&error.to_string());
+        }
+
+        self.conn.hang_up()?;
+
+        // A token that didn't work won't next time either:
+        if error == ServerError::BadLogin {
+            settings::update(|s| s.token.clear())?;
+        }
+        // Dropping the room from the url keeps us from joining it again, and
+        // reloads the page, so the error's shown once that's done:
+        toasts::after_reload(&error.to_string())?;
+        self.global.window.location().set_search("")
+    }
+
+    /// The game as far as we can see it: the board, our own hand and whose
+    /// turn it is. Other hands and the pool are left empty.
+    fn predicted_state(&self) -> GameState {
+        let mut game = Game::default();
+        game.set_board(self.board.grid().clone());
+
+        let mut hands = vec![Vec::new(); self.players.len()];
+        let mut active_player = self.active_player;
+        if let Some(me) = self.my_index() {
+            let hand = &mut hands[me];
+            hand.extend(self.hand.grid().values().copied());
+            hand.extend(self.selected_piece);
+            hand.extend(self.selected_group.iter().map(|&(_, piece)| piece));
+
+            // The server tells us our turn started before it says whose it is:
+            if self.is_turn {
+                active_player = me;
+            }
+        }
+
+        GameState {
+            game,
+            hands,
+            active_player,
+            turn_start_board: self.turn_start_board.clone(),
+            winner: None,
+            vertical_groups: self.vertical_groups,
+            board_size: self.board_size,
+        }
+    }
+
+    /// Whether the server will accept `action` from us, according to the
+    /// same rules it plays by.
+    fn predict(&self, action: Action) -> Result<(), Rejection> {
+        let me = self.my_index().ok_or(Rejection::NotYourTurn)?;
+
+        reduce(&self.predicted_state(), me, &action).map(|_| ())
+    }
+
+    fn my_index(&self) -> Option<usize> {
+        self.players.iter().position(|p| *p == self.player_name)
+    }
+
+    fn is_host(&self) -> bool {
+        self.players.get(self.host) == Some(&self.player_name)
+    }
+
+    fn on_kick(&mut self, idx: usize) -> JsResult<()> {
+        let question = format!("Remove {} from the room?", self.players[idx]);
+        if self.global.window.confirm_with_message(&question)? {
+            self.send_message(ClientMessage::Kick(idx))?;
+        }
+
+        Ok(())
+    }
+
+    fn on_make_bot(&mut self, idx: usize) -> JsResult<()> {
+        self.send_message(ClientMessage::MakeBot(idx))
+    }
+
+    pub fn on_player_kicked(&mut self, idx: usize) -> JsResult<()> {
+        if self.players[idx] == self.player_name {
+            self.is_turn = false;
+            self.events.push("The host removed you from the room")?;
+            toasts::warning("The host removed you from the room")?;
+            self.conn.hang_up()?;
+            return Ok(());
+        }
+
+        self.kicked.push(idx);
+        self.events
+            .push(&format!("{} was removed by the host", self.players[idx]))?;
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    pub fn on_player_became_bot(&mut self, idx: usize) -> JsResult<()> {
+        self.bots.push(idx);
+        self.events
+            .push(&format!("A bot took over for {}", self.players[idx]))?;
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    pub fn on_player_away(&mut self, idx: usize) -> JsResult<()> {
+        self.away.push(idx);
+        if self.players[idx] == self.player_name {
+            self.events
+                .push("You're away, so your turns are skipped. Play, or say you're back, to take them again")?;
+        } else {
+            self.events
+                .push(&format!("{} is away", self.players[idx]))?;
+        }
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    pub fn on_player_back(&mut self, idx: usize) -> JsResult<()> {
+        self.away.retain(|&i| i != idx);
+        self.events
+            .push(&format!("{} is back", self.players[idx]))?;
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    /// The server's operators have something to say to everyone, like that
+    /// it's going down for maintenance. It's up on the banner too, see
+    /// [`crate::announcement`], but this keeps it after that's dismissed.
+    pub fn on_announcement(&mut self, text: String) -> JsResult<()> {
+        self.events.push(&format!("From the server: {}", text))
+    }
+
+    /// What went on while we were gone, to catch up on in the log. The
+    /// snapshot we joined with already has it all in, so none of it is
+    /// played out again.
+    pub fn on_history(&mut self, past: Vec<PastMessage>) -> JsResult<()> {
+        for PastMessage { at_ms, msg } in past {
+            if let Some(text) = self.describe_past(&msg) {
+                self.events.push_past(&text, at_ms)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How the log would have put `msg`, had we been here for it. Seats are
+    /// as they were then, so ones that aren't any more are left out.
+    fn describe_past(&self, msg: &ServerMessage) -> Option<String> {
+        let name = |idx: &usize| self.players.get(*idx);
+
+        let text = match msg {
+            ServerMessage::PlayerJoined(name) => format!("{} joined", name),
+            ServerMessage::PlayerDisconnected(idx) => format!("{} disconnected", name(idx)?),
+            ServerMessage::PlayerReconnected(idx) => format!("{} reconnected", name(idx)?),
+            ServerMessage::PlayerKicked(idx) => format!("{} was removed by the host", name(idx)?),
+            ServerMessage::PlayerBecameBot(idx) => format!("A bot took over for {}", name(idx)?),
+            ServerMessage::HostChanged(idx) => format!("{} is now the host", name(idx)?),
+            ServerMessage::TurnSkipped(idx) => format!("{}'s turn was skipped", name(idx)?),
+            ServerMessage::PlayerAway(idx) => format!("{} is away", name(idx)?),
+            ServerMessage::PlayerBack(idx) => format!("{} is back", name(idx)?),
+            ServerMessage::StartGame { order, .. } => format!(
+                "{} drew the highest piece and goes first",
+                name(order.first()?)?
+            ),
+            ServerMessage::TurnFinished {
+                ending_player,
+                ending_drew: true,
+                ..
+            } => format!("{} drew a piece and ended their turn", ending_player),
+            ServerMessage::TurnFinished { ending_player, .. } => {
+                format!("{} ended their turn", ending_player)
+            }
+            ServerMessage::Place(id, _, piece) => {
+                format!("{} placed {}", name(&id.seat)?, describe_piece(piece))
+            }
+            ServerMessage::Pickup(id, _, piece) => {
+                format!("{} picked up {}", name(&id.seat)?, describe_piece(piece))
+            }
+            ServerMessage::Moves(id, moves) => {
+                format!("{} moved {} pieces", name(&id.seat)?, moves.len())
+            }
+            ServerMessage::PlayerWon { winner, .. } => format!("{} won the game", winner),
+            ServerMessage::Announcement { text, .. } => format!("From the server: {}", text),
+            _ => return None,
+        };
+
+        Some(text)
+    }
+
+    pub fn on_waiting_for_reconnect(&mut self, player: usize, wait_secs: u64) -> JsResult<()> {
+        self.events.push(&format!(
+            "Waiting {}s for {} to reconnect",
+            wait_secs, self.players[player]
+        ))?;
+
+        self.stop_countdown()?;
+        let tick = Closure::wrap(Box::new(|| {
+            let _ = STATE.lock().unwrap().on_countdown_tick();
+        }) as Box<dyn FnMut()>);
+        let interval = self
+            .global
+            .window
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                tick.as_ref().unchecked_ref(),
+                1_000,
+            )?;
+
+        self.countdown = Some(Countdown {
+            until: Utc::now() + chrono::Duration::seconds(wait_secs as i64),
+            interval,
+            _tick: tick,
+        });
+
+        self.on_countdown_tick()
+    }
+
+    pub fn on_countdown_tick(&mut self) -> JsResult<()> {
+        let left = match &self.countdown {
+            Some(countdown) => (countdown.until - Utc::now()).num_seconds().max(0),
+            None => return Ok(()),
+        };
+
+        self.global
+            .element("current_player")?
+            .set_inner_html(&format!(
+                "{} (reconnecting, {}s)",
+                self.players[self.active_player], left
+            ));
+
+        Ok(())
+    }
+
+    /// Stops counting down, putting the active player back on its own.
+    fn stop_countdown(&mut self) -> JsResult<()> {
+        if let Some(countdown) = self.countdown.take() {
+            self.global
+                .window
+                .clear_interval_with_handle(countdown.interval);
+            self.global
+                .element("current_player")?
+                .set_inner_html(&self.players[self.active_player]);
+        }
+
+        Ok(())
+    }
+
+    pub fn on_host_changed(&mut self, idx: usize) -> JsResult<()> {
+        self.host = idx;
+        self.events
+            .push(&format!("{} is now the host", self.players[idx]))?;
+        self.update_players()?;
+
+        Ok(())
+    }
+
+    pub fn on_current_player(&mut self, idx: usize) -> JsResult<()> {
+        self.global
+            .element("current_player")?
+            .set_inner_html(&format!("{}", self.players[idx]));
+
+        self.global.element("last_player")?.set_inner_html("N/A");
+
+        self.active_player = idx;
+        // Only `StartTurn` starts ours, so anyone else's means it's over:
+        if self.my_index() != Some(idx) {
+            self.is_turn = false;
+        }
+        self.update_players()?;
+
+        self.start_clock()
+    }
+
+    /// Starts ticking the active player's clock, if it isn't already.
+    fn start_clock(&mut self) -> JsResult<()> {
+        if self.clock.is_some() {
+            return Ok(());
+        }
+
+        let tick = Closure::wrap(Box::new(|| {
+            let _ = STATE.lock().unwrap().on_clock_tick();
+        }) as Box<dyn FnMut()>);
+        let interval = self
+            .global
+            .window
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                tick.as_ref().unchecked_ref(),
+                1_000,
+            )?;
+
+        self.clock = Some(Clock {
+            interval,
+            _tick: tick,
+        });
+
+        Ok(())
+    }
+
+    fn stop_clock(&mut self) {
+        if let Some(clock) = self.clock.take() {
+            self.global
+                .window
+                .clear_interval_with_handle(clock.interval);
+        }
+    }
+
+    pub fn on_clock_tick(&mut self) -> JsResult<()> {
+        let active = self.active_player;
+        let selector = format!("[data-clock=\"{}\"]", active);
+        if let Some(cell) = self.global.root.query_selector(&selector)? {
+            cell.set_text_content(Some(&format_clock(self.thinking_ms(active))));
+        }
+
+        self.events.refresh()
+    }
+
+    /// How long `idx` has spent on their turns, counting the current one if
+    /// it's theirs.
+    fn thinking_ms(&self, idx: usize) -> u64 {
+        let before = self.thinking_ms.get(idx).copied().unwrap_or(0);
+        if idx != self.active_player || self.players.len() < 2 {
+            return before;
+        }
+
+        let this_turn = (Utc::now() - self.turn_started).num_milliseconds().max(0);
+        before + this_turn as u64
+    }
+
+    pub fn on_player_reconnected(&mut self, idx: usize) -> JsResult<()> {
+        for i in 0..self.disconnected.len() {
+            if self.disconnected[i] == idx {
+                self.disconnected.swap_remove(i);
+                break;
+            }
+        }
+        self.bots.retain(|&i| i != idx);
+        if idx == self.active_player {
+            self.stop_countdown()?;
+        }
+
+        self.events
+            .push(&format!("{} reconnected", self.players[idx]))?;
+
+        self.update_players()?;
 
         Ok(())
     }
 
-    fn on_invalid_board(&mut self) -> JsResult<()> {
+    pub fn on_player_won(
+        mut self,
+        winner: String,
+        hands: Vec<Vec<Piece>>,
+        at_ms: u64,
+    ) -> JsResult<GameOver> {
+        self.stop_clock();
+        self.global
+            .element("turn_banner")?
+            .set_attribute("hidden", "")?;
+        self.events
+            .push_at(&format!("{} won the game", winner), at_ms)?;
+        if winner == self.player_name {
+            toasts::success("You won!")?;
+        }
+        // The server counted the game before telling us about it:
+        self.send_message(ClientMessage::Stats(self.player_name.clone()))?;
+        GameOver::new(self, winner, hands)
+    }
+
+    /// We're waiting for the server to find us a ranked room.
+    pub fn on_in_ranked_queue(&mut self, rating: i32) -> JsResult<()> {
+        self.global
+            .element("room")?
+            .set_inner_html("Finding players...");
+
+        self.events
+            .push(&format!("Finding players rated near {}", rating))?;
+
+        Ok(())
+    }
+
+    /// Shows our record across games. The server answers for anyone, but we
+    /// only ask about ourselves.
+    pub fn on_player_stats(&mut self, name: String, stats: PlayerStats) -> JsResult<()> {
+        if name != self.player_name {
+            return Ok(());
+        }
+
+        let mut text = match stats.games_played {
+            0 => "No games yet".to_string(),
+            games => format!(
+                "{} won of {} played, {:.0}s and {:.1} tiles a turn",
+                stats.wins,
+                games,
+                stats.avg_turn_secs(),
+                stats.avg_tiles_per_turn()
+            ),
+        };
+        if stats.ranked_games > 0 {
+            text.push_str(&format!(", rated {}", stats.rating));
+        }
+
+        self.global.element("stats")?.set_text_content(Some(&text));
+
+        Ok(())
+    }
+
+    /// Done with the room: tells it we're going, and takes everything we
+    /// put on the page back off so the lobby can be shown in its place.
+    fn leave(mut self) -> JsResult<Global> {
+        if !self.conn.is_hung_up() {
+            self.conn.send_now(ClientMessage::Close)?;
+        }
+        self.conn.hang_up()?;
+
+        self.stop_countdown()?;
+        self.stop_clock();
+        if let Some(frame) = self.frame.take() {
+            self.global.window.cancel_animation_frame(frame)?;
+        }
+        self.resize_observer.disconnect();
+
+        // Our callbacks come off the page as they're dropped along with us:
+        self.board_div.set_inner_html("");
+        self.hand_div.set_inner_html("");
+        self.players_div.set_inner_html("");
+        self.global.element("event_log")?.set_inner_html("");
+        self.global
+            .element("playing")?
+            .set_attribute("hidden", "")?;
+
+        // Coming back to the page shouldn't join the room again:
+        let path = self.global.window.location().pathname()?;
         self.global
             .window
-            .alert_with_message("The board is in an invalid state")
+            .history()?
+            .replace_state_with_url(&JsValue::NULL, "", Some(&path))?;
+
+        Ok(self.global)
+    }
+
+    /// Gives up our seat for the lobby, whether the game's over or not.
+    pub fn on_leave_game(self) -> JsResult<CreateOrJoin> {
+        let global = self.leave()?;
+
+        let lobby = global.element("create_or_join")?;
+        lobby.remove_attribute("style")?;
+        lobby.set_attribute("hidden", "")?;
+
+        CreateOrJoin::new(global)
+    }
+
+    /// Clears the last game away before the server deals again.
+    fn reset_for_rematch(&mut self) {
+        self.board.set_grid(BTreeMap::new());
+        self.hand.set_grid(BTreeMap::new());
+        self.selected_piece = None;
+        self.selected_group.clear();
+        self.history.clear();
+        self.pending.clear();
+        self.is_turn = false;
+        self.hand_sizes.clear();
+        self.turn_start_board.clear();
+        self.opened = false;
+        self.thinking_ms.clear();
+        self.turn_started = Utc::now();
     }
 
-    fn on_piece_place(&mut self, coord: Coord, piece: Piece) -> JsResult<()> {
+    /// What we've put on the board from our hand this turn.
+    fn points_played(&self) -> u32 {
         if !self.is_turn {
-            console_log!("place: {:?} {:?}", coord, piece);
+            return 0;
+        }
 
-            if let Some(old) = self.board.grid_insert(coord, piece) {
-                if !self.is_turn {
-                    console_log!("[ERROR] overwriting piece: {:?}", old);
-                }
+        points(self.board.grid().values()).saturating_sub(points(self.turn_start_board.values()))
+    }
+
+    /// Shows what's left in the pool, in rooms that tell us.
+    fn update_pool(&self, pool: Option<&[(Piece, usize)]>) -> JsResult<()> {
+        let pool_box = self.global.element("pool_box")?;
+
+        match pool {
+            Some(pool) => {
+                self.global
+                    .element("pool")?
+                    .set_inner_html(&pool_histogram(pool));
+                pool_box.remove_attribute("hidden")
             }
+            None => pool_box.set_attribute("hidden", ""),
+        }
+    }
+
+    /// Remembers how the hand's laid out, for when we come back.
+    fn save_hand(&self) {
+        hands::save(&self.room_name, &self.player_name, self.hand.grid());
+    }
+
+    /// Shows what our hand is worth, counting anything we're holding, and how
+    /// far this turn's play is from opening if we haven't yet.
+    fn update_hand_value(&mut self) -> JsResult<()> {
+        let held = self
+            .selected_group
+            .iter()
+            .map(|(_, piece)| piece)
+            .chain(&self.selected_piece);
+        let value = points(self.hand.grid().values().chain(held));
+
+        let text = match OPENING_POINTS.checked_sub(self.points_played()) {
+            _ if self.opened => format!("{} points", value),
+            Some(needed) if needed > 0 => format!("{} points, {} more to open", value, needed),
+            _ => format!("{} points, enough to open", value),
+        };
+
+        self.global
+            .element("hand_value")?
+            .set_text_content(Some(&text));
+
+        Ok(())
+    }
+
+    pub fn on_window_resize(&mut self) -> JsResult<()> {
+        self.board.resize();
+        self.hand.resize();
+        Ok(())
+    }
 
-            self.board.rerender();
+    /// Keeps animation frames coming while the board has tiles sliding.
+    fn request_frame(&mut self) -> JsResult<()> {
+        if self.board.is_animating() && self.frame.is_none() {
+            let frame = self
+                .global
+                .window
+                .request_animation_frame(&self.on_animation_frame)?;
+            self.frame = Some(frame);
         }
 
         Ok(())
     }
 
-    fn on_pickup(&mut self, coord: Coord, piece: Piece) -> JsResult<()> {
-        if !self.is_turn {
-            console_log!("pickup: {:?} {:?}", coord, piece);
+    fn on_animation_frame(&mut self, now: f64) -> JsResult<()> {
+        self.frame = None;
+        self.board.step_animations(now);
+        self.request_frame()
+    }
 
-            if let Some(removed) = self.board.grid_remove(coord) {
-                console_log!("{:?}: removed {:?}, expected {:?}", coord, removed, piece);
+    fn send_message(&mut self, msg: ClientMessage) -> JsResult<()> {
+        self.conn.send(msg).map(|_| ())
+    }
+
+    pub fn send_ping(&mut self) -> JsResult<()> {
+        self.send_message(ClientMessage::Ping)
+    }
+
+    pub fn rerender(&mut self) {
+        self.board.rerender();
+        self.hand.rerender();
+    }
+
+    /// Catches the credits up with the board, after `mover` changed it if
+    /// we know who did.
+    fn credit(&mut self, mover: Option<usize>) {
+        self.credits.update(self.board.grid(), mover);
+        self.outline_credits();
+    }
+
+    fn outline_credits(&mut self) {
+        let cells = if settings::get().who_played {
+            self.credits.cells()
+        } else {
+            Vec::new()
+        };
+        self.board.outline_credits(cells);
+    }
+
+    /// Shows or hides who played each tile, as the setting was changed.
+    pub fn update_credits(&mut self) -> JsResult<()> {
+        self.outline_credits();
+        self.update_players()
+    }
+}
+
+/// The room from a `?room=abcdef` link.
+fn linked_room(window: &Window) -> Option<String> {
+    let search = window.location().search().ok()?;
+    let params = UrlSearchParams::new_with_str(&search).ok()?;
+
+    params.get("room").filter(|room| !room.is_empty())
+}
+
+/// A once a second tick while the active player's turn waits for them.
+pub struct Countdown {
+    until: DateTime<Utc>,
+    interval: i32,
+    _tick: Closure<dyn FnMut()>,
+}
+
+/// A bar for every piece showing how many of it are left in `pool`, a row
+/// per color, and how many jokers.
+fn pool_histogram(pool: &[(Piece, usize)]) -> String {
+    let theme = settings::get().theme.tiles();
+    let left = |piece: Piece| {
+        pool.iter()
+            .find(|(p, _)| *p == piece)
+            .map_or(0, |(_, count)| *count)
+    };
+
+    let mut html = String::from("<table>");
+    for &color in &[Color::Red, Color::Blue, Color::Yellow, Color::Black] {
+        html.push_str("<tr>");
+        for num in 1..=13 {
+            let count = left(Piece::new(color, num));
+            html.push_str(&format!(
+                "<td title=\"{} {}: {} left\"><div class=\"pool_bar\" \
+                 style=\"height: {}px; background-color: {}\"></div></td>",
+                color,
+                num,
+                count,
+                count * 6,
+                theme.ink(color)
+            ));
+        }
+        html.push_str("</tr>");
+    }
+
+    html.push_str("<tr class=\"pool_nums\">");
+    for num in 1..=13 {
+        html.push_str(&format!("<td>{}</td>", num));
+    }
+    html.push_str("</tr></table>");
+
+    html.push_str(&format!("<div>Jokers: {}</div>", left(Piece::joker())));
+    html
+}
+
+/// A once a second tick for the active player's clock.
+pub struct Clock {
+    interval: i32,
+    _tick: Closure<dyn FnMut()>,
+}
+
+/// A time spent thinking, like a chess clock shows it, e.g. "4:07" or
+/// "1:02:45".
+fn format_clock(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, mins, secs)
+    } else {
+        format!("{}:{:02}", mins, secs)
+    }
+}
+
+/// The longest an auto-playing replay waits for the next event, however
+/// long the game did, in milliseconds. Nobody needs to watch someone think.
+const REPLAY_MAX_PAUSE_MS: u64 = 5_000;
+
+type ReplayAction = fn(&mut GameOver) -> JsResult<()>;
+
+/// The replay's buttons, and what each does to the finished game.
+const REPLAY_BUTTONS: [(&str, ReplayAction); 6] = [
+    ("watch_replay", GameOver::on_watch_replay),
+    ("download_game", GameOver::on_download_game),
+    ("replay_step", GameOver::on_replay_step),
+    ("replay_turn", GameOver::on_replay_turn),
+    ("replay_auto", GameOver::on_replay_auto),
+    ("replay_close", GameOver::on_replay_close),
+];
+
+/// The final standings shown over the board once someone has won.
+pub struct GameOver {
+    pub playing: Playing,
+    /// The standings' and replay's buttons, which stay on the page after
+    /// we're gone.
+    _callbacks: CallbackRegistry,
+    /// The saved game being read in, and what takes it once it is.
+    reading: Option<(FileReader, JsClosure<ProgressEvent>)>,
+    /// What the server kept the game as, once it's said.
+    game_id: Option<String>,
+    /// Whether the game is being fetched to download rather than watch.
+    download_requested: bool,
+    /// The game being played back on the board, read-only.
+    replay: Option<Replayer>,
+    /// Steps the replay on a timer, while it's playing itself.
+    auto_play: Option<(i32, Closure<dyn FnMut()>)>,
+}
+
+impl GameOver {
+    pub fn new(playing: Playing, winner: String, hands: Vec<Vec<Piece>>) -> JsResult<Self> {
+        let doc: &Document = &playing.global.doc;
+
+        playing
+            .global
+            .element("winner")?
+            .set_text_content(Some(&format!("{} won!", winner)));
+
+        let scores = playing
+            .players
+            .iter()
+            .position(|name| *name == winner)
+            .map(|winner| scores(&hands, winner))
+            .unwrap_or_default();
+
+        let mut standings: Vec<(&String, usize, i64)> = playing
+            .players
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let tiles = hands.get(i).map(Vec::len).unwrap_or(0);
+                (name, tiles, scores.get(i).copied().unwrap_or(0))
+            })
+            .collect();
+        standings.sort_by_key(|(_, _, score)| -score);
+
+        let body = playing.global.element("standings_body")?;
+        body.set_inner_html("");
+
+        for (name, tiles, score) in standings {
+            let row = doc.create_element("tr")?;
+
+            for text in &[name.clone(), tiles.to_string(), score.to_string()] {
+                let cell = doc.create_element("td")?;
+                cell.set_text_content(Some(text));
+                row.append_child(&cell)?;
+            }
+
+            body.append_child(&row)?;
+        }
+
+        let rematch: HtmlButtonElement = playing.global.element("rematch")?.dyn_into()?;
+        rematch.set_disabled(false);
+        rematch.set_text_content(Some("Rematch"));
+
+        let mut callbacks = CallbackRegistry::default();
+        callbacks.listen(&rematch, "click", move |e: MouseEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_rematch()
+        });
+
+        let leave = playing.global.element("leave_room")?;
+        callbacks.listen(&leave, "click", move |e: MouseEvent| {
+            e.prevent_default();
+            STATE.lock().unwrap().on_leave_room()
+        });
+
+        for &(id, action) in &REPLAY_BUTTONS {
+            let button = playing.global.element(id)?;
+            callbacks.listen(&button, "click", move |e: MouseEvent| {
+                e.prevent_default();
+                match STATE.lock().unwrap().game_over() {
+                    Some(game_over) => action(game_over),
+                    None => Ok(()),
+                }
+            });
+        }
+
+        let open_game = playing.global.element("open_game")?;
+        // Opens a game someone saved, to watch in place of ours:
+        callbacks.listen(&open_game, "change", move |_e: Event| {
+            match STATE.lock().unwrap().game_over() {
+                Some(game_over) => game_over.on_open_game(),
+                None => Ok(()),
             }
+        });
+
+        // Only once the server says it kept the game:
+        for id in &["watch_replay", "download_game"] {
+            let button: HtmlButtonElement = playing.global.element(id)?.dyn_into()?;
+            button.set_disabled(true);
+        }
 
-            self.board.rerender();
+        playing
+            .global
+            .element("game_over")?
+            .remove_attribute("hidden")?;
+
+        Ok(Self {
+            playing,
+            _callbacks: callbacks,
+            reading: None,
+            game_id: None,
+            download_requested: false,
+            replay: None,
+            auto_play: None,
+        })
+    }
+
+    pub fn on_game_saved(&mut self, id: String) -> JsResult<()> {
+        for id in &["watch_replay", "download_game"] {
+            let button: HtmlButtonElement = self.playing.global.element(id)?.dyn_into()?;
+            button.set_disabled(false);
         }
 
+        self.game_id = Some(id);
         Ok(())
     }
 
-    fn on_end_turn(&mut self) -> JsResult<()> {
-        console_log!("on_end_turn");
-        self.send_message(ClientMessage::EndTurn)
+    fn on_watch_replay(&mut self) -> JsResult<()> {
+        self.download_requested = false;
+        self.fetch_game()
     }
 
-    fn on_turn_finished(
-        &mut self,
-        ending_player: String,
-        ending_drew: bool,
-        next_player: usize,
-        pieces_remaining: usize,
-        board: BTreeMap<Coord, Piece>,
-    ) -> JsResult<()> {
-        console_log!("Turn Finished for {}", ending_player);
-        console_log!("{} drew? {}", ending_player, ending_drew);
-        console_log!("{} is the next player", self.players[next_player]);
-        console_log!("There are {} pieces remaining", pieces_remaining);
-        console_log!("board: {:?}", board);
+    fn on_download_game(&mut self) -> JsResult<()> {
+        self.download_requested = true;
+        self.fetch_game()
+    }
 
-        self.active_player = next_player;
+    fn fetch_game(&mut self) -> JsResult<()> {
+        match self.game_id.clone() {
+            Some(id) => self.playing.send_message(ClientMessage::FetchReplay(id)),
+            None => Ok(()),
+        }
+    }
 
-        self.global
-            .doc
-            .get_element_by_id("current_player")
-            .unwrap()
-            .set_inner_html(&format!("{}", self.players[next_player]));
+    /// The game came back from the server, to download or play back.
+    pub fn on_replay(&mut self, id: String, record: Option<GameRecord>) -> JsResult<()> {
+        let record = match record {
+            Some(record) => record,
+            None => {
+                let msg = format!("The server doesn't have the game {} any more", id);
+                return toasts::error(&msg);
+            }
+        };
 
-        self.global
-            .doc
-            .get_element_by_id("last_player")
-            .unwrap()
-            .set_inner_html(&format!("{}", ending_player));
+        if self.download_requested {
+            self.download_requested = false;
+            return download(&self.playing.global.doc, &record);
+        }
 
-        self.global
-            .doc
-            .get_element_by_id("pieces_remaining")
-            .unwrap()
-            .set_inner_html(&format!("{}", pieces_remaining));
+        self.watch(record)
+    }
+
+    /// Reads in the saved game just picked, once the browser has it.
+    fn on_open_game(&mut self) -> JsResult<()> {
+        let input: HtmlInputElement = self.playing.global.element("open_game")?.dyn_into()?;
+        let file = match input.files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let reader = FileReader::new()?;
+        let on_load = set_event_cb(&reader, "load", move |e: ProgressEvent| {
+            let reader: FileReader = e.target().unwrap().dyn_into()?;
+            let json = reader.result()?.as_string().unwrap_or_default();
+            match STATE.lock().unwrap().game_over() {
+                Some(game_over) => game_over.on_game_opened(&json),
+                None => Ok(()),
+            }
+        });
+        reader.read_as_text(&file)?;
 
-        self.update_players();
-        self.rerender();
+        // Picking the same file again should open it again:
+        input.set_value("");
+        self.reading = Some((reader, on_load));
 
         Ok(())
     }
 
-    pub fn on_turn_start(&mut self) -> JsResult<()> {
-        self.is_turn = true;
-        Ok(())
+    fn on_game_opened(&mut self, json: &str) -> JsResult<()> {
+        self.reading = None;
+        match GameRecord::import(json) {
+            Ok(record) => self.watch(record),
+            Err(e) => toasts::error(&e.to_string()),
+        }
     }
 
-    pub fn on_end_turn_valid(&mut self) -> JsResult<()> {
-        self.is_turn = false;
-        Ok(())
+    /// Plays `record` back from the start in place of the standings.
+    fn watch(&mut self, record: GameRecord) -> JsResult<()> {
+        self.stop_auto_play()?;
+
+        self.playing
+            .global
+            .element("game_over")?
+            .set_attribute("hidden", "")?;
+        self.playing
+            .global
+            .element("replay_controls")?
+            .remove_attribute("hidden")?;
+
+        // The board only shows the game, nothing on it is ours to move:
+        self.playing.is_turn = false;
+        self.replay = Some(Replayer::new(&record));
+        self.show_replay()
     }
 
-    pub fn on_player_joined(&mut self, name: String) -> JsResult<()> {
-        console_log!("{} joined", name);
+    fn on_replay_step(&mut self) -> JsResult<()> {
+        let stepped = self.replay.as_mut().is_some_and(Replayer::step);
+        if !stepped {
+            self.stop_auto_play()?;
+        }
 
-        self.players.push(name);
-        self.update_players();
+        self.show_replay()
+    }
 
-        Ok(())
+    fn on_replay_turn(&mut self) -> JsResult<()> {
+        if let Some(replay) = &mut self.replay {
+            replay.next_turn();
+        }
+
+        self.show_replay()
     }
 
-    pub fn on_player_disconnected(&mut self, idx: usize) -> JsResult<()> {
-        console_log!("on_player_disconnected");
-        self.disconnected.push(idx);
+    /// Starts or stops playing the game back at the speed it was played.
+    fn on_replay_auto(&mut self) -> JsResult<()> {
+        if self.auto_play.is_some() {
+            return self.stop_auto_play();
+        }
 
-        self.update_players();
+        let tick = Closure::wrap(Box::new(|| {
+            if let Some(game_over) = STATE.lock().unwrap().game_over() {
+                let _ = game_over.on_replay_tick();
+            }
+        }) as Box<dyn FnMut()>);
+        self.auto_play = Some((0, tick));
+        self.schedule_replay_step()?;
+
+        self.playing
+            .global
+            .element("replay_auto")?
+            .set_text_content(Some("Pause"));
 
         Ok(())
     }
 
-    pub fn on_current_player(&mut self, idx: usize) -> JsResult<()> {
-        self.global
-            .doc
-            .get_element_by_id("current_player")
-            .unwrap()
-            .set_inner_html(&format!("{}", self.players[idx]));
+    fn on_replay_tick(&mut self) -> JsResult<()> {
+        self.on_replay_step()?;
+        self.schedule_replay_step()
+    }
 
-        self.global
-            .doc
-            .get_element_by_id("last_player")
-            .unwrap()
-            .set_inner_html("N/A");
+    /// Waits as long as the game did before its next event, if it's being
+    /// played back, and stops once there are none left.
+    fn schedule_replay_step(&mut self) -> JsResult<()> {
+        let pause_ms = match self.replay.as_ref().and_then(Replayer::pause_ms) {
+            Some(pause_ms) => pause_ms.min(REPLAY_MAX_PAUSE_MS),
+            None => return self.stop_auto_play(),
+        };
 
-        self.active_player = idx;
-        self.update_players();
+        if let Some((timeout, tick)) = &mut self.auto_play {
+            *timeout = self
+                .playing
+                .global
+                .window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    tick.as_ref().unchecked_ref(),
+                    pause_ms as i32,
+                )?;
+        }
 
         Ok(())
     }
 
-    pub fn on_player_reconnected(&mut self, idx: usize) -> JsResult<()> {
-        for i in 0..self.disconnected.len() {
-            if self.disconnected[i] == idx {
-                self.disconnected.swap_remove(i);
-                break;
-            }
+    fn stop_auto_play(&mut self) -> JsResult<()> {
+        if let Some((timeout, _tick)) = self.auto_play.take() {
+            self.playing
+                .global
+                .window
+                .clear_timeout_with_handle(timeout);
         }
 
-        self.update_players();
+        self.playing
+            .global
+            .element("replay_auto")?
+            .set_text_content(Some("Play"));
 
         Ok(())
     }
 
-    pub fn on_player_won(&mut self, name: String) -> JsResult<()> {
-        self.global
-            .window
-            .alert_with_message(&format!("{} won the game! Refresh to play again!", name))
+    /// Leaves the game as it finished, and brings the standings back.
+    fn on_replay_close(&mut self) -> JsResult<()> {
+        self.stop_auto_play()?;
+        if let Some(replay) = &mut self.replay {
+            replay.finish();
+        }
+        self.show_replay()?;
+        self.replay = None;
+
+        self.playing
+            .global
+            .element("replay_controls")?
+            .set_attribute("hidden", "")?;
+        self.playing
+            .global
+            .element("game_over")?
+            .remove_attribute("hidden")
     }
 
-    pub fn on_window_resize(&mut self) -> JsResult<()> {
-        // console_log!("resize");
-        // self.board.resize();
-        // self.hand.resize();
-        // Ok(())
-        Ok(())
+    /// Puts the replay's current state on the board, with the hand of
+    /// whoever's turn it is, or ours once it's over.
+    fn show_replay(&mut self) -> JsResult<()> {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return Ok(()),
+        };
+
+        let frame = replay.frame();
+        let shown = match frame.winner {
+            Some(_) => replay
+                .players()
+                .iter()
+                .position(|name| *name == self.playing.player_name),
+            None => Some(frame.active_player),
+        };
+        let hand = shown
+            .and_then(|idx| frame.hands.get(idx))
+            .cloned()
+            .unwrap_or_default();
+
+        self.playing
+            .global
+            .element("replay_status")?
+            .set_text_content(Some(&replay.describe()));
+
+        self.playing.board.transition_to(frame.game.board().clone());
+        self.playing.hand.set_grid(BTreeMap::new());
+        self.playing.hand.insert_as_hand(&hand);
+
+        self.playing.request_frame()
     }
 
-    fn send_message(&mut self, msg: ClientMessage) -> JsResult<()> {
-        let msg = serde_json::to_string(&msg).unwrap();
-        self.ws.send_with_str(&msg)
+    pub fn on_rematch(mut self) -> JsResult<GameOver> {
+        self.playing.send_message(ClientMessage::Rematch)?;
+
+        let rematch: HtmlButtonElement = self.playing.global.element("rematch")?.dyn_into()?;
+        rematch.set_disabled(true);
+        rematch.set_text_content(Some("Waiting for the others..."));
+
+        Ok(self)
     }
 
-    pub fn send_ping(&mut self) -> JsResult<()> {
-        let msg = serde_json::to_string(&ClientMessage::Ping).unwrap();
-        self.ws.send_with_str(&msg)
+    /// Everyone asked for a rematch and the server is dealing again.
+    pub fn on_rematch_started(mut self) -> JsResult<Playing> {
+        self.close()?;
+        self.playing.reset_for_rematch();
+
+        Ok(self.playing)
     }
 
-    pub fn rerender(&mut self) {
-        self.board.rerender();
-        self.hand.rerender();
+    /// Back to the lobby, to play somewhere else.
+    pub fn on_leave_room(mut self) -> JsResult<CreateOrJoin> {
+        self.close()?;
+        self.playing.on_leave_game()
+    }
+
+    /// Takes the standings and replay down.
+    fn close(&mut self) -> JsResult<()> {
+        self.stop_auto_play()?;
+
+        self.playing
+            .global
+            .element("game_over")?
+            .set_attribute("hidden", "")?;
+        self.playing
+            .global
+            .element("replay_controls")?
+            .set_attribute("hidden", "")
+    }
+}
+
+/// Saves `record` to the player's downloads, as JSON that can be opened
+/// again here or shared.
+fn download(doc: &Document, record: &GameRecord) -> JsResult<()> {
+    let json =
+        serde_json::to_string_pretty(record).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let options = BlobPropertyBag::new();
+    options.set_type("application/json");
+    let parts = js_sys::Array::of1(&JsValue::from_str(&json));
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let link: HtmlAnchorElement = doc.create_element("a")?.dyn_into()?;
+    link.set_href(&url);
+    link.set_download(&format!("rkub-{}.json", record.id));
+    link.click();
+
+    Url::revoke_object_url(&url)
+}
+
+fn describe_piece(piece: &Piece) -> String {
+    match piece.color {
+        Color::Joker => "joker".to_string(),
+        color => format!("{} {}", color, piece.num),
     }
 }
 
 // #[derive(Debug)]
+// There's only ever the one state, so boxing the big ones wouldn't save
+// anything:
+#[allow(clippy::large_enum_variant)]
 pub enum State {
     Empty,
     Connecting(Connecting),
     CreateOrJoin(CreateOrJoin),
     Playing(Playing),
+    GameOver(GameOver),
 }
 
 impl State {
     transitions!(
         CreateOrJoin => [
-            on_join_start(name: String, room: String) -> Connecting,
-            on_create_start(name: String) -> Connecting,
+            on_join_start(name: String, room: String, password: Option<String>) -> Connecting,
+            on_create_start(name: String, password: Option<String>, options: RoomOptions) -> Connecting,
+            on_ranked_start(name: String) -> Connecting,
+            on_practice_start(name: String, bots: usize, options: RoomOptions) -> Connecting,
         ],
         Connecting => [
             on_connected() -> Playing,
         ],
+        Playing => [
+            on_player_won(winner: String, hands: Vec<Vec<Piece>>, at_ms: u64) -> GameOver,
+            on_leave_game() -> CreateOrJoin,
+        ],
+        GameOver => [
+            on_rematch() -> GameOver,
+            on_rematch_started() -> Playing,
+            on_leave_room() -> CreateOrJoin,
+        ],
     );
 
+    pub fn is_game_over(&self) -> bool {
+        matches!(self, State::GameOver(_))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Empty => "changing state",
+            State::Connecting(_) => "connecting",
+            State::CreateOrJoin(_) => "choosing a room",
+            State::Playing(_) => "playing",
+            State::GameOver(_) => "showing the standings",
+        }
+    }
+
+    /// Logs `event` coming while it doesn't apply, rather than stopping the
+    /// page over it.
+    fn ignore(&self, event: &'static str) -> JsError {
+        let e = StateError {
+            event,
+            state: self.name(),
+        };
+        console_log!("ignoring it: {}", e);
+        Ok(())
+    }
+
+    /// Where the game thinks it is, a line at a time, for the debug panel.
+    pub fn debug_info(&self) -> Vec<String> {
+        let mut info = vec![format!("state: {}", self.name())];
+
+        let playing = match self {
+            State::Connecting(connecting) => {
+                info.push(format!("connection: {}", connecting.conn.describe()));
+                return info;
+            }
+            State::Playing(playing) => playing,
+            State::GameOver(game_over) => &game_over.playing,
+            _ => return info,
+        };
+
+        info.push(format!("room: {}", playing.room_name));
+        info.push(format!(
+            "players: {} (us: {})",
+            playing.players.join(", "),
+            playing.player_name
+        ));
+        info.push(format!(
+            "turn: {}, player {} to play, ours: {}",
+            playing.turn, playing.active_player, playing.is_turn
+        ));
+        info.push(format!("board: {} tiles", playing.board.grid().len()));
+        info.push(format!("hand: {} tiles", playing.hand.grid().len()));
+        info.push(format!("connection: {}", playing.conn.describe()));
+        info
+    }
+
+    /// Whether there's a game on screen for server messages to go to.
+    pub fn is_in_game(&self) -> bool {
+        matches!(self, State::Playing(_) | State::GameOver(_))
+    }
+
+    /// The connection opened: the first time, it's time to play, and after
+    /// that we're getting back in.
+    pub fn on_opened(&mut self) -> JsError {
+        match self {
+            State::Connecting(_) => self.on_connected(),
+            _ => self.on_reconnected(),
+        }
+    }
+
+    /// The finished game, if that's what's on screen. Replays only play
+    /// behind the standings.
+    pub fn game_over(&mut self) -> Option<&mut GameOver> {
+        match self {
+            State::GameOver(game_over) => Some(game_over),
+            _ => None,
+        }
+    }
+
+    /// Draws the board and hand again, if there are any on screen.
+    pub fn rerender(&mut self) -> JsError {
+        match self {
+            State::Playing(playing) => playing.rerender(),
+            State::GameOver(game_over) => game_over.playing.rerender(),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Shows or hides who played each tile, if there's a board on screen.
+    pub fn update_credits(&mut self) -> JsError {
+        match self {
+            State::Playing(playing) => playing.update_credits(),
+            State::GameOver(game_over) => game_over.playing.update_credits(),
+            _ => Ok(()),
+        }
+    }
+
     methods!(
         Playing => [
             send_ping(),
-            on_joined_room(room_name: String, players: Vec<String>, hand: Vec<Piece>, pieces_left: usize, board: BTreeMap<Coord, Piece>),
-            on_board_click(x: i32, y: i32),
+            on_joined_room(room_name: String, options: RoomOptions, last_seq: u64, snapshot: RoomSnapshot),
+            on_board_click(x: i32, y: i32, shift: bool),
             on_board_move(x: i32, y: i32),
+            on_board_down(x: i32, y: i32, button: i16),
+            on_board_up(button: i16),
+            on_board_wheel(x: i32, y: i32, dx: i32, dy: i32, zoom: bool),
             on_hand_click(x: i32, y: i32),
             on_hand_move(x: i32, y: i32),
             on_board_leave(),
             on_hand_leave(),
-            on_turn_start(),
-            on_turn_finished(ending_player: String, ending_drew: bool, next_player: usize, pieces_remaining: usize, board: BTreeMap<Coord, Piece>),
+            on_turn_start(turn: u64),
+            on_turn_finished(ending_player: String, ending_drew: bool, next_player: usize, hand_sizes: Vec<usize>, pieces_remaining: usize, board: BTreeMap<Coord, Piece>, thinking_ms: Vec<u64>, pool: Option<Vec<(Piece, usize)>>, turn: u64, at_ms: u64),
+            on_turn_hash(state_hash: u64),
+            on_state_check(state_hash: u64),
+            on_full_sync(snapshot: RoomSnapshot),
+            on_page_shown(),
+            on_missed_messages(count: u64),
+            on_socket_closed(),
+            reconnect(),
+            on_reconnected(),
+            on_redirect(ws_url: String),
+            on_game_start(order: Vec<usize>, at_ms: u64),
             on_player_joined(name: String),
             on_draw_piece(piece: Piece),
-            on_piece_place(coord: Coord, piece: Piece),
-            on_pickup(coord: Coord, piece: Piece),
+            on_hand_sync(hand: Vec<Piece>),
+            on_piece_place(id: MoveId, coord: Coord, piece: Piece),
+            on_pickup(id: MoveId, coord: Coord, piece: Piece),
+            on_moves(id: MoveId, moves: Vec<Move>),
+            on_moves_rejected(seq: u64, moves: Vec<Move>),
             on_player_disconnected(idx: usize),
             on_player_reconnected(idx: usize),
-            on_current_player(idx: usize),
-            on_player_won(name: String),
+            on_player_kicked(idx: usize),
+            on_player_became_bot(idx: usize),
+            on_player_away(idx: usize),
+            on_player_back(idx: usize),
+            on_waiting_for_reconnect(player: usize, wait_secs: u64),
+            on_announcement(text: String),
+            on_history(past: Vec<PastMessage>),
+            on_countdown_tick(),
+            on_clock_tick(),
+            on_host_changed(idx: usize),
+            on_server_error(error: ServerError),
+            on_logged_in(name: String, token: String),
+            on_in_ranked_queue(rating: i32),
+            on_player_stats(name: String, stats: PlayerStats),
+            on_vote_skip(),
+            on_nudge(),
+            on_toggle_away(),
+            on_nudged(from: usize),
+            on_skip_votes(votes: usize, needed: usize),
+            on_skip_vote_too_early(wait_secs: u64),
+            on_turn_skipped(idx: usize),
+            on_kick(idx: usize),
+            on_make_bot(idx: usize),
             on_invalid_board(),
+            on_only_rearranged(),
+            on_kept_board_piece(piece: Piece),
             on_end_turn(),
+            on_tidy(),
+            on_key(key: String, shift: bool),
             on_end_turn_valid(),
             on_window_resize(),
+            on_animation_frame(now: f64),
         ]
     );
 }
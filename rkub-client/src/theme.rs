@@ -0,0 +1,245 @@
+//! How tiles look. Every piece is drawn by the [`TileTheme`] picked in the
+//! settings panel, which decides the tile, the ink and the outline, while
+//! what goes on a tile stays the same between them.
+
+use wasm_svg_graphics::prelude::*;
+
+use rkub_common::{Color, Piece};
+
+/// What's drawn on the joker in place of a number.
+const JOKER_FACE: &str = "☺";
+
+pub trait TileTheme: Sync {
+    /// The tile itself, under everything else.
+    fn tile(&self, width: i32, height: i32) -> SVGElem;
+
+    /// What a color's numbers are drawn in.
+    fn ink(&self, color: Color) -> &'static str;
+
+    /// The line around numbers, and how thick it is, if they have one.
+    fn outline(&self) -> Option<(&'static str, f32)>;
+
+    /// `piece` as a whole tile, `width` by `height` from the origin.
+    fn draw(&self, piece: Piece, width: i32, height: i32) -> SVGElem {
+        // Hidden unless tile shapes are turned on in the settings:
+        let glyph = SVGElem::new(Tag::Text)
+            .set(Attr::Fill, self.ink(piece.color))
+            .set(Attr::X, width - 2)
+            .set(Attr::Y, 2)
+            .set(Attr::DominantBaseline, "hanging")
+            .set(Attr::TextAnchor, "end")
+            .set(
+                Attr::Class,
+                format!("piece_glyph {}", color_class(piece.color)),
+            )
+            .set_inner(color_glyph(piece.color));
+
+        SVGElem::new(Tag::G)
+            .append(self.tile(width, height))
+            .append(self.face(piece, width, height))
+            .append(glyph)
+    }
+
+    /// What's on the tile without the tile, for drawing `piece` over
+    /// something else.
+    fn face(&self, piece: Piece, width: i32, height: i32) -> SVGElem {
+        if piece.color == Color::Joker {
+            return self.joker_face(width, height);
+        }
+
+        let num = SVGElem::new(Tag::Text)
+            .set(Attr::Fill, self.ink(piece.color))
+            .set(Attr::Transform, "scale(1, 1.5)")
+            .set(Attr::X, width / 2)
+            .set(Attr::Y, height / 3)
+            .set(Attr::DominantBaseline, "central")
+            .set(Attr::TextAnchor, "middle")
+            .set(
+                Attr::Class,
+                format!("piece_text {}", color_class(piece.color)),
+            )
+            .set(Attr::TextLength, width - (width / 5))
+            .set(Attr::LengthAdjust, "spacingAndGlyphs")
+            .set_inner(&label(piece));
+
+        self.outlined(num)
+    }
+
+    /// The joker's face, over a bar half red and half black like the
+    /// printed ones. It's left its own shape rather than stretched like the
+    /// numbers.
+    fn joker_face(&self, width: i32, height: i32) -> SVGElem {
+        let face = SVGElem::new(Tag::Text)
+            .set(Attr::Fill, self.ink(Color::Joker))
+            .set(Attr::X, width / 2)
+            .set(Attr::Y, height * 2 / 5)
+            .set(Attr::DominantBaseline, "central")
+            .set(Attr::TextAnchor, "middle")
+            .set(Attr::Class, "piece_text piece_joker")
+            .set_inner(JOKER_FACE);
+
+        let bar = |x: i32, color: Color| {
+            SVGElem::new(Tag::Rect)
+                .set(Attr::Fill, self.ink(color))
+                .set(Attr::X, x)
+                .set(Attr::Y, height * 3 / 4)
+                .set(Attr::Width, width * 3 / 10)
+                .set(Attr::Height, (height / 12).max(2))
+        };
+
+        SVGElem::new(Tag::G)
+            .append(self.outlined(face))
+            .append(bar(width / 5, Color::Red))
+            .append(bar(width / 2, Color::Black))
+    }
+
+    /// `text` with the theme's outline, if it has one.
+    fn outlined(&self, text: SVGElem) -> SVGElem {
+        match self.outline() {
+            Some((stroke, stroke_width)) => text
+                .set(Attr::Stroke, stroke)
+                .set(Attr::StrokeWidth, stroke_width),
+            None => text,
+        }
+    }
+}
+
+/// What's written on `piece`. Never the joker's number, which is only there
+/// to sort it last.
+pub fn label(piece: Piece) -> String {
+    match piece.color {
+        Color::Joker => JOKER_FACE.to_string(),
+        _ => piece.num.to_string(),
+    }
+}
+
+/// Cream plastic with rounded corners, sitting on a shadow like the real
+/// thing.
+pub struct Classic;
+
+impl TileTheme for Classic {
+    fn tile(&self, width: i32, height: i32) -> SVGElem {
+        let corner = width / 8;
+
+        let shadow = SVGElem::new(Tag::Rect)
+            .set(Attr::Fill, "black")
+            .set(Attr::Opacity, 0.25)
+            .set(Attr::Width, width - 2)
+            .set(Attr::Height, height - 2)
+            .set(Attr::X, 2)
+            .set(Attr::Y, 2)
+            .set(Attr::Rx, corner)
+            .set(Attr::Ry, corner);
+
+        let face = SVGElem::new(Tag::Rect)
+            .set(Attr::Class, "piece_tile")
+            .set(Attr::Fill, "#fff4d6")
+            .set(Attr::Stroke, "#8c7a55")
+            .set(Attr::Width, width - 2)
+            .set(Attr::Height, height - 2)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0)
+            .set(Attr::Rx, corner)
+            .set(Attr::Ry, corner);
+
+        SVGElem::new(Tag::G).append(shadow).append(face)
+    }
+
+    fn ink(&self, color: Color) -> &'static str {
+        match color {
+            Color::Red => "#d32f2f",
+            Color::Blue => "#1565c0",
+            Color::Yellow => "#f9a825",
+            Color::Black => "#212121",
+            Color::Joker => "#d32f2f",
+        }
+    }
+
+    fn outline(&self) -> Option<(&'static str, f32)> {
+        Some(("black", 1.0))
+    }
+}
+
+/// Plain squares and plain numbers.
+pub struct Flat;
+
+impl TileTheme for Flat {
+    fn tile(&self, width: i32, height: i32) -> SVGElem {
+        SVGElem::new(Tag::Rect)
+            .set(Attr::Class, "piece_tile")
+            .set(Attr::Fill, "#ffedb7")
+            .set(Attr::Stroke, "#c9b98a")
+            .set(Attr::Width, width)
+            .set(Attr::Height, height)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0)
+    }
+
+    fn ink(&self, color: Color) -> &'static str {
+        match color {
+            Color::Red => "red",
+            Color::Blue => "blue",
+            Color::Yellow => "goldenrod",
+            Color::Black => "black",
+            Color::Joker => "purple",
+        }
+    }
+
+    fn outline(&self) -> Option<(&'static str, f32)> {
+        None
+    }
+}
+
+/// White tiles with heavy borders, and a palette that stays distinct for
+/// most kinds of color blindness.
+pub struct HighContrast;
+
+impl TileTheme for HighContrast {
+    fn tile(&self, width: i32, height: i32) -> SVGElem {
+        SVGElem::new(Tag::Rect)
+            .set(Attr::Class, "piece_tile")
+            .set(Attr::Fill, "#ffffff")
+            .set(Attr::Stroke, "black")
+            .set(Attr::StrokeWidth, 2)
+            .set(Attr::Width, width)
+            .set(Attr::Height, height)
+            .set(Attr::X, 0)
+            .set(Attr::Y, 0)
+    }
+
+    fn ink(&self, color: Color) -> &'static str {
+        match color {
+            Color::Red => "#d55e00",
+            Color::Blue => "#0072b2",
+            Color::Yellow => "#e69f00",
+            Color::Black => "#000000",
+            Color::Joker => "#cc79a7",
+        }
+    }
+
+    fn outline(&self) -> Option<(&'static str, f32)> {
+        Some(("black", 1.0))
+    }
+}
+
+/// The class a color's text gets, for the stylesheet to pick out.
+fn color_class(color: Color) -> &'static str {
+    match color {
+        Color::Red => "piece_red",
+        Color::Blue => "piece_blue",
+        Color::Yellow => "piece_yellow",
+        Color::Black => "piece_black",
+        Color::Joker => "piece_joker",
+    }
+}
+
+/// A shape per color so tiles can be told apart without seeing color.
+fn color_glyph(color: Color) -> &'static str {
+    match color {
+        Color::Red => "▲",
+        Color::Blue => "■",
+        Color::Yellow => "●",
+        Color::Black => "◆",
+        Color::Joker => "★",
+    }
+}
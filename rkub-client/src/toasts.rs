@@ -0,0 +1,179 @@
+//! Messages for the player that pop up in a corner and go away on their own,
+//! instead of alerts that stop everything until they're dismissed. A few
+//! are shown at once and the rest wait their turn.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, MouseEvent};
+
+use crate::ui;
+use crate::{console_log, set_event_cb, JsClosure, JsResult};
+
+/// Where a message is kept while the page reloads, see [`after_reload`].
+const STORAGE_KEY: &str = "rkub_toast";
+
+/// How many toasts are up at once.
+const MAX_SHOWN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn class(self) -> &'static str {
+        match self {
+            Severity::Success => "toast_success",
+            Severity::Warning => "toast_warning",
+            Severity::Error => "toast_error",
+        }
+    }
+
+    /// How long a toast stays up, longer the more it matters.
+    fn lasts_ms(self) -> i32 {
+        match self {
+            Severity::Success => 4_000,
+            Severity::Warning => 6_000,
+            Severity::Error => 9_000,
+        }
+    }
+}
+
+struct Toast {
+    id: u32,
+    elem: Element,
+    timeout: i32,
+    _expire: Closure<dyn FnMut()>,
+    _on_click: JsClosure<MouseEvent>,
+}
+
+#[derive(Default)]
+struct Toasts {
+    next_id: u32,
+    shown: Vec<Toast>,
+    waiting: VecDeque<(Severity, String)>,
+    /// The toast last taken down, kept until the next is, since it's
+    /// usually its own callback doing it.
+    gone: Option<Toast>,
+}
+
+// Like `State`, only ever touched from the page's one thread:
+unsafe impl Send for Toasts {}
+
+lazy_static::lazy_static! {
+    static ref TOASTS: Mutex<Toasts> = Mutex::new(Toasts::default());
+}
+
+pub fn success(message: &str) -> JsResult<()> {
+    show(Severity::Success, message)
+}
+
+pub fn warning(message: &str) -> JsResult<()> {
+    show(Severity::Warning, message)
+}
+
+pub fn error(message: &str) -> JsResult<()> {
+    show(Severity::Error, message)
+}
+
+/// Puts `message` up, or in line behind the ones already up.
+fn show(severity: Severity, message: &str) -> JsResult<()> {
+    let mut toasts = TOASTS.lock().unwrap();
+    toasts.waiting.push_back((severity, message.to_string()));
+    toasts.fill()
+}
+
+/// Shows `message` as an error once the page has reloaded, for when going
+/// back to the lobby reloads it.
+pub fn after_reload(message: &str) -> JsResult<()> {
+    let window = web_sys::window().unwrap();
+    match window.session_storage()? {
+        Some(storage) => storage.set_item(STORAGE_KEY, message),
+        None => Ok(()),
+    }
+}
+
+/// Shows whatever was left for after the reload.
+pub fn init() -> JsResult<()> {
+    let storage = match web_sys::window().unwrap().session_storage()? {
+        Some(storage) => storage,
+        None => return Ok(()),
+    };
+
+    if let Some(message) = storage.get_item(STORAGE_KEY)? {
+        storage.remove_item(STORAGE_KEY)?;
+        error(&message)?;
+    }
+
+    Ok(())
+}
+
+/// Takes the toast `id` down early, making room for the next.
+fn dismiss(id: u32) -> JsResult<()> {
+    let mut toasts = TOASTS.lock().unwrap();
+    if let Some(i) = toasts.shown.iter().position(|toast| toast.id == id) {
+        let toast = toasts.shown.remove(i);
+        web_sys::window()
+            .unwrap()
+            .clear_timeout_with_handle(toast.timeout);
+        toast.elem.remove();
+        toasts.gone = Some(toast);
+    }
+
+    toasts.fill()
+}
+
+impl Toasts {
+    /// Puts up waiting toasts until there are as many up as there's room for.
+    fn fill(&mut self) -> JsResult<()> {
+        while self.shown.len() < MAX_SHOWN {
+            let (severity, message) = match self.waiting.pop_front() {
+                Some(waiting) => waiting,
+                None => break,
+            };
+
+            console_log!("toast: {}", message);
+            let id = self.next_id;
+            self.next_id += 1;
+            let toast = put_up(id, severity, &message)?;
+            self.shown.push(toast);
+        }
+
+        Ok(())
+    }
+}
+
+fn put_up(id: u32, severity: Severity, message: &str) -> JsResult<Toast> {
+    let window = web_sys::window().unwrap();
+    let doc: Document = window.document().unwrap();
+
+    let elem = doc.create_element("div")?;
+    elem.set_class_name(&format!("toast {}", severity.class()));
+    elem.set_attribute("role", "status")?;
+    elem.set_text_content(Some(message));
+    ui::element("toasts")?.append_child(&elem)?;
+
+    // Clicking one takes it down before its time is up:
+    let on_click = set_event_cb(&elem, "click", move |_e: MouseEvent| dismiss(id));
+
+    let expire = Closure::wrap(Box::new(move || {
+        let _ = dismiss(id);
+    }) as Box<dyn FnMut()>);
+    let timeout = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        expire.as_ref().unchecked_ref(),
+        severity.lasts_ms(),
+    )?;
+
+    Ok(Toast {
+        id,
+        elem,
+        timeout,
+        _expire: expire,
+        _on_click: on_click,
+    })
+}
@@ -0,0 +1,296 @@
+//! What a [`Connection`](crate::connection::Connection) sends and takes
+//! messages over: a websocket to the server, or something in the page
+//! standing in for one, like a practice room. Either way what comes back is
+//! numbered the same, and goes to the connection's [`OnEvent`].
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Event, MessageEvent, WebSocket};
+
+use rkub_common::{ClientMessage, Sequenced, ServerMessage, WS_PATH};
+
+use crate::callbacks::CallbackRegistry;
+use crate::connection::{ConnectionEvent, OnEvent};
+use crate::debug;
+use crate::{console_log, JsResult};
+
+/// How often an in memory peer gets to play and send what it has to say.
+const TICK_MS: i32 = 100;
+
+pub trait Transport: fmt::Debug {
+    /// Sends `msg` on, already numbered.
+    fn send(&mut self, msg: Sequenced<ClientMessage>) -> JsResult<()>;
+
+    /// Whether messages can go now.
+    fn is_open(&self) -> bool;
+
+    /// Closes it for good. Sockets say so with a last `Closed`.
+    fn close(&mut self) -> JsResult<()>;
+
+    /// How it's doing, for the debug panel.
+    fn describe(&self) -> &'static str;
+
+    /// Whether it stays in the page, with no server on the other end.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// Passes on what arrived as `Message`s, in order. Messages are numbered,
+/// so one that never arrived shows up as a gap, and is passed on as
+/// `Missed`. Each socket numbers from scratch.
+#[derive(Debug, Default)]
+struct Inbox {
+    last_seq: u64,
+}
+
+impl Inbox {
+    /// Takes message `seq`, returning how many went missing before it, or
+    /// None if it's already been seen.
+    fn take(&mut self, seq: u64) -> Option<u64> {
+        if seq <= self.last_seq {
+            return None;
+        }
+
+        let missed = seq - self.last_seq - 1;
+        self.last_seq = seq;
+        Some(missed)
+    }
+
+    fn deliver(&mut self, sequenced: Sequenced<ServerMessage>, on_event: OnEvent) -> JsResult<()> {
+        if !matches!(sequenced.msg, ServerMessage::Pong) {
+            debug::received(&sequenced);
+        }
+        let Sequenced { seq, msg } = sequenced;
+        let missed = match self.take(seq) {
+            Some(missed) => missed,
+            None => {
+                console_log!("already seen message {}", seq);
+                return Ok(());
+            }
+        };
+
+        on_event(ConnectionEvent::Message(msg))?;
+        if missed > 0 {
+            on_event(ConnectionEvent::Missed(missed))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A websocket to the server that served the page.
+#[derive(Debug)]
+pub struct SocketTransport {
+    ws: WebSocket,
+    /// What's listening to `ws`, dropped along with it.
+    _callbacks: CallbackRegistry,
+}
+
+impl SocketTransport {
+    /// Opens a socket, sending what happens to it to `on_event`.
+    pub fn open(on_event: OnEvent) -> JsResult<Self> {
+        SocketTransport::open_url(&url()?, on_event)
+    }
+
+    /// Opens a socket to `url` rather than the server that served the page.
+    pub fn open_url(url: &str, on_event: OnEvent) -> JsResult<Self> {
+        console_log!("Host: {}", url);
+        let ws = WebSocket::new(url)?;
+        let mut callbacks = CallbackRegistry::default();
+
+        callbacks.listen(&ws, "open", move |_: JsValue| {
+            console_log!("WS Connected");
+            on_event(ConnectionEvent::Opened)
+        });
+
+        let mut inbox = Inbox::default();
+        callbacks.listen(&ws, "message", move |e: MessageEvent| {
+            let sequenced: Sequenced<ServerMessage> =
+                serde_json::from_str(&e.data().as_string().unwrap())
+                    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            inbox.deliver(sequenced, on_event)
+        });
+
+        callbacks.listen(&ws, "error", move |e: Event| {
+            console_log!("WS Error: {:?}", e);
+            Ok(())
+        });
+
+        callbacks.listen(&ws, "close", move |e: Event| {
+            console_log!("WS Closed: {:?}", e);
+            on_event(ConnectionEvent::Closed)
+        });
+
+        Ok(SocketTransport {
+            ws,
+            _callbacks: callbacks,
+        })
+    }
+}
+
+impl Transport for SocketTransport {
+    fn send(&mut self, msg: Sequenced<ClientMessage>) -> JsResult<()> {
+        self.ws.send_with_str(&serde_json::to_string(&msg).unwrap())
+    }
+
+    fn is_open(&self) -> bool {
+        self.ws.ready_state() == WebSocket::OPEN
+    }
+
+    fn close(&mut self) -> JsResult<()> {
+        self.ws.close()
+    }
+
+    fn describe(&self) -> &'static str {
+        match self.ws.ready_state() {
+            WebSocket::CONNECTING => "connecting",
+            WebSocket::OPEN => "open",
+            WebSocket::CLOSING => "closing",
+            _ => "closed",
+        }
+    }
+}
+
+/// Where the game's websocket is: the same host and port as the page.
+fn url() -> JsResult<String> {
+    // Thanks mkeeter for the following hostname code:
+    let location = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .location()
+        .expect("Could not get doc location");
+
+    let ws_protocol = if location.protocol()? == "https:" {
+        "wss"
+    } else {
+        "ws"
+    };
+    Ok(format!("{}://{}{}", ws_protocol, location.host()?, WS_PATH))
+}
+
+/// The other end of a [`MemoryTransport`], answering for the server.
+pub trait Peer: fmt::Debug {
+    /// Takes a message from us at `now_ms`.
+    fn receive(&mut self, msg: Sequenced<ClientMessage>, now_ms: u64);
+
+    /// Everything it has to say to us as of `now_ms`.
+    fn tick(&mut self, now_ms: u64) -> Vec<ServerMessage>;
+}
+
+/// Messages to and from a [`Peer`] in the page. What it has to say is
+/// picked up every so often, so it never answers while we're still sending.
+#[derive(Debug)]
+pub struct MemoryTransport {
+    peer: Rc<RefCell<dyn Peer>>,
+    /// What's been picked up from `peer`. Shared with the ticks, and kept
+    /// apart from `peer` so what's picked up can be handled while it's free
+    /// to be sent to.
+    inbox: Rc<RefCell<Inbox>>,
+    /// Whether it's said it's open yet.
+    opened: Rc<Cell<bool>>,
+    on_event: OnEvent,
+    /// Picks up what `peer` has to say, until closed.
+    ticks: CallbackRegistry,
+}
+
+impl MemoryTransport {
+    /// Talks to `peer`, sending what it says to `on_event`. It opens as
+    /// soon as it's picked up from.
+    pub fn new(peer: Rc<RefCell<dyn Peer>>, on_event: OnEvent) -> JsResult<Self> {
+        let mut transport = MemoryTransport {
+            peer,
+            inbox: Rc::default(),
+            opened: Rc::default(),
+            on_event,
+            ticks: CallbackRegistry::default(),
+        };
+
+        let peer = Rc::clone(&transport.peer);
+        let inbox = Rc::clone(&transport.inbox);
+        let opened = Rc::clone(&transport.opened);
+        transport.ticks.every(TICK_MS, move || {
+            let _ = pick_up(&peer, &inbox, &opened, on_event, now_ms());
+        })?;
+
+        Ok(transport)
+    }
+
+    /// Picks up what the peer has to say now, without waiting for the next
+    /// tick.
+    pub fn pick_up(&self, now_ms: u64) -> JsResult<()> {
+        pick_up(&self.peer, &self.inbox, &self.opened, self.on_event, now_ms)
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, msg: Sequenced<ClientMessage>) -> JsResult<()> {
+        self.peer.borrow_mut().receive(msg, now_ms());
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    fn close(&mut self) -> JsResult<()> {
+        self.ticks = CallbackRegistry::default();
+        Ok(())
+    }
+
+    fn describe(&self) -> &'static str {
+        "in memory"
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+fn pick_up(
+    peer: &RefCell<dyn Peer>,
+    inbox: &RefCell<Inbox>,
+    opened: &Cell<bool>,
+    on_event: OnEvent,
+    now_ms: u64,
+) -> JsResult<()> {
+    if !opened.replace(true) {
+        on_event(ConnectionEvent::Opened)?;
+    }
+
+    // The peer's let go of before anything's handled, since handling it can
+    // send the peer more:
+    let said = peer.borrow_mut().tick(now_ms);
+
+    let mut inbox = inbox.borrow_mut();
+    for msg in said {
+        let seq = inbox.last_seq + 1;
+        inbox.deliver(Sequenced { seq, msg }, on_event)?;
+    }
+
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaps_are_counted_and_repeats_dropped() {
+        let mut inbox = Inbox::default();
+
+        assert_eq!(inbox.take(1), Some(0));
+        assert_eq!(inbox.take(4), Some(2));
+        assert_eq!(inbox.take(4), None);
+        assert_eq!(inbox.take(2), None);
+        assert_eq!(inbox.take(5), Some(0));
+    }
+}
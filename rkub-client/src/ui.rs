@@ -0,0 +1,90 @@
+//! The game's markup. The game puts it on the page itself, under one root
+//! element, so a page only has to say where it goes and everything the game
+//! looks up by id is sure to be there. Lookups only search under the root,
+//! see [`find`], so the page's own elements are left alone even if their ids
+//! are the same.
+
+use std::sync::Mutex;
+
+use wasm_bindgen::JsValue;
+use web_sys::{Document, Element};
+
+/// The id of the element the game goes in, unless it's told otherwise.
+pub const DEFAULT_ROOT: &str = "rkub";
+
+/// Everything the game shows, from the lobby to the standings.
+const PAGE: &str = include_str!("page.html");
+
+/// The element the game was put in.
+struct Root(Element);
+
+// There's only the one thread:
+unsafe impl Send for Root {}
+
+lazy_static::lazy_static! {
+    static ref ROOT: Mutex<Option<Root>> = Mutex::new(None);
+}
+
+/// Why the game couldn't be put on the page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UiError {
+    /// There's no element with this id to put it in.
+    NoRoot(String),
+    /// It's on the page already.
+    AlreadyMounted,
+    /// It isn't on the page yet, see [`mount`].
+    NotMounted,
+    /// The game's markup has no element with this id.
+    Missing(String),
+}
+
+impl std::fmt::Display for UiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UiError::NoRoot(id) => write!(f, "there's no element #{} to put the game in", id),
+            UiError::AlreadyMounted => write!(f, "the game is already on the page"),
+            UiError::NotMounted => write!(f, "the game isn't on the page yet"),
+            UiError::Missing(id) => write!(f, "there's no #{} in the game", id),
+        }
+    }
+}
+
+impl From<UiError> for JsValue {
+    fn from(e: UiError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+/// Puts the game in the element `root_id`, in place of whatever was there,
+/// and returns that element.
+pub fn mount(doc: &Document, root_id: &str) -> Result<Element, UiError> {
+    let root = doc
+        .get_element_by_id(root_id)
+        .ok_or_else(|| UiError::NoRoot(root_id.to_string()))?;
+    root.set_inner_html(PAGE);
+    *ROOT.lock().unwrap() = Some(Root(root.clone()));
+
+    Ok(root)
+}
+
+/// The element the game was put in, for what's looked up outside the
+/// states, which have it in `Global`.
+pub fn root() -> Result<Element, UiError> {
+    match &*ROOT.lock().unwrap() {
+        Some(Root(root)) => Ok(root.clone()),
+        None => Err(UiError::NotMounted),
+    }
+}
+
+/// The element with the id `id` under `root`.
+pub fn find(root: &Element, id: &str) -> Result<Element, UiError> {
+    root.query_selector(&format!("#{}", id))
+        .ok()
+        .flatten()
+        .ok_or_else(|| UiError::Missing(id.to_string()))
+}
+
+/// The element with the id `id` in the game, wherever it was put.
+pub fn element(id: &str) -> Result<Element, UiError> {
+    find(&root()?, id)
+}
@@ -0,0 +1,307 @@
+//! The client's interaction logic, played through clicks on the real page
+//! against a stand-in server.
+
+#![cfg(target_arch = "wasm32")]
+
+extern crate wasm_bindgen_test;
+use wasm_bindgen_test::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::HtmlElement;
+
+use rkub_client::connection::Connection;
+use rkub_client::embed::{create_room, join_room, leave_room};
+use rkub_client::states::{Connecting, Global, Playing, RoomChoice, State};
+use rkub_client::transport::{MemoryTransport, Peer};
+use rkub_client::ui::{self, UiError};
+use rkub_client::{on_connection_event, set_up_page, STATE};
+use rkub_common::{
+    ClientMessage, Color, Coord, GameState, Phase, Piece, RoomOptions, RoomSnapshot, Sequenced,
+    ServerMessage,
+};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Sizes the board and hand the way the stylesheet would, so clicks land
+/// where they would for a player.
+const LAYOUT: &str = "
+    #board, #board > svg { display: block; width: 900px; height: 450px; }
+    #hand, #hand > svg { display: block; width: 900px; height: 180px; }
+";
+
+/// Answers for the server: deals `hand` to whoever creates a room and
+/// starts their turn, and remembers everything it's sent.
+#[derive(Debug)]
+struct Server {
+    hand: Vec<Piece>,
+    /// Everything we sent, oldest first, leaving out heartbeats.
+    heard: Vec<ClientMessage>,
+    says: Vec<ServerMessage>,
+}
+
+impl Peer for Server {
+    fn receive(&mut self, msg: Sequenced<ClientMessage>, _now_ms: u64) {
+        if let ClientMessage::CreateRoom(name, _, options) = &msg.msg {
+            let mut state = GameState::default();
+            state.hands = vec![self.hand.clone(), Vec::new()];
+
+            self.says.push(ServerMessage::JoinedRoom {
+                room_name: "ui-test".to_string(),
+                options: options.clone(),
+                last_seq: 0,
+                snapshot: Box::new(RoomSnapshot {
+                    players: vec![name.clone(), "Bob".to_string()],
+                    hand_sizes: vec![self.hand.len(), 14],
+                    host: 0,
+                    view: state.private_view(0),
+                    board: Default::default(),
+                    board_size: Default::default(),
+                    pieces_remaining: 80,
+                    dealt: true,
+                    active_player: 0,
+                    phase: Phase::Playing,
+                    scores: None,
+                    disconnected: Vec::new(),
+                    bots: Vec::new(),
+                    away: Vec::new(),
+                    kicked: Vec::new(),
+                    thinking_ms: vec![0, 0],
+                    turn_ms: 0,
+                    pool: None,
+                    turn: 1,
+                }),
+            });
+            self.says.push(ServerMessage::StartGame {
+                order: vec![0, 1],
+                at_ms: 0,
+            });
+            self.says.push(ServerMessage::StartTurn(1));
+        }
+
+        if !matches!(msg.msg, ClientMessage::Ping) {
+            self.heard.push(msg.msg);
+        }
+    }
+
+    fn tick(&mut self, _now_ms: u64) -> Vec<ServerMessage> {
+        std::mem::take(&mut self.says)
+    }
+}
+
+fn red(num: u8) -> Piece {
+    Piece::new(Color::Red, num)
+}
+
+/// Puts the game's page up and connects to a [`Server`] dealing `hand`. The
+/// game starts once the connection's been picked up from, see [`settle`].
+fn start(hand: Vec<Piece>) -> Rc<RefCell<Server>> {
+    let window = web_sys::window().unwrap();
+    let doc = window.document().unwrap();
+
+    doc.body()
+        .unwrap()
+        .set_inner_html(r#"<div id="rkub"></div>"#);
+    let root = ui::mount(&doc, ui::DEFAULT_ROOT).unwrap();
+
+    let style = doc.create_element("style").unwrap();
+    style.set_text_content(Some(LAYOUT));
+    doc.body().unwrap().append_child(&style).unwrap();
+
+    set_up_page(&window, &doc, &root).unwrap();
+
+    let server = Rc::new(RefCell::new(Server {
+        hand,
+        heard: Vec::new(),
+        says: Vec::new(),
+    }));
+    let transport = MemoryTransport::new(server.clone(), on_connection_event).unwrap();
+
+    *STATE.lock().unwrap() = State::Connecting(Connecting {
+        global: Global { window, doc, root },
+        conn: Connection::over(Box::new(transport), on_connection_event),
+        player_name: "Alice".to_string(),
+        room: RoomChoice::Create(RoomOptions::default()),
+        password: None,
+    });
+
+    server
+}
+
+/// Waits long enough for a few rounds of messages to be picked up.
+async fn settle() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 350)
+            .unwrap();
+    });
+    JsFuture::from(promise).await.unwrap();
+}
+
+/// Looks at the game on screen.
+fn playing<T>(f: impl FnOnce(&Playing) -> T) -> T {
+    match &*STATE.lock().unwrap() {
+        State::Playing(playing) => f(playing),
+        _ => panic!("not playing"),
+    }
+}
+
+/// Clicks on `piece` in the hand.
+fn click_in_hand(piece: Piece) {
+    let (x, y) = playing(|playing| {
+        let coord = playing
+            .hand
+            .grid()
+            .iter()
+            .find(|(_, p)| **p == piece)
+            .map(|(coord, _)| *coord)
+            .expect("piece isn't in the hand");
+
+        let rect = playing.hand_svg.get_bounding_client_rect();
+        let (x, y) = playing.hand.grid_to_screen(coord);
+        (rect.x() as i32 + x, rect.y() as i32 + y)
+    });
+
+    STATE.lock().unwrap().on_hand_click(x, y).unwrap();
+}
+
+/// Clicks on the board cell at `coord`.
+fn click_on_board(coord: Coord) {
+    let (x, y) = playing(|playing| {
+        let rect = playing.board_svg.get_bounding_client_rect();
+        let (x, y) = playing.board.grid_to_screen(coord);
+        (rect.x() as i32 + x, rect.y() as i32 + y)
+    });
+
+    STATE.lock().unwrap().on_board_click(x, y, false).unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn placing_from_the_hand_tells_the_server() {
+    let server = start(vec![red(10), red(11), red(12)]);
+    settle().await;
+
+    assert!(matches!(
+        server.borrow().heard[0],
+        ClientMessage::CreateRoom(..)
+    ));
+    assert!(playing(|playing| playing.is_turn));
+    assert_eq!(playing(|playing| playing.hand.grid().len()), 3);
+
+    click_in_hand(red(10));
+    assert_eq!(playing(|playing| playing.selected_piece), Some(red(10)));
+    assert_eq!(playing(|playing| playing.hand.grid().len()), 2);
+
+    click_on_board(Coord(4, 2));
+    assert_eq!(
+        server.borrow().heard.last(),
+        Some(&ClientMessage::Place(Coord(4, 2), red(10)))
+    );
+    playing(|playing| {
+        assert_eq!(playing.board.grid().get(&Coord(4, 2)), Some(&red(10)));
+        assert!(playing.hand.grid().values().all(|p| *p != red(10)));
+        assert_eq!(playing.selected_piece, None);
+    });
+}
+
+#[wasm_bindgen_test]
+async fn pieces_cant_go_on_top_of_others() {
+    let server = start(vec![red(10), red(11)]);
+    settle().await;
+
+    click_in_hand(red(10));
+    click_on_board(Coord(4, 2));
+    let heard = server.borrow().heard.len();
+
+    click_in_hand(red(11));
+    click_on_board(Coord(4, 2));
+    assert_eq!(server.borrow().heard.len(), heard);
+    playing(|playing| {
+        assert_eq!(playing.board.grid().get(&Coord(4, 2)), Some(&red(10)));
+        assert_eq!(playing.selected_piece, Some(red(11)));
+    });
+}
+
+#[wasm_bindgen_test]
+async fn only_boards_that_could_be_valid_are_ended_on() {
+    let server = start(vec![red(10), red(11), red(12), red(1)]);
+    settle().await;
+
+    click_in_hand(red(10));
+    click_on_board(Coord(4, 2));
+    STATE.lock().unwrap().on_end_turn().unwrap();
+    assert!(!server.borrow().heard.contains(&ClientMessage::EndTurn));
+
+    click_in_hand(red(11));
+    click_on_board(Coord(5, 2));
+    click_in_hand(red(12));
+    click_on_board(Coord(6, 2));
+    STATE.lock().unwrap().on_end_turn().unwrap();
+    assert_eq!(server.borrow().heard.last(), Some(&ClientMessage::EndTurn));
+}
+
+#[wasm_bindgen_test]
+async fn the_end_turn_button_is_wired_up() {
+    let server = start(vec![red(10), red(11), red(12)]);
+    settle().await;
+
+    for (piece, x) in [(red(10), 0), (red(11), 1), (red(12), 2)].iter() {
+        click_in_hand(*piece);
+        click_on_board(Coord(*x, 0));
+    }
+
+    let end_turn: HtmlElement = ui::element("end_turn").unwrap().dyn_into().unwrap();
+    end_turn.click();
+
+    assert_eq!(server.borrow().heard.last(), Some(&ClientMessage::EndTurn));
+}
+
+#[wasm_bindgen_test]
+async fn embedding_pages_can_leave_for_the_lobby() {
+    let server = start(vec![red(10)]);
+    settle().await;
+
+    // Only the lobby starts games:
+    assert!(create_room("Alice".to_string()).is_err());
+    assert!(playing(|playing| playing.is_turn));
+
+    leave_room().unwrap();
+    assert!(matches!(*STATE.lock().unwrap(), State::CreateOrJoin(_)));
+    assert_eq!(server.borrow().heard.last(), Some(&ClientMessage::Close));
+
+    assert!(join_room(String::new(), "k3x9qa".to_string()).is_err());
+    assert!(leave_room().is_err());
+}
+
+#[wasm_bindgen_test]
+async fn the_game_needs_somewhere_to_go() {
+    start(Vec::new());
+    let doc = web_sys::window().unwrap().document().unwrap();
+
+    assert_eq!(
+        ui::mount(&doc, "nowhere").unwrap_err(),
+        UiError::NoRoot("nowhere".to_string())
+    );
+    assert!(ui::element("create_or_join").is_ok());
+}
+
+#[wasm_bindgen_test]
+fn lookups_stay_under_the_root() {
+    let doc = web_sys::window().unwrap().document().unwrap();
+    doc.body()
+        .unwrap()
+        .set_inner_html(r#"<div id="board">the page's</div><div id="here"></div>"#);
+    let root = ui::mount(&doc, "here").unwrap();
+
+    let board = ui::find(&root, "board").unwrap();
+    assert_ne!(board.text_content().as_deref(), Some("the page's"));
+    assert_eq!(ui::element("board").unwrap(), board);
+    assert_eq!(
+        ui::find(&root, "nothing_here").unwrap_err(),
+        UiError::Missing("nothing_here".to_string())
+    );
+}
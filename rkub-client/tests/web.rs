@@ -5,9 +5,48 @@
 extern crate wasm_bindgen_test;
 use wasm_bindgen_test::*;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rkub_client::connection::ConnectionEvent;
+use rkub_client::practice::PracticeRoom;
+use rkub_client::transport::{MemoryTransport, Transport};
+use rkub_client::JsResult;
+use rkub_common::{ClientMessage, RoomOptions, Sequenced, ServerMessage};
+
 wasm_bindgen_test_configure!(run_in_browser);
 
+thread_local! {
+    /// Everything the transport passed on, oldest first.
+    static HEARD: RefCell<Vec<ConnectionEvent>> = RefCell::new(Vec::new());
+}
+
+fn on_event(event: ConnectionEvent) -> JsResult<()> {
+    HEARD.with(|heard| heard.borrow_mut().push(event));
+    Ok(())
+}
+
 #[wasm_bindgen_test]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn practice_rooms_answer_without_a_server() {
+    let room = Rc::new(RefCell::new(PracticeRoom::new(1, 7)));
+    let mut memory = MemoryTransport::new(room, on_event).unwrap();
+
+    let msg = ClientMessage::CreateRoom("Alice".into(), None, RoomOptions::default());
+    memory.send(Sequenced { seq: 1, msg }).unwrap();
+    memory.pick_up(0).unwrap();
+
+    HEARD.with(|heard| {
+        let heard = heard.borrow();
+        assert!(matches!(heard[0], ConnectionEvent::Opened));
+        assert!(matches!(
+            heard[1],
+            ConnectionEvent::Message(ServerMessage::JoinedRoom { .. })
+        ));
+    });
+    assert!(memory.is_local());
+}
@@ -0,0 +1,69 @@
+//! How long validating a board takes, for boards packed full and for a few
+//! groups spread far apart.
+
+use std::collections::BTreeMap;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use rkub_common::{Color, Coord, Game, Piece, BOARD_COLS, BOARD_ROWS};
+
+const COLORS: [Color; 4] = [Color::Red, Color::Blue, Color::Yellow, Color::Black];
+
+/// A run of `color` from 1 to 3 starting at `at`.
+fn run(board: &mut BTreeMap<Coord, Piece>, at: Coord, color: Color) {
+    for i in 0..3 {
+        board.insert(Coord(at.0 + i, at.1), Piece::new(color, i as u8 + 1));
+    }
+}
+
+fn game(board: BTreeMap<Coord, Piece>) -> Game {
+    let mut game = Game::new();
+    game.set_board(board);
+
+    game
+}
+
+/// Every row of the board filled with runs, an empty cell between each.
+fn full() -> Game {
+    let mut board = BTreeMap::new();
+    for y in 0..BOARD_ROWS {
+        for (i, x) in (0..BOARD_COLS - 2).step_by(4).enumerate() {
+            run(&mut board, Coord(x, y), COLORS[i % COLORS.len()]);
+        }
+    }
+
+    game(board)
+}
+
+/// A run in each corner of a board `size` cells across.
+fn sparse(size: i32) -> Game {
+    let mut board = BTreeMap::new();
+    run(&mut board, Coord(0, 0), Color::Red);
+    run(&mut board, Coord(size - 3, 0), Color::Blue);
+    run(&mut board, Coord(0, size - 1), Color::Yellow);
+    run(&mut board, Coord(size - 3, size - 1), Color::Black);
+
+    game(board)
+}
+
+fn is_valid_board(c: &mut Criterion) {
+    let boards = [
+        ("full", full()),
+        ("sparse board-sized", sparse(BOARD_COLS)),
+        ("sparse 10k", sparse(10_000)),
+        ("sparse i32", sparse(i32::MAX)),
+    ];
+
+    for (name, game) in boards.iter() {
+        c.bench_function(&format!("is_valid_board {}", name), |b| {
+            b.iter(|| black_box(game).is_valid_board())
+        });
+        c.bench_function(&format!("is_valid_board_with columns {}", name), |b| {
+            b.iter(|| black_box(game).is_valid_board_with(true))
+        });
+    }
+}
+
+criterion_group!(benches, is_valid_board);
+criterion_main!(benches);
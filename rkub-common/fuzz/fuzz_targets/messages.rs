@@ -0,0 +1,12 @@
+#![no_main]
+
+//! Anything a client sends is parsed by the server, so parsing must never
+//! panic, whatever the bytes.
+
+use libfuzzer_sys::fuzz_target;
+use rkub_common::{ClientMessage, ServerMessage};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json::from_slice::<ClientMessage>(data);
+    let _ = serde_json::from_slice::<ServerMessage>(data);
+});
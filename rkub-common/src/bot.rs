@@ -0,0 +1,132 @@
+//! A simple player for scripts and load tests. It lays down the first whole
+//! group it can make from its hand, and otherwise draws.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{reduce, Action, Color, Coord, GameState, Move, Piece, BOARD_COLS, BOARD_ROWS};
+
+/// The moves to make this turn, checked against the rules first. None means
+/// just ending the turn and drawing.
+pub fn plan_turn(state: &GameState, me: usize) -> Vec<Move> {
+    let group = match find_group(&state.hands[me]) {
+        Some(group) => group,
+        None => return Vec::new(),
+    };
+
+    let Coord(x, y) = match find_space(state.game.board(), group.len() as i32) {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let moves: Vec<Move> = group
+        .into_iter()
+        .zip(x..)
+        .map(|(piece, x)| Move::Place(Coord(x, y), piece))
+        .collect();
+
+    let played = reduce(state, me, &Action::Moves(moves.clone()))
+        .and_then(|(state, _)| reduce(&state, me, &Action::EndTurn));
+
+    match played {
+        Ok(_) => moves,
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The first place on the board a group `len` long fits without touching
+/// anything already there.
+fn find_space(board: &BTreeMap<Coord, Piece>, len: i32) -> Option<Coord> {
+    (0..BOARD_ROWS)
+        .flat_map(|y| (0..=BOARD_COLS - len).map(move |x| Coord(x, y)))
+        .find(|&Coord(x, y)| (x - 1..=x + len).all(|x| !board.contains_key(&Coord(x, y))))
+}
+
+/// A run of one color, or a set of one number, at least three long.
+fn find_group(hand: &[Piece]) -> Option<Vec<Piece>> {
+    let mut by_color: BTreeMap<Color, BTreeSet<u8>> = BTreeMap::new();
+    let mut by_num: BTreeMap<u8, BTreeSet<Color>> = BTreeMap::new();
+
+    for piece in hand.iter().filter(|p| p.color != Color::Joker) {
+        by_color.entry(piece.color).or_default().insert(piece.num);
+        by_num.entry(piece.num).or_default().insert(piece.color);
+    }
+
+    for (&color, nums) in &by_color {
+        let mut run: Vec<u8> = Vec::new();
+        for &num in nums {
+            if matches!(run.last(), Some(&last) if last + 1 != num) {
+                run.clear();
+            }
+            run.push(num);
+
+            if run.len() == 3 {
+                return Some(run.iter().map(|&n| Piece::new(color, n)).collect());
+            }
+        }
+    }
+
+    by_num
+        .into_iter()
+        .find(|(_, colors)| colors.len() >= 3)
+        .map(|(num, colors)| colors.into_iter().map(|c| Piece::new(c, num)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(hand: Vec<Piece>) -> GameState {
+        GameState {
+            hands: vec![hand],
+            ..GameState::default()
+        }
+    }
+
+    #[test]
+    fn plays_runs_and_sets() {
+        let run = state(vec![
+            Piece::new(Color::Blue, 3),
+            Piece::new(Color::Red, 9),
+            Piece::new(Color::Blue, 5),
+            Piece::new(Color::Blue, 4),
+        ]);
+        assert_eq!(
+            plan_turn(&run, 0),
+            vec![
+                Move::Place(Coord(0, 0), Piece::new(Color::Blue, 3)),
+                Move::Place(Coord(1, 0), Piece::new(Color::Blue, 4)),
+                Move::Place(Coord(2, 0), Piece::new(Color::Blue, 5)),
+            ]
+        );
+
+        let set = state(vec![
+            Piece::new(Color::Red, 7),
+            Piece::new(Color::Black, 7),
+            Piece::new(Color::Yellow, 7),
+        ]);
+        assert_eq!(plan_turn(&set, 0).len(), 3);
+    }
+
+    #[test]
+    fn plays_clear_of_other_groups() {
+        let mut board = BTreeMap::new();
+        for x in 0..BOARD_COLS - 2 {
+            board.insert(Coord(x, 0), Piece::new(Color::Red, 1));
+        }
+        board.insert(Coord(3, 1), Piece::new(Color::Red, 1));
+
+        assert_eq!(find_space(&board, 3), Some(Coord(5, 1)));
+        assert_eq!(find_space(&BTreeMap::new(), BOARD_COLS + 1), None);
+    }
+
+    #[test]
+    fn draws_without_a_group() {
+        let hand = state(vec![
+            Piece::new(Color::Red, 1),
+            Piece::new(Color::Red, 3),
+            Piece::new(Color::Blue, 1),
+            Piece::joker(),
+        ]);
+
+        assert!(plan_turn(&hand, 0).is_empty());
+    }
+}
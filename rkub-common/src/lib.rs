@@ -1,51 +1,601 @@
+use serde::de::{self, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 
+pub mod bot;
+pub mod replay;
+mod report;
+mod state;
+mod webhook;
+
+pub use replay::{GameEvent, GameRecord, ImportError, TimedEvent};
+pub use report::{BugReport, TimedMessage, Traced, REPORT_FORMAT};
+pub use state::{reduce, Action, Event, GameState, Rejection, HAND_SIZE};
+pub use webhook::{Notification, Standing};
+
+/// Where the server accepts websockets, on whatever host and port serves the
+/// page, so a reverse proxy only needs to pass this path through.
+pub const WS_PATH: &str = "/ws";
+
+/// Every message, both ways, as it goes over the websocket: numbered one up
+/// from the last, starting from 1. The server numbers each connection from
+/// scratch, so a client that sees a number skipped has missed something. A
+/// client numbers on from the `last_seq` it's told on joining a room, so the
+/// room can turn down anything it's already taken, like moves sent again
+/// after a reconnect.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    pub msg: T,
+}
+
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ClientMessage {
-    CreateRoom(String),
-    JoinRoom(String, String),
+    /// The player's name, an optional password for the room and how it's
+    /// set up.
+    CreateRoom(String, Option<String>, RoomOptions),
+    /// The player's name, the room and its password, if it has one.
+    JoinRoom(String, String, Option<String>),
     Ready(String),
     Pickup(Coord, Piece),
     Place(Coord, Piece),
+    Moves(Vec<Move>),
     EndTurn,
+    /// Asks to play again in the same room once the game is over.
+    Rematch,
+    /// Host only: removes a player from the room for good.
+    Kick(usize),
+    /// Host only: lets a bot take the turns of a disconnected player.
+    MakeBot(usize),
+    /// Votes to end the active player's turn for them when they've gone
+    /// quiet.
+    VoteSkip,
+    /// Hurries the active player along. Once a turn each.
+    Nudge,
+    /// Steps away from the game for a while, or comes back. Away players'
+    /// turns are skipped, drawing for them.
+    SetAway(bool),
+    /// Asks for a player's stats, by name. Works in or out of a room.
+    Stats(String),
+    /// Makes an account with a name and passphrase, and logs in to it. Like
+    /// logging in, only before joining a room.
+    Register(String, String),
+    /// Logs in to an account with its name and passphrase. Rooms joined
+    /// afterwards know the connection as the account, under its name.
+    LogIn(String, String),
+    /// Logs back in with the token from an earlier `ServerMessage::LoggedIn`.
+    Resume(String),
+    /// Waits for a ranked room with players of about the same rating, instead
+    /// of creating or joining one. Needs an account.
+    QueueRanked,
+    /// Asks for a finished game to play back. Works in or out of a room.
+    FetchReplay(String),
+    /// Asks for the whole game again, as a `ServerMessage::FullSync`, when
+    /// the client's copy no longer matches a `state_hash` from the server, or
+    /// it might have missed something.
+    RequestSync,
+    /// Operators only: the server's admin secret, and what to do. Only
+    /// before joining a room. Answered with what it asked for, or
+    /// `ServerMessage::AdminDone`.
+    Admin(String, AdminCommand),
     Ping,
     Close,
 }
 
+/// What an operator can do without restarting the server, with
+/// `ClientMessage::Admin`.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AdminCommand {
+    /// Every room running, as `ServerMessage::AdminRooms`.
+    ListRooms,
+    /// Everything about the room with this code, hands and all, as
+    /// `ServerMessage::AdminRoom`.
+    InspectRoom(String),
+    /// Ends the active player's turn in a room, as if they'd run out of
+    /// time.
+    ForceEndTurn(String),
+    /// Stops a room, whoever's in it.
+    CloseRoom(String),
+    /// Opens an empty room with these options for players to join, as
+    /// `ServerMessage::AdminRoomCreated`. Whoever sits down first hosts it.
+    CreateRoom(RoomOptions),
+    /// Tells everyone in every room something, like that the server's going
+    /// down for maintenance, as `ServerMessage::Announcement`.
+    Broadcast(String, Severity),
+}
+
+/// Something a room told everyone, in `ServerMessage::History`.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PastMessage {
+    /// When, by the server's clock, in milliseconds since the Unix epoch.
+    pub at_ms: u64,
+    pub msg: ServerMessage,
+}
+
+/// How much an announcement matters, and so how loudly it's shown.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    /// Something's about to happen to everyone's game, like a restart.
+    Critical,
+}
+
+/// One room at a glance, for operators.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RoomSummary {
+    pub id: String,
+    pub players: Vec<String>,
+    pub phase: Phase,
+    pub dealt: bool,
+    pub active_player: usize,
+    pub turn: u64,
+    /// How long since the active player last did anything, in seconds.
+    pub idle_secs: u64,
+}
+
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ServerMessage {
+    /// We're in the room, and this is everything in it we can see, whether
+    /// we're new or back from a lost connection.
     JoinedRoom {
         room_name: String,
-        players: Vec<String>,
-        hand: Vec<Piece>,
-        pieces_remaining: usize,
-        board: BTreeMap<Coord, Piece>,
+        /// What the room's creator chose about it.
+        options: RoomOptions,
+        /// The `seq` of the last message the room took from us, to number
+        /// on from.
+        last_seq: u64,
+        snapshot: Box<RoomSnapshot>,
     },
-    StartGame,
-    StartTurn,
-    CurrentPlayer(usize),
+    /// The game's been dealt. Everyone's seat, in the order they take
+    /// turns, starting with whoever drew the highest piece.
+    StartGame {
+        order: Vec<usize>,
+        /// When, by the server's clock, in milliseconds since the Unix
+        /// epoch.
+        at_ms: u64,
+    },
+    /// It's our turn. Turns are numbered from 1 each game, whoever takes
+    /// them, so everyone's number for a turn is the same.
+    StartTurn(u64),
     PlayerJoined(String),
     PlayerDisconnected(usize),
     PlayerReconnected(usize),
+    PlayerKicked(usize),
+    PlayerBecameBot(usize),
+    HostChanged(usize),
+    /// Someone voted to skip the active player.
+    SkipVotes {
+        votes: usize,
+        needed: usize,
+    },
+    /// The active player hasn't been idle long enough to vote them out yet.
+    SkipVoteTooEarly {
+        wait_secs: u64,
+    },
+    /// The player at this index nudged the active player to hurry up.
+    Nudged(usize),
+    /// The active player was voted out of their turn, or ran out of time.
+    /// Their moves are undone and they draw a piece.
+    TurnSkipped(usize),
+    /// The player said they're stepping away, or ran out of time too many
+    /// turns in a row. Their turns are skipped until they do something.
+    PlayerAway(usize),
+    /// An away player did something, and takes turns again.
+    PlayerBack(usize),
+    /// The active player lost their connection. Their turn waits this long
+    /// for them to come back before moving on without them.
+    WaitingForReconnect {
+        player: usize,
+        wait_secs: u64,
+    },
     GameAlreadyStarted(String),
+    /// The room is run by another server, with this websocket URL. Everything
+    /// sent to get into it, logging in included, goes there instead.
+    Redirect {
+        ws_url: String,
+    },
     DrawPiece(Piece),
+    /// Our own hand as the server has it, sent as each of our turns ends,
+    /// to put right anything we got wrong along the way.
+    HandSync(PrivateView),
     TurnFinished {
         ending_player: String,
         ending_drew: bool,
         next_player: usize,
+        hand_sizes: Vec<usize>,
         pieces_remaining: usize,
         board: BTreeMap<Coord, Piece>,
+        /// The [`state_hash`] of the game as the next turn starts.
+        state_hash: u64,
+        /// How long each player has spent on their turns this game, in
+        /// milliseconds, by player index.
+        thinking_ms: Vec<u64>,
+        /// How many of each piece are left to draw, see [`pool_counts`], in
+        /// rooms that show it.
+        pool: Option<Vec<(Piece, usize)>>,
+        /// The number of the turn starting, `next_player`'s.
+        turn: u64,
+        /// When the turn ended, by the server's clock, in milliseconds since
+        /// the Unix epoch.
+        at_ms: u64,
+    },
+    /// The [`state_hash`] of the game right now, sent every so often so
+    /// clients notice if they've missed something.
+    StateCheck(u64),
+    /// The whole game, in answer to `ClientMessage::RequestSync`.
+    FullSync(Box<RoomSnapshot>),
+    /// What the room told everyone lately, oldest first, sent after
+    /// `JoinedRoom` to a player coming back. The snapshot already has it
+    /// all in, so it's only to show, never to act on again.
+    History(Vec<PastMessage>),
+    /// The game is over. `hands` holds what every player had left, by
+    /// player index.
+    PlayerWon {
+        winner: String,
+        hands: Vec<Vec<Piece>>,
+        /// When, by the server's clock, in milliseconds since the Unix
+        /// epoch.
+        at_ms: u64,
     },
-    PlayerWon(String),
     EndTurnValid,
-    Pickup(Coord, Piece),
-    Place(Coord, Piece),
+    /// Board edits the server took, sent to everyone, whoever made them
+    /// included, as the final word on them.
+    Pickup(MoveId, Coord, Piece),
+    Place(MoveId, Coord, Piece),
+    Moves(MoveId, Vec<Move>),
+    /// Board edits the server turned down, sent only to whoever made them,
+    /// in the message `seq`.
+    MovesRejected(u64, Vec<Move>),
     InvalidBoardState,
+    /// The turn can't end, since the board was only rearranged and nothing
+    /// from the hand went down. Putting it back draws instead.
+    OnlyRearranged,
+    /// The turn can't end, since this piece was on the board when it
+    /// started and is in the hand now. It has to go back on the board.
+    KeptBoardPiece(Piece),
+    /// A player's record across every game on this server, in answer to
+    /// `ClientMessage::Stats`. All zeroes if they haven't played, or the
+    /// server doesn't keep stats.
+    PlayerStats {
+        name: String,
+        stats: PlayerStats,
+    },
+    /// The connection is logged in to the account `name`. `token` logs in
+    /// again later without the passphrase.
+    LoggedIn {
+        name: String,
+        token: String,
+    },
+    /// Waiting for a ranked room, playing at `rating`.
+    InRankedQueue {
+        rating: i32,
+    },
+    /// The game that just ended was kept, and can be fetched again as `id`.
+    GameSaved(String),
+    /// The game `id`, in answer to `ClientMessage::FetchReplay`. None if
+    /// there's no such game, or the server doesn't keep them.
+    Replay {
+        id: String,
+        record: Option<GameRecord>,
+    },
+    /// Every room running, in answer to `AdminCommand::ListRooms`.
+    AdminRooms(Vec<RoomSummary>),
+    /// One room in answer to `AdminCommand::InspectRoom`, as its host sees
+    /// it, with everyone's hand by player index. There's nothing to see
+    /// before anyone's sat down.
+    AdminRoom {
+        summary: RoomSummary,
+        snapshot: Option<Box<RoomSnapshot>>,
+        hands: Vec<Vec<Piece>>,
+    },
+    /// The code of the room `AdminCommand::CreateRoom` opened.
+    AdminRoomCreated(String),
+    /// An admin command that doesn't answer with anything else went
+    /// through.
+    AdminDone,
+    /// Something the server's operators want everyone to know, shown until
+    /// it's dismissed.
+    Announcement {
+        text: String,
+        severity: Severity,
+    },
+    /// A request was turned down.
+    Error(ServerError),
     Pong,
 }
 
+/// Where a room's game is, as far as what it will take.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Phase {
+    /// Nothing's been dealt yet, the first game waits for a second player.
+    Waiting,
+    Playing,
+    /// Someone won, and the room is waiting on a rematch.
+    Over,
+}
+
+/// Everything one player can see of their room, to join it or catch up
+/// from.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub players: Vec<String>,
+    /// How many pieces each player holds, by player index.
+    pub hand_sizes: Vec<usize>,
+    /// The index of the player who can kick others.
+    pub host: usize,
+    /// Our own hand.
+    pub view: PrivateView,
+    pub board: BTreeMap<Coord, Piece>,
+    /// How big the room's board is, which pieces have to stay on.
+    pub board_size: BoardSize,
+    pub pieces_remaining: usize,
+    /// Whether the game's been dealt. The first one waits for a second
+    /// player, and until then it's nobody's turn.
+    pub dealt: bool,
+    pub active_player: usize,
+    pub phase: Phase,
+    /// Everyone's score for the game by player index, once it's been won.
+    pub scores: Option<Vec<i64>>,
+    /// Players who've lost their connection, by player index.
+    pub disconnected: Vec<usize>,
+    /// Players whose turns a bot takes, by player index.
+    pub bots: Vec<usize>,
+    /// Players who are away, see [`ServerMessage::PlayerAway`].
+    pub away: Vec<usize>,
+    /// Players the host kicked, by player index.
+    pub kicked: Vec<usize>,
+    /// How long each player spent on their turns before this one, in
+    /// milliseconds, by player index.
+    pub thinking_ms: Vec<u64>,
+    /// How long the current turn has gone on, in milliseconds.
+    pub turn_ms: u64,
+    /// How many of each piece are left to draw, in rooms that show it.
+    pub pool: Option<Vec<(Piece, usize)>>,
+    /// The number of the turn being played, see
+    /// [`ServerMessage::StartTurn`].
+    pub turn: u64,
+}
+
+/// What only one player gets to see of a game: their own hand. Messages only
+/// carry a hand as one of these, and outside this crate they can only be
+/// made with [`GameState::private_view`], so there's no putting someone
+/// else's hand in a message by mistake. It says whose it is, to check.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PrivateView {
+    seat: usize,
+    hand: Vec<Piece>,
+}
+
+impl PrivateView {
+    /// The index of the player it's for.
+    pub fn seat(&self) -> usize {
+        self.seat
+    }
+
+    pub fn hand(&self) -> &[Piece] {
+        &self.hand
+    }
+
+    pub fn into_hand(self) -> Vec<Piece> {
+        self.hand
+    }
+}
+
+/// What whoever creates a room can choose about it.
+#[derive(Debug, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomOptions {
+    /// Tell everyone how many of each piece are left in the pool as turns
+    /// end, for teaching and analysing games. Off for competitive play.
+    pub show_pool: bool,
+    /// Groups can be laid down a column as well as across a row, a house
+    /// rule.
+    pub vertical_groups: bool,
+}
+
+/// Why the server turned a request down.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ServerError {
+    RoomNotFound(String),
+    WrongPassword,
+    Kicked,
+    ShuttingDown,
+    /// The name belongs to an account, so only it can play under it.
+    NameTaken(String),
+    /// The name, passphrase or token didn't match an account.
+    BadLogin,
+    /// The server doesn't keep accounts.
+    NoAccounts,
+    /// Only accounts can play ranked games.
+    LogInFirst,
+    /// Ranked rooms only let in the players matched into them.
+    RankedRoom,
+    /// The connection's address has created as many rooms as it may for
+    /// now, and can create another in this long.
+    TooManyRooms {
+        wait_secs: u64,
+    },
+    /// The server is running as many rooms as it can take.
+    ServerFull,
+    /// The server is too busy to let anyone new in, and asks to be tried
+    /// again in this long.
+    ServerBusy {
+        retry_after_secs: u64,
+    },
+    /// The admin secret was wrong, or the server doesn't take admin
+    /// commands.
+    NotAdmin,
+    /// A piece was placed outside the room's board. The moves are rejected
+    /// as usual too, this only says why, so it isn't the end of the
+    /// connection like the others.
+    OffBoard(Move),
+}
+
+impl ServerMessage {
+    /// Whether this has something in it only one player may see, their
+    /// hand or what they drew, so it must never be broadcast. Hands shown
+    /// once someone's won are for everyone. Every message is listed, so new
+    /// ones have to be decided on.
+    pub fn is_private(&self) -> bool {
+        match self {
+            ServerMessage::JoinedRoom { .. }
+            | ServerMessage::FullSync(_)
+            | ServerMessage::DrawPiece(_)
+            | ServerMessage::HandSync(_)
+            | ServerMessage::AdminRoom { .. } => true,
+            ServerMessage::StartGame { .. }
+            | ServerMessage::StartTurn(_)
+            | ServerMessage::PlayerJoined(_)
+            | ServerMessage::PlayerDisconnected(_)
+            | ServerMessage::PlayerReconnected(_)
+            | ServerMessage::PlayerKicked(_)
+            | ServerMessage::PlayerBecameBot(_)
+            | ServerMessage::HostChanged(_)
+            | ServerMessage::SkipVotes { .. }
+            | ServerMessage::SkipVoteTooEarly { .. }
+            | ServerMessage::Nudged(_)
+            | ServerMessage::TurnSkipped(_)
+            | ServerMessage::PlayerAway(_)
+            | ServerMessage::PlayerBack(_)
+            | ServerMessage::WaitingForReconnect { .. }
+            | ServerMessage::GameAlreadyStarted(_)
+            | ServerMessage::Redirect { .. }
+            | ServerMessage::TurnFinished { .. }
+            | ServerMessage::StateCheck(_)
+            | ServerMessage::History(_)
+            | ServerMessage::PlayerWon { .. }
+            | ServerMessage::EndTurnValid
+            | ServerMessage::Pickup(..)
+            | ServerMessage::Place(..)
+            | ServerMessage::Moves(..)
+            | ServerMessage::MovesRejected(..)
+            | ServerMessage::InvalidBoardState
+            | ServerMessage::OnlyRearranged
+            | ServerMessage::KeptBoardPiece(_)
+            | ServerMessage::PlayerStats { .. }
+            | ServerMessage::LoggedIn { .. }
+            | ServerMessage::InRankedQueue { .. }
+            | ServerMessage::GameSaved(_)
+            | ServerMessage::Replay { .. }
+            | ServerMessage::AdminRooms(_)
+            | ServerMessage::AdminRoomCreated(_)
+            | ServerMessage::AdminDone
+            | ServerMessage::Announcement { .. }
+            | ServerMessage::Error(_)
+            | ServerMessage::Pong => false,
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::RoomNotFound(room) => write!(f, "There's no room called {}", room),
+            ServerError::WrongPassword => write!(f, "That room needs the right password"),
+            ServerError::Kicked => write!(f, "The host removed you from that room"),
+            ServerError::ShuttingDown => write!(f, "The server is restarting, the game has ended"),
+            ServerError::NameTaken(name) => write!(f, "{} belongs to someone's account", name),
+            ServerError::BadLogin => write!(f, "That name and passphrase don't match an account"),
+            ServerError::NoAccounts => write!(f, "This server doesn't have accounts"),
+            ServerError::LogInFirst => write!(f, "Log in to an account to play ranked games"),
+            ServerError::RankedRoom => write!(f, "That room is for a ranked game"),
+            ServerError::TooManyRooms { wait_secs } => write!(
+                f,
+                "You've created too many rooms, try again in {} seconds",
+                wait_secs
+            ),
+            ServerError::ServerFull => write!(f, "The server is full, try again later"),
+            ServerError::ServerBusy { retry_after_secs } => write!(
+                f,
+                "The server is busy, try again in {} seconds",
+                retry_after_secs
+            ),
+            ServerError::NotAdmin => write!(f, "That isn't the admin secret"),
+            ServerError::OffBoard(_) => write!(f, "That's off the edge of the board"),
+        }
+    }
+}
+
+/// Where every rating starts.
+pub const INITIAL_RATING: i32 = 1500;
+
+/// What the server has counted of one player's games.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerStats {
+    /// Goes up and down with ranked games only.
+    pub rating: i32,
+    pub ranked_games: u32,
+    pub games_played: u32,
+    pub wins: u32,
+    /// Turns they ended themselves, not ones skipped or taken by a bot.
+    pub turns: u32,
+    /// How long those turns took, all together.
+    pub turn_millis: u64,
+    /// Pieces put down from their hand over those turns.
+    pub tiles_placed: u32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        PlayerStats {
+            rating: INITIAL_RATING,
+            ranked_games: 0,
+            games_played: 0,
+            wins: 0,
+            turns: 0,
+            turn_millis: 0,
+            tiles_placed: 0,
+        }
+    }
+}
+
+impl PlayerStats {
+    pub fn avg_turn_secs(&self) -> f64 {
+        match self.turns {
+            0 => 0.0,
+            turns => self.turn_millis as f64 / 1000.0 / turns as f64,
+        }
+    }
+
+    pub fn avg_tiles_per_turn(&self) -> f64 {
+        match self.turns {
+            0 => 0.0,
+            turns => self.tiles_placed as f64 / turns as f64,
+        }
+    }
+}
+
+/// A single board edit, batched in `ClientMessage::Moves`.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Move {
+    Pickup(Coord, Piece),
+    Place(Coord, Piece),
+}
+
+impl Move {
+    /// The move that undoes this one.
+    pub fn inverse(self) -> Move {
+        match self {
+            Move::Pickup(coord, piece) => Move::Place(coord, piece),
+            Move::Place(coord, piece) => Move::Pickup(coord, piece),
+        }
+    }
+}
+
+/// Where board edits the server passes on came from: the player at `seat`,
+/// in their messages up to and including `seq`. Edits waiting for a slow
+/// connection are merged, so one id can cover several messages. Whoever
+/// made them matches it against what they've sent, to tell which of their
+/// guesses the server has had its say on.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MoveId {
+    pub seat: usize,
+    pub seq: u64,
+}
+
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Color {
@@ -84,6 +634,106 @@ impl Piece {
     pub fn joker() -> Self {
         Piece::new(Color::Joker, std::u8::MAX)
     }
+
+    /// What the piece costs when it's left in a hand at the end of a game.
+    pub fn value(&self) -> u32 {
+        match self.color {
+            Color::Joker => 30,
+            _ => self.num as u32,
+        }
+    }
+}
+
+/// How many cells wide the board is.
+pub const BOARD_COLS: i32 = 25;
+
+/// How many cells tall the board is.
+pub const BOARD_ROWS: i32 = 15;
+
+/// How many cells a board has each way. Servers can give their rooms a
+/// different one, see `GameState::board_size`, but the usual one is
+/// [`BOARD_COLS`] by [`BOARD_ROWS`].
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BoardSize {
+    pub cols: i32,
+    pub rows: i32,
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BoardSize {
+            cols: BOARD_COLS,
+            rows: BOARD_ROWS,
+        }
+    }
+}
+
+impl BoardSize {
+    /// Whether `coord` is one of the board's cells. Pieces can only be
+    /// placed on those.
+    pub fn contains(self, coord: Coord) -> bool {
+        (0..self.cols).contains(&coord.0) && (0..self.rows).contains(&coord.1)
+    }
+}
+
+/// How much a player has to play from their hand in one turn before their
+/// first meld, by [`points`]. Clients show it, the server leaves it to the
+/// table.
+pub const OPENING_POINTS: u32 = 30;
+
+/// What `pieces` add up to, whether that's a hand being scored or a meld.
+pub fn points<'a>(pieces: impl IntoIterator<Item = &'a Piece>) -> u32 {
+    pieces.into_iter().map(Piece::value).sum()
+}
+
+/// A cheap fingerprint of what everyone can see of a game: the board, and
+/// how many pieces each player holds, by player index. It's FNV-1a over a
+/// fixed layout, so the server and clients agree on it whatever they run on.
+pub fn state_hash(board: &BTreeMap<Coord, Piece>, hand_sizes: &[usize]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(PRIME);
+        }
+    };
+
+    feed(&(board.len() as u32).to_le_bytes());
+    for (Coord(x, y), piece) in board {
+        feed(&x.to_le_bytes());
+        feed(&y.to_le_bytes());
+        feed(&[piece.color as u8, piece.num]);
+    }
+    for &size in hand_sizes {
+        feed(&(size as u32).to_le_bytes());
+    }
+
+    hash
+}
+
+/// How many of each piece are in `pieces`, in piece order, which says nothing
+/// about the order they'll be drawn in.
+pub fn pool_counts(pieces: &[Piece]) -> Vec<(Piece, usize)> {
+    let mut counts: BTreeMap<Piece, usize> = BTreeMap::new();
+    for &piece in pieces {
+        *counts.entry(piece).or_default() += 1;
+    }
+
+    counts.into_iter().collect()
+}
+
+/// Everyone's score once `winner` has gone out, by player index: the winner
+/// scores what everyone else is left holding, and everyone else loses it.
+pub fn scores(hands: &[Vec<Piece>], winner: usize) -> Vec<i64> {
+    let held: Vec<i64> = hands.iter().map(|hand| i64::from(points(hand))).collect();
+    let total = held.iter().sum();
+
+    held.iter()
+        .enumerate()
+        .map(|(i, &held)| if i == winner { total } else { -held })
+        .collect()
 }
 
 impl fmt::Debug for Piece {
@@ -96,13 +746,9 @@ impl fmt::Debug for Piece {
 pub struct Group(Vec<Piece>);
 
 impl Group {
-    pub fn first_non_joker(&self) -> usize {
-        self.0
-            .iter()
-            .enumerate()
-            .find(|(_, p)| p.color != Color::Joker)
-            .map(|(i, _)| i)
-            .unwrap()
+    /// Where the first piece that isn't a joker is, if there is one.
+    pub fn first_non_joker(&self) -> Option<usize> {
+        self.0.iter().position(|p| p.color != Color::Joker)
     }
 
     pub fn is_valid(&self) -> bool {
@@ -113,8 +759,13 @@ impl Group {
         self.is_valid_run() || self.is_valid_combo()
     }
 
+    /// Whether this counts up in one color. Jokers alone don't say which,
+    /// so a group of nothing else isn't one.
     pub fn is_valid_run(&self) -> bool {
-        let first_idx = self.first_non_joker();
+        let first_idx = match self.first_non_joker() {
+            Some(first_idx) => first_idx,
+            None => return false,
+        };
 
         if first_idx == self.0.len() - 1 {
             return true;
@@ -141,16 +792,17 @@ impl Group {
         true
     }
 
+    /// Whether this is one number in different colors, which jokers alone
+    /// aren't either.
     pub fn is_valid_combo(&self) -> bool {
         let mut seen = [false; 4];
-        let first_idx = self.first_non_joker();
-        let check_num = self.0[0].num;
-
-        if first_idx == self.0.len() - 1 {
-            return true;
-        }
+        let first_idx = match self.first_non_joker() {
+            Some(first_idx) => first_idx,
+            None => return false,
+        };
+        let check_num = self.0[first_idx].num;
 
-        for Piece { color, num } in &self.0[first_idx + 1..] {
+        for Piece { color, num } in &self.0[first_idx..] {
             if *color == Color::Joker {
                 continue;
             }
@@ -170,13 +822,26 @@ impl Group {
 pub struct Game {
     grid: BTreeMap<Coord, Piece>,
     remaining_pieces: Vec<Piece>,
+    /// Every shuffle of the pool follows from this, so a game can be played
+    /// again exactly.
+    seed: u64,
+    /// How many times the pool has been shuffled, so each shuffle differs.
+    shuffles: u64,
 }
 
 impl Game {
     pub fn new() -> Self {
+        Game::new_with_seed(rand::random())
+    }
+
+    /// A game whose pool is always shuffled the same way for the same
+    /// `seed`.
+    pub fn new_with_seed(seed: u64) -> Self {
         let mut game = Self {
             grid: BTreeMap::new(),
             remaining_pieces: Game::create_pieces(),
+            seed,
+            shuffles: 0,
         };
 
         game.shuffle();
@@ -184,10 +849,20 @@ impl Game {
         game
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn shuffle(&mut self) {
         use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
 
-        self.remaining_pieces.shuffle(&mut rand::thread_rng());
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(self.shuffles);
+        self.shuffles += 1;
+
+        self.remaining_pieces.shuffle(&mut rng);
     }
 
     pub fn create_pieces() -> Vec<Piece> {
@@ -230,78 +905,327 @@ impl Game {
         self.remaining_pieces.pop()
     }
 
+    /// Puts `pieces` back into the pool and shuffles it.
+    pub fn return_pieces(&mut self, pieces: Vec<Piece>) {
+        self.remaining_pieces.extend(pieces);
+        self.shuffle();
+    }
+
     pub fn set_board(&mut self, grid: BTreeMap<Coord, Piece>) {
         self.grid = grid;
     }
 
+    /// Applies `moves` to the board and `hand` as a unit. Picked up pieces go
+    /// into `hand` and placed pieces must come from it. If any move is
+    /// invalid nothing is changed and the offending move is returned,
+    /// otherwise the net number of pieces placed on the board. How big the
+    /// board is is up to the caller, see `GameState::board_size`.
+    pub fn apply_moves(&mut self, hand: &mut Vec<Piece>, moves: &[Move]) -> Result<i8, Move> {
+        let mut grid = self.grid.clone();
+        let mut new_hand = hand.clone();
+        let mut delta = 0;
+
+        for &m in moves {
+            match m {
+                Move::Pickup(coord, piece) => {
+                    if grid.get(&coord) != Some(&piece) {
+                        return Err(m);
+                    }
+
+                    grid.remove(&coord);
+                    new_hand.push(piece);
+                    delta -= 1;
+                }
+                Move::Place(coord, piece) => {
+                    if grid.contains_key(&coord) {
+                        return Err(m);
+                    }
+
+                    match new_hand.iter().position(|p| *p == piece) {
+                        Some(i) => new_hand.swap_remove(i),
+                        None => return Err(m),
+                    };
+
+                    grid.insert(coord, piece);
+                    delta += 1;
+                }
+            }
+        }
+
+        self.grid = grid;
+        *hand = new_hand;
+
+        Ok(delta)
+    }
+
+    /// Whether every horizontal run of touching pieces is a valid group,
+    /// and the runs, top to bottom and left to right.
     pub fn is_valid_board(&self) -> (bool, Vec<Group>) {
-        let mut current_group: Option<Group> = None;
-        let mut groups: Vec<Group> = Vec::new();
+        self.is_valid_board_with(false)
+    }
 
-        let min_x = self
-            .board()
-            .iter()
-            .map(|(k, _)| k.0)
-            .min()
-            .unwrap_or_default();
-        let min_y = self
-            .board()
-            .iter()
-            .map(|(k, _)| k.1)
-            .min()
-            .unwrap_or_default();
+    /// Like [`Game::is_valid_board`], also allowing groups laid out down a
+    /// column when `columns` is set. Then each cluster of touching pieces is
+    /// read either across or down, whichever makes every run in it a valid
+    /// group, so a piece is never in two groups at once. Clusters read down
+    /// have their columns after all the rows.
+    ///
+    /// Only the pieces themselves are looked at, so however far apart they
+    /// are, this takes as long as there are pieces.
+    pub fn is_valid_board_with(&self, columns: bool) -> (bool, Vec<Group>) {
+        let rows = board_groups(&self.grid);
+        let group = |run: &[(Coord, Piece)]| Group(run.iter().map(|&(_, piece)| piece).collect());
+
+        if !columns {
+            let groups: Vec<Group> = rows.iter().map(|run| group(run)).collect();
+            let is_valid = groups.iter().all(Group::is_valid);
+
+            return (is_valid, groups);
+        }
+
+        // The grid's already sorted by column:
+        let cols = runs(
+            self.grid.iter().map(|(&coord, &piece)| (coord, piece)),
+            |Coord(x, y)| Some(Coord(x, y.checked_add(1)?)),
+        );
 
-        let max_x = self
-            .board()
+        // Clusters are found by joining up the rows every column touches:
+        let row_of: BTreeMap<Coord, usize> = rows
             .iter()
-            .map(|(k, _)| k.0)
-            .max()
-            .unwrap_or_default();
-        let max_y = self
-            .board()
+            .enumerate()
+            .flat_map(|(i, run)| run.iter().map(move |&(coord, _)| (coord, i)))
+            .collect();
+        let mut parent: Vec<usize> = (0..rows.len()).collect();
+        for col in &cols {
+            for pair in col.windows(2) {
+                let a = find(&mut parent, row_of[&pair[0].0]);
+                let b = find(&mut parent, row_of[&pair[1].0]);
+                parent[a] = b;
+            }
+        }
+        let cluster: Vec<usize> = (0..rows.len()).map(|i| find(&mut parent, i)).collect();
+
+        let mut across = vec![true; rows.len()];
+        let mut down = vec![true; rows.len()];
+        for (i, run) in rows.iter().enumerate() {
+            across[cluster[i]] &= group(run).is_valid();
+        }
+        for col in &cols {
+            down[cluster[row_of[&col[0].0]]] &= group(col).is_valid();
+        }
+
+        // Clusters that don't work either way are shown read across:
+        let read_down = |row: usize| !across[cluster[row]] && down[cluster[row]];
+        let groups: Vec<Group> = rows
             .iter()
-            .map(|(k, _)| k.1)
-            .max()
-            .unwrap_or_default();
+            .enumerate()
+            .filter(|&(i, _)| !read_down(i))
+            .map(|(_, run)| group(run))
+            .chain(
+                cols.iter()
+                    .filter(|col| read_down(row_of[&col[0].0]))
+                    .map(|col| group(col)),
+            )
+            .collect();
+        let is_valid = (0..rows.len()).all(|i| across[cluster[i]] || down[cluster[i]]);
 
-        // println!("({}, {}) ({}, {})", min_x, min_y, max_x, max_y);
+        (is_valid, groups)
+    }
+}
 
-        for y in min_y..=max_y {
-            if let Some(group) = current_group.take() {
-                groups.push(group);
+/// The cluster `i` is in, shortening the way there as it goes.
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+
+    i
+}
+
+/// Splits `cells` into runs where each cell is `next` to the one before it,
+/// if there's room for one. `cells` have to come in the order `next` steps
+/// in.
+fn runs(
+    cells: impl Iterator<Item = (Coord, Piece)>,
+    next: impl Fn(Coord) -> Option<Coord>,
+) -> Vec<Vec<(Coord, Piece)>> {
+    let mut runs: Vec<Vec<(Coord, Piece)>> = Vec::new();
+
+    for (coord, piece) in cells {
+        match runs.last_mut() {
+            Some(run) if run.last().and_then(|&(c, _)| next(c)) == Some(coord) => {
+                run.push((coord, piece));
             }
+            _ => runs.push(vec![(coord, piece)]),
+        }
+    }
 
-            for x in min_x..=max_x {
-                if let Some(piece) = self.board().get(&Coord(x, y)) {
-                    current_group
-                        .get_or_insert(Group(Vec::new()))
-                        .0
-                        .push(*piece);
-                } else if let Some(group) = current_group.take() {
-                    groups.push(group);
+    runs
+}
+
+/// Splits a board into horizontal runs of touching pieces, top to bottom and
+/// left to right.
+pub fn board_groups(board: &BTreeMap<Coord, Piece>) -> Vec<Vec<(Coord, Piece)>> {
+    let by_row: BTreeMap<(i32, i32), (Coord, Piece)> = board
+        .iter()
+        .map(|(&coord, &piece)| ((coord.1, coord.0), (coord, piece)))
+        .collect();
+
+    runs(by_row.into_values(), |Coord(x, y)| {
+        Some(Coord(x.checked_add(1)?, y))
+    })
+}
+
+/// Splits a board into clusters of pieces touching across or down, ordered by
+/// their first piece top to bottom and left to right.
+fn board_clusters(board: &BTreeMap<Coord, Piece>) -> Vec<Vec<(Coord, Piece)>> {
+    let mut cells: Vec<Coord> = board.keys().copied().collect();
+    cells.sort_by_key(|&Coord(x, y)| (y, x));
+
+    let mut seen = BTreeSet::new();
+    let mut clusters = Vec::new();
+
+    for start in cells {
+        if !seen.insert(start) {
+            continue;
+        }
+
+        let mut cluster = Vec::new();
+        let mut todo = vec![start];
+        while let Some(coord @ Coord(x, y)) = todo.pop() {
+            cluster.push((coord, board[&coord]));
+
+            let neighbours = [
+                x.checked_sub(1).map(|x| Coord(x, y)),
+                x.checked_add(1).map(|x| Coord(x, y)),
+                y.checked_sub(1).map(|y| Coord(x, y)),
+                y.checked_add(1).map(|y| Coord(x, y)),
+            ];
+            for next in neighbours.iter().flatten() {
+                if board.contains_key(next) && seen.insert(*next) {
+                    todo.push(*next);
                 }
             }
         }
 
-        if let Some(group) = current_group {
-            groups.push(group);
+        clusters.push(cluster);
+    }
+
+    clusters
+}
+
+/// Lays every group out in left aligned rows `cols` wide, with an empty column
+/// between groups. Groups keep their pieces and order.
+///
+/// With `vertical_groups` a group can run down a column, so whole clusters of
+/// touching pieces are moved instead, keeping their shape, with an empty row
+/// between rows of them too.
+pub fn tidy_board(
+    board: &BTreeMap<Coord, Piece>,
+    cols: i32,
+    vertical_groups: bool,
+) -> BTreeMap<Coord, Piece> {
+    if vertical_groups {
+        return tidy_clusters(board, cols);
+    }
+
+    let mut tidy = BTreeMap::new();
+    let (mut x, mut y) = (0, 0);
+
+    for group in board_groups(board) {
+        let len = group.len() as i32;
+
+        if x > 0 && x + len > cols {
+            x = 0;
+            y += 1;
         }
 
-        let is_valid = groups.iter().all(Group::is_valid);
+        for (i, (_, piece)) in group.into_iter().enumerate() {
+            tidy.insert(Coord(x + i as i32, y), piece);
+        }
 
-        (is_valid, groups)
+        x += len + 1;
+    }
+
+    tidy
+}
+
+/// [`tidy_board`] for rooms with vertical groups.
+fn tidy_clusters(board: &BTreeMap<Coord, Piece>, cols: i32) -> BTreeMap<Coord, Piece> {
+    let mut tidy = BTreeMap::new();
+    let (mut x, mut y, mut height) = (0, 0, 0);
+
+    for cluster in board_clusters(board) {
+        let left = cluster.iter().map(|(Coord(x, _), _)| *x).min().unwrap_or(0);
+        let top = cluster.iter().map(|(Coord(_, y), _)| *y).min().unwrap_or(0);
+        let right = cluster.iter().map(|(Coord(x, _), _)| *x).max().unwrap_or(0);
+        let bottom = cluster.iter().map(|(Coord(_, y), _)| *y).max().unwrap_or(0);
+        let (width, tall) = (right - left + 1, bottom - top + 1);
+
+        if x > 0 && x + width > cols {
+            x = 0;
+            y += height + 1;
+            height = 0;
+        }
+
+        for (Coord(cx, cy), piece) in cluster {
+            tidy.insert(Coord(x + cx - left, y + cy - top), piece);
+        }
+
+        x += width + 1;
+        height = height.max(tall);
     }
+
+    tidy
 }
 
+/// The moves that turn board `from` into board `to`, which must hold the same
+/// pieces. Pieces that don't move are left alone.
+pub fn board_diff(from: &BTreeMap<Coord, Piece>, to: &BTreeMap<Coord, Piece>) -> Vec<Move> {
+    let pickups = from
+        .iter()
+        .filter(|(c, p)| to.get(c) != Some(p))
+        .map(|(c, p)| Move::Pickup(*c, *p));
+
+    let places = to
+        .iter()
+        .filter(|(c, p)| from.get(c) != Some(p))
+        .map(|(c, p)| Move::Place(*c, *p));
+
+    pickups.chain(places).collect()
+}
+
+/// A position on the board. In JSON it's written as the string `"x,y"`, so
+/// boards can be maps keyed by it.
+///
+/// Coords used to be written `"(x,y)"`. Both forms are still read, but older
+/// builds can only read that one, so the client and server have to be
+/// deployed together.
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Coord(pub i32, pub i32);
 
+impl Coord {
+    /// Reads `x,y`, or `(x,y)` from older builds.
+    fn parse(s: &str) -> Option<Coord> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix('(')
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap_or(s);
+
+        let (x, y) = s.split_once(',')?;
+
+        Some(Coord(x.trim().parse().ok()?, y.trim().parse().ok()?))
+    }
+}
+
 impl Serialize for Coord {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let key = format!("({},{})", self.0, self.1);
+        let key = format!("{},{}", self.0, self.1);
         serializer.serialize_str(&key)
     }
 }
@@ -311,17 +1235,24 @@ impl<'de> Deserialize<'de> for Coord {
     where
         D: Deserializer<'de>,
     {
-        let s: &str = Deserialize::deserialize(deserializer)?;
-        let s = &s[1..s.len() - 1];
+        deserializer.deserialize_str(CoordVisitor)
+    }
+}
 
-        let mut nums = s.split(",");
+struct CoordVisitor;
 
-        let (x, y): (i32, i32) = (
-            nums.next().unwrap().parse().unwrap(),
-            nums.next().unwrap().parse().unwrap(),
-        );
+impl<'de> Visitor<'de> for CoordVisitor {
+    type Value = Coord;
 
-        Ok(Coord(x, y))
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a coordinate like \"3,-2\"")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Coord, E>
+    where
+        E: de::Error,
+    {
+        Coord::parse(s).ok_or_else(|| E::invalid_value(Unexpected::Str(s), &self))
     }
 }
 
@@ -329,6 +1260,61 @@ impl<'de> Deserialize<'de> for Coord {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_points() {
+        let meld = [
+            Piece::new(Color::Red, 10),
+            Piece::new(Color::Blue, 10),
+            Piece::joker(),
+        ];
+
+        assert_eq!(points(&meld), 50);
+        assert_eq!(points(&[]), 0);
+    }
+
+    #[test]
+    fn pool_counts_only_say_how_many() {
+        let red = Piece::new(Color::Red, 5);
+        let blue = Piece::new(Color::Blue, 1);
+        let pool = [Piece::joker(), red, blue, red, Piece::joker()];
+
+        assert_eq!(
+            pool_counts(&pool),
+            vec![(red, 2), (blue, 1), (Piece::joker(), 2)]
+        );
+        assert_eq!(pool_counts(&[]), Vec::new());
+    }
+
+    #[test]
+    fn test_player_stats_averages() {
+        let stats = PlayerStats {
+            turns: 4,
+            turn_millis: 10_000,
+            tiles_placed: 6,
+            ..PlayerStats::default()
+        };
+
+        assert_eq!(stats.avg_turn_secs(), 2.5);
+        assert_eq!(stats.avg_tiles_per_turn(), 1.5);
+        assert_eq!(PlayerStats::default().avg_turn_secs(), 0.0);
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let mut board = BTreeMap::new();
+        board.insert(Coord(0, 0), Piece::new(Color::Red, 5));
+        let hash = state_hash(&board, &[14, 13]);
+
+        assert_eq!(hash, state_hash(&board.clone(), &[14, 13]));
+        assert_ne!(hash, state_hash(&board, &[13, 14]));
+        assert_ne!(hash, state_hash(&board, &[14, 13, 0]));
+
+        let moved: BTreeMap<_, _> = board.values().map(|&p| (Coord(1, 0), p)).collect();
+        assert_ne!(hash, state_hash(&moved, &[14, 13]));
+        board.insert(Coord(0, 0), Piece::new(Color::Blue, 5));
+        assert_ne!(hash, state_hash(&board, &[14, 13]));
+    }
+
     #[test]
     fn test_board_valid() {
         let mut grid = BTreeMap::new();
@@ -354,4 +1340,458 @@ mod tests {
             ])]
         );
     }
+
+    #[test]
+    fn test_all_jokers_invalid() {
+        let jokers = Group(vec![Piece::joker(); 3]);
+        assert_eq!(jokers.first_non_joker(), None);
+        assert!(!jokers.is_valid());
+
+        let mut game = Game::new();
+        game.set_board((0..3).map(|x| (Coord(x, 0), Piece::joker())).collect());
+        assert!(!game.is_valid_board().0);
+        assert!(!game.is_valid_board_with(true).0);
+    }
+
+    #[test]
+    fn test_board_columns() {
+        let game = |cells: &[(i32, i32, Color, u8)]| {
+            let mut game = Game::new();
+            game.set_board(
+                cells
+                    .iter()
+                    .map(|&(x, y, color, num)| (Coord(x, y), Piece::new(color, num)))
+                    .collect(),
+            );
+            game
+        };
+        let sevens = [
+            (5, 2, Color::Blue, 7),
+            (5, 3, Color::Red, 7),
+            (5, 4, Color::Black, 7),
+        ];
+
+        // A column of sevens and, apart from it, a run across:
+        let mut cells = sevens.to_vec();
+        cells.extend(&[
+            (7, 3, Color::Red, 8),
+            (8, 3, Color::Red, 9),
+            (9, 3, Color::Red, 10),
+        ]);
+        let mixed = game(&cells);
+        assert!(!mixed.is_valid_board().0);
+        let (is_valid, groups) = mixed.is_valid_board_with(true);
+        assert!(is_valid);
+        assert_eq!(
+            groups,
+            &[
+                Group(vec![
+                    Piece::new(Color::Red, 8),
+                    Piece::new(Color::Red, 9),
+                    Piece::new(Color::Red, 10)
+                ]),
+                Group(vec![
+                    Piece::new(Color::Blue, 7),
+                    Piece::new(Color::Red, 7),
+                    Piece::new(Color::Black, 7)
+                ]),
+            ]
+        );
+
+        // Touching the column, the run would share its red seven:
+        let mut cells = sevens.to_vec();
+        cells.extend(&[(6, 3, Color::Red, 8), (7, 3, Color::Red, 9)]);
+        assert!(!game(&cells).is_valid_board_with(true).0);
+
+        // Columns side by side are read down:
+        let mut cells = sevens.to_vec();
+        cells.extend(&[
+            (6, 2, Color::Blue, 8),
+            (6, 3, Color::Red, 8),
+            (6, 4, Color::Black, 8),
+        ]);
+        assert!(game(&cells).is_valid_board_with(true).0);
+
+        // And rows on top of each other across, as they always were:
+        let stacked = game(&[
+            (0, 0, Color::Red, 1),
+            (1, 0, Color::Red, 2),
+            (2, 0, Color::Red, 3),
+            (0, 1, Color::Blue, 5),
+            (1, 1, Color::Blue, 6),
+            (2, 1, Color::Blue, 7),
+        ]);
+        assert!(stacked.is_valid_board().0);
+        assert_eq!(stacked.is_valid_board_with(true), stacked.is_valid_board());
+    }
+
+    #[test]
+    fn test_apply_moves_is_atomic() {
+        let mut game = Game::new();
+        let mut hand = vec![Piece::new(Color::Red, 5)];
+
+        let moves = [
+            Move::Place(Coord(0, 0), Piece::new(Color::Red, 5)),
+            Move::Place(Coord(1, 0), Piece::new(Color::Red, 6)),
+        ];
+
+        assert_eq!(game.apply_moves(&mut hand, &moves), Err(moves[1]));
+        assert!(game.board().is_empty());
+        assert_eq!(hand, vec![Piece::new(Color::Red, 5)]);
+
+        let moves = [
+            Move::Place(Coord(0, 0), Piece::new(Color::Red, 5)),
+            Move::Pickup(Coord(0, 0), Piece::new(Color::Red, 5)),
+            Move::Place(Coord(3, 0), Piece::new(Color::Red, 5)),
+        ];
+
+        assert_eq!(game.apply_moves(&mut hand, &moves), Ok(1));
+        assert!(hand.is_empty());
+        assert_eq!(
+            game.board().get(&Coord(3, 0)),
+            Some(&Piece::new(Color::Red, 5))
+        );
+    }
+
+    #[test]
+    fn test_seeded_games_repeat() {
+        let mut a = Game::new_with_seed(12345);
+        let mut b = Game::new_with_seed(12345);
+
+        assert_eq!(a.deal(14), b.deal(14));
+
+        // Kicked players' pieces go back in the same way too:
+        let returned = a.deal(3);
+        a.return_pieces(returned.clone());
+        b.deal(3);
+        b.return_pieces(returned);
+        assert_eq!(a.remaining_pieces(), b.remaining_pieces());
+
+        assert_ne!(
+            Game::new_with_seed(1).remaining_pieces(),
+            Game::new_with_seed(2).remaining_pieces()
+        );
+    }
+
+    #[test]
+    fn test_coord_serde() {
+        for coord in [Coord(0, 0), Coord(12, 7), Coord(-3, 5), Coord(-40, -1)].iter() {
+            let json = serde_json::to_string(coord).unwrap();
+            assert_eq!(serde_json::from_str::<Coord>(&json).unwrap(), *coord);
+        }
+
+        assert_eq!(serde_json::to_string(&Coord(-3, 5)).unwrap(), r#""-3,5""#);
+
+        // Written by older builds:
+        assert_eq!(
+            serde_json::from_str::<Coord>(r#""(-3,5)""#).unwrap(),
+            Coord(-3, 5)
+        );
+
+        for bad in [
+            r#""""#,
+            r#""()""#,
+            r#""(1,2""#,
+            r#""1""#,
+            r#""1,2,3""#,
+            r#""a,b""#,
+            "7",
+        ]
+        .iter()
+        {
+            assert!(serde_json::from_str::<Coord>(bad).is_err(), "{}", bad);
+        }
+
+        // As map keys, with escapes that can't be borrowed straight from the
+        // input:
+        let board: BTreeMap<Coord, Piece> =
+            serde_json::from_str(r#"{"\u0031,2": {"color": "Red", "num": 3}}"#).unwrap();
+        assert_eq!(board.get(&Coord(1, 2)), Some(&Piece::new(Color::Red, 3)));
+    }
+
+    #[test]
+    fn test_tidy_board() {
+        let mut board = BTreeMap::new();
+
+        board.insert(Coord(7, 3), Piece::new(Color::Red, 1));
+        board.insert(Coord(8, 3), Piece::new(Color::Red, 2));
+        board.insert(Coord(9, 3), Piece::new(Color::Red, 3));
+        board.insert(Coord(2, 5), Piece::new(Color::Blue, 9));
+        board.insert(Coord(3, 5), Piece::new(Color::Red, 9));
+        board.insert(Coord(4, 5), Piece::new(Color::Black, 9));
+        board.insert(Coord(0, 9), Piece::new(Color::Yellow, 4));
+        board.insert(Coord(1, 9), Piece::new(Color::Yellow, 5));
+        board.insert(Coord(2, 9), Piece::new(Color::Yellow, 6));
+
+        let tidy = tidy_board(&board, 8, false);
+
+        let mut expected = BTreeMap::new();
+        expected.insert(Coord(0, 0), Piece::new(Color::Red, 1));
+        expected.insert(Coord(1, 0), Piece::new(Color::Red, 2));
+        expected.insert(Coord(2, 0), Piece::new(Color::Red, 3));
+        expected.insert(Coord(4, 0), Piece::new(Color::Blue, 9));
+        expected.insert(Coord(5, 0), Piece::new(Color::Red, 9));
+        expected.insert(Coord(6, 0), Piece::new(Color::Black, 9));
+        expected.insert(Coord(0, 1), Piece::new(Color::Yellow, 4));
+        expected.insert(Coord(1, 1), Piece::new(Color::Yellow, 5));
+        expected.insert(Coord(2, 1), Piece::new(Color::Yellow, 6));
+
+        assert_eq!(tidy, expected);
+
+        let mut game = Game::new();
+        game.set_board(board);
+
+        let mut hand = Vec::new();
+        let moves = board_diff(game.board(), &tidy);
+
+        assert_eq!(game.apply_moves(&mut hand, &moves), Ok(0));
+        assert_eq!(game.board(), &tidy);
+        assert!(hand.is_empty());
+    }
+
+    #[test]
+    fn test_board_groups_at_the_edge() {
+        let mut board = BTreeMap::new();
+        board.insert(Coord(i32::MAX - 1, 0), Piece::new(Color::Red, 1));
+        board.insert(Coord(i32::MAX, 0), Piece::new(Color::Red, 2));
+        board.insert(Coord(i32::MIN, 0), Piece::new(Color::Red, 3));
+
+        let groups = board_groups(&board);
+        assert_eq!(
+            groups,
+            vec![
+                vec![(Coord(i32::MIN, 0), Piece::new(Color::Red, 3))],
+                vec![
+                    (Coord(i32::MAX - 1, 0), Piece::new(Color::Red, 1)),
+                    (Coord(i32::MAX, 0), Piece::new(Color::Red, 2)),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tidy_board_columns() {
+        let mut board = BTreeMap::new();
+
+        board.insert(Coord(5, 2), Piece::new(Color::Blue, 7));
+        board.insert(Coord(5, 3), Piece::new(Color::Red, 7));
+        board.insert(Coord(5, 4), Piece::new(Color::Black, 7));
+        board.insert(Coord(7, 3), Piece::new(Color::Red, 8));
+        board.insert(Coord(8, 3), Piece::new(Color::Red, 9));
+        board.insert(Coord(9, 3), Piece::new(Color::Red, 10));
+        for num in 1..=4 {
+            board.insert(Coord(num as i32, 9), Piece::new(Color::Yellow, num));
+        }
+
+        let tidy = tidy_board(&board, 8, true);
+
+        let mut expected = BTreeMap::new();
+        expected.insert(Coord(0, 0), Piece::new(Color::Blue, 7));
+        expected.insert(Coord(0, 1), Piece::new(Color::Red, 7));
+        expected.insert(Coord(0, 2), Piece::new(Color::Black, 7));
+        expected.insert(Coord(2, 0), Piece::new(Color::Red, 8));
+        expected.insert(Coord(3, 0), Piece::new(Color::Red, 9));
+        expected.insert(Coord(4, 0), Piece::new(Color::Red, 10));
+        // Under the column, with a row between them:
+        for num in 1..=4 {
+            expected.insert(Coord(num as i32 - 1, 4), Piece::new(Color::Yellow, num));
+        }
+
+        assert_eq!(tidy, expected);
+
+        let mut game = Game::new();
+        game.set_board(board);
+        let before = game.is_valid_board_with(true);
+        game.set_board(tidy);
+        assert!(before.0);
+        assert_eq!(game.is_valid_board_with(true), before);
+    }
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    const COLORS: [Color; 4] = [Color::Red, Color::Blue, Color::Yellow, Color::Black];
+
+    /// Swaps some of `pieces` for jokers, always leaving at least two real
+    /// pieces, so breaking one of them always breaks the group.
+    fn with_jokers(pieces: Vec<Piece>) -> impl Strategy<Value = Vec<Piece>> {
+        let len = pieces.len();
+
+        (
+            proptest::collection::vec(any::<bool>(), len),
+            0..len,
+            1..len,
+        )
+            .prop_map(move |(jokers, keep, apart)| {
+                let other = (keep + apart) % len;
+                pieces
+                    .iter()
+                    .zip(jokers)
+                    .enumerate()
+                    .map(|(i, (&piece, joker))| {
+                        if joker && i != keep && i != other {
+                            Piece::joker()
+                        } else {
+                            piece
+                        }
+                    })
+                    .collect()
+            })
+    }
+
+    /// Three or more pieces of one color counting up, some of them jokers.
+    fn valid_run() -> impl Strategy<Value = Vec<Piece>> {
+        (select(&COLORS[..]), 1u8..=11)
+            .prop_flat_map(|(color, start)| {
+                (3..=14 - start).prop_map(move |len| {
+                    (start..start + len)
+                        .map(|num| Piece::new(color, num))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .prop_flat_map(with_jokers)
+    }
+
+    /// Three or four pieces of one number in different colors, some of them
+    /// jokers.
+    fn valid_combo() -> impl Strategy<Value = Vec<Piece>> {
+        (1u8..=13, Just(COLORS.to_vec()).prop_shuffle(), 3usize..=4)
+            .prop_map(|(num, colors, len)| {
+                colors[..len]
+                    .iter()
+                    .map(|&color| Piece::new(color, num))
+                    .collect::<Vec<_>>()
+            })
+            .prop_flat_map(with_jokers)
+    }
+
+    fn valid_group() -> impl Strategy<Value = Vec<Piece>> {
+        prop_oneof![valid_run(), valid_combo()]
+    }
+
+    fn piece() -> impl Strategy<Value = Piece> {
+        prop_oneof![
+            10 => (select(&COLORS[..]), 1u8..=13).prop_map(|(color, num)| Piece::new(color, num)),
+            1 => Just(Piece::joker()),
+        ]
+    }
+
+    /// Pieces scattered over a small area, so some of them touch.
+    fn board() -> impl Strategy<Value = BTreeMap<Coord, Piece>> {
+        proptest::collection::btree_map(
+            (-4i32..4, -3i32..3).prop_map(|(x, y)| Coord(x, y)),
+            piece(),
+            0..40,
+        )
+    }
+
+    /// Every horizontal run of touching pieces on `board`, found the naive
+    /// way, by walking each row of its bounding box a cell at a time. Only
+    /// fit for boards as small as [`board`] makes.
+    fn reference_runs(board: &BTreeMap<Coord, Piece>) -> Vec<Vec<Piece>> {
+        let mut runs = Vec::new();
+        let xs = board.keys().map(|c| c.0);
+        let ys = board.keys().map(|c| c.1);
+        let (min_x, max_x) = match (xs.clone().min(), xs.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return runs,
+        };
+        let (min_y, max_y) = (ys.clone().min().unwrap(), ys.max().unwrap());
+
+        for y in min_y..=max_y {
+            let mut run = Vec::new();
+            // One past the edge, to end the last run:
+            for x in min_x..=max_x + 1 {
+                match board.get(&Coord(x, y)) {
+                    Some(&piece) => run.push(piece),
+                    None if !run.is_empty() => runs.push(std::mem::take(&mut run)),
+                    None => {}
+                }
+            }
+        }
+
+        runs
+    }
+
+    /// Whether every horizontal run of touching pieces on `board` is a valid
+    /// group, worked out the simple way.
+    fn reference_is_valid(board: &BTreeMap<Coord, Piece>) -> bool {
+        reference_runs(board)
+            .into_iter()
+            .all(|run| Group(run).is_valid())
+    }
+
+    proptest! {
+        #[test]
+        fn valid_groups_validate(pieces in valid_group()) {
+            prop_assert!(Group(pieces).is_valid());
+        }
+
+        #[test]
+        fn changing_a_number_breaks_a_group(
+            pieces in valid_group(),
+            which in any::<prop::sample::Index>(),
+            by in 1u8..13,
+        ) {
+            let real: Vec<usize> = (0..pieces.len())
+                .filter(|&i| pieces[i].color != Color::Joker)
+                .collect();
+
+            let mut pieces = pieces;
+            let i = real[which.index(real.len())];
+            pieces[i].num = (pieces[i].num - 1 + by) % 13 + 1;
+
+            prop_assert!(!Group(pieces).is_valid());
+        }
+
+        #[test]
+        fn repeating_a_color_breaks_a_combo(
+            pieces in valid_combo(),
+            which in any::<prop::sample::Index>(),
+        ) {
+            let real: Vec<usize> = (0..pieces.len())
+                .filter(|&i| pieces[i].color != Color::Joker)
+                .collect();
+
+            let mut pieces = pieces;
+            let i = which.index(real.len());
+            let (from, to) = (real[i], real[(i + 1) % real.len()]);
+            pieces[to].color = pieces[from].color;
+
+            prop_assert!(!Group(pieces).is_valid());
+        }
+
+        #[test]
+        fn short_groups_are_invalid(pieces in valid_group(), len in 1usize..3) {
+            let real = pieces.iter().position(|p| p.color != Color::Joker).unwrap();
+            let start = real.saturating_sub(len - 1);
+
+            prop_assert!(!Group(pieces[start..start + len].to_vec()).is_valid());
+        }
+
+        #[test]
+        fn board_validation_matches_reference(board in board()) {
+            let mut game = Game::new();
+            game.set_board(board.clone());
+
+            prop_assert_eq!(game.is_valid_board().0, reference_is_valid(&board));
+        }
+
+        #[test]
+        fn coords_never_panic(key in ".*") {
+            let _ = serde_json::from_value::<Coord>(serde_json::Value::String(key));
+        }
+
+        #[test]
+        fn coords_round_trip(x in any::<i32>(), y in any::<i32>()) {
+            let json = serde_json::to_string(&Coord(x, y)).unwrap();
+
+            prop_assert_eq!(serde_json::from_str::<Coord>(&json).unwrap(), Coord(x, y));
+        }
+    }
 }
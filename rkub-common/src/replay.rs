@@ -0,0 +1,332 @@
+//! Finished games, kept as everything that happened to their state in
+//! order, so they can be played back exactly from their seed.
+//!
+//! A [`GameRecord`] is also the format games are exported and shared in, as
+//! JSON. It looks like this:
+//!
+//! ```json
+//! {
+//!   "format": 1,
+//!   "id": "k3x9qa-5f0e2c1d9a",
+//!   "seed": 24689751293,
+//!   "started_ms": 1760612400000,
+//!   "players": ["alice", "bob"],
+//!   "events": [
+//!     { "at_ms": 0, "event": { "Seated": { "name": "alice", "dealt": true } } },
+//!     { "at_ms": 2310, "event": { "Seated": { "name": "bob", "dealt": true } } },
+//!     { "at_ms": 9045, "event": { "Played": [0, { "Place": ["0,0", { "color": "Red", "num": 5 }] }] } },
+//!     { "at_ms": 12000, "event": { "Played": [0, "EndTurn"] } },
+//!     { "at_ms": 12000, "event": { "TurnStarted": 1 } },
+//!     { "at_ms": 70000, "event": "RolledBack" }
+//!   ],
+//!   "scores": [57, -57],
+//!   "vertical_groups": false,
+//!   "board_size": { "cols": 25, "rows": 15 }
+//! }
+//! ```
+//!
+//! - `format` is [`FORMAT`], and goes up whenever the rest changes.
+//! - `started_ms` is when the game started, in milliseconds since the Unix
+//!   epoch, and each event's `at_ms` is how long after that it happened.
+//! - `players` and `scores` are by player index, which is the order players
+//!   were seated in. Events refer to players by index too.
+//! - `events` are [`GameEvent`]s, with actions and pieces as the websocket
+//!   messages have them. Coords are `"x,y"`.
+//! - `scores` are as [`crate::scores`] counts them, empty if nobody won.
+//! - `vertical_groups` is set for games that allowed groups down a column,
+//!   and can be left out otherwise.
+//! - `board_size` is how many cells the board had each way, and can be left
+//!   out for the usual [`BoardSize`].
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::{reduce, Action, GameState};
+use crate::BoardSize;
+
+/// The version of the [`GameRecord`] format this build reads and writes.
+pub const FORMAT: u32 = 1;
+
+/// Something that changed a game's state. Only what the game took is kept,
+/// so replaying never runs into a rejection.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GameEvent {
+    /// `name` took the next seat, with a hand unless they were kicked before
+    /// the game started.
+    Seated { name: String, dealt: bool },
+    /// A player's action went through the rules.
+    Played(usize, Action),
+    /// The active player's turn was undone, for running out of time or
+    /// leaving.
+    RolledBack,
+    /// A player was dealt a piece without ending their own turn, when their
+    /// turn was skipped or played by a bot.
+    Drew(usize),
+    /// A player was removed, and their hand went back in the pool.
+    Kicked(usize),
+    /// A player's turn started.
+    TurnStarted(usize),
+}
+
+/// A [`GameEvent`] and when it happened.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    /// Milliseconds since the game started.
+    pub at_ms: u64,
+    /// The turn it happened on, as the players saw it numbered. Older
+    /// records left it out, as 0.
+    #[serde(default)]
+    pub turn: u64,
+    pub event: GameEvent,
+}
+
+/// One whole game, from the shuffle to the win.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub format: u32,
+    pub id: String,
+    /// What the pool was shuffled from.
+    pub seed: u64,
+    /// Milliseconds since the Unix epoch.
+    pub started_ms: u64,
+    /// Everyone who sat down, by player index.
+    pub players: Vec<String>,
+    pub events: Vec<TimedEvent>,
+    /// Everyone's final score, by player index.
+    pub scores: Vec<i64>,
+    /// Whether groups could be laid down a column. Older records left it
+    /// out, from before they could.
+    #[serde(default)]
+    pub vertical_groups: bool,
+    /// The room's board. Older records left it out, from before boards came
+    /// in other sizes.
+    #[serde(default)]
+    pub board_size: BoardSize,
+}
+
+/// Why a game couldn't be imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// It isn't a game record at all.
+    Json(String),
+    /// It's from a build that writes a different format.
+    Format(u32),
+    /// Its players or scores don't line up with who sat down.
+    Players,
+    /// The event at this index couldn't have happened in the game so far.
+    Event(usize),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Json(e) => write!(f, "That isn't a saved game: {}", e),
+            ImportError::Format(format) => write!(
+                f,
+                "That game was saved in format {}, this version reads {}",
+                format, FORMAT
+            ),
+            ImportError::Players => write!(f, "That game's players don't match its events"),
+            ImportError::Event(i) => write!(f, "That game goes wrong at event {}", i),
+        }
+    }
+}
+
+impl GameRecord {
+    /// Reads a record exported as JSON, checking that every event in it fits
+    /// the game, so it can be played back without surprises.
+    pub fn import(json: &str) -> Result<GameRecord, ImportError> {
+        let record: GameRecord =
+            serde_json::from_str(json).map_err(|e| ImportError::Json(e.to_string()))?;
+
+        if record.format != FORMAT {
+            return Err(ImportError::Format(record.format));
+        }
+
+        let seated = record
+            .events
+            .iter()
+            .filter(|timed| matches!(timed.event, GameEvent::Seated { .. }))
+            .count();
+        let scored = record.scores.is_empty() || record.scores.len() == seated;
+        if record.players.len() != seated || !scored {
+            return Err(ImportError::Players);
+        }
+
+        let played = record.replay().count();
+        if played < record.events.len() {
+            return Err(ImportError::Event(played));
+        }
+
+        Ok(record)
+    }
+
+    /// Plays the game back one event at a time.
+    pub fn replay(&self) -> Replay<'_> {
+        let mut state = GameState::new_with_seed(self.seed);
+        state.vertical_groups = self.vertical_groups;
+        state.board_size = self.board_size;
+
+        Replay {
+            state,
+            events: self.events.iter(),
+        }
+    }
+}
+
+/// Goes through a [`GameRecord`], giving each event with the state it left
+/// the game in. Stops early at an event that doesn't fit.
+pub struct Replay<'a> {
+    state: GameState,
+    events: std::slice::Iter<'a, TimedEvent>,
+}
+
+impl<'a> Replay<'a> {
+    /// The game as of the last event given.
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+}
+
+impl<'a> Iterator for Replay<'a> {
+    type Item = (&'a GameEvent, GameState);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = &self.events.next()?.event;
+        if !apply(&mut self.state, event) {
+            self.events = [].iter();
+            return None;
+        }
+
+        Some((event, self.state.clone()))
+    }
+}
+
+/// Does to `state` what `event` did to the game it was recorded from.
+/// Returns false, leaving `state` alone, if it couldn't have happened.
+pub fn apply(state: &mut GameState, event: &GameEvent) -> bool {
+    let seated = |player: &usize| *player < state.hands.len();
+
+    match event {
+        GameEvent::Seated { dealt: true, .. } => {
+            state.add_player();
+        }
+        GameEvent::Seated { dealt: false, .. } => state.hands.push(Vec::new()),
+        GameEvent::Played(player, action) => match reduce(state, *player, action) {
+            Ok((played, _)) => *state = played,
+            Err(_) => return false,
+        },
+        GameEvent::RolledBack if seated(&state.active_player) => state.rollback_turn(),
+        GameEvent::Drew(player) if seated(player) => {
+            if state.draw(*player).is_none() {
+                return false;
+            }
+        }
+        GameEvent::Kicked(player) if seated(player) => {
+            let hand = std::mem::take(&mut state.hands[*player]);
+            state.game.return_pieces(hand);
+        }
+        GameEvent::TurnStarted(player) if seated(player) => state.start_turn(*player),
+        GameEvent::RolledBack
+        | GameEvent::Drew(_)
+        | GameEvent::Kicked(_)
+        | GameEvent::TurnStarted(_) => return false,
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Coord;
+
+    fn record(events: Vec<GameEvent>) -> GameRecord {
+        GameRecord {
+            format: FORMAT,
+            id: "test".to_string(),
+            seed: 7,
+            started_ms: 0,
+            players: vec!["alice".to_string(), "bob".to_string()],
+            events: events
+                .into_iter()
+                .enumerate()
+                .map(|(i, event)| TimedEvent {
+                    at_ms: i as u64 * 1000,
+                    turn: 0,
+                    event,
+                })
+                .collect(),
+            scores: Vec::new(),
+            vertical_groups: false,
+            board_size: BoardSize::default(),
+        }
+    }
+
+    fn seat(name: &str) -> GameEvent {
+        GameEvent::Seated {
+            name: name.to_string(),
+            dealt: true,
+        }
+    }
+
+    #[test]
+    fn replays_end_where_the_game_did() {
+        let mut game = GameState::new_with_seed(7);
+        game.add_player();
+        game.add_player();
+
+        let piece = game.hands[0][0];
+        let (played, _) = reduce(&game, 0, &Action::Place(Coord(0, 0), piece)).unwrap();
+        game = played;
+        game.rollback_turn();
+        game.draw(0);
+        game.start_turn(1);
+
+        let record = record(vec![
+            seat("alice"),
+            seat("bob"),
+            GameEvent::Played(0, Action::Place(Coord(0, 0), piece)),
+            GameEvent::RolledBack,
+            GameEvent::Drew(0),
+            GameEvent::TurnStarted(1),
+        ]);
+
+        let mut replay = record.replay();
+        let states: Vec<GameState> = replay.by_ref().map(|(_, state)| state).collect();
+        assert_eq!(states.len(), 6);
+        assert_eq!(states[2].game.board().get(&Coord(0, 0)), Some(&piece));
+        assert_eq!(replay.state(), &game);
+    }
+
+    #[test]
+    fn imports_check_the_game() {
+        let good = record(vec![seat("alice"), seat("bob"), GameEvent::TurnStarted(1)]);
+        let json = serde_json::to_string(&good).unwrap();
+        assert_eq!(GameRecord::import(&json), Ok(good.clone()));
+
+        let bad = record(vec![seat("alice"), seat("bob"), GameEvent::Drew(3)]);
+        let json = serde_json::to_string(&bad).unwrap();
+        assert_eq!(GameRecord::import(&json), Err(ImportError::Event(2)));
+
+        let missing = record(vec![seat("alice")]);
+        let json = serde_json::to_string(&missing).unwrap();
+        assert_eq!(GameRecord::import(&json), Err(ImportError::Players));
+
+        let newer = GameRecord {
+            format: FORMAT + 1,
+            ..good
+        };
+        let json = serde_json::to_string(&newer).unwrap();
+        assert_eq!(
+            GameRecord::import(&json),
+            Err(ImportError::Format(FORMAT + 1))
+        );
+        assert!(matches!(
+            GameRecord::import("{}"),
+            Err(ImportError::Json(_))
+        ));
+    }
+}
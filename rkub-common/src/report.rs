@@ -0,0 +1,93 @@
+//! What the web client's debug panel copies for a bug report, and
+//! `rkub-server verify` reads back to find where the client and server fell
+//! out of step. It's JSON:
+//!
+//! ```json
+//! {
+//!   "format": 5,
+//!   "taken_ms": 1760612460000,
+//!   "info": ["state: playing", "room: k3x9qa"],
+//!   "trace": [
+//!     { "at_ms": 1760612455000, "sent": { "seq": 4, "msg": { "Place": ["0,0", { "color": "Red", "num": 5 }] } } },
+//!     { "at_ms": 1760612455040, "received": { "seq": 11, "msg": { "StateCheck": 8211402153640153797 } } }
+//!   ]
+//! }
+//! ```
+//!
+//! - `format` is [`REPORT_FORMAT`], and goes up whenever the rest changes.
+//! - `taken_ms` and every `at_ms` are milliseconds since the Unix epoch.
+//! - `info` is whatever the client had to say about where it was, a line at
+//!   a time, for people to read.
+//! - `trace` is the last messages over the websocket, oldest first, as they
+//!   went over it. Heartbeats are left out.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientMessage, Sequenced, ServerMessage};
+
+/// The version of the [`BugReport`] format this build reads and writes.
+pub const REPORT_FORMAT: u32 = 5;
+
+/// One message over the websocket, whichever way it went.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Traced {
+    Sent(Sequenced<ClientMessage>),
+    Received(Sequenced<ServerMessage>),
+}
+
+/// A [`Traced`] message and when it went.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimedMessage {
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub message: Traced,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BugReport {
+    pub format: u32,
+    pub taken_ms: u64,
+    pub info: Vec<String>,
+    pub trace: Vec<TimedMessage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Color, Coord, Piece};
+
+    #[test]
+    fn reports_read_as_documented() {
+        let json = r#"{
+            "format": 5,
+            "taken_ms": 1760612460000,
+            "info": ["state: playing", "room: k3x9qa"],
+            "trace": [
+                { "at_ms": 1760612455000, "sent": { "seq": 4, "msg": { "Place": ["0,0", { "color": "Red", "num": 5 }] } } },
+                { "at_ms": 1760612455040, "received": { "seq": 11, "msg": { "StateCheck": 8211402153640153797 } } }
+            ]
+        }"#;
+
+        let report: BugReport = serde_json::from_str(json).unwrap();
+        assert_eq!(report.format, REPORT_FORMAT);
+        assert_eq!(
+            report.trace[0].message,
+            Traced::Sent(Sequenced {
+                seq: 4,
+                msg: ClientMessage::Place(Coord(0, 0), Piece::new(Color::Red, 5)),
+            })
+        );
+        assert_eq!(
+            report.trace[1].message,
+            Traced::Received(Sequenced {
+                seq: 11,
+                msg: ServerMessage::StateCheck(8211402153640153797),
+            })
+        );
+
+        let again = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<BugReport>(&again).unwrap(), report);
+    }
+}
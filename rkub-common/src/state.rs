@@ -0,0 +1,699 @@
+//! The rules of a game as one pure function, so the server can enforce them
+//! and the client can predict them without either guessing at the other.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{BoardSize, Coord, Game, Move, Piece, PrivateView};
+
+/// How many pieces each player starts with.
+pub const HAND_SIZE: usize = 14;
+
+/// Everything the rules need to know about a game in progress, and nothing
+/// about who is connected.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GameState {
+    /// The board and the pool left to draw from.
+    pub game: Game,
+    /// Every player's hand, by player index.
+    pub hands: Vec<Vec<Piece>>,
+    pub active_player: usize,
+    /// The board as the active player's turn started, so their turn can be
+    /// measured and rolled back.
+    pub turn_start_board: BTreeMap<Coord, Piece>,
+    /// Whoever emptied their hand. Nothing can be played once it's set.
+    pub winner: Option<usize>,
+    /// Groups can be laid down a column too, a house rule. See
+    /// [`Game::is_valid_board_with`].
+    pub vertical_groups: bool,
+    /// Where pieces can go. Older states left it out, from before boards
+    /// came in other sizes.
+    #[serde(default)]
+    pub board_size: BoardSize,
+}
+
+/// Something a player does on their turn.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    Place(Coord, Piece),
+    Pickup(Coord, Piece),
+    Moves(Vec<Move>),
+    EndTurn,
+}
+
+/// What came of an action, in the order it happened.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Place(Coord, Piece),
+    Pickup(Coord, Piece),
+    Moves(Vec<Move>),
+    /// The active player drew this piece at the end of their turn.
+    Drew(Piece),
+    /// The active player's turn is over. Who goes next is up to the room.
+    TurnEnded {
+        drew: bool,
+    },
+    /// The active player emptied their hand.
+    Won(usize),
+}
+
+/// Why an action was turned down. The state is left as it was.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Rejection {
+    NotYourTurn,
+    GameOver,
+    /// A move didn't fit the board or the player's hand.
+    InvalidMove(Move),
+    /// A piece was placed outside [`GameState::board_size`]. Checked before
+    /// anything else, so no coordinate is too big to turn down quickly.
+    OffBoard(Move),
+    /// The turn can't end with the board like this.
+    InvalidBoard,
+    /// The board was rearranged, but nothing from the player's hand went
+    /// down. Turns like that can't end, the board has to go back the way it
+    /// was so the player draws instead.
+    OnlyRearranged,
+    /// This piece was on the board when the turn started and isn't any
+    /// more. Pieces on the board stay there, however they're rearranged.
+    KeptBoardPiece(Piece),
+}
+
+impl GameState {
+    /// A game with a full, shuffled pool and nobody in it yet.
+    pub fn new() -> Self {
+        GameState::new_with_seed(rand::random())
+    }
+
+    /// Like [`GameState::new`], shuffled the same way every time for the
+    /// same `seed`.
+    pub fn new_with_seed(seed: u64) -> Self {
+        GameState {
+            game: Game::new_with_seed(seed),
+            ..GameState::default()
+        }
+    }
+
+    /// Starts the game over with the same seats. Every piece, on the board
+    /// or in a hand, goes back in the pool, which is shuffled from `seed` the
+    /// way a new game's would be, so the round can be replayed from it. Each
+    /// seat `dealt` picks out is dealt a fresh hand, the rest get nothing.
+    pub fn reset_for_new_round(&mut self, seed: u64, dealt: &[bool]) {
+        *self = GameState {
+            game: Game::new_with_seed(seed),
+            vertical_groups: self.vertical_groups,
+            board_size: self.board_size,
+            ..GameState::default()
+        };
+
+        for &dealt in dealt {
+            let hand = if dealt {
+                self.game.deal(HAND_SIZE)
+            } else {
+                Vec::new()
+            };
+            self.hands.push(hand);
+        }
+    }
+
+    /// Who goes first out of `players`, by the traditional rule: each draws a
+    /// piece and the highest number goes first, with anyone tied drawing
+    /// again. The draws are only looked at, from the bottom of the pool, so
+    /// the pool deals just as it would have. If the pool runs out first,
+    /// whoever still tied comes first in `players` goes first.
+    pub fn highest_draw(&self, players: &[usize]) -> Option<usize> {
+        let mut drawing = players.to_vec();
+        let mut pool = self.game.remaining_pieces().iter();
+
+        while drawing.len() > 1 {
+            let drawn: Vec<(usize, u8)> = drawing
+                .iter()
+                .zip(pool.by_ref())
+                .map(|(&player, piece)| (player, piece.num))
+                .collect();
+            if drawn.len() < drawing.len() {
+                break;
+            }
+
+            let highest = drawn.iter().map(|&(_, num)| num).max()?;
+            drawing = drawn
+                .into_iter()
+                .filter(|&(_, num)| num == highest)
+                .map(|(player, _)| player)
+                .collect();
+        }
+
+        drawing.first().copied()
+    }
+
+    /// Seats a new player and deals them a hand, returning their index.
+    pub fn add_player(&mut self) -> usize {
+        let hand = self.game.deal(HAND_SIZE);
+        self.hands.push(hand);
+
+        self.hands.len() - 1
+    }
+
+    /// What `player` alone can see: their hand.
+    pub fn private_view(&self, player: usize) -> PrivateView {
+        PrivateView {
+            seat: player,
+            hand: self.hands[player].clone(),
+        }
+    }
+
+    /// How many pieces the active player has put down this turn, less what
+    /// they took back.
+    pub fn turn_delta(&self) -> isize {
+        self.game.board().len() as isize - self.turn_start_board.len() as isize
+    }
+
+    /// The pieces that were on the board when the turn started and aren't
+    /// now, so the active player has them in their hand.
+    pub fn taken_from_board(&self) -> Vec<Piece> {
+        let mut board: Vec<Piece> = self.game.board().values().copied().collect();
+        self.turn_start_board
+            .values()
+            .filter(|piece| match board.iter().position(|p| p == *piece) {
+                Some(i) => {
+                    board.swap_remove(i);
+                    false
+                }
+                None => true,
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Whether the active player has moved pieces around the board this
+    /// turn without putting down any from their hand. Only says so once
+    /// nothing's been [taken from it](GameState::taken_from_board).
+    pub fn only_rearranged(&self) -> bool {
+        self.turn_delta() <= 0 && *self.game.board() != self.turn_start_board
+    }
+
+    /// Deals `player` a piece, if there are any left.
+    pub fn draw(&mut self, player: usize) -> Option<Piece> {
+        let piece = self.game.deal_piece()?;
+        self.hands[player].push(piece);
+
+        Some(piece)
+    }
+
+    /// Hands the turn to `player`, starting from the board as it is.
+    pub fn start_turn(&mut self, player: usize) {
+        self.active_player = player;
+        self.turn_start_board = self.game.board().clone();
+    }
+
+    /// Puts the board back the way it was when the active player's turn
+    /// started, returning the pieces they played to their hand.
+    pub fn rollback_turn(&mut self) {
+        let mut placed: Vec<Piece> = self.game.board().values().copied().collect();
+        let mut taken = Vec::new();
+
+        for piece in self.turn_start_board.values() {
+            match placed.iter().position(|p| p == piece) {
+                Some(i) => {
+                    placed.swap_remove(i);
+                }
+                None => taken.push(*piece),
+            }
+        }
+
+        let hand = &mut self.hands[self.active_player];
+        hand.extend(placed);
+        for piece in taken {
+            if let Some(i) = hand.iter().position(|p| *p == piece) {
+                hand.swap_remove(i);
+            }
+        }
+
+        self.game.set_board(self.turn_start_board.clone());
+    }
+
+    /// Every piece there isn't exactly as many of in the pool, hands and
+    /// board together as a full set has, with how many too many there are,
+    /// or too few below zero. Empty while every piece is accounted for.
+    pub fn miscounted(&self) -> Vec<(Piece, isize)> {
+        let mut counts: BTreeMap<Piece, isize> = BTreeMap::new();
+        for piece in Game::create_pieces() {
+            *counts.entry(piece).or_default() -= 1;
+        }
+
+        let pieces = self
+            .game
+            .remaining_pieces()
+            .iter()
+            .chain(self.hands.iter().flatten())
+            .chain(self.game.board().values());
+        for piece in pieces {
+            *counts.entry(*piece).or_default() += 1;
+        }
+
+        counts
+            .into_iter()
+            .filter(|&(_, count)| count != 0)
+            .collect()
+    }
+}
+
+/// Applies `player`'s `action` to `state`, returning the new state and what
+/// happened, or why it isn't allowed.
+pub fn reduce(
+    state: &GameState,
+    player: usize,
+    action: &Action,
+) -> Result<(GameState, Vec<Event>), Rejection> {
+    if state.winner.is_some() {
+        return Err(Rejection::GameOver);
+    }
+
+    if player != state.active_player {
+        return Err(Rejection::NotYourTurn);
+    }
+
+    let mut state = state.clone();
+    let mut events = Vec::new();
+
+    match action {
+        Action::Place(coord, piece) => {
+            apply(&mut state, player, &[Move::Place(*coord, *piece)])?;
+            events.push(Event::Place(*coord, *piece));
+        }
+        Action::Pickup(coord, piece) => {
+            apply(&mut state, player, &[Move::Pickup(*coord, *piece)])?;
+            events.push(Event::Pickup(*coord, *piece));
+        }
+        Action::Moves(moves) => {
+            apply(&mut state, player, moves)?;
+            events.push(Event::Moves(moves.clone()));
+        }
+        Action::EndTurn => {
+            if !state.game.is_valid_board_with(state.vertical_groups).0 {
+                return Err(Rejection::InvalidBoard);
+            }
+            // Every piece on the board when the turn started is still there,
+            // so whatever else is came from the hand:
+            if let Some(&piece) = state.taken_from_board().first() {
+                return Err(Rejection::KeptBoardPiece(piece));
+            }
+            if state.only_rearranged() {
+                return Err(Rejection::OnlyRearranged);
+            }
+
+            // Playing nothing means drawing, while there's anything to draw:
+            let drew = if state.turn_delta() == 0 {
+                match state.draw(player) {
+                    Some(piece) => {
+                        events.push(Event::Drew(piece));
+                        true
+                    }
+                    None => false,
+                }
+            } else {
+                false
+            };
+
+            if !drew && state.hands[player].is_empty() {
+                state.winner = Some(player);
+                events.push(Event::Won(player));
+            } else {
+                state.turn_start_board = state.game.board().clone();
+                events.push(Event::TurnEnded { drew });
+            }
+        }
+    }
+
+    Ok((state, events))
+}
+
+fn apply(state: &mut GameState, player: usize, moves: &[Move]) -> Result<(), Rejection> {
+    let off_board = moves
+        .iter()
+        .find(|m| matches!(m, Move::Place(coord, _) if !state.board_size.contains(*coord)));
+    if let Some(&m) = off_board {
+        return Err(Rejection::OffBoard(m));
+    }
+
+    state
+        .game
+        .apply_moves(&mut state.hands[player], moves)
+        .map(|_| ())
+        .map_err(Rejection::InvalidMove)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Color, BOARD_ROWS};
+
+    fn piece(num: u8) -> Piece {
+        Piece::new(Color::Red, num)
+    }
+
+    /// Two players, the first holding red 1 to 4, and a pool of one blue 9.
+    fn state() -> GameState {
+        let mut state = GameState {
+            hands: vec![(1..=4).map(piece).collect(), vec![piece(10)]],
+            ..Default::default()
+        };
+        state.game.return_pieces(vec![Piece::new(Color::Blue, 9)]);
+
+        state
+    }
+
+    fn play(state: &GameState, player: usize, actions: &[Action]) -> (GameState, Vec<Event>) {
+        let mut state = state.clone();
+        let mut events = Vec::new();
+
+        for action in actions {
+            let (next, happened) = reduce(&state, player, action).unwrap();
+            state = next;
+            events.extend(happened);
+        }
+
+        (state, events)
+    }
+
+    #[test]
+    fn new_rounds_take_every_piece_back_and_deal_again() {
+        let mut state = GameState::new_with_seed(7);
+        state.vertical_groups = true;
+        state.board_size = BoardSize { cols: 30, rows: 20 };
+        state.add_player();
+        state.add_player();
+        let piece = state.hands[0].remove(0);
+        state.game.board_mut().insert(Coord(0, 0), piece);
+        state.winner = Some(1);
+
+        state.reset_for_new_round(8, &[true, false]);
+        assert!(state.game.board().is_empty());
+        assert_eq!(state.hands[0].len(), HAND_SIZE);
+        assert!(state.hands[1].is_empty());
+        assert_eq!(
+            state.game.remaining_pieces().len() + HAND_SIZE,
+            Game::create_pieces().len()
+        );
+        assert_eq!((state.winner, state.active_player), (None, 0));
+        assert!(state.vertical_groups);
+        assert_eq!(state.board_size, BoardSize { cols: 30, rows: 20 });
+
+        // Dealt the same as a new game from the same seed:
+        let mut fresh = GameState::new_with_seed(8);
+        fresh.add_player();
+        assert_eq!(state.hands[0], fresh.hands[0]);
+    }
+
+    #[test]
+    fn every_piece_is_accounted_for() {
+        let mut state = GameState::new_with_seed(7);
+        state.add_player();
+        state.add_player();
+        state.draw(0);
+        assert_eq!(state.miscounted(), Vec::new());
+
+        // A piece played without leaving the hand, and one lost altogether:
+        let played = state.hands[0][0];
+        state.game.board_mut().insert(Coord(0, 0), played);
+        let lost = state.hands[1].pop().unwrap();
+        let mut expected = vec![(lost, -1), (played, 1)];
+        expected.sort();
+        assert_eq!(state.miscounted(), expected);
+    }
+
+    #[test]
+    fn the_highest_draw_goes_first() {
+        let mut state = GameState::default();
+        state.game.remaining_pieces = [9, 12, 12, 3, 7].iter().map(|&num| piece(num)).collect();
+
+        // Seats 1 and 2 tie on 12s, and draw again:
+        assert_eq!(state.highest_draw(&[0, 1, 2]), Some(2));
+        assert_eq!(state.highest_draw(&[2, 0]), Some(0));
+        assert_eq!(state.highest_draw(&[]), None);
+        // Whatever was drawn is still there to deal:
+        assert_eq!(state.game.remaining_pieces().len(), 5);
+
+        state.game.remaining_pieces.truncate(2);
+        state.game.remaining_pieces[1] = piece(9);
+        assert_eq!(state.highest_draw(&[1, 0]), Some(1));
+    }
+
+    #[test]
+    fn only_the_active_player_can_play() {
+        let state = state();
+
+        assert_eq!(
+            reduce(&state, 1, &Action::Place(Coord(0, 0), piece(10))),
+            Err(Rejection::NotYourTurn)
+        );
+        assert_eq!(
+            reduce(&state, 1, &Action::EndTurn),
+            Err(Rejection::NotYourTurn)
+        );
+    }
+
+    #[test]
+    fn moves_must_fit_the_board_and_hand() {
+        let (state, _) = play(&state(), 0, &[Action::Place(Coord(0, 0), piece(1))]);
+
+        // Taken:
+        assert_eq!(
+            reduce(&state, 0, &Action::Place(Coord(0, 0), piece(2))),
+            Err(Rejection::InvalidMove(Move::Place(Coord(0, 0), piece(2))))
+        );
+        // Not in hand:
+        assert_eq!(
+            reduce(&state, 0, &Action::Place(Coord(1, 0), piece(10))),
+            Err(Rejection::InvalidMove(Move::Place(Coord(1, 0), piece(10))))
+        );
+        // Not on the board:
+        assert_eq!(
+            reduce(&state, 0, &Action::Pickup(Coord(1, 0), piece(1))),
+            Err(Rejection::InvalidMove(Move::Pickup(Coord(1, 0), piece(1))))
+        );
+        // Off the edge:
+        assert_eq!(
+            reduce(&state, 0, &Action::Place(Coord(0, BOARD_ROWS), piece(2))),
+            Err(Rejection::OffBoard(Move::Place(
+                Coord(0, BOARD_ROWS),
+                piece(2)
+            )))
+        );
+        assert_eq!(
+            reduce(&state, 0, &Action::Place(Coord(-1, 0), piece(2))),
+            Err(Rejection::OffBoard(Move::Place(Coord(-1, 0), piece(2))))
+        );
+    }
+
+    #[test]
+    fn absurd_coordinates_are_turned_down_before_anything_else() {
+        let (state, _) = play(&state(), 0, &[Action::Place(Coord(0, 0), piece(1))]);
+
+        // Even after a valid move, and for a piece they don't have:
+        let far = Move::Place(Coord(i32::MAX, i32::MIN), piece(10));
+        assert_eq!(
+            reduce(
+                &state,
+                0,
+                &Action::Moves(vec![Move::Place(Coord(1, 0), piece(2)), far])
+            ),
+            Err(Rejection::OffBoard(far))
+        );
+
+        // Nothing's left behind that would make validating the board slow:
+        let mut state = state;
+        let mut grid = state.game.board().clone();
+        grid.insert(Coord(i32::MAX, i32::MIN), piece(3));
+        state.game.set_board(grid);
+        assert_eq!(
+            reduce(&state, 0, &Action::EndTurn),
+            Err(Rejection::InvalidBoard)
+        );
+    }
+
+    #[test]
+    fn the_board_is_as_big_as_the_state_says() {
+        let small = GameState {
+            board_size: BoardSize { cols: 4, rows: 2 },
+            ..state()
+        };
+        let at = |coord| Action::Moves(vec![Move::Place(coord, piece(1))]);
+
+        assert!(reduce(&small, 0, &at(Coord(3, 1))).is_ok());
+        assert_eq!(
+            reduce(&small, 0, &at(Coord(4, 0))),
+            Err(Rejection::OffBoard(Move::Place(Coord(4, 0), piece(1))))
+        );
+        assert_eq!(
+            reduce(&small, 0, &at(Coord(0, 2))),
+            Err(Rejection::OffBoard(Move::Place(Coord(0, 2), piece(1))))
+        );
+
+        // And past the usual board's edge, if it's bigger:
+        let big = GameState {
+            board_size: BoardSize { cols: 40, rows: 20 },
+            ..state()
+        };
+        assert!(reduce(&big, 0, &at(Coord(30, BOARD_ROWS))).is_ok());
+    }
+
+    #[test]
+    fn invalid_boards_cannot_end_a_turn() {
+        let (state, _) = play(&state(), 0, &[Action::Place(Coord(0, 0), piece(1))]);
+
+        assert_eq!(
+            reduce(&state, 0, &Action::EndTurn),
+            Err(Rejection::InvalidBoard)
+        );
+    }
+
+    #[test]
+    fn vertical_groups_only_end_a_turn_when_allowed() {
+        let mut state = state();
+        state.hands[0].extend(&[Piece::new(Color::Blue, 1), Piece::new(Color::Black, 1)]);
+        let ones = Action::Moves(vec![
+            Move::Place(Coord(0, 0), piece(1)),
+            Move::Place(Coord(0, 1), Piece::new(Color::Blue, 1)),
+            Move::Place(Coord(0, 2), Piece::new(Color::Black, 1)),
+        ]);
+
+        let (played, _) = play(&state, 0, std::slice::from_ref(&ones));
+        assert_eq!(
+            reduce(&played, 0, &Action::EndTurn),
+            Err(Rejection::InvalidBoard)
+        );
+
+        state.vertical_groups = true;
+        let (played, _) = play(&state, 0, &[ones]);
+        assert!(reduce(&played, 0, &Action::EndTurn).is_ok());
+    }
+
+    #[test]
+    fn ending_a_turn_without_playing_draws() {
+        let (state, events) = play(&state(), 0, &[Action::EndTurn]);
+
+        let blue = Piece::new(Color::Blue, 9);
+        assert_eq!(
+            events,
+            vec![Event::Drew(blue), Event::TurnEnded { drew: true }]
+        );
+        assert!(state.hands[0].contains(&blue));
+        assert!(state.game.remaining_pieces().is_empty());
+    }
+
+    #[test]
+    fn turns_that_only_rearrange_the_board_cant_end() {
+        let mut state = state();
+        let run = [piece(1), piece(2), piece(3)];
+        for (x, piece) in run.iter().enumerate() {
+            state.game.board_mut().insert(Coord(x as i32, 0), *piece);
+        }
+        state.start_turn(0);
+
+        let moves = Action::Moves(vec![
+            Move::Pickup(Coord(0, 0), piece(1)),
+            Move::Pickup(Coord(1, 0), piece(2)),
+            Move::Pickup(Coord(2, 0), piece(3)),
+            Move::Place(Coord(0, 1), piece(1)),
+            Move::Place(Coord(1, 1), piece(2)),
+            Move::Place(Coord(2, 1), piece(3)),
+        ]);
+        let (moved, _) = play(&state, 0, &[moves]);
+        assert!(moved.only_rearranged());
+        assert_eq!(
+            reduce(&moved, 0, &Action::EndTurn),
+            Err(Rejection::OnlyRearranged)
+        );
+
+        // Adding to the run from the hand is a turn like any other:
+        let four = Action::Place(Coord(3, 1), piece(4));
+        let (played, events) = play(&moved, 0, &[four, Action::EndTurn]);
+        assert!(!played.only_rearranged());
+        assert!(events.contains(&Event::TurnEnded { drew: false }));
+    }
+
+    #[test]
+    fn pieces_taken_from_the_board_have_to_go_back() {
+        let mut state = state();
+        for (x, num) in (5..=8).enumerate() {
+            state
+                .game
+                .board_mut()
+                .insert(Coord(x as i32 + 2, 0), piece(num));
+        }
+        state.start_turn(0);
+
+        // Two from the hand go down, so the board gained one, but the eight
+        // went into the hand:
+        let moves = Action::Moves(vec![
+            Move::Pickup(Coord(5, 0), piece(8)),
+            Move::Place(Coord(1, 0), piece(4)),
+            Move::Place(Coord(0, 0), piece(3)),
+        ]);
+        let (kept, _) = play(&state, 0, &[moves]);
+        assert_eq!(kept.turn_delta(), 1);
+        assert!(kept.game.is_valid_board_with(false).0);
+        assert_eq!(kept.taken_from_board(), vec![piece(8)]);
+        assert_eq!(
+            reduce(&kept, 0, &Action::EndTurn),
+            Err(Rejection::KeptBoardPiece(piece(8)))
+        );
+
+        // Putting it back anywhere will do:
+        let back = Action::Place(Coord(5, 0), piece(8));
+        let (played, events) = play(&kept, 0, &[back, Action::EndTurn]);
+        assert!(played.taken_from_board().is_empty());
+        assert!(events.contains(&Event::TurnEnded { drew: false }));
+    }
+
+    #[test]
+    fn picking_up_and_putting_back_still_draws() {
+        let moves = vec![
+            Move::Place(Coord(0, 0), piece(1)),
+            Move::Pickup(Coord(0, 0), piece(1)),
+        ];
+        let (_, events) = play(&state(), 0, &[Action::Moves(moves), Action::EndTurn]);
+
+        assert!(events.contains(&Event::TurnEnded { drew: true }));
+    }
+
+    #[test]
+    fn emptying_a_hand_wins() {
+        let places = (1..=4)
+            .map(|num| Action::Place(Coord(num as i32, 0), piece(num)))
+            .collect::<Vec<_>>();
+
+        let (state, events) = play(&state(), 0, &places);
+        let (state, ended) = reduce(&state, 0, &Action::EndTurn).unwrap();
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(ended, vec![Event::Won(0)]);
+        assert_eq!(state.winner, Some(0));
+        assert_eq!(
+            reduce(&state, 0, &Action::EndTurn),
+            Err(Rejection::GameOver)
+        );
+    }
+
+    #[test]
+    fn rollback_returns_played_pieces() {
+        let start = state();
+        let (mut state, _) = play(
+            &start,
+            0,
+            &[
+                Action::Place(Coord(0, 0), piece(1)),
+                Action::Place(Coord(1, 0), piece(2)),
+            ],
+        );
+
+        state.rollback_turn();
+
+        assert_eq!(state.game.board(), start.game.board());
+        let mut hand = state.hands[0].clone();
+        hand.sort();
+        assert_eq!(hand, start.hands[0]);
+        assert_eq!(state.turn_delta(), 0);
+    }
+}
@@ -0,0 +1,110 @@
+//! What the server tells webhooks about its rooms and games, see
+//! `rkub-server`'s `webhooks`. It's sent as JSON, tagged with what happened:
+//!
+//! ```json
+//! {
+//!   "event": "game_finished",
+//!   "room": "k3x9qa",
+//!   "winner": "alice",
+//!   "standings": [{ "name": "alice", "score": 57 }, { "name": "bob", "score": -57 }]
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Something a hook is told about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    RoomCreated {
+        room: String,
+        ranked: bool,
+        /// Only joinable with a password, so not for inviting just anyone.
+        private: bool,
+    },
+    /// A game's first turn started, with whoever's sat down so far, or a
+    /// rematch did.
+    GameStarted { room: String, players: Vec<String> },
+    GameFinished {
+        room: String,
+        winner: String,
+        /// Best score first.
+        standings: Vec<Standing>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Standing {
+    pub name: String,
+    pub score: i64,
+}
+
+impl Notification {
+    /// A game in `room` was won, with everyone's `scores` by player index.
+    pub fn game_finished(room: &str, players: &[String], winner: usize, scores: &[i64]) -> Self {
+        let mut standings: Vec<Standing> = players
+            .iter()
+            .zip(scores)
+            .map(|(name, &score)| Standing {
+                name: name.clone(),
+                score,
+            })
+            .collect();
+        standings.sort_by_key(|standing| -standing.score);
+
+        Notification::GameFinished {
+            room: room.to_string(),
+            winner: players[winner].clone(),
+            standings,
+        }
+    }
+
+    /// What happened, in a sentence.
+    pub fn summary(&self) -> String {
+        match self {
+            Notification::RoomCreated {
+                room, ranked: true, ..
+            } => {
+                format!("Ranked room {} was created", room)
+            }
+            Notification::RoomCreated { room, .. } => format!("Room {} was created", room),
+            Notification::GameStarted { room, players } => {
+                format!("A game started in {}: {}", room, players.join(", "))
+            }
+            Notification::GameFinished {
+                room,
+                winner,
+                standings,
+            } => {
+                let standings: Vec<String> = standings
+                    .iter()
+                    .map(|standing| format!("{} {}", standing.name, standing.score))
+                    .collect();
+                format!("{} won in {}: {}", winner, room, standings.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standings_go_best_first() {
+        let players = vec!["alice".to_string(), "bob".to_string(), "carol".to_string()];
+        let finished = Notification::game_finished("k3x9qa", &players, 2, &[-12, -3, 15]);
+
+        assert_eq!(
+            finished.summary(),
+            "carol won in k3x9qa: carol 15, bob -3, alice -12"
+        );
+
+        let json = serde_json::to_string(&finished).unwrap();
+        assert!(json.starts_with(r#"{"event":"game_finished","room":"k3x9qa""#));
+        assert_eq!(
+            serde_json::from_str::<Notification>(&json).unwrap(),
+            finished
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Opening rooms for the channel, with the server's admin commands.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use futures::{SinkExt, StreamExt};
+use tokio::time::timeout;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use rkub_common::{AdminCommand, ClientMessage, RoomOptions, Sequenced, ServerMessage};
+
+/// How long the server has to open a room before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keeps a room open for the channel on the server at `server`, opening
+/// another whenever a game starts in the last one, so there's always one
+/// to join.
+pub struct Rooms {
+    server: String,
+    secret: String,
+    /// The room waiting for players, if one's been opened.
+    open: Mutex<Option<String>>,
+}
+
+impl Rooms {
+    pub fn new(server: &str, secret: &str) -> Self {
+        Rooms {
+            server: server.to_string(),
+            secret: secret.to_string(),
+            open: Mutex::new(None),
+        }
+    }
+
+    /// Opens a new room. Its link is posted once the server's webhook says
+    /// it's been created, like any other room's.
+    pub async fn open(&self) {
+        match timeout(TIMEOUT, create_room(&self.server, &self.secret)).await {
+            Ok(Ok(room)) => {
+                println!("Opened room {}", room);
+                *self.open.lock().unwrap_or_else(PoisonError::into_inner) = Some(room);
+            }
+            Ok(Err(e)) => eprintln!("couldn't open a room: {}", e),
+            Err(_) => eprintln!("couldn't open a room: the server took too long"),
+        }
+    }
+
+    /// A game started in `room`, so if it's the one we opened, opens the
+    /// next.
+    pub async fn started(&self, room: &str) {
+        let ours = {
+            let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+            if open.as_deref() == Some(room) {
+                *open = None;
+                true
+            } else {
+                false
+            }
+        };
+
+        if ours {
+            self.open().await;
+        }
+    }
+}
+
+/// Asks the server at `server` for an empty room, returning its code.
+async fn create_room(server: &str, secret: &str) -> anyhow::Result<String> {
+    let (mut ws, _) = connect_async(server).await?;
+
+    let command = ClientMessage::Admin(
+        secret.to_string(),
+        AdminCommand::CreateRoom(RoomOptions::default()),
+    );
+    let json = serde_json::to_string(&Sequenced {
+        seq: 1,
+        msg: command,
+    })?;
+    ws.send(Message::text(json)).await?;
+
+    while let Some(msg) = ws.next().await {
+        if let Message::Text(json) = msg? {
+            match serde_json::from_str::<Sequenced<ServerMessage>>(&json)?.msg {
+                ServerMessage::Pong => continue,
+                ServerMessage::AdminRoomCreated(room) => {
+                    let _ = ws.close(None).await;
+                    return Ok(room);
+                }
+                ServerMessage::Error(e) => bail!("{}", e),
+                msg => bail!("unexpected answer {:?}", msg),
+            }
+        }
+    }
+
+    Err(anyhow!("the server hung up"))
+}
@@ -0,0 +1,90 @@
+//! What's posted to the channel, and posting it.
+
+use std::time::Duration;
+
+use rkub_common::Notification;
+
+const API: &str = "https://discord.com/api/v10";
+
+/// How long Discord has to answer before a message is given up on.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What to post for `notification`, if anything, with rooms linked to in
+/// the client at `client`. Private rooms aren't for just anyone, and ranked
+/// ones are already full, so only public rooms are invited to.
+pub fn message(notification: &Notification, client: &str) -> Option<String> {
+    match notification {
+        Notification::RoomCreated {
+            room,
+            ranked: false,
+            private: false,
+        } => Some(format!(
+            "Room {} is open, join at {}?room={}",
+            room,
+            client.trim_end_matches('?'),
+            room
+        )),
+        Notification::GameFinished { .. } => Some(notification.summary()),
+        Notification::RoomCreated { .. } | Notification::GameStarted { .. } => None,
+    }
+}
+
+/// A bot posting into one channel.
+pub struct Discord {
+    url: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl Discord {
+    pub fn new(token: &str, channel: &str) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+
+        Ok(Discord {
+            url: format!("{}/channels/{}/messages", API, channel),
+            token: token.to_string(),
+            client,
+        })
+    }
+
+    pub async fn post(&self, content: &str) -> reqwest::Result<()> {
+        self.client
+            .post(&self.url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .json(&serde_json::json!({ "content": content }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_rooms_and_results_are_posted() {
+        let client = "https://rkub.example/";
+        let created = |ranked, private| Notification::RoomCreated {
+            room: "k3x9qa".to_string(),
+            ranked,
+            private,
+        };
+
+        assert_eq!(
+            message(&created(false, false), client).unwrap(),
+            "Room k3x9qa is open, join at https://rkub.example/?room=k3x9qa"
+        );
+        assert_eq!(message(&created(false, true), client), None);
+        assert_eq!(message(&created(true, false), client), None);
+
+        let players = vec!["alice".to_string(), "bob".to_string()];
+        let finished = Notification::game_finished("k3x9qa", &players, 0, &[57, -57]);
+        assert_eq!(
+            message(&finished, client).unwrap(),
+            "alice won in k3x9qa: alice 57, bob -57"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Just enough HTTP to take the server's webhooks: one POST per connection,
+//! with a JSON body.
+
+use anyhow::{anyhow, bail};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use rkub_common::Notification;
+
+/// Webhooks are a few hundred bytes, anything much bigger isn't one.
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// Reads the notification POSTed on `socket` and answers it, with an error
+/// for the server to log if it wasn't one.
+pub async fn receive<S>(mut socket: S) -> anyhow::Result<Notification>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let received = read_request(&mut socket).await;
+    let status = match &received {
+        Ok(_) => "204 No Content",
+        Err(_) => "400 Bad Request",
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+
+    received
+}
+
+async fn read_request<S>(socket: &mut S) -> anyhow::Result<Notification>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+
+    let head_len = loop {
+        if let Some(end) = find(&request, b"\r\n\r\n") {
+            break end + 4;
+        }
+        if request.len() > MAX_REQUEST {
+            bail!("request too big");
+        }
+
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            bail!("connection closed mid-request");
+        }
+        request.extend_from_slice(&buf[..n]);
+    };
+
+    let head = std::str::from_utf8(&request[..head_len])?;
+    if !head.starts_with("POST ") {
+        bail!("only POSTs are webhooks");
+    }
+
+    let body_len = content_length(head)?;
+    if body_len > MAX_REQUEST {
+        bail!("request too big");
+    }
+    while request.len() < head_len + body_len {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            bail!("connection closed mid-body");
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    Ok(serde_json::from_slice(
+        &request[head_len..head_len + body_len],
+    )?)
+}
+
+fn content_length(head: &str) -> anyhow::Result<usize> {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .ok_or_else(|| anyhow!("no content-length"))?
+        .1
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("bad content-length"))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::duplex;
+
+    async fn post(request: &str) -> (anyhow::Result<Notification>, String) {
+        let (mut server, socket) = duplex(MAX_REQUEST);
+        server.write_all(request.as_bytes()).await.unwrap();
+
+        let received = receive(socket).await;
+        let mut response = String::new();
+        server.read_to_string(&mut response).await.unwrap();
+
+        (received, response)
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_read_and_answered() {
+        let body = r#"{"event":"room_created","room":"k3x9qa","ranked":false,"private":false,"content":"Room k3x9qa was created"}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nhost: 127.0.0.1\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let (received, response) = post(&request).await;
+        assert_eq!(
+            received.unwrap(),
+            Notification::RoomCreated {
+                room: "k3x9qa".to_string(),
+                ranked: false,
+                private: false,
+            }
+        );
+        assert!(response.starts_with("HTTP/1.1 204"));
+
+        let (received, response) = post("GET / HTTP/1.1\r\n\r\n").await;
+        assert!(received.is_err());
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+}
@@ -0,0 +1,144 @@
+//! Brings a server's rooms into a Discord channel: it takes the server's
+//! webhooks (see `RKUB_WEBHOOK`), posts a link to every public room as it's
+//! opened so anyone in the channel can join, and the standings when a game's
+//! won.
+//!
+//! Given the server's admin secret, it also keeps a room of its own open for
+//! the channel, see [`admin::Rooms`].
+
+mod admin;
+mod discord;
+mod hook;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use rkub_common::Notification;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use admin::Rooms;
+use discord::Discord;
+
+const USAGE: &str = "usage: rkub-discord --channel ID [--client URL] [--listen ADDR]
+                    [--server WS_URL]
+
+Posts the server's rooms and results to the Discord channel ID, as the bot
+whose token is in DISCORD_TOKEN. Point the server's RKUB_WEBHOOK at
+http://ADDR/, and rooms are linked to at URL.
+
+With --server and the server's admin secret in RKUB_ADMIN_SECRET, it keeps a
+room open on the server at WS_URL for the channel, opening another whenever a
+game starts in the last one.";
+
+/// How long a webhook has to arrive, so a connection that never sends one
+/// doesn't hang around.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Args {
+    channel: String,
+    client: String,
+    listen: String,
+    server: Option<String>,
+}
+
+impl Args {
+    fn parse() -> anyhow::Result<Self> {
+        let mut channel = None;
+        let mut args = Args {
+            channel: String::new(),
+            client: "http://127.0.0.1:8080/".to_string(),
+            listen: "127.0.0.1:5556".to_string(),
+            server: None,
+        };
+
+        let mut argv = std::env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            let value = match arg.as_str() {
+                "-h" | "--help" => bail!(USAGE),
+                _ => argv.next().ok_or_else(|| anyhow!(USAGE))?,
+            };
+
+            match arg.as_str() {
+                "--channel" => channel = Some(value),
+                "--client" => args.client = value,
+                "--listen" => args.listen = value,
+                "--server" => args.server = Some(value),
+                _ => bail!(USAGE),
+            }
+        }
+
+        args.channel = channel.ok_or_else(|| anyhow!(USAGE))?;
+        Ok(args)
+    }
+}
+
+/// Everything a webhook's connection needs to be handled on its own.
+struct Bridge {
+    discord: Discord,
+    client: String,
+    rooms: Option<Rooms>,
+}
+
+impl Bridge {
+    /// Takes the webhook on `socket` and posts whatever it says.
+    async fn take(&self, socket: TcpStream, addr: SocketAddr) {
+        let notification = match timeout(HOOK_TIMEOUT, hook::receive(socket)).await {
+            Ok(Ok(notification)) => notification,
+            Ok(Err(e)) => {
+                eprintln!("bad webhook from {}: {}", addr, e);
+                return;
+            }
+            Err(_) => {
+                eprintln!("webhook from {} took too long", addr);
+                return;
+            }
+        };
+
+        if let (Some(rooms), Notification::GameStarted { room, .. }) = (&self.rooms, &notification)
+        {
+            rooms.started(room).await;
+        }
+
+        let content = match discord::message(&notification, &self.client) {
+            Some(content) => content,
+            None => return,
+        };
+        if let Err(e) = self.discord.post(&content).await {
+            eprintln!("couldn't post to discord: {}", e);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse()?;
+    let token = std::env::var("DISCORD_TOKEN").map_err(|_| anyhow!(USAGE))?;
+    let rooms = match &args.server {
+        Some(server) => {
+            let secret = std::env::var("RKUB_ADMIN_SECRET").map_err(|_| anyhow!(USAGE))?;
+            Some(Rooms::new(server, &secret))
+        }
+        None => None,
+    };
+    let bridge = Arc::new(Bridge {
+        discord: Discord::new(&token, &args.channel)?,
+        client: args.client,
+        rooms,
+    });
+
+    let listener = TcpListener::bind(&args.listen).await?;
+    println!("Taking webhooks on {}", listener.local_addr()?);
+
+    if let Some(rooms) = &bridge.rooms {
+        rooms.open().await;
+    }
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let bridge = bridge.clone();
+        tokio::spawn(async move { bridge.take(socket, addr).await });
+    }
+}
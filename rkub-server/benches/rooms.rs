@@ -0,0 +1,46 @@
+//! How many rooms can be created and looked up per second as more
+//! connections do it at once. Run with `cargo bench -p rkub-server`.
+
+use std::time::Instant;
+
+use rkub_server::{RoomConfig, Supervisor};
+
+/// Rooms each task creates and then finds again, as a joining player would.
+const ROOMS_PER_TASK: usize = 2_000;
+
+#[tokio::main]
+async fn main() {
+    for &tasks in &[1, 2, 4, 8, 16] {
+        let supervisor = Supervisor::default();
+        let start = Instant::now();
+
+        let handles: Vec<_> = (0..tasks)
+            .map(|_| {
+                let supervisor = supervisor.clone();
+                tokio::spawn(async move {
+                    for _ in 0..ROOMS_PER_TASK {
+                        let (id, _) = supervisor.create_room(RoomConfig::default()).unwrap();
+                        assert!(supervisor.find(&id).is_some());
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        let rooms = supervisor.len();
+        println!(
+            "{:>2} tasks: {} rooms in {:.2?}, {:.0} create+join/s",
+            tasks,
+            rooms,
+            elapsed,
+            rooms as f64 / elapsed.as_secs_f64()
+        );
+
+        supervisor.shutdown().await;
+    }
+}
@@ -0,0 +1,222 @@
+//! Accounts players can log in to, so their stats, their seat and hosting
+//! follow them rather than whatever name they typed. A passphrase logs in
+//! once, and the token it's traded for logs back in after that. Anyone can
+//! still play as a guest, only not under an account's name.
+//!
+//! Accounts are only kept once the server is given somewhere to keep them,
+//! see [`open`]. Tokens are stored as they are, so keep that private.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use rkub_common::ServerError;
+
+use crate::storage::{self, Files, Storage};
+
+static ACCOUNTS: OnceLock<Accounts> = OnceLock::new();
+
+/// Keeps accounts in the file at `path`, starting from what's already
+/// there. Only the first call counts, of this or [`open_in`].
+pub fn open(path: &Path) -> io::Result<()> {
+    let (files, key) = Files::for_file(path)?;
+    open_in(Arc::new(files), &key)
+}
+
+/// Keeps accounts under `key` in `store`, starting from what's already
+/// there.
+pub fn open_in(store: Arc<dyn Storage>, key: &str) -> io::Result<()> {
+    let accounts = Accounts::load(store, key)?;
+    let _ = ACCOUNTS.set(accounts);
+
+    Ok(())
+}
+
+/// The server's accounts, if it keeps them.
+pub fn get() -> Option<&'static Accounts> {
+    ACCOUNTS.get()
+}
+
+/// Whether `name` belongs to an account, and so can't be played under as a
+/// guest.
+pub fn is_taken(name: &str) -> bool {
+    get().is_some_and(|accounts| accounts.exists(name))
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Saved {
+    /// Each account's passphrase hash, in PHC string format, by name.
+    hashes: BTreeMap<String, String>,
+    /// Which account each token logs in to.
+    tokens: BTreeMap<String, String>,
+}
+
+pub struct Accounts {
+    store: Arc<dyn Storage>,
+    key: String,
+    saved: Mutex<Saved>,
+    /// Saves one at a time, so an older save can't land on top of a newer
+    /// one.
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl Accounts {
+    /// Reads the accounts saved under `key` in `store`. Nothing saved there
+    /// yet is no accounts at all.
+    pub fn load(store: Arc<dyn Storage>, key: &str) -> io::Result<Self> {
+        let saved = match store.get(key)? {
+            Some(blob) => storage::decode(&blob)?,
+            None => Saved::default(),
+        };
+
+        Ok(Accounts {
+            store,
+            key: key.to_string(),
+            saved: Mutex::new(saved),
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.saved().hashes.contains_key(name)
+    }
+
+    /// Makes the account `name`, returning a token for it. Hashing is slow
+    /// on purpose, so this is best kept off the async threads.
+    pub fn register(&self, name: &str, passphrase: &str) -> Result<String, ServerError> {
+        if name.is_empty() || self.exists(name) {
+            return Err(ServerError::NameTaken(name.to_string()));
+        }
+
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let salt = SaltString::encode_b64(&salt).map_err(|_| ServerError::BadLogin)?;
+        let hash = Argon2::default()
+            .hash_password(passphrase.as_bytes(), &salt)
+            .map_err(|_| ServerError::BadLogin)?
+            .to_string();
+
+        let mut saved = self.saved();
+        // Someone else may have got there while we were hashing:
+        if saved.hashes.contains_key(name) {
+            return Err(ServerError::NameTaken(name.to_string()));
+        }
+        saved.hashes.insert(name.to_string(), hash);
+
+        Ok(new_token(&mut saved, name))
+    }
+
+    /// Checks `passphrase` against `name`'s, returning a new token for the
+    /// account. Slow, like [`Accounts::register`].
+    pub fn log_in(&self, name: &str, passphrase: &str) -> Result<String, ServerError> {
+        let hash = self
+            .saved()
+            .hashes
+            .get(name)
+            .cloned()
+            .ok_or(ServerError::BadLogin)?;
+        let hash = PasswordHash::new(&hash).map_err(|_| ServerError::BadLogin)?;
+
+        Argon2::default()
+            .verify_password(passphrase.as_bytes(), &hash)
+            .map_err(|_| ServerError::BadLogin)?;
+
+        Ok(new_token(&mut self.saved(), name))
+    }
+
+    /// The account `token` logs in to.
+    pub fn resume(&self, token: &str) -> Result<String, ServerError> {
+        self.saved()
+            .tokens
+            .get(token)
+            .cloned()
+            .ok_or(ServerError::BadLogin)
+    }
+
+    /// Writes everything out, in place of the last save.
+    pub async fn save(&self) -> io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let blob = storage::encode(&*self.saved())?;
+
+        storage::put(&self.store, &self.key, blob).await
+    }
+
+    fn saved(&self) -> MutexGuard<'_, Saved> {
+        self.saved.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A new random token for `name`. Tokens are kept for good, so logging in
+/// somewhere new doesn't log out anywhere else.
+fn new_token(saved: &mut Saved, name: &str) -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    let mut token = String::with_capacity(bytes.len() * 2);
+    for byte in &bytes {
+        let _ = write!(token, "{:02x}", byte);
+    }
+
+    saved.tokens.insert(token.clone(), name.to_string());
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::Memory;
+
+    fn accounts() -> Accounts {
+        Accounts::load(Arc::new(Memory::default()), "accounts").unwrap()
+    }
+
+    #[test]
+    fn passphrases_and_tokens_log_in() {
+        let accounts = accounts();
+        let token = accounts.register("alice", "correct horse").unwrap();
+
+        assert_eq!(accounts.resume(&token), Ok("alice".to_string()));
+        let again = accounts.log_in("alice", "correct horse").unwrap();
+        assert_ne!(again, token);
+        assert_eq!(accounts.resume(&again), Ok("alice".to_string()));
+    }
+
+    #[test]
+    fn wrong_passphrases_and_taken_names_are_refused() {
+        let accounts = accounts();
+        accounts.register("alice", "correct horse").unwrap();
+
+        assert_eq!(
+            accounts.log_in("alice", "battery staple"),
+            Err(ServerError::BadLogin)
+        );
+        assert_eq!(accounts.log_in("bob", ""), Err(ServerError::BadLogin));
+        assert_eq!(
+            accounts.register("alice", "anything"),
+            Err(ServerError::NameTaken("alice".to_string()))
+        );
+        assert_eq!(accounts.resume("not a token"), Err(ServerError::BadLogin));
+    }
+
+    #[tokio::test]
+    async fn accounts_survive_a_restart() {
+        let accounts = accounts();
+        let token = accounts.register("alice", "correct horse").unwrap();
+        accounts.save().await.unwrap();
+
+        let reloaded = Accounts::load(accounts.store.clone(), &accounts.key).unwrap();
+
+        assert!(reloaded.exists("alice"));
+        assert_eq!(reloaded.resume(&token), Ok("alice".to_string()));
+        assert!(reloaded.log_in("alice", "correct horse").is_ok());
+    }
+}
@@ -0,0 +1,98 @@
+//! Commands for the server's operators, to see what rooms are up to and get
+//! stuck games going again without a restart. They come in over the same
+//! websocket as everything else, before joining a room, and are only taken
+//! with the admin secret, see [`set_secret`].
+
+use std::sync::OnceLock;
+
+use rkub_common::{AdminCommand, ServerError, ServerMessage};
+use tracing::{info, warn};
+
+use crate::room::{Room, RoomConfig, RoomEvent};
+use crate::room_id;
+use crate::supervisor::Supervisor;
+
+static SECRET: OnceLock<String> = OnceLock::new();
+
+/// Takes admin commands sent with `secret`. Only the first call counts, and
+/// without one no commands are taken at all.
+pub fn set_secret(secret: String) {
+    let _ = SECRET.set(secret);
+}
+
+/// Whether `secret` is the admin secret, taking as long to say so whatever
+/// it is, as long as it's the right length.
+fn is_secret(secret: &str) -> bool {
+    let expected = match SECRET.get() {
+        Some(expected) if !expected.is_empty() => expected.as_bytes(),
+        _ => return false,
+    };
+
+    expected.len() == secret.len()
+        && expected
+            .iter()
+            .zip(secret.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Does what `command` asks, if `secret` is the admin secret, returning the
+/// answer.
+pub async fn run(
+    supervisor: &Supervisor,
+    secret: &str,
+    command: AdminCommand,
+) -> Result<ServerMessage, ServerError> {
+    if !is_secret(secret) {
+        warn!(?command, "admin command with the wrong secret");
+        return Err(ServerError::NotAdmin);
+    }
+    info!(?command, "admin command");
+
+    match command {
+        AdminCommand::ListRooms => {
+            let mut rooms = supervisor.map_rooms(Room::summary);
+            rooms.sort();
+            Ok(ServerMessage::AdminRooms(rooms))
+        }
+        AdminCommand::InspectRoom(id) => {
+            let id = room_id::normalize(&id);
+            supervisor
+                .with_room(&id, Room::inspect)
+                .ok_or(ServerError::RoomNotFound(id))
+        }
+        AdminCommand::ForceEndTurn(id) => {
+            let id = room_id::normalize(&id);
+            let room = supervisor
+                .find(&id)
+                .ok_or_else(|| ServerError::RoomNotFound(id.clone()))?;
+            room.send(RoomEvent::ForceEndTurn)
+                .await
+                .map_err(|_| ServerError::RoomNotFound(id))?;
+            Ok(ServerMessage::AdminDone)
+        }
+        AdminCommand::CloseRoom(id) => {
+            let id = room_id::normalize(&id);
+            if !supervisor.close_room(&id).await {
+                return Err(ServerError::RoomNotFound(id));
+            }
+            Ok(ServerMessage::AdminDone)
+        }
+        AdminCommand::CreateRoom(options) => {
+            // Operators aren't held to the rate players are, but the server
+            // still only takes as many rooms as it can run:
+            supervisor.admit()?;
+            let (id, _) = supervisor.create_room(RoomConfig {
+                options,
+                ..RoomConfig::default()
+            })?;
+            Ok(ServerMessage::AdminRoomCreated(id))
+        }
+        AdminCommand::Broadcast(text, severity) => {
+            supervisor
+                .tell_all(|| RoomEvent::Announce(text.clone(), severity))
+                .await;
+            Ok(ServerMessage::AdminDone)
+        }
+    }
+}
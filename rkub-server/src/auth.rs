@@ -0,0 +1,225 @@
+//! Who may send what to a room. Every message a player sends passes through
+//! [`authorize`] before the room acts on it, so a new kind of message only
+//! has to say here who it's for.
+
+use std::fmt;
+
+use rkub_common::ClientMessage;
+pub use rkub_common::Phase;
+
+/// What the room knows about whoever sent a message.
+#[derive(Debug, Copy, Clone)]
+pub struct Seat {
+    pub host: bool,
+    /// It's their turn.
+    pub active: bool,
+    /// Removed by the host. They can still be connected, but they're only
+    /// watching.
+    pub kicked: bool,
+}
+
+/// Why a message was turned away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Denied {
+    Kicked,
+    GameOver,
+    /// Rematches are for once the game is over.
+    StillPlaying,
+    /// Nothing can be played before the game's been dealt.
+    NotStarted,
+    NotYourTurn,
+    /// Players can't vote to skip themselves.
+    YourTurn,
+    NotHost,
+    /// Only makes sense before joining a room.
+    NotInRooms,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Denied::Kicked => "they were kicked",
+            Denied::GameOver => "the game is over",
+            Denied::StillPlaying => "the game isn't over",
+            Denied::NotStarted => "the game hasn't started",
+            Denied::NotYourTurn => "it isn't their turn",
+            Denied::YourTurn => "it's their own turn",
+            Denied::NotHost => "only the host can do that",
+            Denied::NotInRooms => "they're already in a room",
+        };
+
+        f.write_str(reason)
+    }
+}
+
+/// Whether `seat` may send `msg` while the room is in `phase`.
+pub fn authorize(phase: Phase, seat: Seat, msg: &ClientMessage) -> Result<(), Denied> {
+    if seat.kicked {
+        return Err(Denied::Kicked);
+    }
+
+    match msg {
+        ClientMessage::Ping
+        | ClientMessage::Close
+        | ClientMessage::Stats(_)
+        | ClientMessage::FetchReplay(_)
+        | ClientMessage::RequestSync => Ok(()),
+        ClientMessage::Rematch if phase != Phase::Over => Err(Denied::StillPlaying),
+        ClientMessage::Rematch => Ok(()),
+        // Nothing else can be played between a win and a rematch:
+        _ if phase == Phase::Over => Err(Denied::GameOver),
+        // Or before anyone's been dealt a hand to play from:
+        ClientMessage::Pickup(..)
+        | ClientMessage::Place(..)
+        | ClientMessage::Moves(_)
+        | ClientMessage::EndTurn
+        | ClientMessage::VoteSkip
+        | ClientMessage::Nudge
+            if phase == Phase::Waiting =>
+        {
+            Err(Denied::NotStarted)
+        }
+        ClientMessage::Pickup(..)
+        | ClientMessage::Place(..)
+        | ClientMessage::Moves(_)
+        | ClientMessage::EndTurn
+            if !seat.active =>
+        {
+            Err(Denied::NotYourTurn)
+        }
+        ClientMessage::Pickup(..)
+        | ClientMessage::Place(..)
+        | ClientMessage::Moves(_)
+        | ClientMessage::EndTurn => Ok(()),
+        ClientMessage::VoteSkip | ClientMessage::Nudge if seat.active => Err(Denied::YourTurn),
+        ClientMessage::VoteSkip | ClientMessage::Nudge => Ok(()),
+        ClientMessage::SetAway(_) => Ok(()),
+        ClientMessage::Kick(_) | ClientMessage::MakeBot(_) if !seat.host => Err(Denied::NotHost),
+        ClientMessage::Kick(_) | ClientMessage::MakeBot(_) => Ok(()),
+        ClientMessage::CreateRoom(..)
+        | ClientMessage::JoinRoom(..)
+        | ClientMessage::Ready(_)
+        | ClientMessage::Register(..)
+        | ClientMessage::LogIn(..)
+        | ClientMessage::Resume(_)
+        | ClientMessage::QueueRanked
+        | ClientMessage::Admin(..) => Err(Denied::NotInRooms),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::{Coord, Piece};
+
+    const PLAYER: Seat = Seat {
+        host: false,
+        active: false,
+        kicked: false,
+    };
+
+    #[test]
+    fn only_the_active_player_plays() {
+        let place = ClientMessage::Place(Coord(0, 0), Piece::joker());
+        let active = Seat {
+            active: true,
+            ..PLAYER
+        };
+
+        assert_eq!(authorize(Phase::Playing, active, &place), Ok(()));
+        assert_eq!(
+            authorize(Phase::Playing, PLAYER, &place),
+            Err(Denied::NotYourTurn)
+        );
+        assert_eq!(
+            authorize(Phase::Playing, active, &ClientMessage::VoteSkip),
+            Err(Denied::YourTurn)
+        );
+        assert_eq!(
+            authorize(Phase::Playing, PLAYER, &ClientMessage::VoteSkip),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(Phase::Playing, active, &ClientMessage::Nudge),
+            Err(Denied::YourTurn)
+        );
+    }
+
+    #[test]
+    fn only_the_host_kicks() {
+        let host = Seat {
+            host: true,
+            ..PLAYER
+        };
+
+        assert_eq!(
+            authorize(Phase::Playing, host, &ClientMessage::Kick(1)),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(Phase::Playing, PLAYER, &ClientMessage::MakeBot(1)),
+            Err(Denied::NotHost)
+        );
+    }
+
+    #[test]
+    fn finished_games_only_take_rematches() {
+        let active = Seat {
+            active: true,
+            ..PLAYER
+        };
+
+        assert_eq!(
+            authorize(Phase::Over, active, &ClientMessage::EndTurn),
+            Err(Denied::GameOver)
+        );
+        assert_eq!(
+            authorize(Phase::Over, PLAYER, &ClientMessage::Rematch),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(Phase::Playing, PLAYER, &ClientMessage::Rematch),
+            Err(Denied::StillPlaying)
+        );
+        assert_eq!(authorize(Phase::Over, PLAYER, &ClientMessage::Ping), Ok(()));
+    }
+
+    #[test]
+    fn nothing_is_played_before_the_deal() {
+        let active = Seat {
+            active: true,
+            ..PLAYER
+        };
+
+        assert_eq!(
+            authorize(Phase::Waiting, active, &ClientMessage::EndTurn),
+            Err(Denied::NotStarted)
+        );
+        assert_eq!(
+            authorize(Phase::Waiting, PLAYER, &ClientMessage::Nudge),
+            Err(Denied::NotStarted)
+        );
+        assert_eq!(
+            authorize(Phase::Waiting, PLAYER, &ClientMessage::Rematch),
+            Err(Denied::StillPlaying)
+        );
+        assert_eq!(
+            authorize(Phase::Waiting, active, &ClientMessage::SetAway(true)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn kicked_players_are_ignored() {
+        let kicked = Seat {
+            kicked: true,
+            ..PLAYER
+        };
+
+        assert_eq!(
+            authorize(Phase::Playing, kicked, &ClientMessage::Ping),
+            Err(Denied::Kicked)
+        );
+    }
+}
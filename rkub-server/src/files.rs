@@ -0,0 +1,203 @@
+//! Serves the built web client from the same port as the websockets, so a
+//! small deployment needs nothing else in front of it. Plain HTTP requests
+//! get a file, anything asking to upgrade goes on to the game.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+/// The most of a request we look at. Browsers send far less.
+const MAX_HEAD: usize = 8 * 1024;
+
+/// How long a connection gets to send its request line and headers.
+const HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Serves files from `dir`, usually the client's `deploy` folder. Only the
+/// first call counts.
+pub fn set_root(dir: PathBuf) {
+    let _ = ROOT.set(dir);
+}
+
+pub fn enabled() -> bool {
+    ROOT.get().is_some()
+}
+
+/// Whether `stream` is asking for a websocket, from a peek at its headers.
+/// Nothing is read off the stream, so the handshake can still happen.
+pub async fn wants_websocket(stream: &TcpStream) -> io::Result<bool> {
+    let mut buf = vec![0; MAX_HEAD];
+
+    let peeked = timeout(HEAD_TIMEOUT, async {
+        loop {
+            let n = stream.peek(&mut buf).await?;
+            if n == 0 || n == buf.len() || buf[..n].windows(4).any(|w| w == b"\r\n\r\n") {
+                return Ok::<_, io::Error>(n);
+            }
+
+            // The rest of the headers are still on their way:
+            sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request headers"))??;
+
+    Ok(is_upgrade(&String::from_utf8_lossy(&buf[..peeked])))
+}
+
+fn is_upgrade(head: &str) -> bool {
+    head.lines().skip(1).any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("upgrade:") && line.contains("websocket")
+    })
+}
+
+/// Answers one plain HTTP request with a file, then hangs up.
+pub async fn serve_file(mut stream: TcpStream) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_HEAD {
+        let n = timeout(HEAD_TIMEOUT, stream.read(&mut buf))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request headers"))??;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request = head.lines().next().unwrap_or("").split_whitespace();
+    let (method, target) = (request.next().unwrap_or(""), request.next().unwrap_or(""));
+
+    let response = match (method, ROOT.get().and_then(|root| resolve(root, target))) {
+        ("GET", Some(path)) | ("HEAD", Some(path)) => match tokio::fs::read(&path).await {
+            Ok(body) => Response::ok(content_type(&path), body),
+            Err(_) => Response::not_found(),
+        },
+        ("GET", None) | ("HEAD", None) => Response::not_found(),
+        _ => Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain",
+            body: b"method not allowed".to_vec(),
+        },
+    };
+
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    if method != "HEAD" {
+        stream.write_all(&response.body).await?;
+    }
+
+    stream.shutdown().await
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Response {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn not_found() -> Self {
+        Response {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: b"not found".to_vec(),
+        }
+    }
+}
+
+/// The file under `root` a request for `target` means, as long as it stays
+/// inside `root`. The query is ignored and `/` is the index.
+fn resolve(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next()?;
+    let path = path.strip_prefix('/')?;
+    let path = if path.is_empty() { "index.html" } else { path };
+
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(root.join(relative))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_stay_inside_the_root() {
+        let root = Path::new("/srv/rkub");
+
+        assert_eq!(resolve(root, "/"), Some(root.join("index.html")));
+        assert_eq!(
+            resolve(root, "/rkub_client_bg.wasm?v=2"),
+            Some(root.join("rkub_client_bg.wasm"))
+        );
+        assert_eq!(resolve(root, "/../etc/passwd"), None);
+        assert_eq!(resolve(root, "/a/../../b"), None);
+        assert_eq!(resolve(root, "//etc/passwd"), None);
+        assert_eq!(resolve(root, "index.html"), None);
+    }
+
+    #[test]
+    fn only_websocket_upgrades_go_to_the_game() {
+        let upgrade =
+            "GET / HTTP/1.1\r\nHost: x\r\nUpgrade: WebSocket\r\nConnection: Upgrade\r\n\r\n";
+        let page = "GET / HTTP/1.1\r\nHost: x\r\nAccept: text/html\r\n\r\n";
+
+        assert!(is_upgrade(upgrade));
+        assert!(!is_upgrade(page));
+        assert!(!is_upgrade("GET /upgrade:websocket HTTP/1.1\r\n\r\n"));
+    }
+
+    #[test]
+    fn wasm_is_served_as_wasm() {
+        assert_eq!(
+            content_type(Path::new("rkub_client_bg.wasm")),
+            "application/wasm"
+        );
+        assert_eq!(
+            content_type(Path::new("index.html")),
+            "text/html; charset=utf-8"
+        );
+    }
+}
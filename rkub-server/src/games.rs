@@ -0,0 +1,95 @@
+//! Every finished game, by id, so it can be played back later. Games are
+//! only kept once the server is given somewhere to keep them, see [`open`].
+//! Each is the same JSON the client exports games as, so kept in files they
+//! can be shared and opened there as they are.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use rkub_common::GameRecord;
+
+use crate::storage::{self, Files, Storage};
+
+static GAMES: OnceLock<Games> = OnceLock::new();
+
+/// Keeps games in files in `dir`, making it if it isn't there. Only the
+/// first call counts, of this or [`open_in`].
+pub fn open(dir: PathBuf) -> io::Result<()> {
+    open_in(Arc::new(Files::new(dir)?));
+
+    Ok(())
+}
+
+/// Keeps games in `store`, under their ids.
+pub fn open_in(store: Arc<dyn Storage>) {
+    let _ = GAMES.set(Games { store });
+}
+
+/// The server's games, if it keeps them.
+pub fn get() -> Option<&'static Games> {
+    GAMES.get()
+}
+
+/// The game `id`, if it's been kept.
+pub async fn lookup(id: &str) -> Option<GameRecord> {
+    get()?.load(id).await.ok()
+}
+
+pub struct Games {
+    store: Arc<dyn Storage>,
+}
+
+impl Games {
+    /// Writes `record` out under its id. Games aren't wrapped like the
+    /// server's other blobs, since they have a `format` of their own.
+    pub async fn save(&self, record: &GameRecord) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(record)?;
+        storage::put(&self.store, &record.id, json).await
+    }
+
+    pub async fn load(&self, id: &str) -> io::Result<GameRecord> {
+        match storage::get(&self.store, id).await? {
+            Some(json) => Ok(serde_json::from_slice(&json)?),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no such game")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::{replay, BoardSize, GameEvent, TimedEvent};
+
+    #[tokio::test]
+    async fn games_are_kept_by_id() {
+        let dir = std::env::temp_dir().join(format!("rkub-games-{}", std::process::id()));
+        let games = Games {
+            store: Arc::new(Files::new(dir.clone()).unwrap()),
+        };
+
+        let record = GameRecord {
+            format: replay::FORMAT,
+            id: "abc123-1f".to_string(),
+            seed: 0x1f,
+            started_ms: 1_760_612_400_000,
+            players: vec!["alice".to_string()],
+            events: vec![TimedEvent {
+                at_ms: 0,
+                turn: 1,
+                event: GameEvent::TurnStarted(0),
+            }],
+            scores: Vec::new(),
+            vertical_groups: false,
+            board_size: BoardSize::default(),
+        };
+        games.save(&record).await.unwrap();
+
+        assert_eq!(games.load("abc123-1f").await.unwrap(), record);
+        assert!(games.load("../abc123-1f").await.is_err());
+        assert!(games.load("missing").await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
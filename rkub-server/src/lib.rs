@@ -0,0 +1,682 @@
+//! The rkub game server: rooms of players connected over websockets.
+
+pub mod accounts;
+pub mod admin;
+mod auth;
+#[cfg(feature = "static-files")]
+pub mod files;
+pub mod games;
+pub mod limits;
+mod load;
+mod matchmaking;
+mod queue;
+mod rating;
+mod room;
+pub mod room_id;
+pub mod shards;
+pub mod stats;
+pub mod storage;
+mod supervisor;
+pub mod verify;
+pub mod webhooks;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rkub_common::{ClientMessage, Sequenced, ServerError, ServerMessage, WS_PATH};
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_hdr_async_with_config, WebSocketStream};
+
+pub use limits::{ConnectionLimits, LoadLimits, RoomLimits};
+use room::RoomEvent;
+pub use room::{set_board_size, set_piece_checks, PieceChecks, RoomConfig, MAX_BOARD_SIDE};
+pub use supervisor::Supervisor;
+
+/// How often someone waiting for a ranked room checks for a match.
+const MATCHMAKE_EVERY: Duration = Duration::from_secs(1);
+
+/// How often players in a room are sent a websocket ping. Browsers answer
+/// them by themselves, so they're heard from even if the page is stuck.
+const PING_EVERY: Duration = Duration::from_secs(15);
+
+/// How long a player in a room can go without sending anything, pongs
+/// included, before they're taken for gone.
+const PEER_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// The longest message a client can send. The biggest a client has reason to
+/// is a turn's worth of `Moves`, which is nowhere near.
+const MAX_MESSAGE_BYTES: usize = 32 * 1024;
+
+/// The longest message tungstenite reads in at all. It's more than
+/// `MAX_MESSAGE_BYTES` so anything in between is read, and the client told
+/// why it's hung up on, while anything bigger is dropped without being
+/// buffered.
+const MAX_FRAME_BYTES: usize = 4 * MAX_MESSAGE_BYTES;
+
+/// Numbers everything sent down one connection, see [`Sequenced`].
+#[derive(Debug, Default)]
+struct Numbering {
+    last: u64,
+}
+
+impl Numbering {
+    /// `json`, an already serialized `ServerMessage`, as the next
+    /// `Sequenced` one. It's spliced in as is so broadcasts still only get
+    /// serialized the once.
+    fn next(&mut self, json: &str) -> Message {
+        self.last += 1;
+        Message::text(format!(r#"{{"seq":{},"msg":{}}}"#, self.last, json))
+    }
+}
+
+/// Sends `message` as the next on the connection.
+async fn send(
+    ws: &mut WebSocketStream<TcpStream>,
+    numbering: &mut Numbering,
+    message: &ServerMessage,
+) -> anyhow::Result<()> {
+    let json = serde_json::to_string(message)?;
+    ws.send(numbering.next(&json)).await?;
+
+    Ok(())
+}
+
+async fn run_player(
+    addr: SocketAddr,
+    name: String,
+    account: bool,
+    password: Option<String>,
+    stream: WebSocketStream<TcpStream>,
+    mut numbering: Numbering,
+    room: mpsc::Sender<RoomEvent>,
+) -> anyhow::Result<()> {
+    Span::current().record("player", name.as_str());
+    info!("running player");
+
+    let (mut outgoing, mut incoming) = stream.split();
+    let (ws_tx, mut ws_rx) = mpsc::channel(queue::PLAYER_QUEUE);
+    let (joined_tx, joined_rx) = oneshot::channel();
+
+    room.send(RoomEvent::Join {
+        addr,
+        name: name.clone(),
+        account,
+        password,
+        sender: ws_tx,
+        joined: joined_tx,
+    })
+    .await?;
+
+    let server_to_client = async {
+        // The first ping waits a whole period rather than going out as they
+        // join:
+        let start = tokio::time::Instant::now() + PING_EVERY;
+        let mut ping = tokio::time::interval_at(start, PING_EVERY);
+        loop {
+            let message = tokio::select! {
+                message = ws_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = ping.tick() => {
+                    outgoing.send(Message::Ping(Default::default())).await?;
+                    continue;
+                }
+            };
+
+            // Take everything that piled up while the last write was going
+            // out, and send it as little as possible:
+            let mut batch = vec![message];
+            while let Ok(message) = ws_rx.try_recv() {
+                batch.push(message);
+            }
+            queue::METRICS.player_depth(batch.len());
+
+            for message in queue::coalesce(batch) {
+                // Broadcasts share one serialization, tungstenite just needs
+                // its own copy of the text:
+                outgoing.send(numbering.next(&message.json)).await?;
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    if !joined_rx.await.unwrap_or(false) {
+        // The room has hung up, but still has to tell them why:
+        let res = server_to_client.await;
+        let _ = outgoing.close().await;
+        return res;
+    }
+
+    // Why we're hanging up on them, if it's for something they sent:
+    let mut unreadable = None;
+    let client_to_server = async {
+        loop {
+            let message = match timeout(PEER_TIMEOUT, incoming.next()).await {
+                Ok(Some(message)) => message?,
+                Ok(None) => break,
+                Err(_) => {
+                    info!("stopped hearing from them");
+                    break;
+                }
+            };
+
+            match message {
+                Message::Text(_) | Message::Binary(_) => match decode(&message) {
+                    Ok(Some(message)) => room.send(RoomEvent::Message(addr, message)).await?,
+                    Ok(None) => {}
+                    Err(frame) => {
+                        unreadable = Some(frame);
+                        break;
+                    }
+                },
+                // Tungstenite queues the close frame's answer, and sends it
+                // when the connection's closed below:
+                Message::Close(frame) => {
+                    info!(?frame, "they closed the connection");
+                    break;
+                }
+                // Pings are answered by tungstenite on the next read, and
+                // pongs only matter for having heard from them:
+                _ => {}
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    // Either side finishing means the connection is done with: they left, or
+    // the room stopped writing to them.
+    info!("running streams");
+    let res = tokio::select! {
+        res = server_to_client => res,
+        res = client_to_server => res,
+    };
+    info!("finished streams");
+
+    let _ = room.send(RoomEvent::Hangup(addr)).await;
+    if let Some(frame) = unreadable {
+        let _ = outgoing.send(Message::Close(Some(frame))).await;
+    }
+    // Either starts the closing handshake or finishes the one they started,
+    // whoever's gone doesn't get a say:
+    let _ = outgoing.close().await;
+
+    res
+}
+
+/// Where a connection goes once it's chosen.
+enum Lobby {
+    /// Into a room, as `name`.
+    Room {
+        name: String,
+        signed_in: bool,
+        password: Option<String>,
+        room: mpsc::Sender<RoomEvent>,
+    },
+    /// Into the ranked queue, as the account `name`.
+    Ranked(String),
+    /// Nowhere, the connection's done with.
+    Leave,
+}
+
+/// Runs a connection, from its handshake to leaving its room. It holds one
+/// of the half-open places until it's in a room, and is closed if it takes
+/// longer than `limits` allow to get there.
+async fn handle_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    supervisor: Supervisor,
+    limits: ConnectionLimits,
+    half_open: OwnedSemaphorePermit,
+) -> anyhow::Result<()> {
+    info!("incoming connection");
+
+    let mut ws = match timeout(limits.handshake, upgrade(stream)).await {
+        Ok(Ok(Some(ws))) => ws,
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => {
+            // There's no websocket yet to say why on:
+            info!("timed out upgrading");
+            return Ok(());
+        }
+    };
+    let mut numbering = Numbering::default();
+
+    let lobby = timeout(
+        limits.join,
+        lobby(&mut ws, addr, &supervisor, &mut numbering),
+    )
+    .await;
+    let lobby = match lobby {
+        Ok(lobby) => lobby?,
+        Err(_) => {
+            info!("timed out choosing a room");
+            let frame = close_frame(CloseCode::Policy, "took too long to join a room");
+            return hang_up(&mut ws, frame).await;
+        }
+    };
+    drop(half_open);
+
+    // Closing won't go through if they've already gone, which is just as
+    // well:
+    match lobby {
+        Lobby::Room {
+            name,
+            signed_in,
+            password,
+            room,
+        } => run_player(addr, name, signed_in, password, ws, numbering, room).await,
+        Lobby::Ranked(name) => {
+            match wait_for_match(&supervisor, &name, &mut ws, &mut numbering).await? {
+                Some(room) => run_player(addr, name, true, None, ws, numbering, room).await,
+                None => {
+                    let _ = ws.close(None).await;
+                    Ok(())
+                }
+            }
+        }
+        Lobby::Leave => {
+            let _ = ws.close(None).await;
+            Ok(())
+        }
+    }
+}
+
+/// Upgrades `stream` to a websocket, or serves it the file it's after
+/// instead, returning `None`.
+async fn upgrade(stream: TcpStream) -> anyhow::Result<Option<WebSocketStream<TcpStream>>> {
+    #[cfg(feature = "static-files")]
+    {
+        if files::enabled() && !files::wants_websocket(&stream).await? {
+            files::serve_file(stream).await?;
+            return Ok(None);
+        }
+    }
+
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_FRAME_BYTES))
+        .max_frame_size(Some(MAX_FRAME_BYTES));
+    let ws = accept_hdr_async_with_config(stream, only_ws_path, Some(config)).await?;
+
+    Ok(Some(ws))
+}
+
+fn close_frame(code: CloseCode, reason: &'static str) -> CloseFrame {
+    CloseFrame {
+        code,
+        reason: reason.into(),
+    }
+}
+
+/// Closes `ws` with `frame` saying why.
+async fn hang_up(ws: &mut WebSocketStream<TcpStream>, frame: CloseFrame) -> anyhow::Result<()> {
+    ws.close(Some(frame)).await?;
+
+    Ok(())
+}
+
+/// The message in a frame from a client, or `None` for frames that aren't
+/// messages, like pings. Messages are JSON in text or binary frames alike.
+/// Anything that can't be read is the client hung up on, with the close
+/// frame to say why.
+fn decode(frame: &Message) -> Result<Option<Sequenced<ClientMessage>>, CloseFrame> {
+    let json: &[u8] = match frame {
+        Message::Text(text) => text.as_bytes(),
+        Message::Binary(bytes) => bytes,
+        _ => return Ok(None),
+    };
+
+    if json.len() > MAX_MESSAGE_BYTES {
+        info!(bytes = json.len(), "message too large");
+        return Err(close_frame(CloseCode::Size, "message too large"));
+    }
+
+    match serde_json::from_slice(json) {
+        Ok(message) => Ok(Some(message)),
+        Err(e) => {
+            info!(error = %e, "malformed message");
+            Err(close_frame(CloseCode::Invalid, "malformed message"))
+        }
+    }
+}
+
+/// Answers what a connection asks before it's in a room, until it chooses
+/// where to go.
+async fn lobby(
+    ws: &mut WebSocketStream<TcpStream>,
+    addr: SocketAddr,
+    supervisor: &Supervisor,
+    numbering: &mut Numbering,
+) -> anyhow::Result<Lobby> {
+    // The account the connection logged in to, if it did:
+    let mut account = None;
+
+    while let Some(message) = ws.next().await {
+        let message = match message {
+            Ok(message @ Message::Text(_)) | Ok(message @ Message::Binary(_)) => message,
+            // Tungstenite has queued the answer, this just sends it now:
+            Ok(Message::Ping(_)) => {
+                ws.flush().await?;
+                continue;
+            }
+            Ok(Message::Close(frame)) => {
+                info!(?frame, "closed before choosing a room");
+                break;
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+
+        // Nothing's been taken from the connection yet that could be taken
+        // twice, so its numbers only matter once it's in a room:
+        let message = match decode(&message) {
+            Ok(Some(message)) => message.msg,
+            Ok(None) => continue,
+            Err(frame) => {
+                hang_up(ws, frame).await?;
+                return Ok(Lobby::Leave);
+            }
+        };
+
+        // Rooms run on whichever server their code belongs to:
+        if let Some(ws_url) = supervisor.redirect(&message) {
+            info!(%ws_url, "sending them to another server");
+            send(ws, numbering, &ServerMessage::Redirect { ws_url }).await?;
+            return Ok(Lobby::Leave);
+        }
+
+        match message {
+            ClientMessage::Ping => {
+                info!("ping");
+                send(ws, numbering, &ServerMessage::Pong).await?;
+            }
+            ClientMessage::Stats(name) => {
+                let stats = stats::lookup(&name);
+                let message = ServerMessage::PlayerStats { name, stats };
+                send(ws, numbering, &message).await?;
+            }
+            ClientMessage::FetchReplay(id) => {
+                let record = games::lookup(&id).await;
+                let message = ServerMessage::Replay { id, record };
+                send(ws, numbering, &message).await?;
+            }
+            ClientMessage::Register(..) | ClientMessage::LogIn(..) | ClientMessage::Resume(_) => {
+                match log_in(message).await {
+                    Ok((name, token)) => {
+                        info!(account = %name, "logged in");
+                        let message = ServerMessage::LoggedIn {
+                            name: name.clone(),
+                            token,
+                        };
+                        send(ws, numbering, &message).await?;
+                        account = Some(name);
+                    }
+                    Err(e) => {
+                        // Carrying on as a guest isn't what they asked for:
+                        info!(error = %e, "couldn't log in");
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                }
+            }
+            ClientMessage::CreateRoom(name, password, options) => {
+                let name = match playing_as(&account, name) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                };
+                info!(player = %name, "creating room");
+
+                let config = RoomConfig {
+                    password: password.clone(),
+                    options,
+                    ..RoomConfig::default()
+                };
+                let (new_id, room) = match supervisor.create_room_for(addr.ip(), config) {
+                    Ok(created) => created,
+                    Err(e) => {
+                        info!(error = %e, "turned down creating a room");
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                };
+                info!(room = %new_id, "created room");
+
+                return Ok(Lobby::Room {
+                    name,
+                    signed_in: account.is_some(),
+                    password,
+                    room,
+                });
+            }
+            ClientMessage::JoinRoom(player_name, room, password) => {
+                let player_name = match playing_as(&account, player_name) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                };
+                let room = room_id::normalize(&room);
+                info!(player = %player_name, %room, "joining room");
+
+                // However busy it is, players coming back to their seat are
+                // let in, it's only new ones that are turned away:
+                let seated = supervisor
+                    .with_room(&room, |r| r.has_seat(&player_name, account.is_some()))
+                    .unwrap_or(false);
+                if !seated {
+                    if let Err(e) = supervisor.admit() {
+                        info!(error = %e, "turned down joining a room");
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                }
+
+                match supervisor.find(&room) {
+                    Some(room) => {
+                        return Ok(Lobby::Room {
+                            name: player_name,
+                            signed_in: account.is_some(),
+                            password,
+                            room,
+                        })
+                    }
+                    None => {
+                        info!(%room, "room not found");
+                        let e = ServerError::RoomNotFound(room);
+                        send_error(ws, numbering, e).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                }
+            }
+            ClientMessage::Admin(secret, command) => {
+                match admin::run(supervisor, &secret, command).await {
+                    Ok(answer) => send(ws, numbering, &answer).await?,
+                    Err(ServerError::NotAdmin) => {
+                        send_error(ws, numbering, ServerError::NotAdmin).await?;
+                        return Ok(Lobby::Leave);
+                    }
+                    Err(e) => send_error(ws, numbering, e).await?,
+                }
+            }
+            ClientMessage::QueueRanked => {
+                return match account {
+                    Some(name) => Ok(Lobby::Ranked(name)),
+                    None => {
+                        send_error(ws, numbering, ServerError::LogInFirst).await?;
+                        Ok(Lobby::Leave)
+                    }
+                };
+            }
+            message => {
+                warn!(?message, "unexpected message");
+            }
+        }
+    }
+
+    Ok(Lobby::Leave)
+}
+
+/// Waits in line for a ranked room until one's found, returning it, or
+/// until the connection closes or the account queues from somewhere else.
+async fn wait_for_match(
+    supervisor: &Supervisor,
+    name: &str,
+    ws: &mut WebSocketStream<TcpStream>,
+    numbering: &mut Numbering,
+) -> anyhow::Result<Option<mpsc::Sender<RoomEvent>>> {
+    let rating = stats::lookup(name).rating;
+    info!(player = %name, rating, "waiting for a ranked room");
+
+    let mut found = supervisor.queue_ranked(name.to_string(), rating);
+    send(ws, numbering, &ServerMessage::InRankedQueue { rating }).await?;
+
+    let mut matchmake = tokio::time::interval(MATCHMAKE_EVERY);
+    loop {
+        tokio::select! {
+            room = &mut found => return Ok(room.ok()),
+            _ = matchmake.tick() => supervisor.matchmake(),
+            message = ws.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ok(None),
+                Some(Ok(message)) => match decode(&message) {
+                    Ok(Some(Sequenced { msg: ClientMessage::Ping, .. })) => {
+                        send(ws, numbering, &ServerMessage::Pong).await?;
+                    }
+                    Ok(_) => {}
+                    Err(frame) => {
+                        hang_up(ws, frame).await?;
+                        return Ok(None);
+                    }
+                },
+            },
+        }
+    }
+}
+
+/// Registers, logs in or resumes as `message` asks, returning the account's
+/// name and a token for it. Hashing passphrases is slow, so it's done off the
+/// async threads.
+async fn log_in(message: ClientMessage) -> Result<(String, String), ServerError> {
+    let accounts = accounts::get().ok_or(ServerError::NoAccounts)?;
+    let new_token = !matches!(message, ClientMessage::Resume(_));
+
+    let (name, token) = tokio::task::spawn_blocking(move || match message {
+        ClientMessage::Register(name, passphrase) => {
+            let token = accounts.register(&name, &passphrase)?;
+            Ok((name, token))
+        }
+        ClientMessage::LogIn(name, passphrase) => {
+            let token = accounts.log_in(&name, &passphrase)?;
+            Ok((name, token))
+        }
+        ClientMessage::Resume(token) => Ok((accounts.resume(&token)?, token)),
+        _ => Err(ServerError::BadLogin),
+    })
+    .await
+    .map_err(|_| ServerError::BadLogin)??;
+
+    // A new token is no use if it's forgotten on a restart:
+    if new_token {
+        if let Err(e) = accounts.save().await {
+            error!(error = %e, "couldn't save accounts");
+        }
+    }
+
+    Ok((name, token))
+}
+
+/// The name a connection plays under: its account's if it logged in to one,
+/// otherwise the one it asked for, as long as no account has it.
+fn playing_as(account: &Option<String>, name: String) -> Result<String, ServerError> {
+    match account {
+        Some(account) => Ok(account.clone()),
+        None if accounts::is_taken(&name) => Err(ServerError::NameTaken(name)),
+        None => Ok(name),
+    }
+}
+
+/// Turns down websockets anywhere but `WS_PATH`, so the rest of the port is
+/// free for whatever serves the page.
+#[allow(clippy::result_large_err)]
+fn only_ws_path(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    if request.uri().path() == WS_PATH {
+        return Ok(response);
+    }
+
+    let mut not_found = ErrorResponse::new(Some(format!("websockets are at {}", WS_PATH)));
+    *not_found.status_mut() = StatusCode::NOT_FOUND;
+
+    Err(not_found)
+}
+
+async fn send_error(
+    ws: &mut WebSocketStream<TcpStream>,
+    numbering: &mut Numbering,
+    error: ServerError,
+) -> anyhow::Result<()> {
+    send(ws, numbering, &ServerMessage::Error(error)).await
+}
+
+/// Accepts connections on `listener` until the task is dropped, running each
+/// one on its own task within `limits`.
+pub async fn serve(listener: TcpListener, supervisor: Supervisor, limits: ConnectionLimits) {
+    let half_open = Arc::new(Semaphore::new(limits.max_half_open));
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!(error = %e, "accept failed");
+                continue;
+            }
+        };
+
+        // Connections that haven't made it into a room yet are cheap to open
+        // and hold, so only so many get the chance:
+        let half_open = match half_open.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!(%addr, "too many connections on their way in, hanging up");
+                continue;
+            }
+        };
+
+        let supervisor = supervisor.clone();
+        let span = info_span!("conn", %addr, player = field::Empty);
+        tokio::spawn(
+            async move {
+                if let Err(e) = handle_connection(stream, addr, supervisor, limits, half_open).await
+                {
+                    error!(error = %e, "connection failed");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+/// Logs the queue metrics every so often, forever.
+pub async fn report_metrics() {
+    loop {
+        tokio::time::sleep(queue::REPORT_EVERY).await;
+        queue::METRICS.report();
+    }
+}
@@ -0,0 +1,285 @@
+//! Limits on creating rooms. Each one deals a whole set of pieces and runs a
+//! task of its own, so without them anyone could fill a public server with
+//! empty rooms. Each address can only create so many over a sliding window,
+//! and only so many rooms run at once.
+//!
+//! Connections on their way into a room are limited too, see
+//! [`ConnectionLimits`], and nobody new gets into one while the server is
+//! struggling, see [`LoadLimits`].
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use rkub_common::ServerError;
+
+/// How many rooms can be created and run, see [`RoomLimits::from_env`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomLimits {
+    /// How many rooms one address can create each `window`.
+    pub per_ip: usize,
+    pub window: Duration,
+    /// How many rooms can run at once, ranked ones included. Players are
+    /// only told the server's full once it's past
+    /// [`LoadLimits::busy_rooms`] too, which is fewer by default, so until
+    /// then they're told it's busy instead.
+    pub max_rooms: usize,
+}
+
+impl Default for RoomLimits {
+    fn default() -> Self {
+        RoomLimits {
+            per_ip: 10,
+            window: Duration::from_secs(10 * 60),
+            max_rooms: 1_000,
+        }
+    }
+}
+
+impl RoomLimits {
+    /// The defaults, with any of `RKUB_ROOMS_PER_IP`,
+    /// `RKUB_ROOM_WINDOW_SECS` and `RKUB_MAX_ROOMS` that are set in their
+    /// place.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut limits = RoomLimits::default();
+
+        if let Ok(per_ip) = std::env::var("RKUB_ROOMS_PER_IP") {
+            limits.per_ip = per_ip.parse()?;
+        }
+        if let Ok(secs) = std::env::var("RKUB_ROOM_WINDOW_SECS") {
+            limits.window = Duration::from_secs(secs.parse()?);
+        }
+        if let Ok(max_rooms) = std::env::var("RKUB_MAX_ROOMS") {
+            limits.max_rooms = max_rooms.parse()?;
+        }
+
+        Ok(limits)
+    }
+}
+
+/// How long connections get to make it into a room, and how many can be on
+/// their way at once, see [`ConnectionLimits::from_env`]. Without them a
+/// connection that never finishes its handshake, or never says what room it
+/// wants, is held open forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// How long the websocket upgrade gets.
+    pub handshake: Duration,
+    /// How long from the upgrade until the connection is in a room, or
+    /// waiting for a ranked one.
+    pub join: Duration,
+    /// How many connections can be upgrading or choosing a room at once.
+    /// Any more are hung up on straight away.
+    pub max_half_open: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits {
+            handshake: Duration::from_secs(10),
+            join: Duration::from_secs(30),
+            max_half_open: 256,
+        }
+    }
+}
+
+impl ConnectionLimits {
+    /// The defaults, with any of `RKUB_HANDSHAKE_SECS`, `RKUB_JOIN_SECS` and
+    /// `RKUB_MAX_HALF_OPEN` that are set in their place.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut limits = ConnectionLimits::default();
+
+        if let Ok(secs) = std::env::var("RKUB_HANDSHAKE_SECS") {
+            limits.handshake = Duration::from_secs(secs.parse()?);
+        }
+        if let Ok(secs) = std::env::var("RKUB_JOIN_SECS") {
+            limits.join = Duration::from_secs(secs.parse()?);
+        }
+        if let Ok(max_half_open) = std::env::var("RKUB_MAX_HALF_OPEN") {
+            limits.max_half_open = max_half_open.parse()?;
+        }
+
+        Ok(limits)
+    }
+}
+
+/// When the server is too busy to take anyone new, see
+/// [`LoadLimits::from_env`]. Past any of them, creating or joining a room is
+/// turned down with how long to wait, and the games already going keep what
+/// the server has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadLimits {
+    /// How many rooms can run before nobody new is let in. Under
+    /// [`RoomLimits::max_rooms`], so the rooms that are left go to players
+    /// already in them coming back. It can't be more than that, see
+    /// [`LoadLimits::check`].
+    pub busy_rooms: usize,
+    /// How many messages can be waiting in any one room's queue.
+    pub max_queue: usize,
+    /// How far behind its timers the server can fall.
+    pub max_lag: Duration,
+    /// How long whoever's turned away is told to wait.
+    pub retry_after: Duration,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        LoadLimits {
+            busy_rooms: 900,
+            max_queue: crate::queue::ROOM_QUEUE / 2,
+            max_lag: Duration::from_millis(250),
+            retry_after: Duration::from_secs(30),
+        }
+    }
+}
+
+impl LoadLimits {
+    /// The defaults, with any of `RKUB_BUSY_ROOMS`, `RKUB_BUSY_QUEUE`,
+    /// `RKUB_BUSY_LAG_MS` and `RKUB_BUSY_RETRY_SECS` that are set in their
+    /// place.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut limits = LoadLimits::default();
+
+        if let Ok(busy_rooms) = std::env::var("RKUB_BUSY_ROOMS") {
+            limits.busy_rooms = busy_rooms.parse()?;
+        }
+        if let Ok(max_queue) = std::env::var("RKUB_BUSY_QUEUE") {
+            limits.max_queue = max_queue.parse()?;
+        }
+        if let Ok(ms) = std::env::var("RKUB_BUSY_LAG_MS") {
+            limits.max_lag = Duration::from_millis(ms.parse()?);
+        }
+        if let Ok(secs) = std::env::var("RKUB_BUSY_RETRY_SECS") {
+            limits.retry_after = Duration::from_secs(secs.parse()?);
+        }
+
+        Ok(limits)
+    }
+
+    /// Turns down a `busy_rooms` past `rooms`' `max_rooms`, since the
+    /// server would be full before it was ever busy.
+    pub fn check(&self, rooms: &RoomLimits) -> anyhow::Result<()> {
+        if self.busy_rooms > rooms.max_rooms {
+            anyhow::bail!(
+                "RKUB_BUSY_ROOMS ({}) can't be more than RKUB_MAX_ROOMS ({})",
+                self.busy_rooms,
+                rooms.max_rooms
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// When each address created the rooms it has within the window.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    limits: RoomLimits,
+    created: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl Limiter {
+    pub fn new(limits: RoomLimits) -> Self {
+        Limiter {
+            limits,
+            created: HashMap::new(),
+        }
+    }
+
+    /// Counts a room `ip` creates at `now`, with `running` rooms already
+    /// going, or says why it can't.
+    pub fn allow(&mut self, ip: IpAddr, running: usize, now: Instant) -> Result<(), ServerError> {
+        if running >= self.limits.max_rooms {
+            return Err(ServerError::ServerFull);
+        }
+
+        // Addresses that haven't created anything lately are forgotten, and
+        // the rest only remember what's in the window:
+        let window = self.limits.window;
+        self.created.retain(|_, created| {
+            while let Some(&at) = created.front() {
+                if now.duration_since(at) < window {
+                    break;
+                }
+                created.pop_front();
+            }
+            !created.is_empty()
+        });
+
+        let created = self.created.entry(ip).or_default();
+        if created.len() >= self.limits.per_ip {
+            let oldest = created.front().copied().unwrap_or(now);
+            let wait = window - now.duration_since(oldest);
+            return Err(ServerError::TooManyRooms {
+                wait_secs: wait.as_secs().max(1),
+            });
+        }
+
+        created.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn limiter() -> Limiter {
+        Limiter::new(RoomLimits {
+            per_ip: 2,
+            window: Duration::from_secs(60),
+            max_rooms: 3,
+        })
+    }
+
+    #[test]
+    fn each_address_only_creates_so_many_per_window() {
+        let start = Instant::now();
+        let mut limiter = limiter();
+        let alice = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let bob = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert_eq!(limiter.allow(alice, 0, start), Ok(()));
+        let later = start + Duration::from_secs(20);
+        assert_eq!(limiter.allow(alice, 0, later), Ok(()));
+        assert_eq!(
+            limiter.allow(alice, 0, later),
+            Err(ServerError::TooManyRooms { wait_secs: 40 })
+        );
+        assert_eq!(limiter.allow(bob, 0, later), Ok(()));
+
+        // The window slides, so only the first room has aged out of it:
+        let after_first = start + Duration::from_secs(60);
+        assert_eq!(limiter.allow(alice, 0, after_first), Ok(()));
+        assert_eq!(
+            limiter.allow(alice, 0, after_first),
+            Err(ServerError::TooManyRooms { wait_secs: 20 })
+        );
+    }
+
+    #[test]
+    fn nobody_creates_rooms_once_the_server_is_full() {
+        let now = Instant::now();
+        let mut limiter = limiter();
+        let alice = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert_eq!(limiter.allow(alice, 3, now), Err(ServerError::ServerFull));
+        // Turning it down didn't count against them:
+        assert_eq!(limiter.allow(alice, 2, now), Ok(()));
+        assert_eq!(limiter.allow(alice, 2, now), Ok(()));
+    }
+    #[test]
+    fn servers_are_busy_before_theyre_full() {
+        let rooms = RoomLimits::default();
+        assert!(LoadLimits::default().busy_rooms < rooms.max_rooms);
+        assert!(LoadLimits::default().check(&rooms).is_ok());
+
+        let past_full = LoadLimits {
+            busy_rooms: rooms.max_rooms + 1,
+            ..LoadLimits::default()
+        };
+        assert!(past_full.check(&rooms).is_err());
+    }
+}
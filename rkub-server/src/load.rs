@@ -0,0 +1,78 @@
+//! How hard the server is working, measured every so often, so new players
+//! can be turned away while it's struggling instead of let in to slow down
+//! every game already going. See [`LoadLimits`] for when that is.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rkub_common::ServerError;
+
+use crate::limits::LoadLimits;
+
+/// How often the load is measured.
+pub const SAMPLE_EVERY: Duration = Duration::from_secs(1);
+
+/// The last measurements, shared by every connection checking them.
+#[derive(Debug, Default)]
+pub struct Load {
+    /// How late the last sample woke up, in milliseconds. Everything else
+    /// waiting on the runtime is about as far behind.
+    lag_ms: AtomicU64,
+    /// The most messages waiting in any one room's queue.
+    deepest_room: AtomicUsize,
+}
+
+impl Load {
+    pub fn record(&self, lag: Duration, deepest_room: usize) {
+        self.lag_ms.store(lag.as_millis() as u64, Ordering::Relaxed);
+        self.deepest_room.store(deepest_room, Ordering::Relaxed);
+    }
+
+    /// Whether someone new can be let in with `rooms` running, or how long
+    /// they should wait before trying again.
+    pub fn admit(&self, rooms: usize, limits: &LoadLimits) -> Result<(), ServerError> {
+        let lag = Duration::from_millis(self.lag_ms.load(Ordering::Relaxed));
+        let deepest_room = self.deepest_room.load(Ordering::Relaxed);
+
+        if rooms >= limits.busy_rooms || deepest_room >= limits.max_queue || lag >= limits.max_lag {
+            return Err(ServerError::ServerBusy {
+                retry_after_secs: limits.retry_after.as_secs().max(1),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: LoadLimits = LoadLimits {
+        busy_rooms: 10,
+        max_queue: 64,
+        max_lag: Duration::from_millis(200),
+        retry_after: Duration::from_secs(30),
+    };
+
+    const BUSY: Result<(), ServerError> = Err(ServerError::ServerBusy {
+        retry_after_secs: 30,
+    });
+
+    #[test]
+    fn anything_over_its_limit_turns_players_away() {
+        let load = Load::default();
+        assert_eq!(load.admit(9, &LIMITS), Ok(()));
+        assert_eq!(load.admit(10, &LIMITS), BUSY);
+
+        load.record(Duration::from_millis(250), 0);
+        assert_eq!(load.admit(0, &LIMITS), BUSY);
+
+        load.record(Duration::from_millis(5), 64);
+        assert_eq!(load.admit(0, &LIMITS), BUSY);
+
+        // It's only as busy as it was last measured:
+        load.record(Duration::from_millis(5), 3);
+        assert_eq!(load.admit(0, &LIMITS), Ok(()));
+    }
+}
@@ -0,0 +1,156 @@
+//! The queue for ranked games. Players wait in it until there are enough of
+//! them close enough in rating to fill a room. How close is close enough
+//! widens the longer someone has been waiting, so nobody waits forever for a
+//! perfect match.
+
+use std::time::Instant;
+
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use crate::room::RoomEvent;
+
+/// How many players a ranked room is made for.
+pub const RANKED_PLAYERS: usize = 4;
+
+/// How far apart ratings in one room can be straight away.
+const BASE_SPREAD: i32 = 100;
+
+/// How much further apart they can be for every second the longest waiter
+/// in the room has waited.
+const WIDEN_PER_SEC: i32 = 10;
+
+/// Someone waiting for a ranked room.
+pub struct Ticket {
+    pub name: String,
+    pub rating: i32,
+    since: Instant,
+    /// Where their room is sent once they're matched. Closed once they stop
+    /// waiting.
+    room: oneshot::Sender<Sender<RoomEvent>>,
+}
+
+impl Ticket {
+    /// Hands the ticket's holder their room. Returns false if they've
+    /// stopped waiting.
+    pub fn send(self, room: Sender<RoomEvent>) -> bool {
+        self.room.send(room).is_ok()
+    }
+}
+
+#[derive(Default)]
+pub struct Queue {
+    tickets: Vec<Ticket>,
+}
+
+impl Queue {
+    /// Puts `name` in the queue, in place of any ticket they already had,
+    /// returning where their room will arrive.
+    pub fn join(
+        &mut self,
+        name: String,
+        rating: i32,
+        now: Instant,
+    ) -> oneshot::Receiver<Sender<RoomEvent>> {
+        let (room, receiver) = oneshot::channel();
+
+        self.tickets.retain(|ticket| ticket.name != name);
+        self.tickets.push(Ticket {
+            name,
+            rating,
+            since: now,
+            room,
+        });
+
+        receiver
+    }
+
+    /// Takes out the closest-rated players that can fill a room, if any can.
+    pub fn take_match(&mut self, now: Instant) -> Option<Vec<Ticket>> {
+        self.tickets.retain(|ticket| !ticket.room.is_closed());
+        self.tickets.sort_by_key(|ticket| ticket.rating);
+
+        let (start, _) = self
+            .tickets
+            .windows(RANKED_PLAYERS)
+            .enumerate()
+            .filter_map(|(start, window)| {
+                let spread = window[RANKED_PLAYERS - 1].rating - window[0].rating;
+                let waited = window
+                    .iter()
+                    .map(|ticket| now.saturating_duration_since(ticket.since).as_secs())
+                    .max()?;
+                let allowed =
+                    BASE_SPREAD.saturating_add(WIDEN_PER_SEC.saturating_mul(waited as i32));
+
+                (spread <= allowed).then_some((start, spread))
+            })
+            .min_by_key(|&(_, spread)| spread)?;
+
+        Some(self.tickets.drain(start..start + RANKED_PLAYERS).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn names(tickets: &[Ticket]) -> Vec<&str> {
+        tickets.iter().map(|ticket| ticket.name.as_str()).collect()
+    }
+
+    #[test]
+    fn close_ratings_are_matched_first() {
+        let now = Instant::now();
+        let mut queue = Queue::default();
+        let _waiting: Vec<_> = [
+            ("a", 1500),
+            ("b", 1900),
+            ("c", 1520),
+            ("d", 1480),
+            ("e", 1510),
+        ]
+        .iter()
+        .map(|&(name, rating)| queue.join(name.to_string(), rating, now))
+        .collect();
+
+        let matched = queue.take_match(now).unwrap();
+        assert_eq!(names(&matched), vec!["d", "a", "e", "c"]);
+        assert_eq!(queue.tickets.len(), 1);
+        assert!(queue.take_match(now).is_none());
+    }
+
+    #[test]
+    fn waiting_widens_the_match() {
+        let start = Instant::now();
+        let mut queue = Queue::default();
+        let _waiting: Vec<_> = [1200, 1400, 1600, 1800]
+            .iter()
+            .map(|&rating| queue.join(rating.to_string(), rating, start))
+            .collect();
+
+        assert!(queue.take_match(start).is_none());
+        assert!(queue.take_match(start + Duration::from_secs(30)).is_none());
+        assert!(queue.take_match(start + Duration::from_secs(60)).is_some());
+    }
+
+    #[test]
+    fn players_who_left_are_not_matched() {
+        let now = Instant::now();
+        let mut queue = Queue::default();
+        let mut waiting: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| queue.join(name.to_string(), 1500, now))
+            .collect();
+
+        waiting.pop();
+        assert!(queue.take_match(now).is_none());
+        assert_eq!(queue.tickets.len(), 3);
+
+        // Queueing again replaces the old ticket:
+        let _again = queue.join("a".to_string(), 1500, now);
+        assert_eq!(queue.tickets.len(), 3);
+    }
+}
@@ -0,0 +1,252 @@
+//! Limits on the queues between connections and rooms, and a record of how
+//! full they get.
+
+use tracing::info;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rkub_common::{Move, MoveId, ServerMessage};
+
+/// Messages from players waiting for their room. Senders wait when it's
+/// full, which slows a flooding client down to the room's pace.
+pub const ROOM_QUEUE: usize = 256;
+
+/// Messages waiting to be written to one player's socket. A player who falls
+/// this far behind is disconnected rather than buffered forever.
+pub const PLAYER_QUEUE: usize = 128;
+
+/// How often the queue metrics are logged.
+pub const REPORT_EVERY: Duration = Duration::from_secs(60);
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// A message on its way to players. It's serialized once, however many
+/// players it goes to, and kept alongside the message for coalescing.
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    pub msg: Arc<ServerMessage>,
+    pub json: Arc<str>,
+}
+
+impl Outgoing {
+    pub fn new(msg: ServerMessage) -> Self {
+        let json = serde_json::to_string(&msg).expect("server messages always serialize");
+
+        Self {
+            msg: Arc::new(msg),
+            json: json.into(),
+        }
+    }
+}
+
+impl From<ServerMessage> for Outgoing {
+    fn from(msg: ServerMessage) -> Self {
+        Outgoing::new(msg)
+    }
+}
+
+pub struct Metrics {
+    room_depth: AtomicUsize,
+    player_depth: AtomicUsize,
+    slow_consumers: AtomicUsize,
+    coalesced: AtomicUsize,
+    miscounted: AtomicUsize,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            room_depth: AtomicUsize::new(0),
+            player_depth: AtomicUsize::new(0),
+            slow_consumers: AtomicUsize::new(0),
+            coalesced: AtomicUsize::new(0),
+            miscounted: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn room_depth(&self, depth: usize) {
+        self.room_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn player_depth(&self, depth: usize) {
+        self.player_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn slow_consumer(&self) {
+        self.slow_consumers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn coalesced(&self, count: usize) {
+        self.coalesced.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// A room's pieces stopped adding up, see [`crate::room::PieceChecks`].
+    pub fn miscounted(&self) {
+        self.miscounted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Logs everything since the last report and starts counting again.
+    pub fn report(&self) {
+        info!(
+            deepest_room = self.room_depth.swap(0, Ordering::Relaxed),
+            deepest_player = self.player_depth.swap(0, Ordering::Relaxed),
+            slow_players_dropped = self.slow_consumers.swap(0, Ordering::Relaxed),
+            board_updates_coalesced = self.coalesced.swap(0, Ordering::Relaxed),
+            rooms_miscounted = self.miscounted.swap(0, Ordering::Relaxed),
+            "queues"
+        );
+    }
+}
+
+/// Merges runs of board edits waiting for the same player into a single
+/// `Moves`, so someone catching up gets one update instead of dozens. A run
+/// is one player's edits, and the merged `Moves` carries the id of its last.
+/// Lone edits and everything else pass through untouched.
+pub fn coalesce(batch: Vec<Outgoing>) -> Vec<Outgoing> {
+    let mut out = Vec::with_capacity(batch.len());
+    let mut run = Vec::new();
+    let mut run_seat = None;
+
+    for outgoing in batch {
+        match edit_id(&outgoing.msg) {
+            Some(id) => {
+                if run_seat != Some(id.seat) {
+                    flush(&mut run, &mut out);
+                    run_seat = Some(id.seat);
+                }
+                run.push(outgoing);
+            }
+            None => {
+                flush(&mut run, &mut out);
+                run_seat = None;
+                out.push(outgoing);
+            }
+        }
+    }
+    flush(&mut run, &mut out);
+
+    out
+}
+
+/// The id of a board edit, or None for anything else.
+fn edit_id(msg: &ServerMessage) -> Option<MoveId> {
+    match msg {
+        ServerMessage::Place(id, ..)
+        | ServerMessage::Pickup(id, ..)
+        | ServerMessage::Moves(id, _) => Some(*id),
+        _ => None,
+    }
+}
+
+fn flush(run: &mut Vec<Outgoing>, out: &mut Vec<Outgoing>) {
+    if run.len() < 2 {
+        out.append(run);
+        return;
+    }
+
+    METRICS.coalesced(run.len());
+
+    let mut moves = Vec::new();
+    let mut last = None;
+    for outgoing in run.drain(..) {
+        match &*outgoing.msg {
+            ServerMessage::Place(id, coord, piece) => {
+                moves.push(Move::Place(*coord, *piece));
+                last = Some(*id);
+            }
+            ServerMessage::Pickup(id, coord, piece) => {
+                moves.push(Move::Pickup(*coord, *piece));
+                last = Some(*id);
+            }
+            ServerMessage::Moves(id, batch) => {
+                moves.extend(batch.iter().copied());
+                last = Some(*id);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    let id = last.expect("runs are never empty here");
+    out.push(ServerMessage::Moves(id, moves).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::{Color, Coord, Piece};
+
+    fn coalesce_msgs(batch: Vec<ServerMessage>) -> Vec<ServerMessage> {
+        let batch = batch.into_iter().map(Outgoing::new).collect();
+
+        coalesce(batch)
+            .into_iter()
+            .map(|outgoing| (*outgoing.msg).clone())
+            .collect()
+    }
+
+    #[test]
+    fn coalesce_merges_runs_of_edits() {
+        let a = Piece::new(Color::Red, 1);
+        let b = Piece::new(Color::Blue, 2);
+        let id = |seat, seq| MoveId { seat, seq };
+
+        let batch = vec![
+            ServerMessage::Place(id(0, 4), Coord(0, 0), a),
+            ServerMessage::Moves(id(0, 5), vec![Move::Pickup(Coord(0, 0), a)]),
+            ServerMessage::Place(id(0, 7), Coord(1, 0), b),
+            ServerMessage::EndTurnValid,
+            ServerMessage::Pickup(id(1, 2), Coord(1, 0), b),
+        ];
+
+        assert_eq!(
+            coalesce_msgs(batch),
+            vec![
+                ServerMessage::Moves(
+                    id(0, 7),
+                    vec![
+                        Move::Place(Coord(0, 0), a),
+                        Move::Pickup(Coord(0, 0), a),
+                        Move::Place(Coord(1, 0), b),
+                    ]
+                ),
+                ServerMessage::EndTurnValid,
+                ServerMessage::Pickup(id(1, 2), Coord(1, 0), b),
+            ]
+        );
+    }
+
+    #[test]
+    fn coalesce_keeps_each_players_edits_apart() {
+        let a = Piece::new(Color::Red, 1);
+        let id = |seat, seq| MoveId { seat, seq };
+
+        let batch = vec![
+            ServerMessage::Place(id(0, 4), Coord(0, 0), a),
+            ServerMessage::Pickup(id(1, 9), Coord(0, 0), a),
+        ];
+
+        assert_eq!(coalesce_msgs(batch.clone()), batch);
+    }
+
+    #[test]
+    fn coalesce_leaves_other_messages_alone() {
+        let batch = vec![ServerMessage::Pong, ServerMessage::StartTurn(1)];
+
+        assert_eq!(coalesce_msgs(batch.clone()), batch);
+    }
+
+    #[test]
+    fn outgoing_is_serialized_once_and_shared() {
+        let outgoing = Outgoing::new(ServerMessage::StartTurn(1));
+        let copy = outgoing.clone();
+
+        assert!(Arc::ptr_eq(&outgoing.json, &copy.json));
+        assert_eq!(
+            &*outgoing.json,
+            serde_json::to_string(&ServerMessage::StartTurn(1)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Elo ratings for ranked games. A game between several players is rated as
+//! if every pair of them had played each other, whoever finished ahead
+//! beating the other, so a game of four moves ratings about as much as one
+//! game of two.
+
+/// How far one game can move a rating.
+const K: f64 = 32.0;
+
+/// How far apart two ratings are for the higher one to be expected to win
+/// ten games to one.
+const SCALE: f64 = 400.0;
+
+/// Everyone's rating after a game, given their ratings before it and their
+/// scores, in the same order. Higher scores finished ahead, and equal scores
+/// drew.
+pub fn update(ratings: &[i32], scores: &[i64]) -> Vec<i32> {
+    assert_eq!(ratings.len(), scores.len());
+    if ratings.len() < 2 {
+        return ratings.to_vec();
+    }
+
+    let k = K / (ratings.len() - 1) as f64;
+
+    ratings
+        .iter()
+        .zip(scores)
+        .map(|(&rating, &score)| {
+            let change: f64 = ratings
+                .iter()
+                .zip(scores)
+                .map(|(&other, &other_score)| {
+                    let actual = match score.cmp(&other_score) {
+                        std::cmp::Ordering::Greater => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Less => 0.0,
+                    };
+
+                    actual - expected(rating, other)
+                })
+                .sum();
+
+            // Everyone counted a draw against themselves, which cancels out:
+            rating + (k * change).round() as i32
+        })
+        .collect()
+}
+
+/// How likely someone rated `rating` is to beat someone rated `other`.
+fn expected(rating: i32, other: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(f64::from(other - rating) / SCALE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winners_take_what_losers_give() {
+        let after = update(&[1500, 1500, 1500, 1500], &[0, -10, -20, -30]);
+
+        assert_eq!(after, vec![1516, 1505, 1495, 1484]);
+        assert_eq!(after.iter().sum::<i32>(), 6000);
+    }
+
+    #[test]
+    fn upsets_move_ratings_further() {
+        let expected = update(&[1700, 1300], &[0, -5]);
+        let upset = update(&[1700, 1300], &[-5, 0]);
+
+        assert!(expected[0] - 1700 < 1700 - upset[0]);
+        assert!(upset[1] > 1300 + 16);
+        assert_eq!(update(&[1600, 1600], &[-3, -3]), vec![1600, 1600]);
+    }
+}
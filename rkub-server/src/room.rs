@@ -0,0 +1,2291 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use rkub_common::{
+    points, pool_counts, reduce, replay, scores, state_hash, Action, BoardSize, ClientMessage,
+    Event, GameEvent, GameRecord, GameState, MoveId, Notification, PastMessage, Piece, Rejection,
+    RoomOptions, RoomSnapshot, RoomSummary, Sequenced, ServerError, ServerMessage, Severity,
+    TimedEvent,
+};
+
+use futures::FutureExt;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::sync::oneshot;
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
+
+use crate::auth::{self, Phase, Seat};
+use crate::games;
+use crate::queue::{self, Outgoing};
+use crate::stats;
+use crate::webhooks;
+
+/// How long the active player has to do nothing before others can vote to
+/// skip them.
+const SKIP_IDLE: Duration = Duration::from_secs(60);
+
+/// How long the active player has to do nothing before their turn is ended
+/// for them, unless the room says otherwise.
+pub const DEFAULT_TURN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long the active player's turn waits for them after they lose their
+/// connection, unless the room says otherwise.
+pub const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(30);
+
+/// How many turns in a row a player can run out of time on before they're
+/// marked away.
+const AWAY_AFTER: usize = 3;
+
+/// How many of the messages a room last told everyone it keeps, to catch
+/// players coming back up on what they missed.
+const RECENT_MESSAGES: usize = 30;
+
+/// How often everyone is sent the game's hash, to check they're still in
+/// step with it.
+const STATE_CHECK_EVERY: Duration = Duration::from_secs(15);
+
+/// What rooms do about pieces that stop adding up, checked after every event
+/// a room handles. Between the pool, the hands and the board there should
+/// always be exactly one full set, and anything else is a bug.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PieceChecks {
+    Off,
+    /// Logs what's missing or doubled up and counts it in the metrics.
+    Log,
+    /// Does the same, then stops the room taking any more moves, so what
+    /// went wrong is kept as it was to look into.
+    Freeze,
+}
+
+impl FromStr for PieceChecks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(PieceChecks::Off),
+            "log" => Ok(PieceChecks::Log),
+            "freeze" => Ok(PieceChecks::Freeze),
+            _ => anyhow::bail!("piece checks are off, log or freeze, not {:?}", s),
+        }
+    }
+}
+
+/// Checks are logged in debug builds, and off otherwise.
+static PIECE_CHECKS: AtomicU8 = AtomicU8::new(cfg!(debug_assertions) as u8);
+
+/// Sets what new rooms do about their pieces. Existing rooms keep what they
+/// started with.
+pub fn set_piece_checks(checks: PieceChecks) {
+    PIECE_CHECKS.store(checks as u8, Ordering::Relaxed);
+}
+
+fn piece_checks() -> PieceChecks {
+    match PIECE_CHECKS.load(Ordering::Relaxed) {
+        0 => PieceChecks::Off,
+        1 => PieceChecks::Log,
+        _ => PieceChecks::Freeze,
+    }
+}
+
+/// The most cells a board can have either way, see [`set_board_size`].
+pub const MAX_BOARD_SIDE: i32 = 100;
+
+static BOARD_COLS: AtomicI32 = AtomicI32::new(rkub_common::BOARD_COLS);
+static BOARD_ROWS: AtomicI32 = AtomicI32::new(rkub_common::BOARD_ROWS);
+
+/// Sets how big new rooms' boards are, each way between 1 and
+/// [`MAX_BOARD_SIDE`]. Existing rooms keep theirs.
+pub fn set_board_size(size: BoardSize) {
+    BOARD_COLS.store(size.cols.clamp(1, MAX_BOARD_SIDE), Ordering::Relaxed);
+    BOARD_ROWS.store(size.rows.clamp(1, MAX_BOARD_SIDE), Ordering::Relaxed);
+}
+
+fn board_size() -> BoardSize {
+    BoardSize {
+        cols: BOARD_COLS.load(Ordering::Relaxed),
+        rows: BOARD_ROWS.load(Ordering::Relaxed),
+    }
+}
+
+/// Everything a room's actor handles, in the order it arrived.
+pub enum RoomEvent {
+    /// A connection wants to play as `name`, which is its account's if
+    /// `account` is set. Whether it got in is sent back on `joined`, anything
+    /// else goes through `sender`.
+    Join {
+        addr: SocketAddr,
+        name: String,
+        account: bool,
+        password: Option<String>,
+        sender: Sender<Outgoing>,
+        joined: oneshot::Sender<bool>,
+    },
+    Message(SocketAddr, Sequenced<ClientMessage>),
+    /// The connection is gone, whether or not it said so.
+    Hangup(SocketAddr),
+    /// The active player ran out of time. Only the actor sends itself this.
+    TurnTimedOut,
+    /// Time to send everyone a `StateCheck`. Only the actor sends itself
+    /// this.
+    CheckState,
+    /// An operator ended the active player's turn, see
+    /// [`Room::force_end_turn`].
+    ForceEndTurn,
+    /// Something the operators want everyone in the room to know, as a
+    /// `ServerMessage::Announcement`.
+    Announce(String, Severity),
+    /// The server is stopping.
+    Shutdown,
+}
+
+/// A room's queue of events. It outlives any one actor, so a restarted actor
+/// carries on where the last one stopped.
+pub type Events = Arc<tokio::sync::Mutex<Receiver<RoomEvent>>>;
+
+/// The room as it was after the last event its actor finished.
+pub type Snapshot = Arc<Mutex<Room>>;
+
+/// The room's actor. It owns the room, handles its events one at a time and
+/// leaves a snapshot behind after each, returning once the room is over.
+/// Expects to run in the room's span.
+pub async fn run_room(mut room: Room, events: Events, snapshot: Snapshot) {
+    info!("running room");
+
+    let mut state_checks = tokio::time::interval_at(
+        tokio::time::Instant::now() + STATE_CHECK_EVERY,
+        STATE_CHECK_EVERY,
+    );
+
+    loop {
+        let deadline = room.turn_deadline();
+        let event = {
+            let mut events = events.lock().await;
+            tokio::select! {
+                event = events.recv() => {
+                    queue::METRICS.room_depth(events.len() + 1);
+                    event
+                }
+                _ = sleep_until_some(deadline) => Some(RoomEvent::TurnTimedOut),
+                _ = state_checks.tick() => Some(RoomEvent::CheckState),
+            }
+        };
+
+        let keep_going = match event {
+            Some(RoomEvent::Join {
+                addr,
+                name: player_name,
+                account,
+                password,
+                sender,
+                joined,
+            }) => {
+                let result = room
+                    .add_player(addr, &player_name, account, password.as_deref(), sender)
+                    .await;
+                if let Err(e) = &result {
+                    info!(%addr, player = %player_name, error = %e, "couldn't join");
+                }
+
+                let _ = joined.send(result.is_ok());
+                true
+            }
+            Some(RoomEvent::Message(addr, Sequenced { seq, msg })) => {
+                if room.take_seq(addr, seq) {
+                    handle_message(&mut room, addr, msg).await
+                } else {
+                    info!(%addr, seq, message = ?msg, "already taken");
+                    true
+                }
+            }
+            Some(RoomEvent::Hangup(addr)) => {
+                handle_message(&mut room, addr, ClientMessage::Close).await
+            }
+            Some(RoomEvent::TurnTimedOut) => {
+                room.time_out().await;
+                true
+            }
+            Some(RoomEvent::CheckState) => {
+                room.check_state().await;
+                true
+            }
+            Some(RoomEvent::ForceEndTurn) => {
+                room.force_end_turn().await;
+                true
+            }
+            Some(RoomEvent::Announce(text, severity)) => {
+                room.broadcast(ServerMessage::Announcement { text, severity })
+                    .await;
+                true
+            }
+            Some(RoomEvent::Shutdown) | None => {
+                room.broadcast(ServerMessage::Error(ServerError::ShuttingDown))
+                    .await;
+                false
+            }
+        };
+
+        if !keep_going || !room.drop_failed_senders().await {
+            break;
+        }
+        room.check_pieces();
+
+        *snapshot.lock().unwrap_or_else(PoisonError::into_inner) = room.clone();
+    }
+}
+
+/// Has `room` handle `msg` from `addr`, returning whether it keeps running.
+async fn handle_message(room: &mut Room, addr: SocketAddr, msg: ClientMessage) -> bool {
+    // A bad message, or a bug handling one, only costs whoever sent it their
+    // connection. Everyone else keeps playing:
+    let span = info_span!("msg", %addr, player = field::Empty, turn = field::Empty);
+    let result = AssertUnwindSafe(room.on_message(addr, msg).instrument(span))
+        .catch_unwind()
+        .await;
+
+    match result {
+        Ok(Ok(keep_going)) => keep_going,
+        Ok(Err(e)) => {
+            warn!(%addr, error = %e, "dropping connection");
+            room.drop_connection(addr).await
+        }
+        Err(_) => {
+            error!(%addr, "dropping connection, their message panicked");
+            room.drop_connection(addr).await
+        }
+    }
+}
+
+/// Waits until `deadline`, or forever without one.
+async fn sleep_until_some(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Now, in milliseconds since the Unix epoch.
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// How a room is set up when it's created.
+#[derive(Debug, Clone)]
+pub struct RoomConfig {
+    /// Private rooms can only be joined with this.
+    pub password: Option<String>,
+    /// Shuffles the first game's pool the same way every time, to replay a
+    /// game exactly. Rematches are always shuffled at random.
+    pub seed: Option<u64>,
+    /// How long the active player can do nothing before their turn is ended
+    /// for them. Without one, turns last as long as they take.
+    pub turn_timeout: Option<Duration>,
+    /// How long the active player's turn waits for them to reconnect. Zero
+    /// moves on straight away.
+    pub reconnect_grace: Duration,
+    /// The accounts matched into a ranked room, and the only ones it lets
+    /// in. Empty for rooms that aren't ranked.
+    pub ranked: Vec<String>,
+    /// What the creator chose about the room.
+    pub options: RoomOptions,
+    /// Where pieces can go. Clients draw the usual board, so anything else
+    /// is for servers whose players know what to expect.
+    pub board_size: BoardSize,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            password: None,
+            seed: None,
+            turn_timeout: Some(DEFAULT_TURN_TIMEOUT),
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            ranked: Vec::new(),
+            options: RoomOptions::default(),
+            board_size: board_size(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Room {
+    pub name: String,
+    /// Private rooms can only be joined with this.
+    pub password: Option<String>,
+    /// Who a ranked room was matched for, see [`RoomConfig::ranked`].
+    ranked: Vec<String>,
+    options: RoomOptions,
+    started: bool,
+    /// Whether the current game's been dealt. The first one waits for a
+    /// second player.
+    dealt: bool,
+    /// What the current game's pool is shuffled from, once it's dealt.
+    seed: u64,
+    connections: HashMap<SocketAddr, usize>,
+    players: Vec<Player>,
+    /// The game itself, only ever changed through the shared rules.
+    state: GameState,
+    /// Everything that's happened to `state` this game, to keep once it's
+    /// over.
+    log: Vec<TimedEvent>,
+    /// When the current game started, which the log's times count from.
+    game_started: Instant,
+    /// The same, in milliseconds since the Unix epoch, for the record.
+    game_started_ms: u64,
+    /// The player allowed to kick others, the creator unless they leave.
+    /// Creators with accounts get it back when they return.
+    host: usize,
+    /// Counts the turns of the current game from 1, for the logs.
+    turn: u64,
+    /// When the active player last did something, for skip votes and the
+    /// turn timer.
+    last_activity: Instant,
+    /// When the active player's turn started, for their stats.
+    turn_started: Instant,
+    turn_timeout: Option<Duration>,
+    reconnect_grace: Duration,
+    /// When the active player, who lost their connection, stops being
+    /// waited for.
+    reconnect_deadline: Option<Instant>,
+    /// Players who voted to skip the active player this turn.
+    skip_votes: HashSet<usize>,
+    /// Players who nudged the active player this turn.
+    nudged: HashSet<usize>,
+    /// Players who want to play again after the game ended.
+    rematch_votes: HashSet<usize>,
+    piece_checks: PieceChecks,
+    /// Whether the pieces stopped adding up. It's only reported once, until
+    /// they add up again.
+    miscounted: bool,
+    /// Whether the room stopped taking moves, see [`PieceChecks::Freeze`].
+    frozen: bool,
+    /// The last few messages broadcast, oldest first, and when they were,
+    /// see [`RECENT_MESSAGES`].
+    recent: VecDeque<(u64, Arc<ServerMessage>)>,
+}
+
+impl Room {
+    /// A room with nobody in it. Nothing's dealt until the game starts, see
+    /// [`Room::start_game`].
+    pub fn new(config: RoomConfig) -> Self {
+        let state = GameState {
+            vertical_groups: config.options.vertical_groups,
+            board_size: config.board_size,
+            ..GameState::default()
+        };
+
+        Room {
+            name: String::new(),
+            password: config.password,
+            ranked: config.ranked,
+            options: config.options,
+            started: false,
+            dealt: false,
+            seed: config.seed.unwrap_or_else(rand::random),
+            connections: HashMap::new(),
+            players: Vec::new(),
+            state,
+            log: Vec::new(),
+            game_started: Instant::now(),
+            game_started_ms: unix_ms(),
+            host: 0,
+            turn: 1,
+            last_activity: Instant::now(),
+            turn_started: Instant::now(),
+            turn_timeout: config.turn_timeout,
+            reconnect_grace: config.reconnect_grace,
+            reconnect_deadline: None,
+            skip_votes: HashSet::new(),
+            nudged: HashSet::new(),
+            rematch_votes: HashSet::new(),
+            piece_checks: piece_checks(),
+            miscounted: false,
+            frozen: false,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// What the current game's pool was shuffled from, to replay it with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Ranked games move ratings, and have no host.
+    pub fn is_ranked(&self) -> bool {
+        !self.ranked.is_empty()
+    }
+
+    /// Whether someone called `name` has a seat here to come back to, by the
+    /// same rule [`Room::add_player`] gives them it back: accounts can always
+    /// take theirs, guests only one that's been left empty.
+    pub fn has_seat(&self, name: &str, account: bool) -> bool {
+        self.players.iter().any(|p| {
+            p.name == name && p.account == account && (!p.connected || account) && !p.kicked
+        })
+    }
+
+    pub fn has_started(&self) -> bool {
+        self.started
+    }
+
+    /// Whether `password` lets someone into this room. Rooms without one are
+    /// open to anyone.
+    pub fn check_password(&self, password: Option<&str>) -> bool {
+        match &self.password {
+            Some(expected) => password == Some(expected.as_str()),
+            None => true,
+        }
+    }
+
+    /// Whether message `seq` from `addr` is newer than any the room's taken
+    /// from their seat, remembering it if so. Seats keep counting through
+    /// reconnects, so moves sent again after one aren't played twice.
+    /// Connections that aren't seated are left to `on_message` to turn away.
+    pub fn take_seq(&mut self, addr: SocketAddr, seq: u64) -> bool {
+        let player = match self.connections.get(&addr) {
+            Some(&idx) => &mut self.players[idx],
+            None => return true,
+        };
+
+        if seq <= player.last_seq {
+            return false;
+        }
+
+        player.last_seq = seq;
+        true
+    }
+
+    /// Handles one message, returning whether the room should keep running.
+    /// Errors only concern the connection that sent `msg`. Fills in who sent
+    /// it on the current span, if it has the fields.
+    pub async fn on_message(
+        &mut self,
+        addr: SocketAddr,
+        msg: ClientMessage,
+    ) -> anyhow::Result<bool> {
+        let idx = match self.connections.get(&addr) {
+            Some(&idx) => idx,
+            None => anyhow::bail!("{} isn't a player in {}", addr, self.name),
+        };
+
+        Span::current()
+            .record("player", self.players[idx].name.as_str())
+            .record("turn", self.turn);
+        debug!(message = ?msg, "received");
+
+        // Pings are sent on their own, so they don't show anyone is there:
+        if !matches!(
+            msg,
+            ClientMessage::Ping
+                | ClientMessage::Close
+                | ClientMessage::Stats(_)
+                | ClientMessage::RequestSync
+                | ClientMessage::SetAway(_)
+        ) {
+            self.came_back(idx).await;
+        }
+
+        if let Err(denied) = auth::authorize(self.phase(), self.seat(idx), &msg) {
+            info!(message = ?msg, reason = %denied, "denied");
+            return Ok(true);
+        }
+
+        match msg {
+            ClientMessage::Ping => {
+                self.players[idx].send_msg(ServerMessage::Pong).await;
+            }
+            ClientMessage::RequestSync => {
+                info!("sending the whole game");
+                let msg = ServerMessage::FullSync(Box::new(self.snapshot(idx)));
+                self.players[idx].send_msg(msg).await;
+            }
+            ClientMessage::FetchReplay(id) => {
+                let record = games::lookup(&id).await;
+                self.players[idx]
+                    .send_msg(ServerMessage::Replay { id, record })
+                    .await;
+            }
+            ClientMessage::Stats(name) => {
+                let stats = stats::lookup(&name);
+                self.players[idx]
+                    .send_msg(ServerMessage::PlayerStats { name, stats })
+                    .await;
+            }
+            ClientMessage::Close => {
+                info!("closed");
+
+                return Ok(self.disconnect(idx).await);
+            }
+            ClientMessage::EndTurn => {
+                info!(delta = self.state.turn_delta(), "ending turn");
+                self.play(idx, Action::EndTurn).await;
+            }
+            ClientMessage::Pickup(coord, piece) => {
+                self.play(idx, Action::Pickup(coord, piece)).await;
+            }
+            ClientMessage::Place(coord, piece) => {
+                self.play(idx, Action::Place(coord, piece)).await;
+            }
+            ClientMessage::Moves(moves) => {
+                self.play(idx, Action::Moves(moves)).await;
+            }
+            ClientMessage::Rematch => {
+                info!("wants a rematch");
+                self.rematch_votes.insert(idx);
+
+                let everyone = self
+                    .players
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| p.connected)
+                    .all(|(i, _)| self.rematch_votes.contains(&i));
+
+                if everyone {
+                    self.restart().await;
+                }
+            }
+            ClientMessage::Kick(idx) => {
+                if idx == self.host || idx >= self.players.len() || self.players[idx].kicked {
+                    info!(seat = idx, "can't kick them");
+                    return Ok(true);
+                }
+
+                info!(kicked = %self.players[idx].name, "kicking");
+
+                if self.state.active_player == idx {
+                    self.rollback_turn();
+                }
+
+                // Let the kicked player hear about it before cutting them off:
+                self.broadcast(ServerMessage::PlayerKicked(idx)).await;
+
+                let player = &mut self.players[idx];
+                player.kicked = true;
+                player.connected = false;
+                player.bot = false;
+                let hand = std::mem::take(&mut self.state.hands[idx]);
+                self.state.game.return_pieces(hand);
+                self.log_event(GameEvent::Kicked(idx));
+
+                if self.state.active_player == idx {
+                    self.pass_turn(idx, false).await;
+                }
+            }
+            ClientMessage::VoteSkip => {
+                let idle = self.last_activity.elapsed();
+                if idle < SKIP_IDLE {
+                    let wait_secs = (SKIP_IDLE - idle).as_secs() + 1;
+                    let msg = ServerMessage::SkipVoteTooEarly { wait_secs };
+                    self.players[idx].send_msg(msg).await;
+                    return Ok(true);
+                }
+
+                info!("votes to skip");
+                self.skip_votes.insert(idx);
+
+                // Everyone but the player being skipped gets a say:
+                let voters = self
+                    .players
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, p)| p.connected && i != self.state.active_player)
+                    .count();
+                let needed = voters / 2 + 1;
+                let votes = self.skip_votes.len();
+
+                self.broadcast(ServerMessage::SkipVotes { votes, needed })
+                    .await;
+
+                if votes >= needed {
+                    self.skip_turn().await;
+                }
+            }
+            ClientMessage::Nudge => {
+                if !self.nudged.insert(idx) {
+                    info!("already nudged this turn");
+                    return Ok(true);
+                }
+
+                info!("nudges");
+                self.broadcast(ServerMessage::Nudged(idx)).await;
+            }
+            ClientMessage::SetAway(true) => {
+                self.step_away(idx).await;
+
+                if self.state.active_player == idx && self.plays_itself(idx) {
+                    self.skip_turn().await;
+                }
+            }
+            ClientMessage::SetAway(false) => self.came_back(idx).await,
+            ClientMessage::MakeBot(idx) => {
+                match self.players.get(idx) {
+                    Some(p) if !p.connected && !p.kicked && !p.bot => {}
+                    _ => {
+                        info!(seat = idx, "can't become a bot");
+                        return Ok(true);
+                    }
+                }
+
+                info!(bot = %self.players[idx].name, "now a bot");
+                self.players[idx].bot = true;
+
+                self.broadcast(ServerMessage::PlayerBecameBot(idx)).await;
+
+                // No point waiting for them any longer:
+                if idx == self.state.active_player && self.reconnect_deadline.is_some() {
+                    self.stop_waiting().await;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn phase(&self) -> Phase {
+        match self.state.winner {
+            Some(_) => Phase::Over,
+            None if !self.dealt => Phase::Waiting,
+            None => Phase::Playing,
+        }
+    }
+
+    /// What `idx` is allowed to do, for [`auth::authorize`].
+    fn seat(&self, idx: usize) -> Seat {
+        Seat {
+            host: idx == self.host && !self.is_ranked(),
+            active: idx == self.state.active_player,
+            kicked: self.players[idx].kicked,
+        }
+    }
+
+    /// When the active player's turn runs out, if it can. It can't while
+    /// they're waiting on someone to play against.
+    pub fn turn_deadline(&self) -> Option<Instant> {
+        if self.reconnect_deadline.is_some() {
+            return self.reconnect_deadline;
+        }
+
+        let timeout = self.turn_timeout?;
+        let active = self.players.get(self.state.active_player)?;
+        let taking_turns = self.players.iter().filter(|p| p.takes_turns()).count();
+
+        if self.state.winner.is_some() || !active.connected || taking_turns < 2 || self.frozen {
+            return None;
+        }
+
+        Some(self.last_activity + timeout)
+    }
+
+    /// Ends the active player's turn for them once it's run out, marking them
+    /// away when it keeps happening.
+    pub async fn time_out(&mut self) {
+        if self.reconnect_deadline.is_some() {
+            return self.stop_waiting().await;
+        }
+
+        let idx = self.state.active_player;
+        let player = &mut self.players[idx];
+        player.timeouts += 1;
+        info!(player = %player.name, timeouts = player.timeouts, "ran out of time");
+
+        if player.timeouts >= AWAY_AFTER {
+            self.step_away(idx).await;
+        }
+
+        self.skip_turn().await;
+    }
+
+    /// Ends the active player's turn for the operators, the way running out
+    /// of time would without counting against them. Does nothing if nobody's
+    /// turn is going.
+    pub async fn force_end_turn(&mut self) {
+        if self.phase() != Phase::Playing {
+            return;
+        }
+
+        info!(player = %self.players[self.state.active_player].name, "ending their turn for the operators");
+        if self.reconnect_deadline.is_some() {
+            return self.stop_waiting().await;
+        }
+        self.skip_turn().await;
+    }
+
+    /// Gives up on the disconnected active player coming back, undoing what
+    /// they played this turn.
+    async fn stop_waiting(&mut self) {
+        let idx = self.state.active_player;
+        info!(player = %self.players[idx].name, "stopped waiting to reconnect");
+
+        self.rollback_turn();
+        self.pass_turn(idx, false).await;
+    }
+
+    /// Skips `idx`'s turns from now on, until they do something.
+    async fn step_away(&mut self, idx: usize) {
+        let player = &mut self.players[idx];
+
+        if !player.away {
+            info!(player = %player.name, "away");
+            player.away = true;
+            self.broadcast(ServerMessage::PlayerAway(idx)).await;
+        }
+    }
+
+    /// `idx` did something, so they're here whatever the turn timer thinks.
+    async fn came_back(&mut self, idx: usize) {
+        let player = &mut self.players[idx];
+        player.timeouts = 0;
+
+        if player.away && !player.kicked {
+            info!(player = %player.name, "back");
+            player.away = false;
+            self.broadcast(ServerMessage::PlayerBack(idx)).await;
+        }
+    }
+
+    /// Whether `idx`'s turns are taken for them as soon as they start. Bots
+    /// draw, and away players are skipped while anyone else is still here.
+    fn plays_itself(&self, idx: usize) -> bool {
+        let player = &self.players[idx];
+        player.bot || (player.away && self.players.iter().any(|p| p.connected && !p.away))
+    }
+
+    /// Runs `idx`'s `action` through the rules, and tells everyone what came
+    /// of it.
+    async fn play(&mut self, idx: usize, action: Action) {
+        if self.frozen {
+            info!(?action, "frozen, ignoring");
+            return;
+        }
+
+        let placed = self.state.turn_delta().max(0) as usize;
+        let (state, events) = match reduce(&self.state, idx, &action) {
+            Ok(played) => played,
+            Err(rejection) => {
+                info!(?action, ?rejection, "rejected");
+                match rejection {
+                    Rejection::InvalidBoard => {
+                        self.players[idx]
+                            .send_msg(ServerMessage::InvalidBoardState)
+                            .await
+                    }
+                    Rejection::OnlyRearranged => {
+                        self.players[idx]
+                            .send_msg(ServerMessage::OnlyRearranged)
+                            .await
+                    }
+                    Rejection::KeptBoardPiece(piece) => {
+                        self.players[idx]
+                            .send_msg(ServerMessage::KeptBoardPiece(piece))
+                            .await
+                    }
+                    Rejection::InvalidMove(bad) | Rejection::OffBoard(bad) => {
+                        let moves = match &action {
+                            Action::Moves(moves) => moves.clone(),
+                            _ => vec![bad],
+                        };
+                        let seq = self.players[idx].last_seq;
+                        self.players[idx]
+                            .send_msg(ServerMessage::MovesRejected(seq, moves))
+                            .await;
+
+                        // So they know it was where, not what:
+                        if let Rejection::OffBoard(bad) = rejection {
+                            self.players[idx]
+                                .send_msg(ServerMessage::Error(ServerError::OffBoard(bad)))
+                                .await
+                        }
+                    }
+                    _ => {}
+                }
+                return;
+            }
+        };
+
+        info!(?action, "accepted");
+        self.state = state;
+        if action != Action::EndTurn {
+            self.last_activity = Instant::now();
+        }
+        self.log_event(GameEvent::Played(idx, action));
+
+        // Whoever played gets them back too, to check against what they
+        // guessed:
+        let id = MoveId {
+            seat: idx,
+            seq: self.players[idx].last_seq,
+        };
+        for event in events {
+            match event {
+                Event::Place(coord, piece) => {
+                    self.broadcast(ServerMessage::Place(id, coord, piece)).await
+                }
+                Event::Pickup(coord, piece) => {
+                    self.broadcast(ServerMessage::Pickup(id, coord, piece))
+                        .await
+                }
+                Event::Moves(moves) => self.broadcast(ServerMessage::Moves(id, moves)).await,
+                Event::Drew(piece) => {
+                    self.players[idx]
+                        .send_msg(ServerMessage::DrawPiece(piece))
+                        .await
+                }
+                Event::Won(winner) => {
+                    info!(winner = %self.players[winner].name, "won the game");
+                    self.record_stats(idx, placed, true).await;
+
+                    // Keep the room around in case everyone wants a rematch:
+                    let msg = ServerMessage::PlayerWon {
+                        winner: self.players[winner].name.clone(),
+                        hands: self.state.hands.clone(),
+                        at_ms: unix_ms(),
+                    };
+                    self.broadcast(msg).await;
+                    self.keep_game().await;
+
+                    let record = self.record();
+                    webhooks::notify(Notification::game_finished(
+                        &self.name,
+                        &record.players,
+                        winner,
+                        &record.scores,
+                    ));
+                }
+                Event::TurnEnded { drew } => {
+                    self.record_stats(idx, placed, false).await;
+                    self.players[idx]
+                        .send_msg(ServerMessage::EndTurnValid)
+                        .await;
+                    self.pass_turn(idx, drew).await;
+                }
+            }
+        }
+    }
+
+    /// Saves the game that just ended for replays, and tells everyone where
+    /// to find it.
+    async fn keep_game(&mut self) {
+        let games = match games::get() {
+            Some(games) => games,
+            None => return,
+        };
+
+        let record = self.record();
+        match games.save(&record).await {
+            Ok(()) => self.broadcast(ServerMessage::GameSaved(record.id)).await,
+            Err(e) => warn!(error = %e, "couldn't save the game"),
+        }
+    }
+
+    /// Counts the turn `idx` just ended, having put down `placed`, and the
+    /// game too if they won it.
+    async fn record_stats(&mut self, idx: usize, placed: usize, won: bool) {
+        let stats = match stats::get() {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        // Only accounts have stats, anyone could be playing under a guest's
+        // name:
+        let player = &self.players[idx];
+        if player.account {
+            stats.record_turn(&player.name, self.turn_started.elapsed(), placed);
+        }
+
+        if won {
+            let players = self.players.iter().filter(|p| p.account && !p.kicked);
+            stats.record_game(players.map(|p| p.name.as_str()), &player.name);
+
+            if self.is_ranked() {
+                // Whoever was left holding the fewest points did best:
+                let names: Vec<&str> = self.players.iter().map(|p| p.name.as_str()).collect();
+                let scores: Vec<i64> = self
+                    .state
+                    .hands
+                    .iter()
+                    .map(|hand| -i64::from(points(hand)))
+                    .collect();
+                stats.record_ranked(&names, &scores);
+            }
+
+            if let Err(e) = stats.save().await {
+                warn!(error = %e, "couldn't save stats");
+            }
+        }
+    }
+
+    /// Marks `idx` as gone, passing on hosting and their turn. Returns false
+    /// once nobody is left.
+    async fn disconnect(&mut self, idx: usize) -> bool {
+        self.players[idx].connected = false;
+        self.broadcast(ServerMessage::PlayerDisconnected(idx)).await;
+
+        if self.players.iter().all(|p| !p.connected) {
+            return false;
+        }
+
+        if self.host == idx {
+            self.host = (1..self.players.len())
+                .map(|i| (idx + i) % self.players.len())
+                .find(|&i| self.players[i].connected)
+                .unwrap();
+            info!(host = %self.players[self.host].name, "passed on hosting");
+
+            self.broadcast(ServerMessage::HostChanged(self.host)).await;
+        }
+
+        if self.state.active_player == idx {
+            if self.reconnect_grace == Duration::default() {
+                self.rollback_turn();
+                self.pass_turn(idx, false).await;
+            } else {
+                self.reconnect_deadline = Some(Instant::now() + self.reconnect_grace);
+                let msg = ServerMessage::WaitingForReconnect {
+                    player: idx,
+                    wait_secs: self.reconnect_grace.as_secs(),
+                };
+                self.broadcast(msg).await;
+            }
+        }
+
+        true
+    }
+
+    /// Cuts off `addr` after its message failed. Anything else it sends is
+    /// refused, but the player can reconnect as usual.
+    pub async fn drop_connection(&mut self, addr: SocketAddr) -> bool {
+        let idx = match self.connections.get(&addr) {
+            Some(&idx) => idx,
+            None => return true,
+        };
+
+        self.cut_off(idx);
+
+        if self.players[idx].connected {
+            self.disconnect(idx).await
+        } else {
+            true
+        }
+    }
+
+    /// Stops writing to `idx`'s connections and refuses anything else they
+    /// send.
+    fn cut_off(&mut self, idx: usize) {
+        self.connections.retain(|_, i| *i != idx);
+
+        // Dropping the only sender ends the connection's writer:
+        let (closed, _) = mpsc::channel(1);
+        self.players[idx].sender = closed;
+    }
+
+    /// Disconnects anyone whose connection stopped taking messages, or fell
+    /// too far behind. Returns
+    /// false once nobody is left.
+    pub async fn drop_failed_senders(&mut self) -> bool {
+        while let Some(idx) = self
+            .players
+            .iter()
+            .position(|p| p.connected && p.send_failed)
+        {
+            warn!(player = %self.players[idx].name, "can't reach them");
+            self.players[idx].send_failed = false;
+            self.cut_off(idx);
+
+            if !self.disconnect(idx).await {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Hands the turn on from `ending` to the next player who takes turns.
+    /// Bots take theirs straight away by drawing a piece.
+    async fn pass_turn(&mut self, mut ending: usize, mut drew: bool) {
+        loop {
+            self.players[ending].thinking += self.turn_started.elapsed();
+            self.last_activity = Instant::now();
+            self.turn_started = self.last_activity;
+            self.reconnect_deadline = None;
+            self.skip_votes.clear();
+            self.nudged.clear();
+
+            let mut next = (self.state.active_player + 1) % self.players.len();
+            while !self.players[next].takes_turns() {
+                next = (next + 1) % self.players.len();
+            }
+            self.turn += 1;
+            self.start_turn(next);
+
+            let plays_itself = self.plays_itself(next);
+            if !plays_itself {
+                self.players[next]
+                    .send_msg(ServerMessage::StartTurn(self.turn))
+                    .await;
+            }
+
+            // However their copy got here, this is the hand they ended with:
+            let view = self.state.private_view(ending);
+            self.players[ending]
+                .send_msg(ServerMessage::HandSync(view))
+                .await;
+
+            let msg = ServerMessage::TurnFinished {
+                ending_player: self.players[ending].name.clone(),
+                ending_drew: drew,
+                next_player: next,
+                hand_sizes: self.hand_sizes(),
+                pieces_remaining: self.state.game.remaining_pieces().len(),
+                board: self.state.game.board().clone(),
+                state_hash: self.state_hash(),
+                thinking_ms: self.thinking_ms(),
+                pool: self.pool(),
+                turn: self.turn,
+                at_ms: unix_ms(),
+            };
+
+            self.broadcast(msg).await;
+
+            if !plays_itself {
+                break;
+            }
+
+            ending = next;
+            drew = self.draw_for(ending).await;
+        }
+    }
+
+    /// Adds `event` to the log, timed from the start of the game.
+    fn log_event(&mut self, event: GameEvent) {
+        let at_ms = self.game_started.elapsed().as_millis() as u64;
+        self.log.push(TimedEvent {
+            at_ms,
+            turn: self.turn,
+            event,
+        });
+    }
+
+    /// The current game as it can be kept and exported.
+    fn record(&self) -> GameRecord {
+        GameRecord {
+            format: replay::FORMAT,
+            id: format!("{}-{:x}", self.name, self.seed()),
+            seed: self.seed(),
+            started_ms: self.game_started_ms,
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+            events: self.log.clone(),
+            scores: self
+                .state
+                .winner
+                .map(|winner| scores(&self.state.hands, winner))
+                .unwrap_or_default(),
+            vertical_groups: self.options.vertical_groups,
+            board_size: self.state.board_size,
+        }
+    }
+
+    /// Undoes what the active player played this turn.
+    fn rollback_turn(&mut self) {
+        self.state.rollback_turn();
+        self.log_event(GameEvent::RolledBack);
+    }
+
+    fn start_turn(&mut self, idx: usize) {
+        self.state.start_turn(idx);
+        self.log_event(GameEvent::TurnStarted(idx));
+    }
+
+    /// Deals `idx` a piece, if there are any left.
+    async fn draw_for(&mut self, idx: usize) -> bool {
+        match self.state.draw(idx) {
+            Some(piece) => {
+                self.log_event(GameEvent::Drew(idx));
+                self.players[idx]
+                    .send_msg(ServerMessage::DrawPiece(piece))
+                    .await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ends the active player's turn for them as if they had drawn without
+    /// playing.
+    async fn skip_turn(&mut self) {
+        let idx = self.state.active_player;
+        info!(player = %self.players[idx].name, "skipping their turn");
+
+        self.rollback_turn();
+        self.broadcast(ServerMessage::TurnSkipped(idx)).await;
+
+        let drew = self.draw_for(idx).await;
+        self.pass_turn(idx, drew).await;
+    }
+
+    /// Starts a new game with the same players, on a pool shuffled at
+    /// random.
+    async fn restart(&mut self) {
+        self.seed = rand::random();
+        info!(seed = self.seed(), "starting a rematch");
+
+        self.start_game();
+        self.send_joined_rooms().await;
+        self.send_game_start().await;
+    }
+
+    /// Takes every piece back, shuffles the pool from the room's seed and
+    /// deals everyone seated a fresh hand, then starts the turn of whoever
+    /// draws the highest piece. It's up to the caller to tell everyone.
+    fn start_game(&mut self) {
+        self.dealt = true;
+        self.state.vertical_groups = self.options.vertical_groups;
+        self.log.clear();
+        self.game_started = Instant::now();
+        self.game_started_ms = unix_ms();
+        self.turn = 1;
+
+        self.last_activity = Instant::now();
+        self.turn_started = self.last_activity;
+        self.skip_votes.clear();
+        self.nudged.clear();
+        self.rematch_votes.clear();
+
+        // Kicked players keep their seat, but nothing to play with:
+        let dealt: Vec<bool> = self.players.iter().map(|p| !p.kicked).collect();
+        self.state.reset_for_new_round(self.seed, &dealt);
+        for player in &mut self.players {
+            player.thinking = Duration::ZERO;
+            self.log.push(TimedEvent {
+                at_ms: 0,
+                turn: self.turn,
+                event: GameEvent::Seated {
+                    name: player.name.clone(),
+                    dealt: !player.kicked,
+                },
+            });
+        }
+
+        let drawing: Vec<usize> = (0..self.players.len())
+            .filter(|&i| self.players[i].connected)
+            .collect();
+        let first = self.state.highest_draw(&drawing).unwrap_or(0);
+        self.start_turn(first);
+        self.announce_game_start();
+    }
+
+    /// Tells everyone the order the game's turns go in, and whoever's first
+    /// that it's their turn.
+    async fn send_game_start(&mut self) {
+        let first = self.state.active_player;
+        let seats = self.players.len();
+        let order = (0..seats)
+            .map(|i| (first + i) % seats)
+            .filter(|&i| !self.players[i].kicked)
+            .collect();
+        info!(first = %self.players[first].name, "starting the game");
+
+        self.broadcast(ServerMessage::StartGame {
+            order,
+            at_ms: unix_ms(),
+        })
+        .await;
+        self.players[first]
+            .send_msg(ServerMessage::StartTurn(self.turn))
+            .await;
+    }
+
+    /// Tells the webhook a game started, with whoever's playing it.
+    fn announce_game_start(&self) {
+        webhooks::notify(Notification::GameStarted {
+            room: self.name.clone(),
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+        });
+    }
+
+    /// Sends everyone connected the whole room again, for when what they
+    /// have may not match it.
+    pub async fn resync(&mut self) {
+        self.send_joined_rooms().await;
+        self.players[self.state.active_player]
+            .send_msg(ServerMessage::StartTurn(self.turn))
+            .await;
+    }
+
+    /// Sends everyone connected the room as they see it.
+    async fn send_joined_rooms(&mut self) {
+        for idx in 0..self.players.len() {
+            if self.players[idx].connected {
+                let msg = self.joined_room(idx);
+                self.players[idx].send_msg(msg).await;
+            }
+        }
+    }
+
+    /// Seats `name`, or gives them back their seat. `account` says `name` is
+    /// the account the connection logged in to.
+    pub async fn add_player(
+        &mut self,
+        addr: SocketAddr,
+        name: &str,
+        account: bool,
+        password: Option<&str>,
+        ws_sender: Sender<Outgoing>,
+    ) -> anyhow::Result<()> {
+        if !self.check_password(password) {
+            info!(%addr, player = name, "wrong password");
+            ws_sender
+                .send(ServerMessage::Error(ServerError::WrongPassword).into())
+                .await?;
+            anyhow::bail!("{} gave the wrong password for {}", name, self.name);
+        }
+
+        if self.has_started() {
+            ws_sender
+                .send(ServerMessage::GameAlreadyStarted(self.name.clone()).into())
+                .await?;
+        }
+
+        if self.is_ranked() && !(account && self.ranked.iter().any(|r| r == name)) {
+            info!(%addr, player = name, "wasn't matched into this ranked room");
+            ws_sender
+                .send(ServerMessage::Error(ServerError::RankedRoom).into())
+                .await?;
+            anyhow::bail!("{} wasn't matched into {}", name, self.name);
+        }
+
+        if self.players.iter().any(|p| p.name == name && p.kicked) {
+            info!(%addr, player = name, "was kicked, not letting them back in");
+            ws_sender
+                .send(ServerMessage::Error(ServerError::Kicked).into())
+                .await?;
+            anyhow::bail!("{} was kicked from {}", name, self.name);
+        }
+
+        // Accounts can take their seat back whenever they log in again, even
+        // before the old connection is noticed to be gone. Guests only get
+        // an empty seat under their name:
+        if let Some(idx) = self
+            .players
+            .iter()
+            .position(|p| p.name == name && p.account == account && (!p.connected || account))
+        {
+            if self.players[idx].connected {
+                info!(%addr, player = name, "taking over from their old connection");
+                self.cut_off(idx);
+            }
+            self.connections.insert(addr, idx);
+        }
+
+        if self.connections.contains_key(&addr) {
+            info!(%addr, player = name, "reconnected");
+            // Take back over from the bot, if there was one:
+            self.players[self.connections[&addr]].connected = true;
+            self.players[self.connections[&addr]].bot = false;
+            ws_sender
+                .send(self.joined_room(self.connections[&addr]).into())
+                .await?;
+            if !self.recent.is_empty() {
+                ws_sender.send(self.history().into()).await?;
+            }
+
+            self.players[self.connections[&addr]].sender = ws_sender;
+            self.players[self.connections[&addr]].send_failed = false;
+            self.broadcast(ServerMessage::PlayerReconnected(self.connections[&addr]))
+                .await;
+            self.came_back(self.connections[&addr]).await;
+
+            // Whoever made the room gets hosting back, if they can prove it's
+            // them:
+            if self.connections[&addr] == 0 && account && self.host != 0 {
+                self.host = 0;
+                info!(host = name, "took hosting back");
+                self.broadcast(ServerMessage::HostChanged(0)).await;
+            }
+
+            // Their turn was waiting for them:
+            if self.state.active_player == self.connections[&addr]
+                && self.reconnect_deadline.take().is_some()
+            {
+                self.last_activity = Instant::now();
+                self.players[self.connections[&addr]]
+                    .send_msg(ServerMessage::StartTurn(self.turn))
+                    .await;
+            }
+
+            return Ok(());
+        }
+
+        // Once the game's going, anyone joining is dealt in straight away:
+        let idx = if self.dealt {
+            let idx = self.state.add_player();
+            self.log_event(GameEvent::Seated {
+                name: name.to_string(),
+                dealt: true,
+            });
+            idx
+        } else {
+            self.state.hands.push(Vec::new());
+            self.state.hands.len() - 1
+        };
+        let mut player = Player::new(name.to_string(), ws_sender.clone());
+        player.account = account;
+
+        self.broadcast(ServerMessage::PlayerJoined(name.to_string()))
+            .await;
+
+        self.players.push(player);
+
+        // The game only starts once there's someone to play against, and
+        // everyone's sent the hand they were dealt then, whoever just joined
+        // included:
+        if !self.dealt && self.players.len() == 2 {
+            self.connections.insert(addr, idx);
+            self.start_game();
+            self.send_joined_rooms().await;
+            self.send_game_start().await;
+            return Ok(());
+        }
+
+        ws_sender.send(self.joined_room(idx).into()).await?;
+
+        self.connections.insert(addr, idx);
+
+        Ok(())
+    }
+
+    pub fn hand_sizes(&self) -> Vec<usize> {
+        self.state.hands.iter().map(Vec::len).collect()
+    }
+
+    /// What the room told everyone lately, to catch someone coming back up.
+    fn history(&self) -> ServerMessage {
+        let past = self.recent.iter().map(|(at_ms, msg)| PastMessage {
+            at_ms: *at_ms,
+            msg: (**msg).clone(),
+        });
+
+        ServerMessage::History(past.collect())
+    }
+
+    /// The whole room as `idx` can see it, for when they join.
+    fn joined_room(&self, idx: usize) -> ServerMessage {
+        ServerMessage::JoinedRoom {
+            room_name: self.name.clone(),
+            options: self.options.clone(),
+            last_seq: self.players[idx].last_seq,
+            snapshot: Box::new(self.snapshot(idx)),
+        }
+    }
+
+    /// The room at a glance, for the operators.
+    pub fn summary(&self) -> RoomSummary {
+        RoomSummary {
+            id: self.name.clone(),
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+            phase: self.phase(),
+            dealt: self.dealt,
+            active_player: self.state.active_player,
+            turn: self.turn,
+            idle_secs: self.last_activity.elapsed().as_secs(),
+        }
+    }
+
+    /// Everything about the room for the operators, hands and all, as a
+    /// `ServerMessage::AdminRoom`.
+    pub fn inspect(&self) -> ServerMessage {
+        ServerMessage::AdminRoom {
+            summary: self.summary(),
+            snapshot: self
+                .state
+                .hands
+                .get(self.host)
+                .map(|_| Box::new(self.snapshot(self.host))),
+            hands: self.state.hands.clone(),
+        }
+    }
+
+    /// The whole room as `idx` can see it, for when they join or have fallen
+    /// out of step.
+    fn snapshot(&self, idx: usize) -> RoomSnapshot {
+        let seats = |f: fn(&Player) -> bool| {
+            (0..self.players.len())
+                .filter(|&i| f(&self.players[i]))
+                .collect()
+        };
+
+        RoomSnapshot {
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+            hand_sizes: self.hand_sizes(),
+            host: self.host,
+            view: self.state.private_view(idx),
+            board: self.state.game.board().clone(),
+            board_size: self.state.board_size,
+            pieces_remaining: self.state.game.remaining_pieces().len(),
+            dealt: self.dealt,
+            active_player: self.state.active_player,
+            phase: self.phase(),
+            scores: self
+                .state
+                .winner
+                .map(|winner| scores(&self.state.hands, winner)),
+            disconnected: seats(|p| !p.connected),
+            bots: seats(|p| p.bot),
+            away: seats(|p| p.away),
+            kicked: seats(|p| p.kicked),
+            thinking_ms: self.thinking_ms(),
+            turn_ms: self.turn_started.elapsed().as_millis() as u64,
+            pool: self.pool(),
+            turn: self.turn,
+        }
+    }
+
+    /// What's left to draw, if the room shows it.
+    fn pool(&self) -> Option<Vec<(Piece, usize)>> {
+        if !self.options.show_pool {
+            return None;
+        }
+
+        Some(pool_counts(self.state.game.remaining_pieces()))
+    }
+
+    /// How long everyone's spent on their turns, by player index.
+    fn thinking_ms(&self) -> Vec<u64> {
+        self.players
+            .iter()
+            .map(|p| p.thinking.as_millis() as u64)
+            .collect()
+    }
+
+    /// What everyone's copy of the game should hash to, see [`state_hash`].
+    fn state_hash(&self) -> u64 {
+        state_hash(self.state.game.board(), &self.hand_sizes())
+    }
+
+    /// Sends everyone the game's hash, while there's a game to check.
+    pub async fn check_state(&mut self) {
+        if self.phase() == Phase::Playing && !self.players.is_empty() {
+            let hash = self.state_hash();
+            self.broadcast(ServerMessage::StateCheck(hash)).await;
+        }
+    }
+
+    /// Checks every piece is still accounted for, once the game's been dealt,
+    /// and does what the room's [`PieceChecks`] say if they aren't.
+    pub fn check_pieces(&mut self) {
+        if self.piece_checks == PieceChecks::Off || !self.dealt {
+            return;
+        }
+
+        let miscounted = self.state.miscounted();
+        if miscounted.is_empty() || self.miscounted {
+            self.miscounted = !miscounted.is_empty();
+            return;
+        }
+
+        error!(?miscounted, turn = self.turn, "pieces don't add up");
+        queue::METRICS.miscounted();
+        self.miscounted = true;
+        if self.piece_checks == PieceChecks::Freeze {
+            warn!("freezing the room");
+            self.frozen = true;
+        }
+    }
+
+    /// Sends `msg` to everyone connected. It's serialized once and shared
+    /// between them.
+    pub async fn broadcast(&mut self, msg: ServerMessage) {
+        debug_assert!(!msg.is_private(), "broadcasting {:?}", msg);
+        let outgoing = Outgoing::new(msg);
+
+        // Hashes are only worth anything as they're sent:
+        if !matches!(*outgoing.msg, ServerMessage::StateCheck(_)) {
+            if self.recent.len() == RECENT_MESSAGES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((unix_ms(), outgoing.msg.clone()));
+        }
+
+        for player in self.players.iter_mut().filter(|p| p.connected) {
+            player.send(outgoing.clone());
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Player {
+    name: String,
+    /// Logged in as the account `name`, rather than a guest playing under
+    /// it.
+    account: bool,
+    connected: bool,
+    /// Removed by the host, and kept out of the room.
+    kicked: bool,
+    /// Disconnected, but still taking turns by drawing.
+    bot: bool,
+    /// Connected, but ran out of time too often. Skipped until they do
+    /// something.
+    away: bool,
+    /// Turns in a row they ran out of time on.
+    timeouts: usize,
+    /// Time spent on their own turns this game.
+    thinking: Duration,
+    /// The `seq` of the last message taken from them, see
+    /// [`Room::take_seq`].
+    last_seq: u64,
+    sender: Sender<Outgoing>,
+    /// A message couldn't be sent, so the connection is probably gone.
+    send_failed: bool,
+}
+
+impl Player {
+    pub fn new(name: String, sender: Sender<Outgoing>) -> Self {
+        Self {
+            name,
+            account: false,
+            connected: true,
+            kicked: false,
+            bot: false,
+            away: false,
+            timeouts: 0,
+            thinking: Duration::ZERO,
+            last_seq: 0,
+            sender,
+            send_failed: false,
+        }
+    }
+
+    /// Whether the turn order should stop at this player.
+    pub fn takes_turns(&self) -> bool {
+        self.connected || self.bot
+    }
+
+    pub async fn send_msg(&mut self, msg: ServerMessage) {
+        self.send(msg.into());
+    }
+
+    /// Queues `outgoing` for the player's connection without waiting on it.
+    /// If the queue is full they're too far behind to catch up, so they're
+    /// marked to be disconnected along with closed connections.
+    fn send(&mut self, outgoing: Outgoing) {
+        match self.sender.try_send(outgoing) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                queue::METRICS.slow_consumer();
+                self.send_failed = true;
+            }
+            Err(TrySendError::Closed(_)) => self.send_failed = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::iter;
+
+    use rkub_common::{Color, Coord, Game, Move, HAND_SIZE};
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    /// A room with two players, Alice to play, and what each of them has
+    /// been sent.
+    async fn two_player_room() -> (Room, Receiver<Outgoing>, Receiver<Outgoing>) {
+        let mut room = Room::new(RoomConfig::default());
+        let (alice, alice_rx) = mpsc::channel(64);
+        let (bob, bob_rx) = mpsc::channel(64);
+
+        room.add_player(addr(1), "alice", false, None, alice)
+            .await
+            .unwrap();
+        room.add_player(addr(2), "bob", false, None, bob)
+            .await
+            .unwrap();
+        // Whoever drew the highest, Alice goes first:
+        room.start_turn(0);
+
+        (room, alice_rx, bob_rx)
+    }
+
+    fn received(rx: &mut Receiver<Outgoing>) -> Vec<ServerMessage> {
+        iter::from_fn(|| rx.try_recv().ok())
+            .map(|outgoing| (*outgoing.msg).clone())
+            .collect()
+    }
+
+    /// Goes through everything each player's been sent since last time, as
+    /// it goes over the wire, checking that any hand in it is theirs and as
+    /// it is now. Returns how many messages were private.
+    fn check_hands_are_private(room: &Room, rxs: &mut [Receiver<Outgoing>]) -> usize {
+        fn walk(value: &serde_json::Value, seats: &mut Vec<serde_json::Value>, all: &mut bool) {
+            match value {
+                serde_json::Value::Object(fields) => {
+                    if fields.contains_key("hand") {
+                        seats.push(fields.get("seat").cloned().unwrap_or_default());
+                    }
+                    *all |= fields.contains_key("hands");
+                    fields.values().for_each(|value| walk(value, seats, all));
+                }
+                serde_json::Value::Array(values) => {
+                    values.iter().for_each(|value| walk(value, seats, all))
+                }
+                _ => {}
+            }
+        }
+
+        let mut private = 0;
+        for (seat, rx) in rxs.iter_mut().enumerate() {
+            let hand = &room.state.hands[seat];
+
+            for msg in received(rx) {
+                let mut seats = Vec::new();
+                let mut all = false;
+                walk(&serde_json::to_value(&msg).unwrap(), &mut seats, &mut all);
+
+                assert!(seats.iter().all(|s| *s == seat), "{} sent {:?}", seat, msg);
+                assert_eq!(all, room.state.winner.is_some(), "{:?}", msg);
+                match &msg {
+                    ServerMessage::JoinedRoom { snapshot, .. } => {
+                        assert_eq!(snapshot.view.hand(), &hand[..])
+                    }
+                    ServerMessage::FullSync(sync) => assert_eq!(sync.view.hand(), &hand[..]),
+                    ServerMessage::HandSync(view) => assert_eq!(view.hand(), &hand[..]),
+                    ServerMessage::DrawPiece(piece) => assert!(hand.contains(piece)),
+                    _ => assert!(!msg.is_private(), "{:?}", msg),
+                }
+                private += msg.is_private() as usize;
+            }
+        }
+
+        private
+    }
+
+    #[tokio::test]
+    async fn hands_are_only_sent_to_their_owners() {
+        let mut room = Room::new(RoomConfig::default());
+        let mut rxs = Vec::new();
+        for (port, name) in [(1, "alice"), (2, "bob"), (3, "carol")].iter() {
+            let (sender, receiver) = mpsc::channel(64);
+            room.add_player(addr(*port), name, false, None, sender)
+                .await
+                .unwrap();
+            rxs.push(receiver);
+
+            // Alice joins before there's anything to deal her:
+            if *port == 1 {
+                assert_eq!(check_hands_are_private(&room, &mut rxs), 1);
+            }
+        }
+        assert_eq!(check_hands_are_private(&room, &mut rxs), 3);
+        room.start_turn(0);
+
+        // Alice plays a piece, ends her turn on a bad board, takes it back
+        // and draws instead:
+        let piece = room.state.hands[0][0];
+        let actions = [
+            ClientMessage::Place(Coord(0, 0), piece),
+            ClientMessage::EndTurn,
+            ClientMessage::Pickup(Coord(0, 0), piece),
+            ClientMessage::EndTurn,
+        ];
+        for msg in actions.iter() {
+            room.on_message(addr(1), msg.clone()).await.unwrap();
+            check_hands_are_private(&room, &mut rxs);
+        }
+        assert_eq!(room.state.hands[0].len(), 15);
+
+        // Bob falls out of step, and Carol reconnects:
+        room.on_message(addr(2), ClientMessage::RequestSync)
+            .await
+            .unwrap();
+        assert_eq!(check_hands_are_private(&room, &mut rxs), 1);
+        room.on_message(addr(3), ClientMessage::Close)
+            .await
+            .unwrap();
+        let (carol, carol_rx) = mpsc::channel(64);
+        room.add_player(addr(4), "carol", false, None, carol)
+            .await
+            .unwrap();
+        rxs[2] = carol_rx;
+        assert_eq!(check_hands_are_private(&room, &mut rxs), 1);
+
+        // Only once Bob's won does everyone see every hand:
+        let run: Vec<Piece> = (1..=3).map(|num| Piece::new(Color::Red, num)).collect();
+        room.state.hands[1] = run.clone();
+        let moves = run
+            .iter()
+            .enumerate()
+            .map(|(x, &piece)| Move::Place(Coord(x as i32 + 5, 5), piece))
+            .collect();
+        room.on_message(addr(2), ClientMessage::Moves(moves))
+            .await
+            .unwrap();
+        check_hands_are_private(&room, &mut rxs);
+        room.on_message(addr(2), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        assert_eq!(room.state.winner, Some(1));
+        check_hands_are_private(&room, &mut rxs);
+    }
+
+    #[tokio::test]
+    async fn failing_sender_is_disconnected() {
+        let (mut room, mut alice_rx, bob_rx) = two_player_room().await;
+
+        // Bob's connection dies without a Close reaching the room:
+        drop(bob_rx);
+        room.broadcast(ServerMessage::Pong).await;
+
+        assert!(room.drop_failed_senders().await);
+        assert!(room.players[0].connected);
+        assert!(!room.players[1].connected);
+        assert!(received(&mut alice_rx).contains(&ServerMessage::PlayerDisconnected(1)));
+    }
+
+    #[tokio::test]
+    async fn slow_consumer_is_disconnected() {
+        let mut room = Room::new(RoomConfig::default());
+        // Alice's queue fills up with her JoinedRoom:
+        let (alice, _alice_rx) = mpsc::channel(1);
+        let (bob, mut bob_rx) = mpsc::channel(64);
+
+        room.add_player(addr(1), "alice", false, None, alice)
+            .await
+            .unwrap();
+        room.add_player(addr(2), "bob", false, None, bob)
+            .await
+            .unwrap();
+
+        assert!(room.drop_failed_senders().await);
+        assert!(!room.players[0].connected);
+        assert!(received(&mut bob_rx).contains(&ServerMessage::PlayerDisconnected(0)));
+
+        // Anything else from her is refused:
+        assert!(room.on_message(addr(1), ClientMessage::Ping).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn failing_active_host_passes_turn_and_hosting() {
+        let (mut room, alice_rx, mut bob_rx) = two_player_room().await;
+
+        drop(alice_rx);
+        room.broadcast(ServerMessage::Pong).await;
+
+        // Hosting moves on straight away, the turn waits for her a while:
+        assert!(room.drop_failed_senders().await);
+        assert_eq!(room.host, 1);
+        assert_eq!(room.state.active_player, 0);
+        assert!(room.turn_deadline() <= Some(Instant::now() + DEFAULT_RECONNECT_GRACE));
+
+        let heard = received(&mut bob_rx);
+        assert!(heard.contains(&ServerMessage::HostChanged(1)));
+        assert!(heard.contains(&ServerMessage::WaitingForReconnect {
+            player: 0,
+            wait_secs: DEFAULT_RECONNECT_GRACE.as_secs(),
+        }));
+
+        room.time_out().await;
+        assert_eq!(room.state.active_player, 1);
+        assert!(received(&mut bob_rx).contains(&ServerMessage::StartTurn(2)));
+    }
+
+    #[tokio::test]
+    async fn reconnecting_in_time_keeps_the_turn() {
+        let (mut room, _alice_rx, _bob_rx) = two_player_room().await;
+
+        room.on_message(addr(1), ClientMessage::Close)
+            .await
+            .unwrap();
+        assert!(room.reconnect_deadline.is_some());
+
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        room.add_player(addr(3), "alice", false, None, alice)
+            .await
+            .unwrap();
+
+        assert_eq!(room.state.active_player, 0);
+        assert_eq!(room.reconnect_deadline, None);
+        assert!(received(&mut alice_rx).contains(&ServerMessage::StartTurn(1)));
+    }
+
+    #[tokio::test]
+    async fn messages_are_only_taken_once_per_seat() {
+        let (mut room, _alice_rx, _bob_rx) = two_player_room().await;
+
+        assert!(room.take_seq(addr(1), 1));
+        assert!(room.take_seq(addr(1), 3));
+        assert!(!room.take_seq(addr(1), 3));
+        assert!(!room.take_seq(addr(1), 2));
+        assert!(room.take_seq(addr(2), 1));
+
+        // The seat keeps counting from a new connection, and says where from:
+        room.on_message(addr(1), ClientMessage::Close)
+            .await
+            .unwrap();
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        room.add_player(addr(3), "alice", false, None, alice)
+            .await
+            .unwrap();
+
+        assert!(received(&mut alice_rx)
+            .iter()
+            .any(|m| matches!(m, ServerMessage::JoinedRoom { last_seq: 3, .. })));
+        assert!(!room.take_seq(addr(3), 3));
+        assert!(room.take_seq(addr(3), 4));
+    }
+
+    #[tokio::test]
+    async fn accounts_take_back_their_seat_and_hosting() {
+        let mut room = Room::new(RoomConfig::default());
+        let (alice, _alice_rx) = mpsc::channel(64);
+        let (bob, _bob_rx) = mpsc::channel(64);
+        room.add_player(addr(1), "alice", true, None, alice)
+            .await
+            .unwrap();
+        room.add_player(addr(2), "bob", false, None, bob)
+            .await
+            .unwrap();
+
+        room.on_message(addr(1), ClientMessage::Close)
+            .await
+            .unwrap();
+        assert_eq!(room.host, 1);
+
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        room.add_player(addr(3), "alice", true, None, alice)
+            .await
+            .unwrap();
+        assert_eq!(room.host, 0);
+        assert!(received(&mut alice_rx).contains(&ServerMessage::HostChanged(0)));
+
+        // Logging in somewhere else takes over without waiting for the old
+        // connection to go:
+        let (again, _again_rx) = mpsc::channel(64);
+        room.add_player(addr(4), "alice", true, None, again)
+            .await
+            .unwrap();
+        assert_eq!(room.players.len(), 2);
+        assert!(room.on_message(addr(3), ClientMessage::Ping).await.is_err());
+        assert!(room.on_message(addr(4), ClientMessage::Ping).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn everyone_failing_ends_the_room() {
+        let (mut room, alice_rx, bob_rx) = two_player_room().await;
+
+        drop(alice_rx);
+        drop(bob_rx);
+        room.broadcast(ServerMessage::Pong).await;
+
+        assert!(!room.drop_failed_senders().await);
+    }
+
+    #[tokio::test]
+    async fn dropped_connection_is_refused() {
+        let (mut room, mut alice_rx, _bob_rx) = two_player_room().await;
+
+        assert!(room.drop_connection(addr(2)).await);
+        assert!(!room.players[1].connected);
+        assert!(received(&mut alice_rx).contains(&ServerMessage::PlayerDisconnected(1)));
+
+        assert!(room.on_message(addr(2), ClientMessage::Ping).await.is_err());
+        assert!(room.on_message(addr(1), ClientMessage::Ping).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn unknown_connection_is_an_error() {
+        let mut room = Room::new(RoomConfig::default());
+
+        assert!(room.on_message(addr(1), ClientMessage::Ping).await.is_err());
+        assert!(room.drop_connection(addr(1)).await);
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_refused() {
+        let mut room = Room::new(RoomConfig::default());
+        room.password = Some("hunter2".to_string());
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        let (bob, _bob_rx) = mpsc::channel(64);
+
+        assert!(room
+            .add_player(addr(1), "alice", false, None, alice)
+            .await
+            .is_err());
+        assert_eq!(
+            received(&mut alice_rx),
+            vec![ServerMessage::Error(ServerError::WrongPassword)]
+        );
+        assert!(room.players.is_empty());
+
+        room.add_player(addr(2), "bob", false, Some("hunter2"), bob)
+            .await
+            .unwrap();
+        assert_eq!(room.players.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn players_who_keep_running_out_of_time_are_skipped_until_they_act() {
+        let (mut room, _alice_rx, mut bob_rx) = two_player_room().await;
+        assert!(room.turn_deadline().is_some());
+
+        for _ in 0..AWAY_AFTER {
+            room.time_out().await;
+            assert_eq!(room.state.active_player, 1);
+            room.on_message(addr(2), ClientMessage::EndTurn)
+                .await
+                .unwrap();
+        }
+
+        // Alice's turn went straight back to Bob:
+        assert!(room.players[0].away);
+        assert_eq!(room.state.active_player, 1);
+        assert!(received(&mut bob_rx).contains(&ServerMessage::PlayerAway(0)));
+
+        room.on_message(addr(1), ClientMessage::VoteSkip)
+            .await
+            .unwrap();
+        assert!(!room.players[0].away);
+        assert!(received(&mut bob_rx).contains(&ServerMessage::PlayerBack(0)));
+
+        room.on_message(addr(2), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        assert_eq!(room.state.active_player, 0);
+    }
+
+    #[tokio::test]
+    async fn players_can_step_away_in_the_middle_of_their_turn() {
+        let (mut room, _alice_rx, mut bob_rx) = two_player_room().await;
+        assert_eq!(room.state.active_player, 0);
+        received(&mut bob_rx);
+
+        room.on_message(addr(1), ClientMessage::SetAway(true))
+            .await
+            .unwrap();
+        assert!(room.players[0].away);
+        assert_eq!(room.state.active_player, 1);
+        assert_eq!(room.state.hands[0].len(), 15);
+        let heard = received(&mut bob_rx);
+        assert!(heard.contains(&ServerMessage::PlayerAway(0)));
+        assert!(heard.contains(&ServerMessage::TurnSkipped(0)));
+
+        // Saying so again changes nothing:
+        room.on_message(addr(1), ClientMessage::SetAway(true))
+            .await
+            .unwrap();
+        assert!(received(&mut bob_rx).is_empty());
+
+        room.on_message(addr(1), ClientMessage::SetAway(false))
+            .await
+            .unwrap();
+        assert!(!room.players[0].away);
+        assert_eq!(received(&mut bob_rx), vec![ServerMessage::PlayerBack(0)]);
+    }
+
+    #[tokio::test]
+    async fn turns_count_towards_their_players_clocks() {
+        let (mut room, _alice_rx, mut bob_rx) = two_player_room().await;
+        received(&mut bob_rx);
+
+        room.turn_started = Instant::now() - Duration::from_secs(5);
+        room.on_message(addr(1), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+
+        let thinking_ms = received(&mut bob_rx)
+            .into_iter()
+            .find_map(|msg| match msg {
+                ServerMessage::TurnFinished { thinking_ms, .. } => Some(thinking_ms),
+                _ => None,
+            })
+            .unwrap();
+        assert!(thinking_ms[0] >= 5_000);
+        assert_eq!(thinking_ms[1], 0);
+
+        // A rematch starts everyone's clocks again:
+        room.restart().await;
+        assert_eq!(room.thinking_ms(), vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn only_rooms_that_show_the_pool_count_it() {
+        let (mut room, _alice_rx, mut bob_rx) = two_player_room().await;
+        room.options.show_pool = true;
+        received(&mut bob_rx);
+
+        room.on_message(addr(1), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        let pool = received(&mut bob_rx)
+            .into_iter()
+            .find_map(|msg| match msg {
+                ServerMessage::TurnFinished { pool, .. } => pool,
+                _ => None,
+            })
+            .unwrap();
+        let counted: usize = pool.iter().map(|(_, count)| count).sum();
+        assert_eq!(counted, room.state.game.remaining_pieces().len());
+
+        room.options.show_pool = false;
+        assert_eq!(room.snapshot(1).pool, None);
+    }
+
+    #[tokio::test]
+    async fn vertical_groups_last_through_rematches_and_records() {
+        let room = Room::new(RoomConfig {
+            options: RoomOptions {
+                vertical_groups: true,
+                ..RoomOptions::default()
+            },
+            ..RoomConfig::default()
+        });
+        assert!(room.state.vertical_groups);
+        assert!(room.record().vertical_groups);
+
+        let (mut room, _alice_rx, _bob_rx) = two_player_room().await;
+        room.options.vertical_groups = true;
+        room.restart().await;
+        assert!(room.state.vertical_groups);
+    }
+
+    #[tokio::test]
+    async fn pieces_off_the_board_are_rejected_and_said_to_be() {
+        let (mut room, mut alice_rx, _bob_rx) = two_player_room().await;
+        room.state.board_size = BoardSize { cols: 4, rows: 2 };
+        assert_eq!(room.record().board_size, room.state.board_size);
+        assert_eq!(room.snapshot(0).board_size, room.state.board_size);
+        received(&mut alice_rx);
+
+        let off = Move::Place(Coord(4, 0), room.state.hands[0][0]);
+        room.on_message(addr(1), ClientMessage::Moves(vec![off]))
+            .await
+            .unwrap();
+        let msgs = received(&mut alice_rx);
+        assert!(matches!(msgs[0], ServerMessage::MovesRejected(_, _)));
+        assert_eq!(msgs[1], ServerMessage::Error(ServerError::OffBoard(off)));
+        assert!(room.state.game.board().is_empty());
+    }
+
+    #[tokio::test]
+    async fn nobody_wins_before_the_deal() {
+        let mut room = Room::new(RoomConfig::default());
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        room.add_player(addr(1), "alice", false, None, alice)
+            .await
+            .unwrap();
+        assert!(room.state.hands[0].is_empty());
+        assert_eq!(room.snapshot(0).phase, Phase::Waiting);
+        received(&mut alice_rx);
+
+        room.on_message(addr(1), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        assert!(!received(&mut alice_rx)
+            .iter()
+            .any(|msg| matches!(msg, ServerMessage::PlayerWon { .. })));
+        assert_eq!(room.state.winner, None);
+    }
+
+    #[tokio::test]
+    async fn nudges_are_once_a_turn() {
+        let (mut room, mut alice_rx, _bob_rx) = two_player_room().await;
+        received(&mut alice_rx);
+
+        for _ in 0..2 {
+            room.on_message(addr(2), ClientMessage::Nudge)
+                .await
+                .unwrap();
+        }
+        assert_eq!(received(&mut alice_rx), vec![ServerMessage::Nudged(1)]);
+
+        // Nudging back on her own turn isn't allowed, but Bob can again on
+        // his next:
+        room.on_message(addr(1), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        room.on_message(addr(1), ClientMessage::Nudge)
+            .await
+            .unwrap();
+        room.on_message(addr(2), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        received(&mut alice_rx);
+
+        room.on_message(addr(2), ClientMessage::Nudge)
+            .await
+            .unwrap();
+        assert_eq!(received(&mut alice_rx), vec![ServerMessage::Nudged(1)]);
+    }
+
+    #[tokio::test]
+    async fn turns_dont_run_out_waiting_for_players() {
+        let mut room = Room::new(RoomConfig::default());
+        let (alice, _alice_rx) = mpsc::channel(64);
+        room.add_player(addr(1), "alice", false, None, alice)
+            .await
+            .unwrap();
+
+        assert_eq!(room.turn_deadline(), None);
+    }
+
+    #[tokio::test]
+    async fn players_out_of_step_get_the_whole_game() {
+        let (mut room, mut alice_rx, mut bob_rx) = two_player_room().await;
+        received(&mut alice_rx);
+        received(&mut bob_rx);
+
+        room.on_message(addr(2), ClientMessage::RequestSync)
+            .await
+            .unwrap();
+        match received(&mut bob_rx).as_slice() {
+            [ServerMessage::FullSync(sync)] => {
+                assert_eq!(sync.view.hand(), &room.state.hands[1][..]);
+                assert_eq!(sync.active_player, 0);
+                assert_eq!(sync.phase, Phase::Playing);
+                assert_eq!(state_hash(&sync.board, &sync.hand_sizes), room.state_hash());
+            }
+            sent => panic!("expected FullSync, got {:?}", sent),
+        }
+
+        room.check_state().await;
+        let check = ServerMessage::StateCheck(room.state_hash());
+        assert_eq!(received(&mut alice_rx), vec![check]);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_dealt_until_there_is_someone_to_play() {
+        let mut room = Room::new(RoomConfig::default());
+        let (alice, mut alice_rx) = mpsc::channel(64);
+        room.add_player(addr(1), "alice", false, None, alice)
+            .await
+            .unwrap();
+        assert!(room.state.hands[0].is_empty());
+        assert!(room.state.game.remaining_pieces().is_empty());
+        assert!(room.log.is_empty());
+        received(&mut alice_rx);
+
+        let (bob, _bob_rx) = mpsc::channel(64);
+        room.add_player(addr(2), "bob", false, None, bob)
+            .await
+            .unwrap();
+        assert_eq!(room.hand_sizes(), vec![HAND_SIZE, HAND_SIZE]);
+        let dealt = received(&mut alice_rx).into_iter().any(|msg| match msg {
+            ServerMessage::JoinedRoom { snapshot, .. } => {
+                snapshot.view.into_hand() == room.state.hands[0]
+            }
+            _ => false,
+        });
+        assert!(dealt);
+
+        // A rematch takes every piece back before dealing again:
+        let piece = room.state.hands[0][0];
+        room.on_message(addr(1), ClientMessage::Place(Coord(0, 0), piece))
+            .await
+            .unwrap();
+        room.restart().await;
+        assert!(room.state.game.board().is_empty());
+        assert_eq!(room.hand_sizes(), vec![HAND_SIZE, HAND_SIZE]);
+        assert_eq!(
+            room.state.game.remaining_pieces().len() + 2 * HAND_SIZE,
+            Game::create_pieces().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn seeded_rooms_deal_the_same_game() {
+        let config = RoomConfig {
+            seed: Some(12345),
+            ..RoomConfig::default()
+        };
+        let mut first = Room::new(config.clone());
+        let mut second = Room::new(config);
+
+        let mut receivers = Vec::new();
+        for room in [&mut first, &mut second].iter_mut() {
+            for (port, name) in [(1, "alice"), (2, "bob")].iter() {
+                let (sender, receiver) = mpsc::channel(64);
+                room.add_player(addr(*port), name, false, None, sender)
+                    .await
+                    .unwrap();
+                receivers.push(receiver);
+            }
+        }
+
+        assert_eq!(first.seed(), 12345);
+        assert_eq!(first.state, second.state);
+    }
+
+    #[tokio::test]
+    async fn the_log_replays_the_game() {
+        let (mut room, _alice_rx, _bob_rx) = two_player_room().await;
+
+        let piece = room.state.hands[0][0];
+        room.on_message(addr(1), ClientMessage::Place(Coord(0, 0), piece))
+            .await
+            .unwrap();
+        room.time_out().await;
+        room.on_message(addr(2), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+
+        let record = room.record();
+        assert_eq!(record.players, vec!["alice", "bob"]);
+
+        let mut replay = record.replay();
+        assert_eq!(replay.by_ref().count(), record.events.len());
+        assert_eq!(replay.state(), &room.state);
+        assert!(room
+            .log
+            .iter()
+            .any(|timed| timed.event == GameEvent::RolledBack));
+
+        let times: Vec<u64> = room.log.iter().map(|timed| timed.at_ms).collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Each turn's events are kept under the number its players saw. The
+        // first is started twice, once again to hand it to Alice:
+        let turns: Vec<u64> = room
+            .log
+            .iter()
+            .filter(|timed| matches!(timed.event, GameEvent::TurnStarted(_)))
+            .map(|timed| timed.turn)
+            .collect();
+        assert_eq!(turns, vec![1, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn rooms_freeze_when_their_pieces_dont_add_up() {
+        let (mut room, _alice_rx, _bob_rx) = two_player_room().await;
+        room.piece_checks = PieceChecks::Freeze;
+
+        room.on_message(addr(1), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        room.check_pieces();
+        assert!(!room.frozen);
+
+        // Bob ends up with a copy of a piece Alice still has:
+        let piece = room.state.hands[0][0];
+        room.state.hands[1].push(piece);
+        room.check_pieces();
+        assert!(room.frozen);
+        assert_eq!(room.turn_deadline(), None);
+
+        let before = room.state.clone();
+        room.on_message(addr(2), ClientMessage::Place(Coord(0, 0), piece))
+            .await
+            .unwrap();
+        room.on_message(addr(2), ClientMessage::EndTurn)
+            .await
+            .unwrap();
+        assert_eq!(room.state, before);
+    }
+}
@@ -0,0 +1,48 @@
+//! Room codes, short enough to read out loud and random enough not to be
+//! guessed.
+
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Crockford's base32, lowercased. It has no i, l, o or u, so codes can't be
+/// misread or spell much.
+const ALPHABET: &[u8] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// Six characters give a little over a billion codes.
+pub const DEFAULT_LEN: usize = 6;
+
+/// Four give about a million, any fewer and they'd run out, and be guessed.
+pub const MIN_LEN: usize = 4;
+
+static LEN: AtomicUsize = AtomicUsize::new(DEFAULT_LEN);
+
+/// Sets how long new codes are, at least [`MIN_LEN`]. Existing rooms keep
+/// theirs.
+pub fn set_len(len: usize) {
+    LEN.store(len.max(MIN_LEN), Ordering::Relaxed);
+}
+
+/// A new random code, drawn from the operating system's RNG.
+pub fn generate() -> String {
+    let mut rng = OsRng;
+
+    (0..LEN.load(Ordering::Relaxed))
+        .map(|_| *ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect()
+}
+
+/// Reads a code the way someone might have typed it: any case, with the
+/// letters Crockford leaves out read as the digits they look like.
+pub fn normalize(code: &str) -> String {
+    code.trim()
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| match c.to_ascii_lowercase() {
+            'i' | 'l' => '1',
+            'o' => '0',
+            c => c,
+        })
+        .collect()
+}
@@ -0,0 +1,165 @@
+//! Spreading rooms over several servers, for when one isn't enough. Every
+//! room code belongs to one of them, by its hash, so each room only ever runs
+//! in one place and the servers don't have to share anything. A front server
+//! runs no rooms and sends every connection on to the server its room
+//! belongs to, see `ServerMessage::Redirect`. The others only make codes
+//! that are theirs, and send on anyone who asks them for a room that isn't.
+//!
+//! Accounts, stats and saved games are each server's own.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, bail};
+use rkub_common::ClientMessage;
+
+use crate::room_id;
+
+#[derive(Debug)]
+pub struct Shards {
+    /// Every server's websocket URL, in the order they all agree on.
+    nodes: Vec<String>,
+    /// Which of them this is, or None for the front server.
+    this: Option<usize>,
+    /// Which server the front sends the next new room to.
+    next: AtomicUsize,
+}
+
+impl Shards {
+    /// Rooms spread over `nodes`, where this server is `nodes[this]`, or
+    /// the front server if `this` is None.
+    pub fn new(nodes: Vec<String>, this: Option<usize>) -> anyhow::Result<Self> {
+        if nodes.is_empty() {
+            bail!("there are no servers to spread rooms over");
+        }
+        if let Some(this) = this.filter(|&this| this >= nodes.len()) {
+            bail!("there's no server {} of {}", this, nodes.len());
+        }
+
+        Ok(Shards {
+            nodes,
+            this,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// The servers in `RKUB_SHARDS`, a comma separated list of websocket
+    /// URLs, with this one at index `RKUB_SHARD`, or the front server if
+    /// that isn't set. None if rooms aren't spread out.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let nodes = match std::env::var("RKUB_SHARDS") {
+            Ok(nodes) => nodes.split(',').map(|url| url.trim().to_string()).collect(),
+            Err(_) => return Ok(None),
+        };
+        let this = match std::env::var("RKUB_SHARD") {
+            Ok(this) => Some(this.parse().map_err(|_| anyhow!("bad RKUB_SHARD"))?),
+            Err(_) => None,
+        };
+
+        Shards::new(nodes, this).map(Some)
+    }
+
+    pub fn is_front(&self) -> bool {
+        self.this.is_none()
+    }
+
+    /// Which server the room `code` runs on. FNV-1a, so it's the same on
+    /// every server whatever it was built with.
+    pub fn owner(&self, code: &str) -> usize {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let hash = code.bytes().fold(OFFSET, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        });
+        (hash % self.nodes.len() as u64) as usize
+    }
+
+    /// Whether this server can run a room called `code`. The front server
+    /// never asks, since it sends every new room on.
+    pub fn owns(&self, code: &str) -> bool {
+        match self.this {
+            Some(this) => self.owner(code) == this,
+            None => true,
+        }
+    }
+
+    /// The websocket URL `msg` should go to instead of this server, if it
+    /// should. New rooms are handed out to each server in turn, and ranked
+    /// players all wait in the one line, on the first server.
+    pub fn redirect(&self, msg: &ClientMessage) -> Option<&str> {
+        let node = match msg {
+            ClientMessage::CreateRoom(..) if self.is_front() => {
+                self.next.fetch_add(1, Ordering::Relaxed) % self.nodes.len()
+            }
+            ClientMessage::JoinRoom(_, room, _) => self.owner(&room_id::normalize(room)),
+            ClientMessage::QueueRanked => 0,
+            _ => return None,
+        };
+
+        if Some(node) == self.this {
+            return None;
+        }
+        Some(&self.nodes[node])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::RoomOptions;
+
+    fn nodes() -> Vec<String> {
+        vec!["ws://a/ws".to_string(), "ws://b/ws".to_string()]
+    }
+
+    fn join(room: &str) -> ClientMessage {
+        ClientMessage::JoinRoom("alice".to_string(), room.to_string(), None)
+    }
+
+    #[test]
+    fn every_room_has_one_owner() {
+        let front = Shards::new(nodes(), None).unwrap();
+        let servers: Vec<_> = (0..2)
+            .map(|i| Shards::new(nodes(), Some(i)).unwrap())
+            .collect();
+
+        for code in &["k3x9qa", "000000", "zzzzzz", "7hb2mn"] {
+            let owner = front.owner(code);
+            assert!(servers[owner].owns(code));
+            assert!(!servers[1 - owner].owns(code));
+
+            // Everyone sends it the same way, whatever case it's typed in:
+            let url = nodes()[owner].clone();
+            assert_eq!(front.redirect(&join(code)), Some(url.as_str()));
+            assert_eq!(
+                servers[1 - owner].redirect(&join(&code.to_uppercase())),
+                Some(url.as_str())
+            );
+            assert_eq!(servers[owner].redirect(&join(code)), None);
+        }
+    }
+
+    #[test]
+    fn only_the_front_sends_new_rooms_on() {
+        let create = ClientMessage::CreateRoom("alice".to_string(), None, RoomOptions::default());
+        let front = Shards::new(nodes(), None).unwrap();
+        let server = Shards::new(nodes(), Some(1)).unwrap();
+
+        assert_eq!(front.redirect(&create), Some("ws://a/ws"));
+        assert_eq!(front.redirect(&create), Some("ws://b/ws"));
+        assert_eq!(server.redirect(&create), None);
+
+        assert_eq!(
+            server.redirect(&ClientMessage::QueueRanked),
+            Some("ws://a/ws")
+        );
+        assert_eq!(server.redirect(&ClientMessage::Ping), None);
+    }
+
+    #[test]
+    fn servers_have_to_be_in_the_list() {
+        assert!(Shards::new(nodes(), Some(2)).is_err());
+        assert!(Shards::new(Vec::new(), None).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Every account's record across games, by account name. Guests don't get
+//! any, since anyone could be playing under their name. Stats are only kept
+//! once the server is given somewhere to keep them, see [`open`].
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+use rkub_common::PlayerStats;
+
+use crate::rating;
+use crate::storage::{self, Files, Storage};
+
+static STATS: OnceLock<Stats> = OnceLock::new();
+
+/// Keeps stats in the file at `path`, starting from what's already there.
+/// Only the first call counts, of this or [`open_in`].
+pub fn open(path: &Path) -> io::Result<()> {
+    let (files, key) = Files::for_file(path)?;
+    open_in(Arc::new(files), &key)
+}
+
+/// Keeps stats under `key` in `store`, starting from what's already there.
+pub fn open_in(store: Arc<dyn Storage>, key: &str) -> io::Result<()> {
+    let stats = Stats::load(store, key)?;
+    let _ = STATS.set(stats);
+
+    Ok(())
+}
+
+/// The server's stats, if it keeps them.
+pub fn get() -> Option<&'static Stats> {
+    STATS.get()
+}
+
+/// `name`'s stats, all zeroes and a starting rating if nobody's keeping
+/// them.
+pub fn lookup(name: &str) -> PlayerStats {
+    get().map(|stats| stats.player(name)).unwrap_or_default()
+}
+
+pub struct Stats {
+    store: Arc<dyn Storage>,
+    key: String,
+    players: Mutex<BTreeMap<String, PlayerStats>>,
+    /// Rooms save one at a time, so an older save can't land on top of a
+    /// newer one.
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl Stats {
+    /// Reads the stats saved under `key` in `store`. Nothing saved there
+    /// yet is no stats at all.
+    pub fn load(store: Arc<dyn Storage>, key: &str) -> io::Result<Self> {
+        let players = match store.get(key)? {
+            Some(blob) => storage::decode(&blob)?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(Stats {
+            store,
+            key: key.to_string(),
+            players: Mutex::new(players),
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn player(&self, name: &str) -> PlayerStats {
+        self.players().get(name).copied().unwrap_or_default()
+    }
+
+    /// `name` ended a turn that took `took`, having put down `tiles`.
+    pub fn record_turn(&self, name: &str, took: Duration, tiles: usize) {
+        let mut players = self.players();
+        let stats = players.entry(name.to_string()).or_default();
+
+        stats.turns += 1;
+        stats.turn_millis += took.as_millis() as u64;
+        stats.tiles_placed += tiles as u32;
+    }
+
+    /// A game between `names` was won by `winner`.
+    pub fn record_game<'a>(&self, names: impl IntoIterator<Item = &'a str>, winner: &str) {
+        let mut players = self.players();
+
+        for name in names {
+            let stats = players.entry(name.to_string()).or_default();
+            stats.games_played += 1;
+            if name == winner {
+                stats.wins += 1;
+            }
+        }
+    }
+
+    /// A ranked game between `names` ended with their `scores`, in the same
+    /// order. Moves everyone's rating, see [`rating::update`].
+    pub fn record_ranked(&self, names: &[&str], scores: &[i64]) {
+        let mut players = self.players();
+
+        let ratings: Vec<i32> = names
+            .iter()
+            .map(|&name| players.get(name).copied().unwrap_or_default().rating)
+            .collect();
+
+        for (&name, rating) in names.iter().zip(rating::update(&ratings, scores)) {
+            let stats = players.entry(name.to_string()).or_default();
+            stats.rating = rating;
+            stats.ranked_games += 1;
+        }
+    }
+
+    /// Writes everything out, in place of the last save.
+    pub async fn save(&self) -> io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let blob = storage::encode(&*self.players())?;
+
+        storage::put(&self.store, &self.key, blob).await
+    }
+
+    fn players(&self) -> MutexGuard<'_, BTreeMap<String, PlayerStats>> {
+        self.players.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::INITIAL_RATING;
+
+    use crate::storage::Memory;
+
+    #[tokio::test]
+    async fn stats_survive_a_restart() {
+        let store: Arc<dyn Storage> = Arc::new(Memory::default());
+        let stats = Stats::load(store.clone(), "stats").unwrap();
+
+        stats.record_turn("alice", Duration::from_secs(3), 2);
+        stats.record_turn("alice", Duration::from_secs(5), 0);
+        stats.record_game(vec!["alice", "bob"], "alice");
+        stats.save().await.unwrap();
+
+        let reloaded = Stats::load(store, "stats").unwrap();
+
+        let alice = reloaded.player("alice");
+        assert_eq!((alice.games_played, alice.wins, alice.turns), (1, 1, 2));
+        assert_eq!(alice.avg_turn_secs(), 4.0);
+        assert_eq!(alice.avg_tiles_per_turn(), 1.0);
+
+        let bob = reloaded.player("bob");
+        assert_eq!((bob.games_played, bob.wins, bob.turns), (1, 0, 0));
+        assert_eq!(reloaded.player("carol"), PlayerStats::default());
+    }
+
+    #[test]
+    fn ranked_games_move_ratings() {
+        let stats = Stats::load(Arc::new(Memory::default()), "stats").unwrap();
+
+        stats.record_ranked(&["alice", "bob"], &[0, -20]);
+
+        let (alice, bob) = (stats.player("alice"), stats.player("bob"));
+        assert!(alice.rating > INITIAL_RATING && bob.rating < INITIAL_RATING);
+        assert_eq!((alice.ranked_games, alice.games_played), (1, 0));
+    }
+}
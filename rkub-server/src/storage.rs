@@ -0,0 +1,271 @@
+//! Where what the server keeps is kept. Accounts, stats and finished games
+//! are each blobs under a key in a [`Storage`], so none of them care whether
+//! that's a directory of files, a sled database or only memory.
+//!
+//! Blobs that aren't meant to be read by anything else are wrapped with the
+//! [`VERSION`] they were written at, see [`encode`], so a server can tell
+//! what it's reading and bring older ones up to date as it loads them, see
+//! [`decode`]. Finished games are left as they are, since they're shared as
+//! files and carry their own `format`.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The version [`encode`] writes blobs at. Goes up whenever the shape of
+/// something stored changes, along with a step in [`migrate`] from the one
+/// before.
+pub const VERSION: u32 = 1;
+
+/// Blobs by key. Putting is all or nothing, so a crash halfway through
+/// leaves what was there before.
+pub trait Storage: Send + Sync {
+    /// The blob under `key`, or None if nothing's been put there.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Puts `blob` under `key`, in place of whatever was there.
+    fn put(&self, key: &str, blob: &[u8]) -> io::Result<()>;
+}
+
+/// [`Storage::get`], off the async threads.
+pub async fn get(store: &Arc<dyn Storage>, key: &str) -> io::Result<Option<Vec<u8>>> {
+    let (store, key) = (store.clone(), key.to_string());
+    tokio::task::spawn_blocking(move || store.get(&key)).await?
+}
+
+/// [`Storage::put`], off the async threads.
+pub async fn put(store: &Arc<dyn Storage>, key: &str, blob: Vec<u8>) -> io::Result<()> {
+    let (store, key) = (store.clone(), key.to_string());
+    tokio::task::spawn_blocking(move || store.put(&key, &blob)).await?
+}
+
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+/// `value` as a blob, marked with the [`VERSION`] it was written at.
+pub fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    let versioned = Versioned {
+        version: VERSION,
+        data: value,
+    };
+    Ok(serde_json::to_vec_pretty(&versioned)?)
+}
+
+/// Reads a blob [`encode`] wrote, at any version up to this build's.
+pub fn decode<T: DeserializeOwned>(blob: &[u8]) -> io::Result<T> {
+    let json: Value = serde_json::from_slice(blob)?;
+    let Versioned { version, data } = match json {
+        Value::Object(ref map)
+            if map.len() == 2 && map.get("version").is_some_and(Value::is_u64) =>
+        {
+            serde_json::from_value(json)?
+        }
+        // Blobs used to be written as they were, before they had versions:
+        data => Versioned { version: 0, data },
+    };
+
+    Ok(serde_json::from_value(migrate(version, data)?)?)
+}
+
+/// Brings a blob written at `version` up to [`VERSION`], one version at a
+/// time.
+fn migrate(version: u32, data: Value) -> io::Result<Value> {
+    if version > VERSION {
+        let msg = format!("written at version {}, newer than this server", version);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+
+    // Version 0 is version 1 without the wrapping, so there's nothing to do
+    // yet. Later versions go here, as `if version < 2 { ... }` and so on.
+    Ok(data)
+}
+
+/// A directory, with each blob in a file named after its key. The files
+/// are JSON whenever the blobs are, so they can be read and backed up like
+/// any other.
+pub struct Files {
+    dir: PathBuf,
+}
+
+impl Files {
+    /// Keeps blobs in `dir`, making it if it isn't there.
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Files { dir })
+    }
+
+    /// The directory and key that keep a blob at `path`, a file named after
+    /// its key.
+    pub fn for_file(path: &Path) -> io::Result<(Self, String)> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let key = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+
+        let files = Files::new(dir)?;
+        if files.path(key).is_none() {
+            let msg = format!("{} can't be kept in a file of that name", key);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        Ok((files, key.to_string()))
+    }
+
+    /// Where `key` is kept. Keys can come from players, so anything that
+    /// could reach outside the directory isn't one.
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let valid = !key.is_empty()
+            && !key.starts_with('.')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+
+        valid.then(|| self.dir.join(format!("{}.json", key)))
+    }
+}
+
+impl Storage for Files {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let path = match self.path(key) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        match std::fs::read(path) {
+            Ok(blob) => Ok(Some(blob)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, key: &str, blob: &[u8]) -> io::Result<()> {
+        let path = self
+            .path(key)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a key"))?;
+
+        // Written to the side and renamed over, so it's replaced in one go:
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, blob)?;
+        std::fs::rename(&partial, &path)
+    }
+}
+
+/// Nothing but memory, gone with the server. For tests, and for trying
+/// things out.
+#[derive(Default)]
+pub struct Memory {
+    blobs: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Storage for Memory {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let blobs = self.blobs.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(blobs.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, blob: &[u8]) -> io::Result<()> {
+        let mut blobs = self.blobs.lock().unwrap_or_else(PoisonError::into_inner);
+        blobs.insert(key.to_string(), blob.to_vec());
+        Ok(())
+    }
+}
+
+/// One tree of a sled database. Everything can share the one database, in
+/// trees of their own.
+#[cfg(feature = "sled-storage")]
+pub struct Sled {
+    tree: sled::Tree,
+}
+
+#[cfg(feature = "sled-storage")]
+impl Sled {
+    /// The tree `name` in `db`, made if it isn't there.
+    pub fn open(db: &sled::Db, name: &str) -> io::Result<Self> {
+        Ok(Sled {
+            tree: db.open_tree(name)?,
+        })
+    }
+}
+
+#[cfg(feature = "sled-storage")]
+impl Storage for Sled {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?.map(|blob| blob.to_vec()))
+    }
+
+    fn put(&self, key: &str, blob: &[u8]) -> io::Result<()> {
+        self.tree.insert(key, blob)?;
+        // Only as good as a file until it's on disk:
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_keep_what_was_put() {
+        let dir = std::env::temp_dir().join(format!("rkub-storage-{}", std::process::id()));
+        let files = Files::new(dir.clone()).unwrap();
+
+        assert_eq!(files.get("stats").unwrap(), None);
+        files.put("stats", b"{}").unwrap();
+        files.put("stats", b"[]").unwrap();
+        assert_eq!(files.get("stats").unwrap(), Some(b"[]".to_vec()));
+        assert_eq!(std::fs::read(dir.join("stats.json")).unwrap(), b"[]");
+
+        assert!(files.put("../stats", b"{}").is_err());
+        assert_eq!(files.get("../stats").unwrap(), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "sled-storage")]
+    #[test]
+    fn sled_keeps_what_was_put() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let stats = Sled::open(&db, "stats").unwrap();
+
+        assert_eq!(stats.get("stats").unwrap(), None);
+        stats.put("stats", b"{}").unwrap();
+        assert_eq!(stats.get("stats").unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(
+            Sled::open(&db, "games").unwrap().get("stats").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn blobs_from_before_versions_still_load() {
+        let mut map = BTreeMap::new();
+        map.insert("alice".to_string(), 3);
+
+        let blob = encode(&map).unwrap();
+        let json: Value = serde_json::from_slice(&blob).unwrap();
+        assert_eq!(json["version"], VERSION);
+        assert_eq!(decode::<BTreeMap<String, u32>>(&blob).unwrap(), map);
+
+        // As it would have been saved before:
+        let old = br#"{ "alice": 3 }"#;
+        assert_eq!(decode::<BTreeMap<String, u32>>(old).unwrap(), map);
+    }
+
+    #[test]
+    fn blobs_from_newer_servers_are_refused() {
+        let blob = br#"{ "version": 99, "data": {} }"#;
+        assert!(decode::<BTreeMap<String, u32>>(blob).is_err());
+    }
+}
@@ -0,0 +1,471 @@
+//! Keeps track of every room's actor, restarts any that crash from their last
+//! snapshot, and stops them all when the server shuts down.
+
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use rkub_common::{ClientMessage, Notification, ServerError};
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::limits::{Limiter, LoadLimits, RoomLimits};
+use crate::load::{self, Load};
+use crate::matchmaking;
+use crate::queue;
+use crate::room::{run_room, Room, RoomConfig, RoomEvent, Snapshot};
+use crate::room_id;
+use crate::shards::Shards;
+use crate::webhooks;
+
+/// How many times one room's actor is restarted before the room is given up
+/// on.
+const MAX_RESTARTS: usize = 3;
+
+/// How long rooms get to tell their players the server is stopping.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// How many codes a new room tries before the server counts itself full.
+/// Plenty, unless codes are short or spread over many servers.
+const ID_ATTEMPTS: usize = 100;
+
+/// Rooms are kept in a sharded map, so creating, finding and removing rooms
+/// only contend when they land in the same shard. Shard guards are never held
+/// across an await.
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    rooms: Arc<DashMap<String, RoomEntry>>,
+    /// Players waiting for a ranked room.
+    ranked: Arc<Mutex<matchmaking::Queue>>,
+    /// Who's created rooms lately, to hold them to the limits.
+    limiter: Arc<Mutex<Limiter>>,
+    /// How hard the server's working, as of the last measurement.
+    load: Arc<Load>,
+    load_limits: LoadLimits,
+    /// The other servers rooms are spread over, if they are.
+    shards: Option<Arc<Shards>>,
+}
+
+struct RoomEntry {
+    send: Sender<RoomEvent>,
+    task: JoinHandle<()>,
+    /// The room as of the last event its actor finished.
+    snapshot: Snapshot,
+}
+
+impl Supervisor {
+    /// A supervisor that only lets players create rooms within `limits`.
+    pub fn with_limits(limits: RoomLimits) -> Self {
+        Supervisor {
+            limiter: Arc::new(Mutex::new(Limiter::new(limits))),
+            ..Supervisor::default()
+        }
+    }
+
+    /// The same supervisor, turning players away once the server's load is
+    /// past `limits`. See [`Supervisor::watch_load`].
+    pub fn with_load_limits(self, limits: LoadLimits) -> Self {
+        Supervisor {
+            load_limits: limits,
+            ..self
+        }
+    }
+
+    /// The same supervisor, running only the rooms `shards` says are this
+    /// server's.
+    pub fn with_shards(self, shards: Shards) -> Self {
+        Supervisor {
+            shards: Some(Arc::new(shards)),
+            ..self
+        }
+    }
+
+    /// The websocket URL of the server `msg` should go to instead, if it's
+    /// about a room that isn't this one's.
+    pub fn redirect(&self, msg: &ClientMessage) -> Option<String> {
+        self.shards.as_ref()?.redirect(msg).map(String::from)
+    }
+
+    /// Whether the server can take on someone new, or how long they should
+    /// wait before trying again.
+    pub fn admit(&self) -> Result<(), ServerError> {
+        self.load.admit(self.rooms.len(), &self.load_limits)
+    }
+
+    /// Measures the load every so often, forever, for [`Supervisor::admit`]
+    /// to go by: how late the measurement itself wakes up, and how far
+    /// behind the busiest room is.
+    pub async fn watch_load(self) {
+        let mut busy = false;
+        loop {
+            let due = Instant::now() + load::SAMPLE_EVERY;
+            tokio::time::sleep_until(due).await;
+            let lag = Instant::now().saturating_duration_since(due);
+
+            let deepest_room = self
+                .rooms
+                .iter()
+                .map(|entry| queue::ROOM_QUEUE - entry.send.capacity())
+                .max()
+                .unwrap_or(0);
+
+            self.load.record(lag, deepest_room);
+
+            // Only changes are logged, there could be a lot of samples either
+            // way:
+            if busy != self.admit().is_err() {
+                busy = !busy;
+                let lag_ms = lag.as_millis() as u64;
+                let rooms = self.rooms.len();
+                if busy {
+                    warn!(lag_ms, deepest_room, rooms, "too busy to let anyone new in");
+                } else {
+                    info!(lag_ms, deepest_room, rooms, "letting players in again");
+                }
+            }
+        }
+    }
+
+    /// Starts a new room for a player connecting from `ip`, like
+    /// [`Supervisor::create_room`], as long as the limits and the load allow
+    /// it.
+    pub fn create_room_for(
+        &self,
+        ip: IpAddr,
+        config: RoomConfig,
+    ) -> Result<(String, Sender<RoomEvent>), ServerError> {
+        self.admit()?;
+        self.limiter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .allow(ip, self.rooms.len(), std::time::Instant::now())?;
+
+        self.create_room(config)
+    }
+
+    /// Starts a new room under an unused code, and one of this server's own
+    /// if rooms are spread over several, returning the code and the room's
+    /// queue. Only the code's shard is locked, and only to claim it. Gives
+    /// up with `ServerFull` if no code comes up free in [`ID_ATTEMPTS`]
+    /// tries.
+    pub fn create_room(
+        &self,
+        config: RoomConfig,
+    ) -> Result<(String, Sender<RoomEvent>), ServerError> {
+        let (send, recv) = mpsc::channel(queue::ROOM_QUEUE);
+
+        let mut claimed = None;
+        for _ in 0..ID_ATTEMPTS {
+            let new_id = room_id::generate();
+            if let Some(shards) = self.shards.as_ref().filter(|s| !s.owns(&new_id)) {
+                debug!(room = %new_id, owner = shards.owner(&new_id), "not ours");
+                continue;
+            }
+            match self.rooms.entry(new_id.clone()) {
+                Entry::Vacant(vacant) => {
+                    claimed = Some((new_id, vacant));
+                    break;
+                }
+                Entry::Occupied(_) => warn!(room = %new_id, "room id collision"),
+            }
+        }
+        let (new_id, vacant) = claimed.ok_or_else(|| {
+            warn!(rooms = self.rooms.len(), "no room codes left to give out");
+            ServerError::ServerFull
+        })?;
+
+        let ranked = !config.ranked.is_empty();
+        let private = config.password.is_some();
+        let mut room = Room::new(config);
+        room.name = new_id.clone();
+        // Everything the room logs, across restarts, is under its code and
+        // not whoever's connection happened to create it:
+        let span = info_span!(parent: None, "room", id = %new_id);
+        span.in_scope(|| info!(seed = room.seed(), "new room"));
+        webhooks::notify(Notification::RoomCreated {
+            room: new_id.clone(),
+            ranked,
+            private,
+        });
+
+        let snapshot = Arc::new(Mutex::new(room));
+        let task = tokio::spawn(
+            self.clone()
+                .supervise(new_id.clone(), snapshot.clone(), recv)
+                .instrument(span),
+        );
+        vacant.insert(RoomEntry {
+            send: send.clone(),
+            task,
+            snapshot,
+        });
+
+        Ok((new_id, send))
+    }
+
+    /// Puts the account `name` in line for a ranked room, returning where
+    /// the room will arrive. Dropping it leaves the line.
+    pub fn queue_ranked(&self, name: String, rating: i32) -> oneshot::Receiver<Sender<RoomEvent>> {
+        let mut ranked = self.ranked.lock().unwrap_or_else(PoisonError::into_inner);
+        ranked.join(name, rating, std::time::Instant::now())
+    }
+
+    /// Starts a ranked room for every group in line that's close enough in
+    /// rating, and sends each of them theirs.
+    pub fn matchmake(&self) {
+        loop {
+            let matched = {
+                let mut ranked = self.ranked.lock().unwrap_or_else(PoisonError::into_inner);
+                match ranked.take_match(std::time::Instant::now()) {
+                    Some(matched) => matched,
+                    None => return,
+                }
+            };
+
+            let made = self.create_room(RoomConfig {
+                ranked: matched.iter().map(|ticket| ticket.name.clone()).collect(),
+                ..RoomConfig::default()
+            });
+            // Their tickets are dropped, which tells them there's no room:
+            let (id, room) = match made {
+                Ok(made) => made,
+                Err(e) => {
+                    warn!(error = %e, "couldn't start a ranked room");
+                    return;
+                }
+            };
+            info!(room = %id, "matched a ranked room");
+
+            for ticket in matched {
+                // Whoever left in the meantime just forfeits their seat:
+                let name = ticket.name.clone();
+                if !ticket.send(room.clone()) {
+                    info!(room = %id, player = %name, "stopped waiting for their match");
+                }
+            }
+        }
+    }
+
+    /// The queue of the room called `id`, if it's still running.
+    pub fn find(&self, id: &str) -> Option<Sender<RoomEvent>> {
+        self.rooms.get(id).map(|entry| entry.send.clone())
+    }
+
+    /// `f` of the room called `id` as of the last event it finished, if it's
+    /// still running. Doesn't wait on the room, however stuck it is.
+    pub(crate) fn with_room<T>(&self, id: &str, f: impl FnOnce(&Room) -> T) -> Option<T> {
+        let snapshot = self.rooms.get(id)?.snapshot.clone();
+        let room = snapshot.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(f(&room))
+    }
+
+    /// `f` of every room, the same way as [`Supervisor::with_room`].
+    pub(crate) fn map_rooms<T>(&self, mut f: impl FnMut(&Room) -> T) -> Vec<T> {
+        let snapshots: Vec<Snapshot> = self.rooms.iter().map(|e| e.snapshot.clone()).collect();
+        snapshots
+            .iter()
+            .map(|snapshot| f(&snapshot.lock().unwrap_or_else(PoisonError::into_inner)))
+            .collect()
+    }
+
+    /// Sends every room `event`, made fresh for each.
+    pub(crate) async fn tell_all(&self, event: impl Fn() -> RoomEvent) {
+        let rooms: Vec<Sender<RoomEvent>> = self.rooms.iter().map(|e| e.send.clone()).collect();
+        for room in rooms {
+            let _ = room.send(event()).await;
+        }
+    }
+
+    /// How many rooms are running.
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    /// Tells every room the server is stopping, and waits a little while for
+    /// them to let their players know.
+    pub async fn shutdown(&self) {
+        // Collect the codes first, since removing while iterating would
+        // deadlock on the shard being iterated:
+        let ids: Vec<String> = self.rooms.iter().map(|e| e.key().clone()).collect();
+        let rooms: Vec<RoomEntry> = ids
+            .iter()
+            .filter_map(|id| self.rooms.remove(id))
+            .map(|(_, entry)| entry)
+            .collect();
+        info!(rooms = rooms.len(), "shutting down");
+
+        stop(rooms).await;
+    }
+
+    /// Stops the room called `id` whoever is in it, telling its players, and
+    /// forgets it. Returns false if there's no such room.
+    pub async fn close_room(&self, id: &str) -> bool {
+        match self.rooms.remove(id) {
+            Some((_, entry)) => {
+                info!(room = id, "closing room");
+                stop(vec![entry]).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the actor of the room called `name`, starting from `snapshot`,
+    /// until the room is over. If it panics, a new one takes over from the
+    /// last snapshot and everyone is sent the room again.
+    async fn supervise(self, name: String, snapshot: Snapshot, recv: mpsc::Receiver<RoomEvent>) {
+        let events = Arc::new(tokio::sync::Mutex::new(recv));
+        let mut restarts = 0;
+
+        loop {
+            let mut room = snapshot
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            if restarts > 0 {
+                room.resync().await;
+            }
+
+            let actor =
+                tokio::spawn(run_room(room, events.clone(), snapshot.clone()).in_current_span());
+
+            match actor.await {
+                Ok(()) => break,
+                Err(e) if e.is_panic() && restarts < MAX_RESTARTS => {
+                    restarts += 1;
+                    error!(
+                        restarts,
+                        "room crashed, restarting it from its last snapshot"
+                    );
+                }
+                Err(e) => {
+                    error!(error = %e, "giving up on room");
+                    break;
+                }
+            }
+        }
+
+        self.rooms.remove(&name);
+        info!("removed room");
+    }
+}
+
+/// Tells `rooms` to stop and waits a little while for them to let their
+/// players know, aborting any that take too long.
+async fn stop(rooms: Vec<RoomEntry>) {
+    let deadline = Instant::now() + SHUTDOWN_GRACE;
+    for entry in &rooms {
+        let _ = timeout_at(deadline, entry.send.send(RoomEvent::Shutdown)).await;
+    }
+
+    for mut entry in rooms {
+        if timeout_at(deadline, &mut entry.task).await.is_err() {
+            entry.task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::queue::Outgoing;
+    use rkub_common::{ServerError, ServerMessage};
+    use std::net::SocketAddr;
+    use tokio::sync::oneshot;
+
+    /// Joins `room` as `name`, returning what they're sent.
+    async fn join(room: &Sender<RoomEvent>, port: u16, name: &str) -> mpsc::Receiver<Outgoing> {
+        let (sender, outgoing) = mpsc::channel(64);
+        let (joined_tx, joined) = oneshot::channel();
+        room.send(RoomEvent::Join {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            name: name.to_string(),
+            account: false,
+            password: None,
+            sender,
+            joined: joined_tx,
+        })
+        .await
+        .unwrap();
+        assert!(joined.await.unwrap());
+
+        outgoing
+    }
+
+    /// The last thing sent before the connection was closed.
+    async fn last_message(mut outgoing: mpsc::Receiver<Outgoing>) -> Option<ServerMessage> {
+        let mut last = None;
+        while let Some(msg) = outgoing.recv().await {
+            last = Some((*msg.msg).clone());
+        }
+
+        last
+    }
+
+    #[tokio::test]
+    async fn shutdown_tells_players_and_forgets_rooms() {
+        let supervisor = Supervisor::default();
+        let (id, room) = supervisor.create_room(RoomConfig::default()).unwrap();
+        let outgoing = join(&room, 1, "alice").await;
+
+        supervisor.shutdown().await;
+        assert!(supervisor.find(&id).is_none());
+
+        assert_eq!(
+            last_message(outgoing).await,
+            Some(ServerMessage::Error(ServerError::ShuttingDown))
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_turns_run_out() {
+        let supervisor = Supervisor::default();
+        let (_, room) = supervisor
+            .create_room(RoomConfig {
+                turn_timeout: Some(Duration::from_millis(50)),
+                ..RoomConfig::default()
+            })
+            .unwrap();
+        let _alice = join(&room, 1, "alice").await;
+        let mut bob = join(&room, 2, "bob").await;
+
+        let skipped = async {
+            while let Some(outgoing) = bob.recv().await {
+                if *outgoing.msg == ServerMessage::TurnSkipped(0) {
+                    return true;
+                }
+            }
+            false
+        };
+        assert!(tokio::time::timeout(SHUTDOWN_GRACE, skipped).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn closing_a_room_leaves_the_others() {
+        let supervisor = Supervisor::default();
+        let (closed, room) = supervisor.create_room(RoomConfig::default()).unwrap();
+        let outgoing = join(&room, 1, "alice").await;
+        let (open, other) = supervisor.create_room(RoomConfig::default()).unwrap();
+
+        assert!(supervisor.close_room(&closed).await);
+        assert!(!supervisor.close_room(&closed).await);
+        assert!(supervisor.find(&closed).is_none());
+        assert_eq!(
+            last_message(outgoing).await,
+            Some(ServerMessage::Error(ServerError::ShuttingDown))
+        );
+
+        assert!(supervisor.find(&open).is_some());
+        join(&other, 2, "bob").await;
+    }
+}
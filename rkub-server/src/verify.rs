@@ -0,0 +1,496 @@
+//! `rkub-server verify`: follows the game through a client's [`BugReport`]
+//! the way the server would, putting every move the client sent through the
+//! rules, and points out each place the client's copy of the game and the
+//! server's came apart.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::bail;
+use rkub_common::{
+    reduce, state_hash, Action, BugReport, ClientMessage, Coord, GameState, Move, MoveId, Piece,
+    Rejection, RoomSnapshot, ServerMessage, TimedMessage, Traced, REPORT_FORMAT,
+};
+
+/// Somewhere the client and server disagreed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The message it showed at, by its place in the report's trace.
+    pub index: usize,
+    pub at_ms: u64,
+    pub what: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "message {} (at {}ms): {}",
+            self.index, self.at_ms, self.what
+        )
+    }
+}
+
+/// Reads a report as the client's debug panel copied it.
+pub fn read(json: &str) -> anyhow::Result<BugReport> {
+    let report: BugReport = serde_json::from_str(json)?;
+    if report.format != REPORT_FORMAT {
+        bail!(
+            "that report is in format {}, this version reads {}",
+            report.format,
+            REPORT_FORMAT
+        );
+    }
+
+    Ok(report)
+}
+
+/// Everywhere in `report` that the client's game and the server's parted
+/// ways, in order.
+pub fn verify(report: &BugReport) -> Vec<Divergence> {
+    let mut following = Following::default();
+
+    report
+        .trace
+        .iter()
+        .enumerate()
+        .filter_map(|(index, TimedMessage { at_ms, message })| {
+            let what = match message {
+                Traced::Sent(sent) => following.sent(&sent.msg),
+                Traced::Received(received) => following.received(&received.msg),
+            }?;
+
+            Some(Divergence {
+                index,
+                at_ms: *at_ms,
+                what,
+            })
+        })
+        .collect()
+}
+
+/// The game as the client could follow it. Only the client's own hand is
+/// known, so everyone else's is kept empty and only counted.
+#[derive(Default)]
+struct Following {
+    state: GameState,
+    seat: usize,
+    hand_sizes: Vec<usize>,
+    /// Whether we've been told the whole game yet. The trace only keeps the
+    /// last messages, so it can start anywhere.
+    known: bool,
+    /// Whether the last `StateCheck` didn't match. Like the client, one is
+    /// let go, since a move can cross it on the way.
+    out_of_step: bool,
+}
+
+impl Following {
+    fn sent(&mut self, msg: &ClientMessage) -> Option<String> {
+        let action = match msg {
+            ClientMessage::Place(coord, piece) => Action::Place(*coord, *piece),
+            ClientMessage::Pickup(coord, piece) => Action::Pickup(*coord, *piece),
+            ClientMessage::Moves(moves) => Action::Moves(moves.clone()),
+            ClientMessage::EndTurn => Action::EndTurn,
+            _ => return None,
+        };
+        if !self.known {
+            return None;
+        }
+
+        match reduce(&self.state, self.seat, &action) {
+            // What's drawn comes from the server, as a `DrawPiece`:
+            Ok(_) if action == Action::EndTurn => None,
+            Ok((state, _)) => {
+                self.state = state;
+                self.hand_sizes[self.seat] = self.state.hands[self.seat].len();
+                None
+            }
+            Err(rejection) => Some(format!(
+                "the server turns down {:?}, since {}",
+                action,
+                why(rejection)
+            )),
+        }
+    }
+
+    fn received(&mut self, msg: &ServerMessage) -> Option<String> {
+        match msg {
+            ServerMessage::JoinedRoom {
+                options, snapshot, ..
+            } => {
+                let changed = self.changed(&snapshot.board, "joining");
+                self.state.vertical_groups = options.vertical_groups;
+                self.take_snapshot(snapshot);
+                changed
+            }
+            ServerMessage::FullSync(snapshot) => {
+                let changed = self.changed(&snapshot.board, "a full sync");
+                self.take_snapshot(snapshot);
+                changed
+            }
+            ServerMessage::StartTurn(_) => {
+                self.state.start_turn(self.seat);
+                None
+            }
+            ServerMessage::Place(id, coord, piece) => {
+                self.theirs(*id, &[Move::Place(*coord, *piece)])
+            }
+            ServerMessage::Pickup(id, coord, piece) => {
+                self.theirs(*id, &[Move::Pickup(*coord, *piece)])
+            }
+            ServerMessage::Moves(id, moves) => self.theirs(*id, moves),
+            ServerMessage::MovesRejected(_, moves) if self.known => {
+                // The client takes them back, so we do too:
+                let undo: Vec<Move> = moves.iter().rev().map(|m| m.inverse()).collect();
+                let hand = &mut self.state.hands[self.seat];
+                let _ = self.state.game.apply_moves(hand, &undo);
+                self.hand_sizes[self.seat] = hand.len();
+
+                Some(format!(
+                    "the server turned down {} moves the rules let through here",
+                    moves.len()
+                ))
+            }
+            ServerMessage::InvalidBoardState if self.known => {
+                let valid = self
+                    .state
+                    .game
+                    .is_valid_board_with(self.state.vertical_groups)
+                    .0;
+                if valid {
+                    Some("the server wouldn't end the turn, with a board that's valid here".into())
+                } else {
+                    None
+                }
+            }
+            ServerMessage::OnlyRearranged if self.known => {
+                if self.state.only_rearranged() {
+                    None
+                } else {
+                    Some("the server says the board was only rearranged, but it wasn't here".into())
+                }
+            }
+            ServerMessage::KeptBoardPiece(piece) if self.known => {
+                if self.state.taken_from_board().contains(piece) {
+                    None
+                } else {
+                    Some(
+                        "the server says a piece was taken from the board, but it wasn't here"
+                            .into(),
+                    )
+                }
+            }
+            ServerMessage::DrawPiece(piece) if self.known => {
+                self.state.hands[self.seat].push(*piece);
+                self.hand_sizes[self.seat] += 1;
+                None
+            }
+            ServerMessage::HandSync(view) if self.known => {
+                let mut ours = self.state.hands[self.seat].clone();
+                ours.sort();
+                let mut theirs = view.hand().to_vec();
+                theirs.sort();
+
+                self.state.hands[self.seat] = view.hand().to_vec();
+                self.hand_sizes[self.seat] = theirs.len();
+                if ours == theirs {
+                    None
+                } else {
+                    Some("the hand here differs from the server's as the turn ended".into())
+                }
+            }
+            ServerMessage::TurnSkipped(player) if self.known => {
+                let placed = self.state.turn_delta();
+                self.state.rollback_turn();
+                if let Some(size) = self.hand_sizes.get_mut(*player) {
+                    *size = (*size as isize + placed).max(0) as usize;
+                }
+                None
+            }
+            ServerMessage::TurnFinished {
+                ending_player,
+                next_player,
+                hand_sizes,
+                board,
+                state_hash: hash,
+                ..
+            } => {
+                let what = if state_hash(board, hand_sizes) != *hash {
+                    Some("the server's hash doesn't match its own board".to_string())
+                } else {
+                    self.changed(board, &format!("{}'s turn ending", ending_player))
+                };
+
+                let hand = self.state.hands.get(self.seat).cloned().unwrap_or_default();
+                self.take(board, hand_sizes, &hand);
+                self.state.start_turn(*next_player);
+                what
+            }
+            ServerMessage::StateCheck(hash) if self.known => {
+                let matches = state_hash(self.state.game.board(), &self.hand_sizes) == *hash;
+                let out_of_step = self.out_of_step;
+                self.out_of_step = !matches;
+
+                if matches || !out_of_step {
+                    None
+                } else {
+                    Some("the board and hand sizes here missed two state checks in a row".into())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Someone else's moves, as the server passed them on. What they put
+    /// down comes out of a hand we can't see, so only the board has to fit.
+    fn theirs(&mut self, id: MoveId, moves: &[Move]) -> Option<String> {
+        // Our own moves come back to us too, and are already on the board:
+        if !self.known || id.seat == self.seat {
+            return None;
+        }
+
+        let mut hand: Vec<Piece> = moves
+            .iter()
+            .filter_map(|m| match m {
+                Move::Place(_, piece) => Some(*piece),
+                Move::Pickup(..) => None,
+            })
+            .collect();
+        let placed = match self.state.game.apply_moves(&mut hand, moves) {
+            Ok(placed) => placed,
+            Err(bad) => {
+                return Some(format!(
+                    "player {}'s {:?} doesn't fit the board here",
+                    id.seat, bad
+                ))
+            }
+        };
+
+        if let Some(size) = self.hand_sizes.get_mut(id.seat) {
+            *size = (*size as isize - placed as isize).max(0) as usize;
+        }
+        None
+    }
+
+    /// Describes how `board`, from the server after `after`, differs from
+    /// ours, if it does.
+    fn changed(&self, board: &BTreeMap<Coord, Piece>, after: &str) -> Option<String> {
+        if !self.known {
+            return None;
+        }
+
+        let ours = self.state.game.board();
+        let differ = ours
+            .iter()
+            .filter(|(coord, piece)| board.get(coord) != Some(piece))
+            .count()
+            + board
+                .keys()
+                .filter(|coord| !ours.contains_key(coord))
+                .count();
+
+        if differ == 0 {
+            None
+        } else {
+            Some(format!(
+                "{} cells of the board here differ from the server's after {}",
+                differ, after
+            ))
+        }
+    }
+
+    /// Takes the server's word for the whole game, whoever's turn it is
+    /// included.
+    fn take_snapshot(&mut self, snapshot: &RoomSnapshot) {
+        self.seat = snapshot.view.seat();
+        self.take(&snapshot.board, &snapshot.hand_sizes, snapshot.view.hand());
+        if snapshot.dealt {
+            self.state.start_turn(snapshot.active_player);
+        }
+    }
+
+    /// Takes the server's word for the board and hand sizes, with our own
+    /// `hand`.
+    fn take(&mut self, board: &BTreeMap<Coord, Piece>, hand_sizes: &[usize], hand: &[Piece]) {
+        self.state.game.set_board(board.clone());
+        self.state.hands = vec![Vec::new(); hand_sizes.len().max(self.seat + 1)];
+        self.state.hands[self.seat] = hand.to_vec();
+        self.hand_sizes = hand_sizes.to_vec();
+        self.known = true;
+        self.out_of_step = false;
+    }
+}
+
+fn why(rejection: Rejection) -> String {
+    match rejection {
+        Rejection::NotYourTurn => "it isn't the player's turn".to_string(),
+        Rejection::GameOver => "the game is over".to_string(),
+        Rejection::InvalidMove(bad) => format!("{:?} doesn't fit the board or hand", bad),
+        Rejection::OffBoard(bad) => format!("{:?} is off the board", bad),
+        Rejection::InvalidBoard => "the board isn't valid".to_string(),
+        Rejection::OnlyRearranged => "nothing from the hand went down".to_string(),
+        Rejection::KeptBoardPiece(piece) => format!("{:?} was taken from the board", piece),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rkub_common::{Phase, RoomOptions, Sequenced};
+
+    fn report(trace: Vec<Traced>) -> BugReport {
+        BugReport {
+            format: REPORT_FORMAT,
+            taken_ms: 0,
+            info: Vec::new(),
+            trace: trace
+                .into_iter()
+                .enumerate()
+                .map(|(i, message)| TimedMessage {
+                    at_ms: i as u64 * 100,
+                    message,
+                })
+                .collect(),
+        }
+    }
+
+    fn sent(seq: u64, msg: ClientMessage) -> Traced {
+        Traced::Sent(Sequenced { seq, msg })
+    }
+
+    fn received(seq: u64, msg: ServerMessage) -> Traced {
+        Traced::Received(Sequenced { seq, msg })
+    }
+
+    /// Joining a two player game as the first player, with its first piece,
+    /// on `active_player`'s turn.
+    fn joined(active_player: usize) -> (Traced, Piece) {
+        let mut state = GameState::new_with_seed(7);
+        state.add_player();
+        state.add_player();
+        let view = state.private_view(0);
+        let piece = view.hand()[0];
+
+        let msg = ServerMessage::JoinedRoom {
+            room_name: "k3x9qa".to_string(),
+            options: RoomOptions::default(),
+            last_seq: 0,
+            snapshot: Box::new(RoomSnapshot {
+                players: vec!["alice".to_string(), "bob".to_string()],
+                hand_sizes: vec![14, 14],
+                host: 0,
+                view,
+                board: BTreeMap::new(),
+                board_size: Default::default(),
+                pieces_remaining: 78,
+                dealt: true,
+                active_player,
+                phase: Phase::Playing,
+                scores: None,
+                disconnected: Vec::new(),
+                bots: Vec::new(),
+                away: Vec::new(),
+                kicked: Vec::new(),
+                thinking_ms: vec![0, 0],
+                turn_ms: 0,
+                pool: None,
+                turn: 1,
+            }),
+        };
+        (received(1, msg), piece)
+    }
+
+    #[test]
+    fn a_game_that_kept_in_step_has_nothing_to_report() {
+        let (joined, piece) = joined(0);
+        let board: BTreeMap<Coord, Piece> = vec![(Coord(0, 0), piece)].into_iter().collect();
+
+        let report = report(vec![
+            joined,
+            received(2, ServerMessage::StartTurn(1)),
+            sent(1, ClientMessage::Place(Coord(0, 0), piece)),
+            received(
+                3,
+                ServerMessage::Place(MoveId { seat: 0, seq: 1 }, Coord(0, 0), piece),
+            ),
+            received(4, ServerMessage::StateCheck(state_hash(&board, &[13, 14]))),
+        ]);
+
+        assert_eq!(verify(&report), Vec::new());
+    }
+
+    #[test]
+    fn moves_the_rules_turn_down_are_reported() {
+        let (joined, piece) = joined(1);
+
+        let report = report(vec![
+            joined,
+            sent(1, ClientMessage::Place(Coord(0, 0), piece)),
+        ]);
+
+        let divergences = verify(&report);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].index, 1);
+        assert!(divergences[0].what.contains("it isn't the player's turn"));
+    }
+
+    #[test]
+    fn boards_that_part_ways_are_reported() {
+        let (joined, piece) = joined(1);
+        let stale = state_hash(&BTreeMap::new(), &[14, 14]);
+        let board: BTreeMap<Coord, Piece> = vec![(Coord(3, 0), piece)].into_iter().collect();
+
+        let report = report(vec![
+            joined,
+            // Bob's move went missing, so his turn ending shows it:
+            received(
+                2,
+                ServerMessage::TurnFinished {
+                    ending_player: "bob".to_string(),
+                    ending_drew: false,
+                    next_player: 0,
+                    hand_sizes: vec![14, 13],
+                    pieces_remaining: 78,
+                    board: board.clone(),
+                    state_hash: state_hash(&board, &[14, 13]),
+                    thinking_ms: vec![0, 0],
+                    pool: None,
+                    turn: 2,
+                    at_ms: 0,
+                },
+            ),
+            received(3, ServerMessage::StateCheck(stale)),
+            received(4, ServerMessage::StateCheck(stale)),
+        ]);
+
+        let divergences = verify(&report);
+        assert_eq!(divergences.len(), 2);
+        assert_eq!(divergences[0].index, 1);
+        assert!(divergences[0].what.contains("after bob's turn ending"));
+        assert_eq!(divergences[1].index, 3);
+    }
+
+    #[test]
+    fn hands_that_part_ways_are_reported() {
+        let (joined, _) = joined(1);
+        let view = |seed| {
+            let mut state = GameState::new_with_seed(seed);
+            state.add_player();
+            state.add_player();
+            state.private_view(0)
+        };
+
+        let report = report(vec![
+            joined,
+            received(2, ServerMessage::HandSync(view(7))),
+            // A hand dealt from another shuffle, that the client never had:
+            received(3, ServerMessage::HandSync(view(8))),
+        ]);
+
+        let divergences = verify(&report);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].index, 2);
+        assert!(divergences[0].what.contains("as the turn ended"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Tells another service about rooms and games as they happen, so a
+//! community running a server can have them posted to e.g. a Discord or
+//! Slack channel. Nothing is sent until the server's given a URL, see
+//! [`open`].
+//!
+//! Every [`Notification`] is POSTed on its own as JSON, plus a `text` and
+//! `content` saying the same in a sentence for chat hooks that only read
+//! those.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use rkub_common::Notification;
+
+static WEBHOOK: OnceLock<Webhook> = OnceLock::new();
+
+/// How long a hook has to answer before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends notifications to `url` from now on. Only the first call counts.
+pub fn open(url: &str) -> anyhow::Result<()> {
+    let webhook = Webhook::new(url)?;
+    let _ = WEBHOOK.set(webhook);
+
+    Ok(())
+}
+
+/// Sends `notification` to the hook, if there is one, in the background. A
+/// hook that's down just misses it.
+pub fn notify(notification: Notification) {
+    let webhook = match WEBHOOK.get() {
+        Some(webhook) => webhook,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        match webhook.send(&notification).await {
+            Ok(()) => debug!(?notification, "sent webhook"),
+            Err(e) => warn!(error = %e, ?notification, "webhook failed"),
+        }
+    });
+}
+
+/// What's actually sent.
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    notification: &'a Notification,
+    /// Slack reads this.
+    text: String,
+    /// Discord reads this.
+    content: String,
+}
+
+pub struct Webhook {
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    fn new(url: &str) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+
+        Ok(Webhook {
+            url: url.parse()?,
+            client,
+        })
+    }
+
+    pub async fn send(&self, notification: &Notification) -> reqwest::Result<()> {
+        let summary = notification.summary();
+        let payload = Payload {
+            notification,
+            text: summary.clone(),
+            content: summary,
+        };
+
+        self.client
+            .post(self.url.clone())
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn notifications_are_posted_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let webhook = Webhook::new(&url).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            // Read until the whole JSON body's arrived:
+            while !request.ends_with(b"}") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            socket
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let created = Notification::RoomCreated {
+            room: "k3x9qa".to_string(),
+            ranked: false,
+            private: false,
+        };
+        webhook.send(&created).await.unwrap();
+
+        let request = server.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /hook HTTP/1.1"));
+
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "event": "room_created",
+                "room": "k3x9qa",
+                "ranked": false,
+                "private": false,
+                "text": "Room k3x9qa was created",
+                "content": "Room k3x9qa was created",
+            })
+        );
+    }
+}
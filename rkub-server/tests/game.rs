@@ -0,0 +1,952 @@
+//! Whole games against a real server, played by scripted websocket clients.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use rkub_common::{
+    state_hash, AdminCommand, ClientMessage, Coord, Move, MoveId, Piece, RoomOptions, Sequenced,
+    ServerError, ServerMessage, Severity, WS_PATH,
+};
+use rkub_server::shards::Shards;
+use rkub_server::{serve, ConnectionLimits, LoadLimits, RoomLimits, Supervisor};
+
+/// How long a client waits for a message before the test fails.
+const PATIENCE: Duration = Duration::from_secs(5);
+
+/// Starts a server on a free port.
+async fn start_server() -> SocketAddr {
+    start_server_with(Supervisor::default(), ConnectionLimits::default()).await
+}
+
+async fn start_server_with(supervisor: Supervisor, limits: ConnectionLimits) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(serve(listener, supervisor, limits));
+
+    addr
+}
+
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The `seq` of the last message we sent.
+    sent: u64,
+    /// The `seq` of the last message the server sent.
+    received: u64,
+}
+
+impl Client {
+    async fn connect(server: SocketAddr) -> Self {
+        let (ws, _) = connect_async(format!("ws://{}{}", server, WS_PATH))
+            .await
+            .unwrap();
+
+        Client {
+            ws,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    /// Connects and creates a room, returning the client and the room's
+    /// code. Nothing's dealt until someone joins, see [`Client::dealt`].
+    async fn create(server: SocketAddr, name: &str, password: Option<&str>) -> (Self, String) {
+        let mut client = Client::connect(server).await;
+        client
+            .send(ClientMessage::CreateRoom(
+                name.to_string(),
+                password.map(String::from),
+                RoomOptions::default(),
+            ))
+            .await;
+
+        match client.recv().await {
+            ServerMessage::JoinedRoom { room_name, .. } => (client, room_name),
+            msg => panic!("expected JoinedRoom, got {:?}", msg),
+        }
+    }
+
+    /// Waits for a room's creator to be dealt in, once the second player's
+    /// joined, returning their hand.
+    async fn dealt(&mut self) -> Vec<Piece> {
+        match self
+            .recv_until(|m| matches!(m, ServerMessage::JoinedRoom { .. }))
+            .await
+        {
+            ServerMessage::JoinedRoom { snapshot, .. } => snapshot.view.into_hand(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Waits for the game to start, returning the turn order. Whoever goes
+    /// first, sat in `seat` or not, is told it's their turn straight after.
+    async fn started(&mut self, seat: usize) -> Vec<usize> {
+        let order = match self.recv().await {
+            ServerMessage::StartGame { order, .. } => order,
+            msg => panic!("expected StartGame, got {:?}", msg),
+        };
+        if order[0] == seat {
+            assert_eq!(self.recv().await, ServerMessage::StartTurn(1));
+        }
+        order
+    }
+
+    /// Connects and asks to join `room`, returning the client and the first
+    /// thing the server said.
+    async fn join(
+        server: SocketAddr,
+        name: &str,
+        room: &str,
+        password: Option<&str>,
+    ) -> (Self, ServerMessage) {
+        let mut client = Client::connect(server).await;
+        client
+            .send(ClientMessage::JoinRoom(
+                name.to_string(),
+                room.to_string(),
+                password.map(String::from),
+            ))
+            .await;
+
+        let msg = client.recv().await;
+        (client, msg)
+    }
+
+    async fn send(&mut self, msg: ClientMessage) {
+        self.sent += 1;
+        self.send_as(self.sent, msg).await;
+    }
+
+    /// Sends `msg` numbered `seq`, whatever was sent before it.
+    async fn send_as(&mut self, seq: u64, msg: ClientMessage) {
+        let json = serde_json::to_string(&Sequenced { seq, msg }).unwrap();
+        self.ws.send(Message::text(json)).await.unwrap();
+    }
+
+    async fn recv(&mut self) -> ServerMessage {
+        loop {
+            let message = timeout(PATIENCE, self.ws.next())
+                .await
+                .expect("timed out waiting for the server")
+                .expect("the server hung up")
+                .unwrap();
+
+            if let Message::Text(json) = message {
+                let Sequenced { seq, msg } = serde_json::from_str(&json).unwrap();
+                assert_eq!(seq, self.received + 1, "the server skipped a message");
+                self.received = seq;
+
+                if let ServerMessage::JoinedRoom { last_seq, .. } = msg {
+                    self.sent = self.sent.max(last_seq);
+                }
+                return msg;
+            }
+        }
+    }
+
+    /// Skips ahead to the first message `wanted` picks out.
+    async fn recv_until(&mut self, wanted: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+        loop {
+            let msg = self.recv().await;
+            if wanted(&msg) {
+                return msg;
+            }
+        }
+    }
+
+    /// The next frame that isn't a message, skipping any messages before it.
+    async fn recv_frame(&mut self) -> Message {
+        loop {
+            let frame = timeout(PATIENCE, self.ws.next())
+                .await
+                .expect("timed out waiting for the server")
+                .expect("the server hung up")
+                .unwrap();
+
+            if !frame.is_text() {
+                return frame;
+            }
+        }
+    }
+
+    async fn close(mut self) {
+        self.ws.close(None).await.unwrap();
+    }
+}
+
+/// Someone sat at a game [`start_game`] started.
+struct Player {
+    client: Client,
+    name: &'static str,
+    seat: usize,
+    hand: Vec<Piece>,
+}
+
+/// Alice creates a room and Bob joins it, returning them in the order they
+/// play and the room's code.
+async fn start_game(server: SocketAddr) -> (Player, Player, String) {
+    let (mut alice, room) = Client::create(server, "alice", None).await;
+    let (mut bob, joined) = Client::join(server, "bob", &room, None).await;
+    let bob_hand = match joined {
+        ServerMessage::JoinedRoom { snapshot, .. } => snapshot.view.into_hand(),
+        msg => panic!("expected JoinedRoom, got {:?}", msg),
+    };
+    let alice_hand = alice.dealt().await;
+
+    let order = alice.started(0).await;
+    assert_eq!(bob.started(1).await, order);
+
+    let alice = Player {
+        client: alice,
+        name: "alice",
+        seat: 0,
+        hand: alice_hand,
+    };
+    let bob = Player {
+        client: bob,
+        name: "bob",
+        seat: 1,
+        hand: bob_hand,
+    };
+    match order[0] {
+        0 => (alice, bob, room),
+        _ => (bob, alice, room),
+    }
+}
+
+#[tokio::test]
+async fn rooms_past_the_limits_are_turned_down() {
+    let server = start_server_with(
+        Supervisor::with_limits(RoomLimits {
+            per_ip: 1,
+            window: Duration::from_secs(60),
+            max_rooms: 10,
+        }),
+        ConnectionLimits::default(),
+    )
+    .await;
+
+    let (_alice, _) = Client::create(server, "alice", None).await;
+
+    let mut again = Client::connect(server).await;
+    again
+        .send(ClientMessage::CreateRoom(
+            "alice".to_string(),
+            None,
+            RoomOptions::default(),
+        ))
+        .await;
+    assert!(matches!(
+        again.recv().await,
+        ServerMessage::Error(ServerError::TooManyRooms { wait_secs }) if wait_secs <= 60
+    ));
+
+    // A full server turns down even addresses it has never seen:
+    let full = start_server_with(
+        Supervisor::with_limits(RoomLimits {
+            max_rooms: 0,
+            ..RoomLimits::default()
+        }),
+        ConnectionLimits::default(),
+    )
+    .await;
+    let mut turned_away = Client::connect(full).await;
+    turned_away
+        .send(ClientMessage::CreateRoom(
+            "bob".to_string(),
+            None,
+            RoomOptions::default(),
+        ))
+        .await;
+    assert_eq!(
+        turned_away.recv().await,
+        ServerMessage::Error(ServerError::ServerFull)
+    );
+}
+
+#[tokio::test]
+async fn busy_servers_turn_new_players_away() {
+    let server = start_server_with(
+        Supervisor::default().with_load_limits(LoadLimits {
+            busy_rooms: 1,
+            ..LoadLimits::default()
+        }),
+        ConnectionLimits::default(),
+    )
+    .await;
+    let (_alice, room) = Client::create(server, "alice", None).await;
+    let busy = ServerMessage::Error(ServerError::ServerBusy {
+        retry_after_secs: 30,
+    });
+
+    let (_bob, joined) = Client::join(server, "bob", &room, None).await;
+    assert_eq!(joined, busy);
+
+    let mut carol = Client::connect(server).await;
+    carol
+        .send(ClientMessage::CreateRoom(
+            "carol".to_string(),
+            None,
+            RoomOptions::default(),
+        ))
+        .await;
+    assert_eq!(carol.recv().await, busy);
+}
+
+#[tokio::test]
+async fn busy_servers_still_let_players_back_in() {
+    let server = start_server_with(
+        Supervisor::default().with_load_limits(LoadLimits {
+            busy_rooms: 2,
+            ..LoadLimits::default()
+        }),
+        ConnectionLimits::default(),
+    )
+    .await;
+    let (mut alice, room) = Client::create(server, "alice", None).await;
+    let (bob, _) = Client::join(server, "bob", &room, None).await;
+    let (_carol, _) = Client::create(server, "carol", None).await;
+
+    let (_dave, joined) = Client::join(server, "dave", &room, None).await;
+    assert!(matches!(
+        joined,
+        ServerMessage::Error(ServerError::ServerBusy { .. })
+    ));
+    // Someone else taking Alice's name doesn't get her seat, or a new one:
+    let (_other_alice, joined) = Client::join(server, "alice", &room, None).await;
+    assert!(matches!(
+        joined,
+        ServerMessage::Error(ServerError::ServerBusy { .. })
+    ));
+
+    bob.close().await;
+    assert_eq!(
+        alice
+            .recv_until(|m| matches!(m, ServerMessage::PlayerDisconnected(_)))
+            .await,
+        ServerMessage::PlayerDisconnected(1)
+    );
+    let (_bob, rejoined) = Client::join(server, "bob", &room, None).await;
+    assert!(matches!(rejoined, ServerMessage::JoinedRoom { .. }));
+}
+
+#[tokio::test]
+async fn front_servers_send_players_on_to_their_rooms() {
+    let backend = start_server().await;
+    let ws_url = format!("ws://{}{}", backend, WS_PATH);
+    let front = start_server_with(
+        Supervisor::default().with_shards(Shards::new(vec![ws_url.clone()], None).unwrap()),
+        ConnectionLimits::default(),
+    )
+    .await;
+    let redirect = ServerMessage::Redirect { ws_url };
+
+    let mut alice = Client::connect(front).await;
+    alice
+        .send(ClientMessage::CreateRoom(
+            "alice".to_string(),
+            None,
+            RoomOptions::default(),
+        ))
+        .await;
+    assert_eq!(alice.recv().await, redirect);
+    let (_alice, room) = Client::create(backend, "alice", None).await;
+
+    let (_bob, sent_on) = Client::join(front, "bob", &room, None).await;
+    assert_eq!(sent_on, redirect);
+    let (_bob, joined) = Client::join(backend, "bob", &room, None).await;
+    assert!(matches!(joined, ServerMessage::JoinedRoom { .. }));
+}
+
+#[tokio::test]
+async fn connections_that_never_join_are_closed() {
+    let server = start_server_with(
+        Supervisor::default(),
+        ConnectionLimits {
+            join: Duration::from_millis(200),
+            max_half_open: 1,
+            ..ConnectionLimits::default()
+        },
+    )
+    .await;
+
+    let mut idle = Client::connect(server).await;
+    // Only the one can be on its way in at a time:
+    assert!(connect_async(format!("ws://{}{}", server, WS_PATH))
+        .await
+        .is_err());
+
+    // Pings don't buy any more time:
+    idle.send(ClientMessage::Ping).await;
+    assert_eq!(idle.recv().await, ServerMessage::Pong);
+    let closed = timeout(PATIENCE, idle.ws.next())
+        .await
+        .expect("timed out waiting for the server")
+        .expect("the server hung up without a close frame")
+        .unwrap();
+    match closed {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Policy),
+        message => panic!("expected a close frame, got {:?}", message),
+    }
+
+    // Which frees up its place:
+    let (_alice, _) = Client::create(server, "alice", None).await;
+}
+
+#[tokio::test]
+async fn websocket_pings_and_closes_are_answered() {
+    let server = start_server().await;
+
+    let mut lobby = Client::connect(server).await;
+    lobby.ws.send(Message::Ping("lobby".into())).await.unwrap();
+    assert_eq!(lobby.recv_frame().await, Message::Pong("lobby".into()));
+
+    let (mut alice, _) = Client::create(server, "alice", None).await;
+    alice.ws.send(Message::Ping("room".into())).await.unwrap();
+    assert_eq!(alice.recv_frame().await, Message::Pong("room".into()));
+
+    // The server finishes the closing handshake alice starts:
+    alice.ws.close(None).await.unwrap();
+    assert!(matches!(alice.recv_frame().await, Message::Close(_)));
+}
+
+#[tokio::test]
+async fn unreadable_messages_are_hung_up_on() {
+    let server = start_server().await;
+
+    // Binary frames are read the same as text ones:
+    let mut binary = Client::connect(server).await;
+    let ping = Sequenced {
+        seq: 1,
+        msg: ClientMessage::Ping,
+    };
+    let json = serde_json::to_vec(&ping).unwrap();
+    binary.ws.send(Message::binary(json)).await.unwrap();
+    assert_eq!(binary.recv().await, ServerMessage::Pong);
+
+    let mut malformed = Client::connect(server).await;
+    malformed.ws.send(Message::text("{ oops")).await.unwrap();
+    match malformed.recv_frame().await {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Invalid),
+        frame => panic!("expected a close frame, got {:?}", frame),
+    }
+
+    let (mut alice, _) = Client::create(server, "alice", None).await;
+    let oversized = format!(r#"{{"seq":1,"msg":{{"Stats":"{}"}}}}"#, "a".repeat(40_000));
+    alice.ws.send(Message::text(oversized)).await.unwrap();
+    match alice.recv_frame().await {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Size),
+        frame => panic!("expected a close frame, got {:?}", frame),
+    }
+
+    // Far too large to even read in, so the connection's just dropped:
+    let mut huge = Client::connect(server).await;
+    let _ = huge.ws.send(Message::binary(vec![b' '; 1024 * 1024])).await;
+    let next = timeout(PATIENCE, huge.ws.next())
+        .await
+        .expect("timed out waiting for the server");
+    assert!(!matches!(next, Some(Ok(Message::Text(_)))));
+}
+
+#[tokio::test]
+async fn players_join_each_other() {
+    let server = start_server().await;
+    let (mut alice, room) = Client::create(server, "alice", None).await;
+
+    let (mut bob, joined) = Client::join(server, "bob", &room, None).await;
+    match joined {
+        ServerMessage::JoinedRoom {
+            room_name,
+            snapshot,
+            ..
+        } => {
+            assert_eq!(room_name, room);
+            assert_eq!(snapshot.players, vec!["alice", "bob"]);
+            assert_eq!(snapshot.view.seat(), 1);
+            assert_eq!(snapshot.view.hand().len(), 14);
+            assert_eq!(snapshot.hand_sizes, vec![14, 14]);
+            assert!(snapshot.dealt);
+        }
+        msg => panic!("expected JoinedRoom, got {:?}", msg),
+    }
+
+    assert_eq!(
+        alice.recv().await,
+        ServerMessage::PlayerJoined("bob".to_string())
+    );
+    // She's only dealt in now there's someone to play against:
+    assert_eq!(alice.dealt().await.len(), 14);
+
+    // Either of them might go first, but they're told the same order:
+    let mut order = alice.started(0).await;
+    assert_eq!(bob.started(1).await, order);
+    order.sort_unstable();
+    assert_eq!(order, vec![0, 1]);
+}
+
+#[tokio::test]
+async fn codes_are_read_the_way_they_were_typed() {
+    let server = start_server().await;
+    let (_alice, room) = Client::create(server, "alice", None).await;
+
+    let (_bob, joined) = Client::join(server, "bob", &room.to_uppercase(), None).await;
+    assert!(matches!(joined, ServerMessage::JoinedRoom { .. }));
+}
+
+#[tokio::test]
+async fn ending_a_turn_without_playing_draws() {
+    let server = start_server().await;
+    let (mut first, mut second, _) = start_game(server).await;
+
+    first.client.send(ClientMessage::EndTurn).await;
+
+    let drawn = match first.client.recv().await {
+        ServerMessage::DrawPiece(piece) => piece,
+        msg => panic!("expected DrawPiece, got {:?}", msg),
+    };
+    assert_eq!(first.client.recv().await, ServerMessage::EndTurnValid);
+    // Then the hand the server has for them, to check theirs against:
+    match first.client.recv().await {
+        ServerMessage::HandSync(view) => {
+            let mut hand = first.hand.clone();
+            hand.push(drawn);
+            assert_eq!(view.seat(), first.seat);
+            assert_eq!(view.into_hand(), hand);
+        }
+        msg => panic!("expected HandSync, got {:?}", msg),
+    }
+
+    assert_eq!(second.client.recv().await, ServerMessage::StartTurn(2));
+    let mut sizes = vec![14, 14];
+    sizes[first.seat] = 15;
+    match second.client.recv().await {
+        ServerMessage::TurnFinished {
+            ending_player,
+            ending_drew,
+            next_player,
+            hand_sizes,
+            board,
+            state_hash: hash,
+            turn,
+            at_ms,
+            ..
+        } => {
+            assert_eq!(ending_player, first.name);
+            assert!(at_ms > 0);
+            assert!(ending_drew);
+            assert_eq!((next_player, turn), (second.seat, 2));
+            assert_eq!(hand_sizes, sizes);
+            assert_eq!(hash, state_hash(&board, &hand_sizes));
+        }
+        msg => panic!("expected TurnFinished, got {:?}", msg),
+    }
+}
+
+#[tokio::test]
+async fn placed_pieces_are_shown_to_everyone_but_must_be_valid() {
+    let server = start_server().await;
+    let (mut first, mut second, _) = start_game(server).await;
+    let piece = first.hand[0];
+
+    first
+        .client
+        .send(ClientMessage::Place(Coord(0, 0), piece))
+        .await;
+    let id = MoveId {
+        seat: first.seat,
+        seq: first.client.sent,
+    };
+    let placed = ServerMessage::Place(id, Coord(0, 0), piece);
+    assert_eq!(second.client.recv().await, placed);
+    // Whoever placed it hears back too, to know it was taken:
+    assert_eq!(first.client.recv().await, placed);
+
+    // Only they hear about what wasn't, and which message it was in:
+    let onto = Move::Place(Coord(0, 0), first.hand[1]);
+    first.client.send(ClientMessage::Moves(vec![onto])).await;
+    assert_eq!(
+        first.client.recv().await,
+        ServerMessage::MovesRejected(first.client.sent, vec![onto])
+    );
+
+    // One piece on its own isn't a group:
+    first.client.send(ClientMessage::EndTurn).await;
+    assert_eq!(first.client.recv().await, ServerMessage::InvalidBoardState);
+}
+
+#[tokio::test]
+async fn players_can_reconnect_to_their_seat() {
+    let server = start_server().await;
+    // Whoever isn't playing leaves, so there's no turn to wait on:
+    let (mut first, second, room) = start_game(server).await;
+
+    second.client.close().await;
+    assert_eq!(
+        first.client.recv().await,
+        ServerMessage::PlayerDisconnected(second.seat)
+    );
+    if second.seat == 0 {
+        assert_eq!(first.client.recv().await, ServerMessage::HostChanged(1));
+    }
+
+    let (mut again, rejoined) = Client::join(server, second.name, &room, None).await;
+    match rejoined {
+        // Everything about the room comes at once, whose turn it is included:
+        ServerMessage::JoinedRoom { snapshot, .. } => {
+            assert_eq!(snapshot.view.seat(), second.seat);
+            assert_eq!(snapshot.view.into_hand(), second.hand);
+            assert_eq!(snapshot.players, vec!["alice", "bob"]);
+            assert_eq!(snapshot.active_player, first.seat);
+            assert_eq!(snapshot.turn, 1);
+            assert!(snapshot.disconnected.is_empty());
+        }
+        msg => panic!("expected JoinedRoom, got {:?}", msg),
+    }
+
+    // Followed by what they missed, to show as such:
+    match again.recv().await {
+        ServerMessage::History(past) => {
+            let missed: Vec<_> = past.into_iter().map(|past| past.msg).collect();
+            assert!(missed.contains(&ServerMessage::PlayerDisconnected(second.seat)));
+        }
+        msg => panic!("expected History, got {:?}", msg),
+    }
+
+    assert_eq!(
+        first.client.recv().await,
+        ServerMessage::PlayerReconnected(second.seat)
+    );
+}
+
+#[tokio::test]
+async fn moves_sent_again_after_a_reconnect_are_only_played_once() {
+    let server = start_server().await;
+    let (mut first, mut second, room) = start_game(server).await;
+    let piece = first.hand[0];
+    let place = ClientMessage::Place(Coord(0, 0), piece);
+
+    first.client.send(place.clone()).await;
+    let placed = first.client.sent;
+    let id = MoveId {
+        seat: first.seat,
+        seq: placed,
+    };
+    assert_eq!(
+        second.client.recv().await,
+        ServerMessage::Place(id, Coord(0, 0), piece)
+    );
+
+    first.client.close().await;
+    let (mut again, rejoined) = Client::join(server, first.name, &room, None).await;
+    match rejoined {
+        ServerMessage::JoinedRoom { last_seq, .. } => assert_eq!(last_seq, placed),
+        msg => panic!("expected JoinedRoom, got {:?}", msg),
+    }
+
+    // Played again, the piece would be refused for landing on itself. Taken
+    // once, it's just not a group yet:
+    again.send_as(placed, place).await;
+    again.send(ClientMessage::EndTurn).await;
+    assert_eq!(
+        again
+            .recv_until(|m| matches!(
+                m,
+                ServerMessage::MovesRejected(..) | ServerMessage::InvalidBoardState
+            ))
+            .await,
+        ServerMessage::InvalidBoardState
+    );
+}
+
+#[tokio::test]
+async fn rooms_outlive_their_creator() {
+    let server = start_server().await;
+    let (alice, room) = Client::create(server, "alice", None).await;
+    let (mut bob, _) = Client::join(server, "bob", &room, None).await;
+    let order = bob.started(1).await;
+
+    alice.close().await;
+    assert_eq!(bob.recv().await, ServerMessage::PlayerDisconnected(0));
+    assert_eq!(bob.recv().await, ServerMessage::HostChanged(1));
+    // Her turn waits for her, if it was hers:
+    if order[0] == 0 {
+        assert!(matches!(
+            bob.recv().await,
+            ServerMessage::WaitingForReconnect { player: 0, .. }
+        ));
+    }
+
+    let (_carol, joined) = Client::join(server, "carol", &room, None).await;
+    assert!(matches!(joined, ServerMessage::JoinedRoom { .. }));
+    assert_eq!(
+        bob.recv_until(|m| matches!(m, ServerMessage::PlayerJoined(_)))
+            .await,
+        ServerMessage::PlayerJoined("carol".to_string())
+    );
+}
+
+#[tokio::test]
+async fn missing_rooms_and_wrong_passwords_are_refused() {
+    let server = start_server().await;
+    let (_alice, room) = Client::create(server, "alice", Some("hunter2")).await;
+
+    let (_, refused) = Client::join(server, "bob", &room, Some("letmein")).await;
+    assert_eq!(refused, ServerMessage::Error(ServerError::WrongPassword));
+
+    let (_, missing) = Client::join(server, "bob", "nowhere", None).await;
+    assert_eq!(
+        missing,
+        ServerMessage::Error(ServerError::RoomNotFound("n0where".to_string()))
+    );
+
+    let (_, joined) = Client::join(server, "bob", &room, Some("hunter2")).await;
+    assert!(matches!(joined, ServerMessage::JoinedRoom { .. }));
+}
+
+#[tokio::test]
+async fn accounts_keep_their_names() {
+    let file = format!("rkub-game-accounts-{}.json", std::process::id());
+    let path = std::env::temp_dir().join(file);
+    let _ = std::fs::remove_file(&path);
+    rkub_server::accounts::open(&path).unwrap();
+    let server = start_server().await;
+
+    let mut dana = Client::connect(server).await;
+    dana.send(ClientMessage::Register(
+        "dana".to_string(),
+        "pass".to_string(),
+    ))
+    .await;
+    let token = match dana.recv().await {
+        ServerMessage::LoggedIn { name, token } if name == "dana" => token,
+        msg => panic!("expected LoggedIn, got {:?}", msg),
+    };
+
+    // Guests can't play as her:
+    let (_, refused) = Client::join(server, "dana", "anywhere", None).await;
+    assert_eq!(
+        refused,
+        ServerMessage::Error(ServerError::NameTaken("dana".to_string()))
+    );
+
+    // Her token gets her back in, whatever name she types:
+    let mut again = Client::connect(server).await;
+    again.send(ClientMessage::Resume(token)).await;
+    assert!(matches!(again.recv().await, ServerMessage::LoggedIn { .. }));
+    again
+        .send(ClientMessage::CreateRoom(
+            "someone".to_string(),
+            None,
+            RoomOptions::default(),
+        ))
+        .await;
+    match again.recv().await {
+        ServerMessage::JoinedRoom { snapshot, .. } => {
+            assert_eq!(snapshot.players, vec!["dana"])
+        }
+        msg => panic!("expected JoinedRoom, got {:?}", msg),
+    }
+
+    let mut wrong = Client::connect(server).await;
+    wrong
+        .send(ClientMessage::LogIn(
+            "dana".to_string(),
+            "guess".to_string(),
+        ))
+        .await;
+    assert_eq!(
+        wrong.recv().await,
+        ServerMessage::Error(ServerError::BadLogin)
+    );
+}
+
+#[tokio::test]
+async fn ranked_players_are_matched_into_a_room() {
+    let file = format!("rkub-ranked-accounts-{}.json", std::process::id());
+    let path = std::env::temp_dir().join(file);
+    let _ = std::fs::remove_file(&path);
+    rkub_server::accounts::open(&path).unwrap();
+    let server = start_server().await;
+
+    let mut guest = Client::connect(server).await;
+    guest.send(ClientMessage::QueueRanked).await;
+    assert_eq!(
+        guest.recv().await,
+        ServerMessage::Error(ServerError::LogInFirst)
+    );
+
+    let mut players = Vec::new();
+    for name in &["erin", "frank", "grace", "heidi"] {
+        let mut player = Client::connect(server).await;
+        player
+            .send(ClientMessage::Register(
+                name.to_string(),
+                "pass".to_string(),
+            ))
+            .await;
+        assert!(matches!(
+            player.recv().await,
+            ServerMessage::LoggedIn { .. }
+        ));
+
+        player.send(ClientMessage::QueueRanked).await;
+        assert_eq!(
+            player.recv().await,
+            ServerMessage::InRankedQueue { rating: 1500 }
+        );
+        players.push(player);
+    }
+
+    let mut rooms = Vec::new();
+    for player in &mut players {
+        match player.recv().await {
+            ServerMessage::JoinedRoom { room_name, .. } => rooms.push(room_name),
+            msg => panic!("expected JoinedRoom, got {:?}", msg),
+        }
+    }
+    assert!(rooms.iter().all(|room| *room == rooms[0]));
+
+    // Nobody else gets in:
+    let (_, refused) = Client::join(server, "ivan", &rooms[0], None).await;
+    assert_eq!(refused, ServerMessage::Error(ServerError::RankedRoom));
+}
+
+#[tokio::test]
+async fn websockets_only_open_at_their_path() {
+    let server = start_server().await;
+
+    let refused = connect_async(format!("ws://{}/", server)).await;
+    assert!(refused.is_err());
+
+    let (_alice, room) = Client::create(server, "alice", None).await;
+    assert!(!room.is_empty());
+}
+
+#[cfg(feature = "static-files")]
+#[tokio::test]
+async fn pages_and_games_share_a_port() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let root = std::env::temp_dir().join(format!("rkub-static-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("index.html"), "<!DOCTYPE html>").unwrap();
+    rkub_server::files::set_root(root);
+
+    let server = start_server().await;
+
+    let mut http = TcpStream::connect(server).await.unwrap();
+    http.write_all(b"GET / HTTP/1.1\r\nHost: rkub\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    http.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("<!DOCTYPE html>"));
+
+    let (_alice, room) = Client::create(server, "alice", None).await;
+    assert!(!room.is_empty());
+}
+
+#[tokio::test]
+async fn operators_can_see_into_and_unstick_rooms() {
+    rkub_server::admin::set_secret("letmein".to_string());
+    let server = start_server().await;
+    let admin = |command| ClientMessage::Admin("letmein".to_string(), command);
+
+    let (mut alice, room) = Client::create(server, "alice", None).await;
+    let (mut bob, _) = Client::join(server, "bob", &room, None).await;
+    alice.dealt().await;
+
+    let mut operator = Client::connect(server).await;
+    operator.send(admin(AdminCommand::ListRooms)).await;
+    match operator.recv().await {
+        ServerMessage::AdminRooms(rooms) => {
+            assert_eq!(rooms.len(), 1);
+            assert_eq!(
+                (&rooms[0].id, &rooms[0].players[..]),
+                (&room, &["alice".to_string(), "bob".to_string()][..])
+            );
+        }
+        msg => panic!("expected AdminRooms, got {:?}", msg),
+    }
+
+    operator
+        .send(admin(AdminCommand::InspectRoom(room.clone())))
+        .await;
+    match operator.recv().await {
+        ServerMessage::AdminRoom {
+            snapshot, hands, ..
+        } => {
+            assert!(snapshot.unwrap().dealt);
+            assert_eq!(hands.len(), 2);
+        }
+        msg => panic!("expected AdminRoom, got {:?}", msg),
+    }
+
+    operator
+        .send(admin(AdminCommand::ForceEndTurn(room.clone())))
+        .await;
+    assert_eq!(operator.recv().await, ServerMessage::AdminDone);
+    bob.recv_until(|m| matches!(m, ServerMessage::TurnSkipped(_)))
+        .await;
+
+    let notice = "Restarting in 5 minutes".to_string();
+    operator
+        .send(admin(AdminCommand::Broadcast(
+            notice.clone(),
+            Severity::Critical,
+        )))
+        .await;
+    assert_eq!(operator.recv().await, ServerMessage::AdminDone);
+    bob.recv_until(|m| {
+        *m == ServerMessage::Announcement {
+            text: notice.clone(),
+            severity: Severity::Critical,
+        }
+    })
+    .await;
+
+    operator
+        .send(admin(AdminCommand::CloseRoom(room.clone())))
+        .await;
+    assert_eq!(operator.recv().await, ServerMessage::AdminDone);
+    alice
+        .recv_until(|m| *m == ServerMessage::Error(ServerError::ShuttingDown))
+        .await;
+    operator
+        .send(admin(AdminCommand::CloseRoom(room.clone())))
+        .await;
+    assert_eq!(
+        operator.recv().await,
+        ServerMessage::Error(ServerError::RoomNotFound(room))
+    );
+
+    operator
+        .send(admin(AdminCommand::CreateRoom(RoomOptions::default())))
+        .await;
+    let opened = match operator.recv().await {
+        ServerMessage::AdminRoomCreated(id) => id,
+        msg => panic!("expected AdminRoomCreated, got {:?}", msg),
+    };
+    let (_carol, joined) = Client::join(server, "carol", &opened, None).await;
+    assert!(matches!(joined, ServerMessage::JoinedRoom { .. }));
+
+    let mut guesser = Client::connect(server).await;
+    guesser
+        .send(ClientMessage::Admin(
+            "letmeinplease".to_string(),
+            AdminCommand::ListRooms,
+        ))
+        .await;
+    assert_eq!(
+        guesser.recv().await,
+        ServerMessage::Error(ServerError::NotAdmin)
+    );
+}